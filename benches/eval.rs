@@ -0,0 +1,72 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use std::collections::HashMap;
+
+use bigdecimal::{BigDecimal, FromPrimitive};
+use tsc::compile::Program;
+use tsc::config::config_reader;
+use tsc::parser::Parser;
+use tsc::{calc, Value};
+
+/// xの値を変えて評価する数式. 関数を含むものと四則演算だけのもの. yは2とする
+const FORMS: [(&str, &str); 2] = [
+    (
+        "funcs",
+        "\\sin x^{2} + \\frac{x}{3} - \\sqrt{x} + 2x^{3} - \\abs(x - 5)",
+    ),
+    ("arith", "x x x - 2 x x + 3 x y - (x + y)(x - y) + 1"),
+];
+/// 1回の計測で評価する回数
+const POINTS: usize = 1000;
+
+fn xs() -> Vec<f64> {
+    (0..POINTS).map(|i| i as f64 / 100.0).collect()
+}
+
+fn bench_eval(c: &mut Criterion) {
+    let conf = config_reader().unwrap().clone();
+    for (name, form) in FORMS {
+        let (ast, root) = Parser::new(form.to_string(), false)
+            .unwrap()
+            .build_expr()
+            .unwrap();
+        let prog = Program::compile(&ast, root, form);
+        let mut vars: HashMap<String, Value> = HashMap::new();
+        vars.insert("x".to_string(), BigDecimal::from(0).into());
+        vars.insert("y".to_string(), BigDecimal::from(2).into());
+        let x = prog.slot("x").unwrap();
+
+        let mut group = c.benchmark_group(format!("eval_{name}"));
+        group.throughput(Throughput::Elements(POINTS as u64));
+        group.bench_function("calc", |b| {
+            let mut vars = vars.clone();
+            b.iter(|| {
+                for x in xs() {
+                    vars.insert("x".to_string(), BigDecimal::from_f64(x).unwrap().into());
+                    black_box(calc(&ast, root, &vars, form).unwrap());
+                }
+            })
+        });
+        group.bench_function("compiled", |b| {
+            let mut slots = prog.bind(&vars).unwrap();
+            b.iter(|| {
+                for v in xs() {
+                    slots[x] = BigDecimal::from_f64(v).unwrap();
+                    black_box(prog.eval(&slots, &conf).unwrap());
+                }
+            })
+        });
+        group.bench_function("compiled_f64", |b| {
+            let mut slots = prog.bind_f64(&vars).unwrap();
+            b.iter(|| {
+                for v in xs() {
+                    slots[x] = v;
+                    black_box(prog.eval_f64(&slots, &conf).unwrap());
+                }
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_eval);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use tsc::tokenizer::tokenize;
+
+/// 1行ずつ字句解析する数式
+const LINES: [&str; 8] = [
+    "1 + 2 * 3",
+    "1.16E-6 * 0x1 - \\frac{\\sin \\pi}{0b10} / 0x12 + 0.2",
+    "\\sqrt{2} \\times \\log 1,234.5 \\div 3",
+    "a=3; b=0x12_34; \\frac{a}{b} + ab",
+    "\\arcsin \\frac{1}{2} + \\arccos 0.5 - \\arctan 1",
+    "(1 + 2)^{3} - \\abs(-4) + \\exp(1)",
+    "2x^{2} + 3x + 1; :hex",
+    "\\csc 1 + \\sec 1 + \\cot 1 - \\ln e",
+];
+
+fn bench_tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+    group.throughput(Throughput::Elements(LINES.len() as u64));
+    group.bench_function("lines", |b| {
+        b.iter(|| {
+            for line in LINES {
+                black_box(tokenize(black_box(line)).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_tokenize);
+criterion_main!(benches);
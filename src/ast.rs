@@ -0,0 +1,309 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+use crate::parser::Span;
+use crate::registry::registry_reader;
+
+/// Ast内のnodeの位置
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// 1引数の演算
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnaryOp {
+    Sin,
+    Cos,
+    Tan,
+    Csc,
+    Sec,
+    Cot,
+    AcSin,
+    AcCos,
+    AcTan,
+    Sqrt,
+    Log,
+    Ln,
+    Abs,
+    Exp,
+    // 前置
+    Neg,
+}
+
+/// 2引数の演算
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    // 後置
+    Pow,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl UnaryOp {
+    pub fn to_op_str(self) -> &'static str {
+        match self {
+            UnaryOp::Sin => "Sin",
+            UnaryOp::Cos => "Cos",
+            UnaryOp::Tan => "Tan",
+            UnaryOp::Csc => "Csc",
+            UnaryOp::Sec => "Sec",
+            UnaryOp::Cot => "Cot",
+            UnaryOp::AcSin => "AcSin",
+            UnaryOp::AcCos => "AcCos",
+            UnaryOp::AcTan => "AcTan",
+            UnaryOp::Sqrt => "Sqrt",
+            UnaryOp::Log => "Log",
+            UnaryOp::Ln => "Ln",
+            UnaryOp::Abs => "Abs",
+            UnaryOp::Exp => "exp",
+            UnaryOp::Neg => "-",
+        }
+    }
+
+    pub fn to_lisp_op_str(self) -> &'static str {
+        match self {
+            UnaryOp::Sin => "sin",
+            UnaryOp::Cos => "cos",
+            UnaryOp::Tan => "tan",
+            UnaryOp::Csc => "csc",
+            UnaryOp::Sec => "sec",
+            UnaryOp::Cot => "cot",
+            UnaryOp::AcSin => "asin",
+            UnaryOp::AcCos => "acos",
+            UnaryOp::AcTan => "atan",
+            UnaryOp::Sqrt => "sqrt",
+            UnaryOp::Log => "log",
+            UnaryOp::Ln => "log",
+            UnaryOp::Abs => "abs",
+            UnaryOp::Exp => "exp",
+            UnaryOp::Neg => "-",
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn to_op_str(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Pow => "Pow",
+        }
+    }
+
+    pub fn to_lisp_op_str(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Pow => "expt",
+        }
+    }
+}
+
+/// Call nodeの引数. Ast::argsの範囲を表す
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Args {
+    start: u32,
+    len: u32,
+}
+
+impl Args {
+    pub fn len(self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len == 0
+    }
+}
+
+/// nodeの種類. 各variantは自分の被演算子だけを持つ
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum NodeKind {
+    // 数字
+    Num(BigDecimal),
+    // 変数
+    Var(String),
+    Unary(UnaryOp, NodeId),
+    Binary(BinaryOp, NodeId, NodeId),
+    // registryに登録された関数. コマンドのidと引数
+    Call(usize, Args),
+}
+
+impl fmt::Display for NodeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeKind::Num(n) => write!(f, "Num({n})"),
+            NodeKind::Var(v) => write!(f, "Var({v})"),
+            NodeKind::Unary(op, _) => write!(f, "{op}"),
+            NodeKind::Binary(op, _, _) => write!(f, "{op}"),
+            NodeKind::Call(id, _) => write!(f, "Func({id})"),
+        }
+    }
+}
+
+impl NodeKind {
+    /// 演算の名前. 数字と変数はその値
+    pub fn to_op_str(&self) -> String {
+        match self {
+            NodeKind::Num(n) => n.to_string(),
+            NodeKind::Var(v) => v.clone(),
+            NodeKind::Unary(op, _) => op.to_op_str().to_string(),
+            NodeKind::Binary(op, _, _) => op.to_op_str().to_string(),
+            NodeKind::Call(id, _) => func_name(*id),
+        }
+    }
+
+    pub fn to_lisp_op_str(&self) -> String {
+        match self {
+            NodeKind::Unary(op, _) => op.to_lisp_op_str().to_string(),
+            NodeKind::Binary(op, _, _) => op.to_lisp_op_str().to_string(),
+            NodeKind::Call(id, _) => func_name(*id).trim_start_matches('\\').to_string(),
+            _ => self.to_op_str(),
+        }
+    }
+}
+
+/// 登録された関数の名前
+fn func_name(id: usize) -> String {
+    registry_reader()
+        .ok()
+        .and_then(|r| r.get(id).map(|cmd| cmd.name.clone()))
+        .unwrap_or_else(|| format!("Func({id})"))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span,            // nodeが表す部分式全体の位置
+    pub op_span: Option<Span>, // 演算子, コマンドのtokenの位置
+}
+
+/// nodeを格納するarena
+/// nodeは子を NodeId で参照するので, 木全体が2つのVecに収まる
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Ast {
+    nodes: Vec<Node>,
+    args: Vec<NodeId>,
+}
+
+/// Ast::rollbackで戻す位置
+#[derive(Clone, Copy, Debug)]
+pub struct AstMark {
+    nodes: usize,
+    args: usize,
+}
+
+impl Ast {
+    pub fn new() -> Ast {
+        Ast::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// nodeを追加し, そのidを返す
+    pub fn push(&mut self, node: Node) -> NodeId {
+        self.nodes.push(node);
+        NodeId((self.nodes.len() - 1) as u32)
+    }
+
+    /// Call nodeの引数を追加する
+    pub fn push_args(&mut self, args: &[NodeId]) -> Args {
+        let start = self.args.len() as u32;
+        self.args.extend_from_slice(args);
+        Args {
+            start,
+            len: args.len() as u32,
+        }
+    }
+
+    pub fn args(&self, args: Args) -> &[NodeId] {
+        &self.args[args.start as usize..(args.start + args.len) as usize]
+    }
+
+    /// nodeの子を左から順に返す
+    pub fn children(&self, id: NodeId) -> impl DoubleEndedIterator<Item = NodeId> + '_ {
+        let (operands, args) = match &self[id].kind {
+            NodeKind::Num(_) | NodeKind::Var(_) => ([None, None], &[][..]),
+            NodeKind::Unary(_, arg) => ([Some(*arg), None], &[][..]),
+            NodeKind::Binary(_, lhs, rhs) => ([Some(*lhs), Some(*rhs)], &[][..]),
+            NodeKind::Call(_, args) => ([None, None], self.args(*args)),
+        };
+        operands.into_iter().flatten().chain(args.iter().copied())
+    }
+
+    /// 根からの深さがmaxを超える最初のnodeを返す
+    /// 子は親より先に追加されるので, idの順に深さを求めれば再帰しなくてよい
+    pub fn too_deep(&self, max: usize) -> Option<NodeId> {
+        let mut depth = vec![0; self.nodes.len()];
+        for i in 0..self.nodes.len() {
+            let id = NodeId(i as u32);
+            depth[i] = 1 + self
+                .children(id)
+                .map(|c| depth[c.index()])
+                .max()
+                .unwrap_or(0);
+            if depth[i] > max {
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    pub fn mark(&self) -> AstMark {
+        AstMark {
+            nodes: self.nodes.len(),
+            args: self.args.len(),
+        }
+    }
+
+    /// markの後に追加したnodeを捨てる
+    pub fn rollback(&mut self, mark: AstMark) {
+        self.nodes.truncate(mark.nodes);
+        self.args.truncate(mark.args);
+    }
+}
+
+impl Index<NodeId> for Ast {
+    type Output = Node;
+
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.index()]
+    }
+}
+
+impl IndexMut<NodeId> for Ast {
+    fn index_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.index()]
+    }
+}
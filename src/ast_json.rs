@@ -0,0 +1,248 @@
+//! ASTのJSON形式
+//!
+//! 各nodeを次のオブジェクトで表す. 子は入れ子にする
+//!
+//! ```text
+//! {
+//!   "kind": "num" | "var" | "unary" | "binary" | "call",
+//!   "value": 数値の10進表記 | 変数名 | 演算子, コマンド名,
+//!   "children": [被演算子, 引数のnode, ...],
+//!   "span": { "start": 開始位置, "end": 終了位置 }
+//! }
+//! ```
+//!
+//! unaryのvalueは"-"かコマンド名("\\sin"), binaryのvalueは"+", "-", "*", "/", "^",
+//! callのvalueはregistryに登録されたコマンド名. spanは数式中のバイト位置
+//! spanが入力の位置を指すよう, 簡約する前の構文解析したままのASTを出力する
+
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{Ast, BinaryOp, Node, NodeId, NodeKind, UnaryOp};
+use crate::error::*;
+use crate::parser::Span;
+use crate::registry::{registry_reader, Builtin, Evaluator, Registry};
+
+/// JSONの1つのnode
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JsonNode {
+    pub kind: JsonKind,
+    pub value: String,
+    #[serde(default)]
+    pub children: Vec<JsonNode>,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonKind {
+    Num,
+    Var,
+    Unary,
+    Binary,
+    Call,
+}
+
+const BINARY_OPS: [(BinaryOp, &str); 5] = [
+    (BinaryOp::Add, "+"),
+    (BinaryOp::Sub, "-"),
+    (BinaryOp::Mul, "*"),
+    (BinaryOp::Div, "/"),
+    (BinaryOp::Pow, "^"),
+];
+
+/// ASTをJSONに変換する
+pub fn to_json(ast: &Ast, root: NodeId) -> Result<String, MyError> {
+    let registry = registry_reader()?;
+    let node = to_json_node(ast, root, &registry)?;
+    Ok(serde_json::to_string_pretty(&node)?)
+}
+
+/// to_jsonで出力したJSONを読み, 評価できるASTを返す
+pub fn from_json(json: &str) -> Result<(Ast, NodeId), MyError> {
+    let node: JsonNode = serde_json::from_str(json)?;
+    let registry = registry_reader()?;
+    let mut ast = Ast::new();
+    let root = from_json_node(&node, &mut ast, &registry)?;
+    Ok((ast, root))
+}
+
+fn to_json_node(ast: &Ast, id: NodeId, registry: &Registry) -> Result<JsonNode, MyError> {
+    let node = &ast[id];
+    let (kind, value) = match &node.kind {
+        NodeKind::Num(n) => (JsonKind::Num, n.normalized().to_plain_string()),
+        NodeKind::Var(v) => (JsonKind::Var, v.clone()),
+        NodeKind::Unary(UnaryOp::Neg, _) => (JsonKind::Unary, "-".to_string()),
+        NodeKind::Unary(op, _) => match registry.builtin(Builtin::Unary(*op)) {
+            Some(cmd) => (JsonKind::Unary, cmd.name.clone()),
+            None => return Err(MyError::BrokenAstErr),
+        },
+        NodeKind::Binary(op, _, _) => match BINARY_OPS.iter().find(|(o, _)| o == op) {
+            Some((_, s)) => (JsonKind::Binary, s.to_string()),
+            None => return Err(MyError::BrokenAstErr),
+        },
+        NodeKind::Call(func, _) => match registry.get(*func) {
+            Some(cmd) => (JsonKind::Call, cmd.name.clone()),
+            None => return Err(MyError::BrokenAstErr),
+        },
+    };
+    let mut children = Vec::new();
+    for child in ast.children(id) {
+        children.push(to_json_node(ast, child, registry)?);
+    }
+    Ok(JsonNode {
+        kind,
+        value,
+        children,
+        span: node.span,
+    })
+}
+
+fn from_json_node(node: &JsonNode, ast: &mut Ast, registry: &Registry) -> Result<NodeId, MyError> {
+    let err = |msg: &str| Err(MyError::AstJsonErr(format!("{msg} '{}'", node.value)));
+    let arity = match node.kind {
+        JsonKind::Num | JsonKind::Var => 0,
+        JsonKind::Unary => 1,
+        JsonKind::Binary => 2,
+        JsonKind::Call => match registry.lookup(&node.value) {
+            Some((_, cmd)) => match &cmd.eval {
+                Evaluator::List(_) => node.children.len(),
+                Evaluator::Special(f) if f.arity().contains(&node.children.len()) => {
+                    node.children.len()
+                }
+                _ => cmd.arity,
+            },
+            None => return err("undefined command"),
+        },
+    };
+    if node.children.len() != arity {
+        return Err(MyError::AstJsonErr(format!(
+            "'{}' takes {arity} children but {}",
+            node.value,
+            node.children.len()
+        )));
+    }
+    let mut children = Vec::with_capacity(arity);
+    for child in &node.children {
+        children.push(from_json_node(child, ast, registry)?);
+    }
+    let kind = match node.kind {
+        JsonKind::Num => match node.value.parse::<BigDecimal>() {
+            Ok(n) => NodeKind::Num(n),
+            Err(_) => return err("invalid number"),
+        },
+        JsonKind::Var => NodeKind::Var(node.value.clone()),
+        JsonKind::Unary if node.value == "-" => NodeKind::Unary(UnaryOp::Neg, children[0]),
+        JsonKind::Unary => match registry.lookup(&node.value) {
+            Some((_, cmd)) => match cmd.eval {
+                Evaluator::Builtin(Builtin::Unary(op)) => NodeKind::Unary(op, children[0]),
+                _ => return err("not unary operator"),
+            },
+            None => return err("undefined command"),
+        },
+        JsonKind::Binary => match BINARY_OPS.iter().find(|(_, s)| *s == node.value) {
+            Some((op, _)) => NodeKind::Binary(*op, children[0], children[1]),
+            None => return err("undefined operator"),
+        },
+        JsonKind::Call => match registry.lookup(&node.value) {
+            Some((_, cmd)) if matches!(cmd.eval, Evaluator::Builtin(_)) => {
+                return err("builtin operator as call");
+            }
+            Some((id, _)) => NodeKind::Call(id, ast.push_args(&children)),
+            None => return err("undefined command"),
+        },
+    };
+    Ok(ast.push(Node {
+        kind,
+        span: node.span,
+        op_span: None,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::CONSTS;
+
+    fn parse(form: &str) -> (Ast, NodeId) {
+        Parser::new(form.to_string(), false)
+            .unwrap()
+            .build_expr()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_schema() {
+        let (ast, root) = parse("-x^{2}");
+        let json: serde_json::Value = serde_json::from_str(&to_json(&ast, root).unwrap()).unwrap();
+        let expected = serde_json::json!({
+            "kind": "unary",
+            "value": "-",
+            "children": [{
+                "kind": "binary",
+                "value": "^",
+                "children": [
+                    { "kind": "var", "value": "x", "children": [], "span": { "start": 1, "end": 2 } },
+                    { "kind": "num", "value": "2", "children": [], "span": { "start": 4, "end": 5 } },
+                ],
+                "span": { "start": 1, "end": 6 },
+            }],
+            "span": { "start": 0, "end": 6 },
+        });
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_span_in_form() {
+        fn check(node: &JsonNode, len: usize) {
+            assert!(node.span.start <= node.span.end && node.span.end <= len);
+            node.children.iter().for_each(|c| check(c, len));
+        }
+        for form in ["x+2", "1 + x + 1", "\\frac{4}{x+2} \\cdot 1"] {
+            let (ast, root) = parse(form);
+            let node: JsonNode = serde_json::from_str(&to_json(&ast, root).unwrap()).unwrap();
+            check(&node, form.len());
+        }
+        let (ast, root) = parse("x+2");
+        let node: JsonNode = serde_json::from_str(&to_json(&ast, root).unwrap()).unwrap();
+        assert_eq!(node.children[1].span, Span { start: 2, end: 3 });
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut vars = CONSTS.read().unwrap().clone();
+        vars.insert("x".to_string(), BigDecimal::from(3).into());
+        let forms = [
+            "1 + 2 * 3 - 4 / 5",
+            "\\frac{x}{2} + \\sqrt{x} - \\abs(-x)^{3}",
+            "\\sin x \\cos \\pi + \\log 8 - \\ln e + \\exp(1)",
+        ];
+        for form in forms {
+            let (ast, root) = parse(form);
+            let (ast2, root2) = from_json(&to_json(&ast, root).unwrap()).unwrap();
+            assert_eq!(
+                crate::calc(&ast, root, &vars, form).unwrap(),
+                crate::calc(&ast2, root2, &vars, form).unwrap(),
+                "{form}"
+            );
+            assert_eq!(ast2[root2].span, ast[root].span);
+        }
+    }
+
+    #[test]
+    fn test_from_json_err() {
+        let span = r#""span": { "start": 0, "end": 1 }"#;
+        let jsons = [
+            format!(r#"{{ "kind": "binary", "value": "+", "children": [], {span} }}"#),
+            format!(
+                r#"{{ "kind": "unary", "value": "\\undefined", "children": [{{ "kind": "num", "value": "1", {span} }}], {span} }}"#
+            ),
+            format!(r#"{{ "kind": "num", "value": "one", {span} }}"#),
+            format!(r#"{{ "kind": "node", "value": "1", {span} }}"#),
+        ];
+        for json in jsons {
+            assert!(from_json(&json).is_err(), "{json}");
+        }
+    }
+}
@@ -2,10 +2,13 @@ use bigdecimal::{BigDecimal, FromPrimitive};
 use std::collections::{HashMap, HashSet};
 use crate::config::*;
 use crate::error::*;
-use crate::parser::{Node, NodeKind, NumOrVar};
+use crate::ast::{Ast, NodeId, NodeKind, UnaryOp};
+use crate::tex_printer::to_tex;
+use crate::ast_json::to_json;
+use crate::Value;
 
 
-    pub fn show_ast(ast: &Node, vars: &HashMap<String, BigDecimal>) -> Result<(), MyError> {
+    pub fn show_ast(ast: &Ast, root: NodeId, vars: &HashMap<String, Value>) -> Result<(), MyError> {
         let conf = config_reader()?;
         let is_show_ast = cfg!(debug_assertions)
             || conf.debug
@@ -13,19 +16,16 @@ use crate::parser::{Node, NodeKind, NumOrVar};
             || conf.ast_format == AstFormat::Both;
         if is_show_ast {
             let mut msg = String::new();
-            let mut node = ast;
-            let mut level = 0;
-            let mut tr_node_stack = Vec::new();
-            let mut tr_level_stack = Vec::new();
-            let mut is_next_have_chiled = true;
+            // (node, level, 兄弟の中で最後か)
+            let mut tr_stack = vec![(root, 0, true)];
             let mut no_show_bar = Vec::new();
             const TREE_WIDTH: i32 = 3;
-            loop {
+            while let Some((id, level, is_last)) = tr_stack.pop() {
                 // edgeを表すやつを追加
                 for i in 0..(level * TREE_WIDTH) {
                     if i % TREE_WIDTH == 0 {
                         if i / TREE_WIDTH == level - 1 {
-                            if is_next_have_chiled {
+                            if !is_last {
                                 msg += "├";
                                 no_show_bar.retain(|&e| e != level - 1);
                             } else {
@@ -44,34 +44,18 @@ use crate::parser::{Node, NodeKind, NumOrVar};
                     }
                 }
                 // nodeを追加
-                match node.node_kind {
-                    NodeKind::Num | NodeKind::Var => match node.val.clone().unwrap() {
-                        NumOrVar::Num(n) => msg += &(n.to_string()),
-                        NumOrVar::Var(v) => match vars.get(&v) {
-                            Some(n) => msg += &format!("{v} = {n}"),
-                            None => msg += &v,
-                        },
+                match &ast[id].kind {
+                    NodeKind::Var(v) => match vars.get(v).and_then(Value::num) {
+                        Some(n) => msg += &format!("{v} = {n}"),
+                        None => msg += v,
                     },
-                    _ => msg += &node.node_kind.to_op_str(),
+                    kind => msg += &kind.to_op_str(),
                 }
                 msg += "\n";
-                // treeのトラバース、levelの変更
-                is_next_have_chiled = node.left_node.is_some() && node.right_node.is_some();
-                if let Some(rnode) = &node.right_node {
-                    tr_node_stack.push(rnode);
-                    tr_level_stack.push(level + 1);
-                }
-                if let Some(lnode) = &node.left_node {
-                    node = lnode;
-                    level += 1;
-                } else {
-                    match tr_node_stack.pop() {
-                        Some(n) => {
-                            node = n;
-                            level = tr_level_stack.pop().unwrap();
-                        }
-                        None => break,
-                    }
+                // 子を左から順に表示するため, 逆順に積む
+                let children: Vec<NodeId> = ast.children(id).collect();
+                for (i, child) in children.iter().enumerate().rev() {
+                    tr_stack.push((*child, level + 1, i == children.len() - 1));
                 }
             }
             eprintln!("{msg}");
@@ -79,7 +63,7 @@ use crate::parser::{Node, NodeKind, NumOrVar};
         Ok(())
     }
 
-    pub fn show_ast_in_s_expr_rec(node: &Node, vars: &HashMap<String, BigDecimal>) -> Result<(), MyError> {
+    pub fn show_ast_in_s_expr_rec(ast: &Ast, root: NodeId, vars: &HashMap<String, Value>) -> Result<(), MyError> {
         let conf = config_reader()?;
         let is_show_ast = cfg!(debug_assertions)
             || conf.debug
@@ -87,36 +71,89 @@ use crate::parser::{Node, NodeKind, NumOrVar};
             || conf.ast_format == AstFormat::Both;
         if is_show_ast {
             let mut s_expr = String::new();
-            s_expr = show_ast_in_s_expr_rec_inner(node, vars, s_expr, &mut HashSet::new(), false);
+            s_expr = show_ast_in_s_expr_rec_inner(ast, root, vars, s_expr, &mut HashSet::new(), false);
             eprintln!("{s_expr}\n");
         }
         Ok(())
     }
 
+    pub fn show_ast_in_tex(ast: &Ast, root: NodeId) -> Result<(), MyError> {
+        let conf = config_reader()?;
+        let is_show_ast = cfg!(debug_assertions)
+            || conf.debug
+            || conf.ast_format == AstFormat::Tex;
+        if is_show_ast {
+            eprintln!("{}\n", to_tex(ast, root)?);
+        }
+        Ok(())
+    }
+
+    /// Graphviz(dot -Tsvg)で描画できる形式で表示する
+    pub fn show_ast_in_dot(ast: &Ast, root: NodeId, vars: &HashMap<String, Value>) -> Result<(), MyError> {
+        if config_reader()?.ast_format == AstFormat::Dot {
+            eprintln!("{}", to_dot(ast, root, vars));
+        }
+        Ok(())
+    }
+
+    pub fn show_ast_in_json(ast: &Ast, root: NodeId) -> Result<(), MyError> {
+        if config_reader()?.ast_format == AstFormat::Json {
+            eprintln!("{}", to_json(ast, root)?);
+        }
+        Ok(())
+    }
+
+    fn to_dot(ast: &Ast, root: NodeId, vars: &HashMap<String, Value>) -> String {
+        // 子を左から順に並べる
+        let mut dot = String::from("digraph ast {\n    ordering=out;\n    node [shape=box];\n");
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            let label = match &ast[id].kind {
+                NodeKind::Var(v) => match vars.get(v).and_then(Value::num) {
+                    Some(n) => format!("{v} = {n}"),
+                    None => v.clone(),
+                },
+                kind => kind.to_op_str(),
+            };
+            let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+            dot += &format!("    n{} [label=\"{label}\"];\n", id.index());
+            for child in ast.children(id) {
+                dot += &format!("    n{} -> n{};\n", id.index(), child.index());
+            }
+            stack.extend(ast.children(id).rev());
+        }
+        dot += "}";
+        dot
+    }
+
     fn show_ast_in_s_expr_rec_inner(
-        node: &Node,
-        vars: &HashMap<String, BigDecimal>,
+        ast: &Ast,
+        id: NodeId,
+        vars: &HashMap<String, Value>,
         mut s_expr: String,
         is_var_fn_printed: &mut HashSet<String>,
         is_2arg_left: bool,
     ) -> String {
-        match node.node_kind {
-            NodeKind::Num | NodeKind::Var => {
-                match &node.val {
-                    Some(NumOrVar::Num(n)) => s_expr += &n.to_string(),
-                    Some(NumOrVar::Var(v)) => {
+        let node = &ast[id];
+        match &node.kind {
+            NodeKind::Num(_) | NodeKind::Var(_) => {
+                match &node.kind {
+                    NodeKind::Num(n) => s_expr += &n.to_string(),
+                    NodeKind::Var(v) => {
                         if v == "\\pi" {
                             s_expr += "pi"
                         } else {
-                            if let Some(val) = vars.get(v)
+                            // \alpha等のギリシャ文字は\を除いてLispのシンボルにする
+                            let sym = v.trim_start_matches('\\');
+                            if let Some(val) = vars.get(v).and_then(Value::num)
                                 && is_var_fn_printed.get(v).is_none() {
-                                    s_expr = format!("(defvar {v} {val})\n{s_expr}");
+                                    s_expr = format!("(defvar {sym} {val})\n{s_expr}");
                                     is_var_fn_printed.insert(v.clone());
                             }
-                            s_expr += v
+                            s_expr += sym
                         }
                     }
-                    None => unreachable!(),
+                    _ => unreachable!(),
                 }
                 if is_2arg_left {
                     s_expr + " "
@@ -124,33 +161,38 @@ use crate::parser::{Node, NodeKind, NumOrVar};
                     s_expr
                 }
             }
-            _ => {
+            kind => {
+                let unary_op = match kind {
+                    NodeKind::Unary(op, _) => Some(*op),
+                    _ => None,
+                };
                 let mut is_deg2rad = false;
                 let mut is_rad2deg = false;
                 if config_reader().expect("couldn't read config").trig_func_arg
                     == TrigFuncArg::Degree
                 {
-                    match node.node_kind {
-                        NodeKind::Sin
-                        | NodeKind::Cos
-                        | NodeKind::Tan
-                        | NodeKind::Csc
-                        | NodeKind::Sec
-                        | NodeKind::Cot => {
-                            match node.node_kind {
-                                NodeKind::Csc => {
+                    match unary_op {
+                        Some(UnaryOp::Sin
+                        | UnaryOp::Cos
+                        | UnaryOp::Tan
+                        | UnaryOp::Csc
+                        | UnaryOp::Sec
+                        | UnaryOp::Cot) => {
+                            #[allow(clippy::collapsible_match)]
+                            match unary_op {
+                                Some(UnaryOp::Csc) => {
                                     if !is_var_fn_printed.contains("csc") {
                                         s_expr = format!("(defun csc (x) (/ 1 (sin x)))\n{s_expr}");
                                         is_var_fn_printed.insert("csc".to_string());
                                     }
                                 }
-                                NodeKind::Sec => {
+                                Some(UnaryOp::Sec) => {
                                     if !is_var_fn_printed.contains("sec") {
                                         s_expr = format!("(defun sec (x) (/ 1 (cos x)))\n{s_expr}");
                                         is_var_fn_printed.insert("sec".to_string());
                                     }
                                 }
-                                NodeKind::Cot => {
+                                Some(UnaryOp::Cot) => {
                                     if !is_var_fn_printed.contains("cot") {
                                         s_expr = format!("(defun cot (x) (/ 1 (tan x)))\n{s_expr}");
                                         is_var_fn_printed.insert("cot".to_string());
@@ -165,10 +207,10 @@ use crate::parser::{Node, NodeKind, NumOrVar};
                                 is_var_fn_printed.insert("degree2radian".to_string());
                             }
                             s_expr +=
-                                &format!("({} (degree2radian ", node.node_kind.to_lisp_op_str());
+                                &format!("({} (degree2radian ", kind.to_lisp_op_str());
                             is_deg2rad = true;
                         }
-                        NodeKind::AcSin | NodeKind::AcCos | NodeKind::AcTan => {
+                        Some(UnaryOp::AcSin | UnaryOp::AcCos | UnaryOp::AcTan) => {
                             if !is_var_fn_printed.contains("radian2degree") {
                                 s_expr = format!(
                                     "(defun radian2degree (rad) (/ (* rad 180) pi))\n{s_expr}"
@@ -176,63 +218,56 @@ use crate::parser::{Node, NodeKind, NumOrVar};
                                 is_var_fn_printed.insert("radian2degree".to_string());
                             }
                             s_expr +=
-                                &format!("(radian2degree ({} ", node.node_kind.to_lisp_op_str());
+                                &format!("(radian2degree ({} ", kind.to_lisp_op_str());
                             is_rad2deg = true;
                         }
                         _ => {
-                            s_expr += &format!("({} ", node.node_kind.to_lisp_op_str());
+                            s_expr += &format!("({} ", kind.to_lisp_op_str());
                         }
                     }
                 } else {
-                    match node.node_kind {
-                        NodeKind::Csc => {
+                    match unary_op {
+                        Some(UnaryOp::Csc) => {
                             if !is_var_fn_printed.contains("csc") {
                                 s_expr = format!("(defun csc (x) (/ 1 (sin x)))\n{s_expr}");
                                 is_var_fn_printed.insert("csc".to_string());
                             }
-                            s_expr += &format!("({} ", node.node_kind.to_lisp_op_str());
+                            s_expr += &format!("({} ", kind.to_lisp_op_str());
                         }
-                        NodeKind::Sec => {
+                        Some(UnaryOp::Sec) => {
                             if !is_var_fn_printed.contains("sec") {
                                 s_expr = format!("(defun sec (x) (/ 1 (cos x)))\n{s_expr}");
                                 is_var_fn_printed.insert("sec".to_string());
                             }
-                            s_expr += &format!("({} ", node.node_kind.to_lisp_op_str());
+                            s_expr += &format!("({} ", kind.to_lisp_op_str());
                         }
-                        NodeKind::Cot => {
+                        Some(UnaryOp::Cot) => {
                             if !is_var_fn_printed.contains("cot") {
                                 s_expr = format!("(defun cot (x) (/ 1 (tan x)))\n{s_expr}");
                                 is_var_fn_printed.insert("cot".to_string());
                             }
-                            s_expr += &format!("({} ", node.node_kind.to_lisp_op_str());
+                            s_expr += &format!("({} ", kind.to_lisp_op_str());
                         }
                         _ => {
-                            s_expr += &format!("({} ", node.node_kind.to_lisp_op_str());
+                            s_expr += &format!("({} ", kind.to_lisp_op_str());
                         }
                     }
                 }
-                if let Some(lnode) = &node.left_node {
-                    s_expr = show_ast_in_s_expr_rec_inner(
-                        lnode,
-                        vars,
-                        s_expr,
-                        is_var_fn_printed,
-                        true & node.right_node.is_some(),
-                    );
-                }
-                if let Some(rnode) = &node.right_node{
+                let children: Vec<NodeId> = ast.children(id).collect();
+                for (i, child) in children.iter().enumerate() {
                     s_expr = show_ast_in_s_expr_rec_inner(
-                        rnode,
+                        ast,
+                        *child,
                         vars,
                         s_expr,
                         is_var_fn_printed,
-                        false,
+                        i + 1 < children.len(),
                     );
                 }
                 if is_deg2rad || is_rad2deg {
                     s_expr += ")";
                 }
-                if let NodeKind::Log = node.node_kind {
+                if let Some(UnaryOp::Log) = unary_op {
                     let log_base = &config_reader().expect("couldn't read config").log_base;
                     if *log_base != BigDecimal::from_f64(std::f64::consts::E).unwrap() {
                         if s_expr.ends_with(" ") {
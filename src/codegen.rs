@@ -0,0 +1,556 @@
+use bigdecimal::{BigDecimal, FromPrimitive};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ast::{Ast, BinaryOp, NodeId, NodeKind, UnaryOp};
+use crate::config::*;
+use crate::error::*;
+use crate::optimizer::simplify;
+use crate::parser::Parser;
+use crate::registry::{registry_reader, Evaluator, PREC_ATOM, PREC_POWER, PREC_PRODUCT, PREC_SUM};
+use crate::special_functions::SpecialFn;
+
+/// 単項マイナスの結合力. Pythonの-x**2は-(x**2)なので累乗より弱い
+const PREC_NEG: u8 = 30;
+
+/// 定義された関数を展開するネストの上限
+const MAX_INLINE_DEPTH: usize = 64;
+
+/// 引数名にすると衝突する語. 後ろに_を付ける
+const RESERVED: [&str; 44] = [
+    "and", "as", "def", "del", "elif", "else", "for", "from", "if", "import", "in", "is", "lambda",
+    "not", "or", "pass", "return", "while", "with", "auto", "char", "double", "float", "int",
+    "long", "const", "fn", "let", "mut", "match", "loop", "ref", "self", "type", "use", "var",
+    "function", "new", "this", "math", "np", "Math", "M_PI", "M_E",
+];
+
+/// 生成するコードの言語
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lang {
+    /// mathモジュールを使うPython
+    Python,
+    /// numpyを使うPython. 配列を引数にできる
+    Numpy,
+    C,
+    Rust,
+    Js,
+}
+
+impl fmt::Display for Lang {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lang::Python => write!(f, "python"),
+            Lang::Numpy => write!(f, "numpy"),
+            Lang::C => write!(f, "c"),
+            Lang::Rust => write!(f, "rust"),
+            Lang::Js => write!(f, "js"),
+        }
+    }
+}
+
+impl FromStr for Lang {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "python" => Ok(Lang::Python),
+            "numpy" => Ok(Lang::Numpy),
+            "c" => Ok(Lang::C),
+            "rust" => Ok(Lang::Rust),
+            "js" => Ok(Lang::Js),
+            _ => Err(MyError::UnexpectedInput(
+                "python|numpy|c|rust|js".to_string(),
+                s.to_string(),
+            )),
+        }
+    }
+}
+
+/// 数式formを評価する関数fをlangで書いたコードを返す
+/// 定数でない変数は出現順に引数にする. 三角関数の引数の単位とlogの底はconfigに従う
+///
+/// ex)
+/// ```text
+/// :codegen python \sin x + \log y
+///
+/// import math
+///
+///
+/// def f(x, y):
+///     return math.sin(x) + math.log(y)
+/// ```
+pub fn codegen(form: &str, lang: Lang) -> Result<String, MyError> {
+    let conf = config_reader()?.clone();
+    generate(form, lang, &conf)
+}
+
+fn generate(form: &str, lang: Lang, conf: &Config) -> Result<String, MyError> {
+    let (ast, root) = Parser::new(form.to_string(), false)?.build_expr()?;
+    let (ast, root) = simplify(&ast, root, None)?;
+    let mut generator = Codegen {
+        lang,
+        conf,
+        params: Vec::new(),
+        depth: 0,
+    };
+    let body = generator.expr(&ast, root, &HashMap::new())?.0;
+    Ok(generator.function(&body))
+}
+
+/// 関数の引数名と, それに渡す式とその結合力
+type Env = HashMap<String, (String, u8)>;
+
+struct Codegen<'a> {
+    lang: Lang,
+    conf: &'a Config,
+    params: Vec<String>,
+    depth: usize,
+}
+
+impl Codegen<'_> {
+    /// 関数fの定義
+    fn function(&self, body: &str) -> String {
+        match self.lang {
+            Lang::Python | Lang::Numpy => {
+                let import = match self.lang {
+                    Lang::Python => "import math",
+                    _ => "import numpy as np",
+                };
+                format!(
+                    "{import}\n\n\ndef f({}):\n    return {body}",
+                    self.params.join(", ")
+                )
+            }
+            Lang::C => {
+                let params = if self.params.is_empty() {
+                    "void".to_string()
+                } else {
+                    self.params
+                        .iter()
+                        .map(|p| format!("double {p}"))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                };
+                format!(
+                    "#include <math.h>\n\n#ifndef M_PI\n#define M_PI 3.14159265358979323846\n#endif\n#ifndef M_E\n#define M_E 2.71828182845904523536\n#endif\n\ndouble f({params})\n{{\n    return {body};\n}}"
+                )
+            }
+            Lang::Rust => {
+                let params = self
+                    .params
+                    .iter()
+                    .map(|p| format!("{p}: f64"))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("fn f({params}) -> f64 {{\n    {body}\n}}")
+            }
+            Lang::Js => format!(
+                "function f({}) {{\n    return {body};\n}}",
+                self.params.join(", ")
+            ),
+        }
+    }
+
+    /// idのnodeの式と, その結合力を返す
+    fn expr(&mut self, ast: &Ast, id: NodeId, env: &Env) -> Result<(String, u8), MyError> {
+        match &ast[id].kind {
+            NodeKind::Num(n) => Ok(self.num(n)),
+            NodeKind::Var(v) => Ok(self.var(v, env)),
+            NodeKind::Unary(UnaryOp::Neg, arg) => {
+                let (code, prec) = self.expr(ast, *arg, env)?;
+                // JavaScriptの-x ** 2は構文エラーになる
+                let min = if self.lang == Lang::Js {
+                    PREC_POWER + 1
+                } else {
+                    PREC_NEG
+                };
+                let code = if prec < min || code.starts_with('-') {
+                    format!("({code})")
+                } else {
+                    code
+                };
+                Ok((format!("-{code}"), PREC_NEG))
+            }
+            NodeKind::Unary(op, arg) => {
+                let arg = self.expr(ast, *arg, env)?;
+                Ok(self.unary(*op, arg))
+            }
+            NodeKind::Binary(op, lhs, rhs) => {
+                let lhs = self.expr(ast, *lhs, env)?;
+                let rhs = self.expr(ast, *rhs, env)?;
+                Ok(self.binary(*op, lhs, rhs))
+            }
+            NodeKind::Call(func, args) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in ast.args(*args) {
+                    values.push(self.expr(ast, *arg, env)?);
+                }
+                self.call(*func, values)
+            }
+        }
+    }
+
+    fn num(&self, n: &BigDecimal) -> (String, u8) {
+        let mut code = n.normalized().to_plain_string();
+        // CとRustでは整数のリテラルだと整数の演算になる
+        if matches!(self.lang, Lang::C | Lang::Rust) && !code.contains('.') {
+            code += ".0";
+        }
+        let prec = if code.starts_with('-') {
+            PREC_NEG
+        } else {
+            PREC_ATOM
+        };
+        (code, prec)
+    }
+
+    fn var(&mut self, v: &str, env: &Env) -> (String, u8) {
+        if let Some(arg) = env.get(v) {
+            return arg.clone();
+        }
+        let constant = match v {
+            "\\pi" => Some([
+                "math.pi",
+                "np.pi",
+                "M_PI",
+                "std::f64::consts::PI",
+                "Math.PI",
+            ]),
+            "e" => Some(["math.e", "np.e", "M_E", "std::f64::consts::E", "Math.E"]),
+            _ => None,
+        };
+        if let Some(names) = constant {
+            return (self.pick(names).to_string(), PREC_ATOM);
+        }
+        // \alpha等のギリシャ文字は\を除いて識別子にする
+        let mut name = v.trim_start_matches('\\').to_string();
+        if RESERVED.contains(&name.as_str()) || FUNC_NAMES.contains(&name.as_str()) {
+            name += "_";
+        }
+        if !self.params.contains(&name) {
+            self.params.push(name.clone());
+        }
+        (name, PREC_ATOM)
+    }
+
+    /// [Python, numpy, C, Rust, JavaScript]からself.langのものを選ぶ
+    fn pick<'b>(&self, names: [&'b str; 5]) -> &'b str {
+        match self.lang {
+            Lang::Python => names[0],
+            Lang::Numpy => names[1],
+            Lang::C => names[2],
+            Lang::Rust => names[3],
+            Lang::Js => names[4],
+        }
+    }
+
+    /// 名前がnameの数学関数の呼び出し
+    fn func(&self, name: &str, args: &[&str]) -> (String, u8) {
+        let fname = match (self.lang, name) {
+            (Lang::Python, "abs") => "abs".to_string(),
+            (Lang::Python, _) => format!("math.{name}"),
+            (Lang::Numpy, "asin" | "acos" | "atan") => format!("np.arc{}", &name[1..]),
+            (Lang::Numpy, _) => format!("np.{name}"),
+            (Lang::C, "abs") => "fabs".to_string(),
+            (Lang::C, _) => name.to_string(),
+            (Lang::Rust, "log") if args.len() == 1 => "f64::ln".to_string(),
+            (Lang::Rust, "pow") => "f64::powf".to_string(),
+            (Lang::Rust, _) => format!("f64::{name}"),
+            (Lang::Js, "pow") => "Math.pow".to_string(),
+            (Lang::Js, _) => format!("Math.{name}"),
+        };
+        (format!("{fname}({})", args.join(", ")), PREC_ATOM)
+    }
+
+    fn unary(&self, op: UnaryOp, arg: (String, u8)) -> (String, u8) {
+        let is_degree = self.conf.trig_func_arg == TrigFuncArg::Degree;
+        match op {
+            UnaryOp::Sin | UnaryOp::Cos | UnaryOp::Tan => {
+                let arg = if is_degree { self.deg2rad(arg) } else { arg.0 };
+                let name = match op {
+                    UnaryOp::Sin => "sin",
+                    UnaryOp::Cos => "cos",
+                    _ => "tan",
+                };
+                self.func(name, &[&arg])
+            }
+            // csc x = 1 / sin x
+            UnaryOp::Csc | UnaryOp::Sec | UnaryOp::Cot => {
+                let op = match op {
+                    UnaryOp::Csc => UnaryOp::Sin,
+                    UnaryOp::Sec => UnaryOp::Cos,
+                    _ => UnaryOp::Tan,
+                };
+                let denom = self.unary(op, arg).0;
+                (format!("{} / {denom}", self.num(&1.into()).0), PREC_PRODUCT)
+            }
+            UnaryOp::AcSin | UnaryOp::AcCos | UnaryOp::AcTan => {
+                let name = match op {
+                    UnaryOp::AcSin => "asin",
+                    UnaryOp::AcCos => "acos",
+                    _ => "atan",
+                };
+                let res = self.func(name, &[&arg.0]);
+                if is_degree {
+                    self.rad2deg(res)
+                } else {
+                    res
+                }
+            }
+            UnaryOp::Sqrt => self.func("sqrt", &[&arg.0]),
+            UnaryOp::Abs => self.func("abs", &[&arg.0]),
+            UnaryOp::Exp => self.func("exp", &[&arg.0]),
+            UnaryOp::Ln => self.func("log", &[&arg.0]),
+            UnaryOp::Log => self.log(arg),
+            UnaryOp::Neg => unreachable!(),
+        }
+    }
+
+    /// 底がconfのlog_baseの対数
+    fn log(&self, arg: (String, u8)) -> (String, u8) {
+        let base = &self.conf.log_base;
+        if *base == BigDecimal::from_f64(std::f64::consts::E).unwrap() {
+            self.func("log", &[&arg.0])
+        } else if *base == BigDecimal::from(10) {
+            self.func("log10", &[&arg.0])
+        } else if *base == BigDecimal::from(2) {
+            self.func("log2", &[&arg.0])
+        } else {
+            let base = self.num(base).0;
+            match self.lang {
+                Lang::Python | Lang::Rust => self.func("log", &[&arg.0, &base]),
+                _ => {
+                    let num = self.func("log", &[&arg.0]).0;
+                    let denom = self.func("log", &[&base]).0;
+                    (format!("{num} / {denom}"), PREC_PRODUCT)
+                }
+            }
+        }
+    }
+
+    /// 度数法の角度を弧度法にする式
+    fn deg2rad(&self, (code, prec): (String, u8)) -> String {
+        match self.lang {
+            Lang::Python | Lang::Numpy => self.func("radians", &[&code]).0,
+            Lang::Rust => self.func("to_radians", &[&code]).0,
+            Lang::C | Lang::Js => {
+                let code = wrap(code, prec, PREC_PRODUCT);
+                let pi = self.pick(["", "", "M_PI", "", "Math.PI"]);
+                format!("{code} * {pi} / {}", self.num(&180.into()).0)
+            }
+        }
+    }
+
+    /// 弧度法の角度を度数法にする式
+    fn rad2deg(&self, (code, prec): (String, u8)) -> (String, u8) {
+        match self.lang {
+            Lang::Python | Lang::Numpy => self.func("degrees", &[&code]),
+            Lang::Rust => self.func("to_degrees", &[&code]),
+            Lang::C | Lang::Js => {
+                let code = wrap(code, prec, PREC_PRODUCT);
+                let pi = self.pick(["", "", "M_PI", "", "Math.PI"]);
+                (
+                    format!("{code} * {} / {pi}", self.num(&180.into()).0),
+                    PREC_PRODUCT,
+                )
+            }
+        }
+    }
+
+    fn binary(&self, op: BinaryOp, lhs: (String, u8), rhs: (String, u8)) -> (String, u8) {
+        let infix = |sym: &str, prec: u8| {
+            let lhs = wrap(lhs.0.clone(), lhs.1, prec);
+            let rhs = wrap(rhs.0.clone(), rhs.1, prec + 1);
+            (format!("{lhs} {sym} {rhs}"), prec)
+        };
+        match op {
+            BinaryOp::Add => infix("+", PREC_SUM),
+            BinaryOp::Sub => infix("-", PREC_SUM),
+            BinaryOp::Mul => infix("*", PREC_PRODUCT),
+            BinaryOp::Div => infix("/", PREC_PRODUCT),
+            BinaryOp::Pow => match self.lang {
+                // ((a ** b) ** c)のように底は常に括弧で囲む
+                Lang::Python | Lang::Numpy | Lang::Js => (
+                    format!(
+                        "{} ** {}",
+                        wrap(lhs.0, lhs.1, PREC_ATOM),
+                        wrap(rhs.0, rhs.1, PREC_NEG)
+                    ),
+                    PREC_POWER,
+                ),
+                Lang::C | Lang::Rust => self.func("pow", &[&lhs.0, &rhs.0]),
+            },
+        }
+    }
+
+    /// 設定ファイルで定義された関数は本体を展開する
+    fn call(&mut self, func: usize, args: Vec<(String, u8)>) -> Result<(String, u8), MyError> {
+        let (name, eval) = match registry_reader()?.get(func) {
+            Some(cmd) => (cmd.name.clone(), cmd.eval.clone()),
+            None => return Err(MyError::BrokenAstErr),
+        };
+        match eval {
+            Evaluator::Defined { params, body } => {
+                if self.depth >= MAX_INLINE_DEPTH {
+                    return Err(MyError::CodegenErr(format!(
+                        "{name} nests over {MAX_INLINE_DEPTH} levels"
+                    )));
+                }
+                let (ast, root) = body.ast()?;
+                let env: Env = params.into_iter().zip(args).collect();
+                self.depth += 1;
+                let res = self.expr(ast, *root, &env);
+                self.depth -= 1;
+                res
+            }
+            Evaluator::Native(_) => Err(MyError::CodegenErr(format!(
+                "{name} is implemented in Rust and has no {} equivalent",
+                self.lang
+            ))),
+            Evaluator::Special(f) => {
+                let args: Vec<&str> = args.iter().map(|arg| arg.0.as_str()).collect();
+                // Pythonのmathとmath.hにある関数だけを出力できる
+                let fname = match (f, self.lang, args.len()) {
+                    (SpecialFn::Gamma, Lang::Python, 1) => "gamma",
+                    (SpecialFn::Gamma, Lang::C, 1) => "tgamma",
+                    (SpecialFn::LnGamma, Lang::Python | Lang::C, _) => "lgamma",
+                    (SpecialFn::Erf, Lang::Python | Lang::C, _) => "erf",
+                    (SpecialFn::Erfc, Lang::Python | Lang::C, _) => "erfc",
+                    _ => {
+                        return Err(MyError::CodegenErr(format!(
+                            "{} has no {} equivalent",
+                            f.tex_name(),
+                            self.lang
+                        )));
+                    }
+                };
+                Ok(self.func(fname, &args))
+            }
+            Evaluator::List(_) => Err(MyError::CodegenErr(format!(
+                "{name} takes a list and has no {} equivalent",
+                self.lang
+            ))),
+            Evaluator::Builtin(_) => Err(MyError::BrokenAstErr),
+        }
+    }
+}
+
+/// 数学関数の名前. 変数名と衝突しないよう引数名では後ろに_を付ける
+const FUNC_NAMES: [&str; 19] = [
+    "sin", "cos", "tan", "asin", "acos", "atan", "sqrt", "log", "log10", "log2", "exp", "fabs",
+    "pow", "radians", "degrees", "tgamma", "lgamma", "erf", "erfc",
+];
+
+/// 結合力がmin未満なら括弧で囲む
+fn wrap(code: String, prec: u8, min: u8) -> String {
+    if prec < min {
+        format!("({code})")
+    } else {
+        code
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn body(form: &str, lang: Lang, conf: &Config) -> String {
+        let code = generate(form, lang, conf).unwrap();
+        let line = match lang {
+            Lang::Rust => code.lines().nth(1),
+            _ => code.lines().find(|l| l.trim_start().starts_with("return")),
+        };
+        line.unwrap()
+            .trim()
+            .trim_start_matches("return ")
+            .trim_end_matches(';')
+            .to_string()
+    }
+
+    #[test]
+    fn test_codegen() {
+        let conf = Config::default();
+        let form = "-x^{2} + \\frac{\\alpha}{2} \\sin(x + y)";
+        let cases = [
+            (Lang::Python, "-x ** 2 + alpha / 2 * math.sin(x + y)"),
+            (Lang::Numpy, "-x ** 2 + alpha / 2 * np.sin(x + y)"),
+            (Lang::C, "-pow(x, 2.0) + alpha / 2.0 * sin(x + y)"),
+            (
+                Lang::Rust,
+                "-f64::powf(x, 2.0) + alpha / 2.0 * f64::sin(x + y)",
+            ),
+            (Lang::Js, "-(x ** 2) + alpha / 2 * Math.sin(x + y)"),
+        ];
+        for (lang, expected) in cases {
+            assert_eq!(body(form, lang, &conf), expected, "{lang}");
+        }
+        let code = generate(form, Lang::Python, &conf).unwrap();
+        assert!(code.contains("def f(x, alpha, y):"), "{code}");
+        let code = generate("\\pi", Lang::C, &conf).unwrap();
+        assert!(code.contains("double f(void)"), "{code}");
+    }
+
+    #[test]
+    fn test_codegen_config() {
+        let conf = Config {
+            trig_func_arg: TrigFuncArg::Degree,
+            log_base: BigDecimal::from(10),
+            ..Config::default()
+        };
+        let form = "\\sin x + \\arctan y + \\log x";
+        let cases = [
+            (
+                Lang::Python,
+                "math.sin(math.radians(x)) + math.degrees(math.atan(y)) + math.log10(x)",
+            ),
+            (
+                Lang::C,
+                "sin(x * M_PI / 180.0) + atan(y) * 180.0 / M_PI + log10(x)",
+            ),
+            (
+                Lang::Rust,
+                "f64::sin(f64::to_radians(x)) + f64::to_degrees(f64::atan(y)) + f64::log10(x)",
+            ),
+        ];
+        for (lang, expected) in cases {
+            assert_eq!(body(form, lang, &conf), expected, "{lang}");
+        }
+        let conf = Config {
+            log_base: BigDecimal::from(3),
+            ..Config::default()
+        };
+        assert_eq!(
+            body("\\log x", Lang::Js, &conf),
+            "Math.log(x) / Math.log(3)"
+        );
+        assert_eq!(body("\\log x", Lang::Python, &conf), "math.log(x, 3)");
+    }
+
+    #[test]
+    fn test_codegen_call() {
+        use crate::registry::{
+            register, register_functions, ArgStyle, Command, FuncDef, PREC_POWER,
+        };
+        // 定義された関数は展開する
+        register_functions(&[FuncDef {
+            name: "square".to_string(),
+            params: vec!["a".to_string()],
+            style: ArgStyle::Brace,
+            prec: PREC_POWER,
+            body: "a^{2}".to_string(),
+        }])
+        .unwrap();
+        let conf = Config::default();
+        assert_eq!(body("\\square{x + 1}", Lang::Python, &conf), "(x + 1) ** 2");
+        register(Command::native("nativeid", 1, ArgStyle::Paren, |args| {
+            Ok(args[0].clone())
+        }))
+        .unwrap();
+        assert!(matches!(
+            generate("\\nativeid(x)", Lang::C, &conf),
+            Err(MyError::CodegenErr(_))
+        ));
+        assert!("java".parse::<Lang>().is_err());
+    }
+}
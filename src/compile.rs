@@ -0,0 +1,373 @@
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use std::collections::HashMap;
+
+use crate::ast::{Ast, BinaryOp, Node, NodeId, NodeKind, UnaryOp};
+use crate::config::*;
+use crate::domain;
+use crate::error::*;
+use crate::parser::{format_err_span, Span};
+use crate::{calc_binary, calc_unary, call_func, Value, CONSTS};
+
+/// Programの命令
+/// 後置記法の順に並んでおり, スタックに値を積みながら評価する
+#[derive(Clone, Copy, Debug)]
+enum Instr {
+    /// 定数を積む
+    Const(usize),
+    /// 変数のslotの値を積む
+    Load(usize),
+    /// 1つ取り出して演算し, 結果を積む. usizeはエラー表示に使うnodeの番号
+    Unary(UnaryOp, usize),
+    /// 2つ取り出して演算し, 結果を積む
+    Binary(BinaryOp, usize),
+    /// 関数のidと引数の数
+    Call(usize, usize, usize),
+}
+
+/// 変数をslotに割り当て, 命令列に変換した数式
+/// 同じ数式を変数の値だけ変えて何度も評価するときに使う
+///
+/// ex)
+/// ```
+/// use tsc::compile::Program;
+/// use tsc::config::config_reader;
+/// use tsc::parser::Parser;
+///
+/// let form = "x^{2} + 1";
+/// let (ast, root) = Parser::new(form.to_string(), false)?.build_expr()?;
+/// let prog = Program::compile(&ast, root, form);
+/// let conf = config_reader()?.clone();
+/// let x = prog.slot("x").unwrap();
+/// let mut slots = vec![0.0; prog.slots().len()];
+/// for i in 0..10 {
+///     slots[x] = i as f64;
+///     assert_eq!(prog.eval_f64(&slots, &conf)?, (i * i + 1) as f64);
+/// }
+/// # Ok::<(), tsc::error::MyError>(())
+/// ```
+pub struct Program {
+    code: Vec<Instr>,
+    consts: Vec<BigDecimal>,
+    consts_f64: Vec<f64>,
+    slots: Vec<String>,
+    slot_spans: Vec<Span>, // 変数が最初に現れる位置
+    nodes: Vec<Node>,      // 演算のnode. エラー個所の表示に使う
+    stack_size: usize,
+    form: String,
+}
+
+impl Program {
+    /// ASTのrootから下を命令列に変換する
+    ///
+    /// * `form` - エラー個所の表示に使う数式
+    pub fn compile(ast: &Ast, root: NodeId, form: &str) -> Program {
+        let mut prog = Program {
+            code: Vec::new(),
+            consts: Vec::new(),
+            consts_f64: Vec::new(),
+            slots: Vec::new(),
+            slot_spans: Vec::new(),
+            nodes: Vec::new(),
+            stack_size: 0,
+            form: form.to_string(),
+        };
+        prog.emit(ast, root, 0);
+        prog
+    }
+
+    /// idのnodeの命令を追加する
+    ///
+    /// * `height` - このnodeを評価する前のスタックの高さ
+    fn emit(&mut self, ast: &Ast, id: NodeId, height: usize) {
+        self.stack_size = self.stack_size.max(height + 1);
+        let node = &ast[id];
+        match &node.kind {
+            NodeKind::Num(n) => {
+                self.consts.push(n.clone());
+                self.consts_f64.push(n.to_f64().unwrap_or(f64::NAN));
+                self.code.push(Instr::Const(self.consts.len() - 1));
+            }
+            NodeKind::Var(v) => {
+                let slot = match self.slot(v) {
+                    Some(slot) => slot,
+                    None => {
+                        self.slots.push(v.clone());
+                        self.slot_spans.push(node.span);
+                        self.slots.len() - 1
+                    }
+                };
+                self.code.push(Instr::Load(slot));
+            }
+            NodeKind::Unary(op, arg) => {
+                self.emit(ast, *arg, height);
+                self.nodes.push(node.clone());
+                self.code.push(Instr::Unary(*op, self.nodes.len() - 1));
+            }
+            NodeKind::Binary(op, lhs, rhs) => {
+                self.emit(ast, *lhs, height);
+                self.emit(ast, *rhs, height + 1);
+                self.nodes.push(node.clone());
+                self.code.push(Instr::Binary(*op, self.nodes.len() - 1));
+            }
+            NodeKind::Call(func, args) => {
+                for (i, arg) in ast.args(*args).iter().enumerate() {
+                    self.emit(ast, *arg, height + i);
+                }
+                self.nodes.push(node.clone());
+                self.code
+                    .push(Instr::Call(*func, args.len(), self.nodes.len() - 1));
+            }
+        }
+    }
+
+    /// 変数名をslotの番号順に返す
+    pub fn slots(&self) -> &[String] {
+        &self.slots
+    }
+
+    /// 変数nameのslotの番号を返す
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.slots.iter().position(|s| s == name)
+    }
+
+    /// varsと定数から各slotの値を集める
+    pub fn bind(&self, vars: &HashMap<String, Value>) -> Result<Vec<BigDecimal>, MyError> {
+        let consts = match CONSTS.read() {
+            Ok(consts) => consts,
+            Err(e) => return Err(MyError::ConstsReadErr(e.to_string())),
+        };
+        self.slots
+            .iter()
+            .zip(&self.slot_spans)
+            .map(|(name, span)| {
+                let loc = || format_err_span(&self.form, *span);
+                match vars.get(name).or_else(|| consts.get(name)) {
+                    Some(Value::Num(n)) => Ok(n.clone()),
+                    Some(Value::List(_)) => Err(MyError::ListAsNumErr(name.clone(), loc())),
+                    None => Err(MyError::UDvariableErr(name.clone(), loc())),
+                }
+            })
+            .collect()
+    }
+
+    /// bindのf64版
+    pub fn bind_f64(&self, vars: &HashMap<String, Value>) -> Result<Vec<f64>, MyError> {
+        self.bind(vars)?
+            .into_iter()
+            .map(|v| match v.to_f64() {
+                Some(f) => Ok(f),
+                None => Err(MyError::ConvertErr("f64".to_string(), v)),
+            })
+            .collect()
+    }
+
+    fn check_slots(&self, len: usize) -> Result<(), MyError> {
+        if len == self.slots.len() {
+            Ok(())
+        } else {
+            Err(MyError::InvalidInput(format!(
+                "expected {} variable values but {len}",
+                self.slots.len()
+            )))
+        }
+    }
+
+    /// slotsの値を変数の値として評価する
+    /// 結果はcalcと同じになる
+    ///
+    /// * `slots` - slotの番号順に並べた変数の値
+    /// * `conf` - 評価に使う設定
+    pub fn eval(&self, slots: &[BigDecimal], conf: &Config) -> Result<BigDecimal, MyError> {
+        self.check_slots(slots.len())?;
+        let mut stack: Vec<BigDecimal> = Vec::with_capacity(self.stack_size);
+        for instr in &self.code {
+            let val = match *instr {
+                Instr::Const(i) => self.consts[i].clone(),
+                Instr::Load(i) => slots[i].clone(),
+                Instr::Unary(op, n) => {
+                    let arg = pop(&mut stack)?;
+                    calc_unary(op, &self.nodes[n], arg, conf, &self.form)?
+                }
+                Instr::Binary(op, n) => {
+                    let rop = pop(&mut stack)?;
+                    let lop = pop(&mut stack)?;
+                    calc_binary(op, &self.nodes[n], lop, rop, &self.form)?
+                }
+                Instr::Call(func, argc, n) => {
+                    let args = stack.split_off(stack.len() - argc);
+                    call_func(func, &self.nodes[n], args, &self.form)?
+                }
+            };
+            stack.push(val);
+        }
+        pop(&mut stack)
+    }
+
+    /// f64で評価する
+    /// evalより速いが, 精度はf64に落ちる
+    ///
+    /// * `slots` - slotの番号順に並べた変数の値
+    /// * `conf` - 評価に使う設定
+    pub fn eval_f64(&self, slots: &[f64], conf: &Config) -> Result<f64, MyError> {
+        self.check_slots(slots.len())?;
+        // BigDecimalからの変換は遅いので, \logがあるときだけ行う
+        let mut log_base = None;
+        let is_degree = conf.trig_func_arg == TrigFuncArg::Degree;
+        let mut stack: Vec<f64> = Vec::with_capacity(self.stack_size);
+        for instr in &self.code {
+            let val = match *instr {
+                Instr::Const(i) => self.consts_f64[i],
+                Instr::Load(i) => slots[i],
+                Instr::Unary(op, n) => {
+                    let arg = pop(&mut stack)?;
+                    let node = &self.nodes[n];
+                    if op == UnaryOp::Log && log_base.is_none() {
+                        log_base = Some(conf.log_base.to_f64().unwrap_or(f64::NAN));
+                    }
+                    let log_base = log_base.unwrap_or(f64::NAN);
+                    domain::check_unary_f64(op, node, arg, log_base, &self.form)?;
+                    self.unary_f64(op, node, arg, log_base, is_degree)?
+                }
+                Instr::Binary(op, n) => {
+                    let rop = pop(&mut stack)?;
+                    let lop = pop(&mut stack)?;
+                    domain::check_binary_f64(op, &self.nodes[n], lop, rop, &self.form)?;
+                    match op {
+                        BinaryOp::Add => lop + rop,
+                        BinaryOp::Sub => lop - rop,
+                        BinaryOp::Mul => lop * rop,
+                        BinaryOp::Div => lop / rop,
+                        BinaryOp::Pow => lop.powf(rop),
+                    }
+                }
+                Instr::Call(func, argc, n) => {
+                    // 登録された関数はBigDecimalで評価する
+                    let mut args = Vec::with_capacity(argc);
+                    for arg in stack.drain(stack.len() - argc..) {
+                        args.push(
+                            BigDecimal::from_f64(arg)
+                                .ok_or(MyError::CalcErr(format!("{arg} is not a number")))?,
+                        );
+                    }
+                    let res = call_func(func, &self.nodes[n], args, &self.form)?;
+                    res.to_f64()
+                        .ok_or(MyError::ConvertErr("f64".to_string(), res))?
+                }
+            };
+            stack.push(val);
+        }
+        pop(&mut stack)
+    }
+
+    fn unary_f64(
+        &self,
+        op: UnaryOp,
+        node: &Node,
+        x: f64,
+        log_base: f64,
+        is_degree: bool,
+    ) -> Result<f64, MyError> {
+        let rad = if is_degree { x.to_radians() } else { x };
+        let from_rad = |r: f64| if is_degree { r.to_degrees() } else { r };
+        let reciprocal = |denom: f64| {
+            if denom == 0.0 {
+                Err(domain::domain_err(node, &self.form, x.to_string()))
+            } else {
+                Ok(1.0 / denom)
+            }
+        };
+        Ok(match op {
+            UnaryOp::Sin => rad.sin(),
+            UnaryOp::Cos => rad.cos(),
+            UnaryOp::Tan => rad.tan(),
+            UnaryOp::Csc => reciprocal(rad.sin())?,
+            UnaryOp::Sec => reciprocal(rad.cos())?,
+            UnaryOp::Cot => reciprocal(rad.tan())?,
+            UnaryOp::AcSin => from_rad(x.asin()),
+            UnaryOp::AcCos => from_rad(x.acos()),
+            UnaryOp::AcTan => from_rad(x.atan()),
+            UnaryOp::Sqrt => x.sqrt(),
+            UnaryOp::Log => x.ln() / log_base.ln(),
+            UnaryOp::Ln => x.ln(),
+            UnaryOp::Abs => x.abs(),
+            UnaryOp::Exp => x.exp(),
+            UnaryOp::Neg => -x,
+        })
+    }
+}
+
+fn pop<T>(stack: &mut Vec<T>) -> Result<T, MyError> {
+    // compileが作った命令列ではスタックは空にならない
+    stack.pop().ok_or(MyError::BrokenAstErr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn compile(form: &str) -> (Ast, NodeId, Program) {
+        let (ast, root) = Parser::new(form.to_string(), false)
+            .unwrap()
+            .build_expr()
+            .unwrap();
+        let prog = Program::compile(&ast, root, form);
+        (ast, root, prog)
+    }
+
+    #[test]
+    fn test_eval() {
+        let conf = Config::default();
+        // calcはvarsに定数が入っていることを前提にする
+        let mut vars = CONSTS.read().unwrap().clone();
+        vars.insert("x".to_string(), BigDecimal::from(3).into());
+        vars.insert(
+            "y".to_string(),
+            "0.25".parse::<BigDecimal>().unwrap().into(),
+        );
+        let forms = [
+            "1 + 2 * 3",
+            "2x^{2} - \\frac{x}{y} + 1",
+            "-\\abs(-x)^{\\frac{1}{4/2}} + \\sqrt{16}",
+            "\\sin \\frac{\\pi}{2} x y + \\cos y",
+            "\\log 100 + \\ln e^{2} - \\exp(y)",
+            "\\arcsin y + \\arccos y - \\arctan x",
+            "\\csc x + \\sec x + \\cot x",
+            "x y x y (x + y) / (x - y)",
+        ];
+        for form in forms {
+            let (ast, root, prog) = compile(form);
+            let expected = crate::calc(&ast, root, &vars, form).unwrap();
+            let slots = prog.bind(&vars).unwrap();
+            assert_eq!(prog.eval(&slots, &conf).unwrap(), expected, "{form}");
+            let slots = prog.bind_f64(&vars).unwrap();
+            let res = prog.eval_f64(&slots, &conf).unwrap();
+            let expected = expected.to_f64().unwrap();
+            assert!(
+                (res - expected).abs() <= 1e-9 * expected.abs().max(1.0),
+                "{form}: {res} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_err() {
+        let conf = Config::default();
+        let (_, _, prog) = compile("\\frac{1}{x} + \\sqrt{y}");
+        assert_eq!(prog.slots(), ["x", "y"]);
+        assert!(matches!(
+            prog.bind(&HashMap::new()),
+            Err(MyError::UDvariableErr(v, _)) if v == "x"
+        ));
+        let zero = [BigDecimal::from(0), BigDecimal::from(1)];
+        assert!(matches!(
+            prog.eval(&zero, &conf),
+            Err(MyError::DomainErr(_, _, _))
+        ));
+        assert!(matches!(
+            prog.eval_f64(&[1.0, -1.0], &conf),
+            Err(MyError::DomainErr(_, _, _))
+        ));
+        assert!(prog.eval_f64(&[1.0], &conf).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::*;
+use crate::registry::{register_functions, FuncDef};
 use bigdecimal::{BigDecimal, FromPrimitive};
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -13,6 +14,9 @@ pub enum AstFormat {
     Tree,
     Sexpr,
     Both,
+    Tex,
+    Dot,
+    Json,
     None,
 }
 
@@ -20,13 +24,79 @@ impl fmt::Display for AstFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AstFormat::Tree => write!(f, "Tree"),
-            AstFormat::Sexpr => write!(f, "Tree"),
+            AstFormat::Sexpr => write!(f, "Sexpr"),
             AstFormat::Both => write!(f, "Both"),
+            AstFormat::Tex => write!(f, "Tex"),
+            AstFormat::Dot => write!(f, "Dot"),
+            AstFormat::Json => write!(f, "Json"),
             AstFormat::None => write!(f, "None"),
         }
     }
 }
 
+impl std::str::FromStr for AstFormat {
+    type Err = MyError;
+
+    /// :astform, --emit-astの引数を読む
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tree" => Ok(AstFormat::Tree),
+            "sexpr" => Ok(AstFormat::Sexpr),
+            "both" => Ok(AstFormat::Both),
+            "tex" => Ok(AstFormat::Tex),
+            "dot" => Ok(AstFormat::Dot),
+            "json" => Ok(AstFormat::Json),
+            "none" => Ok(AstFormat::None),
+            _ => Err(MyError::UnexpectedInput(
+                "tree|sexpr|both|tex|dot|json|none".to_string(),
+                s.to_string(),
+            )),
+        }
+    }
+}
+
+/// 数式の入力形式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Syntax {
+    #[default]
+    Tex,
+    Sexpr,
+    Plain,
+    Mathml,
+    Asciimath,
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Syntax::Tex => write!(f, "tex"),
+            Syntax::Sexpr => write!(f, "sexpr"),
+            Syntax::Plain => write!(f, "plain"),
+            Syntax::Mathml => write!(f, "mathml"),
+            Syntax::Asciimath => write!(f, "asciimath"),
+        }
+    }
+}
+
+impl std::str::FromStr for Syntax {
+    type Err = MyError;
+
+    /// :syntax, --syntax, --toの引数を読む
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tex" => Ok(Syntax::Tex),
+            "sexpr" => Ok(Syntax::Sexpr),
+            "plain" => Ok(Syntax::Plain),
+            "mathml" => Ok(Syntax::Mathml),
+            "asciimath" => Ok(Syntax::Asciimath),
+            _ => Err(MyError::UnexpectedInput(
+                "tex|sexpr|plain|mathml|asciimath".to_string(),
+                s.to_string(),
+            )),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum TrigFuncArg {
     Radian,
@@ -42,13 +112,19 @@ impl fmt::Display for TrigFuncArg {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Config {
     pub debug: bool,                // デバッグ出力の有無
     pub ast_format: AstFormat,      // ASTのフォーマット
     pub trig_func_arg: TrigFuncArg, // 三角関数の引数, 逆三角関数の結果
     pub log_base: BigDecimal,       // logの底
     pub num_of_digit: u32,          // 結果の小数点以下の桁数
+    #[serde(default)]
+    pub syntax: Syntax, // 数式の入力形式
+    #[serde(default)]
+    pub fast_eval: bool, // :tableをf64で評価するか. 速いが精度はf64に落ちる
+    #[serde(default)]
+    pub functions: Vec<FuncDef>, // ユーザー定義の関数
 }
 
 impl fmt::Display for Config {
@@ -60,6 +136,8 @@ impl fmt::Display for Config {
     {: <14}: {}
     {: <14}: {}
     {: <14}: {}
+    {: <14}: {}
+    {: <14}: {}
     {: <14}: {}",
             "Config".green(),
             "debug".cyan(),
@@ -71,7 +149,11 @@ impl fmt::Display for Config {
             "log_base".cyan(),
             self.log_base,
             "num_of_digit".cyan(),
-            self.num_of_digit
+            self.num_of_digit,
+            "syntax".cyan(),
+            self.syntax,
+            "fast_eval".cyan(),
+            self.fast_eval
         )
     }
 }
@@ -84,6 +166,9 @@ impl Default for Config {
             trig_func_arg: TrigFuncArg::Radian,
             log_base: BigDecimal::from_f64(std::f64::consts::E).unwrap(),
             num_of_digit: 12,
+            syntax: Syntax::Tex,
+            fast_eval: false,
+            functions: Vec::new(),
         }
     }
 }
@@ -148,6 +233,18 @@ impl Config {
     }
 }
 
+/// 設定ファイルを読み込み, 定義された関数を登録する
+/// 関数の本体の構文解析はconfigを読むので, configのlockを外してから登録する
+pub fn load_config() -> Result<PathBuf, MyError> {
+    let (conf_file, functions) = {
+        let mut conf = config_writer()?;
+        let conf_file = conf.load_from_file()?;
+        (conf_file, conf.functions.clone())
+    };
+    register_functions(&functions)?;
+    Ok(conf_file)
+}
+
 pub fn config_reader() -> Result<std::sync::RwLockReadGuard<'static, Config>, MyError> {
     match crate::CONFIG.try_read() {
         Ok(c) => Ok(c),
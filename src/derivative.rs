@@ -0,0 +1,367 @@
+use bigdecimal::{BigDecimal, FromPrimitive};
+use std::collections::HashMap;
+
+use crate::ast::{Ast, BinaryOp, Node, NodeId, NodeKind, UnaryOp};
+use crate::config::*;
+use crate::error::*;
+use crate::optimizer;
+use crate::parser::Span;
+
+/// rootの式をvarで微分した新しいAstとその根を返す
+/// varを含む引数で登録された関数を呼ぶ式は微分できないのでNoneを返す
+/// 三角関数と逆三角関数はconfのtrig_func_argの単位で微分する
+///
+/// ex)
+/// ```text
+/// x^{2} + \sin x  ->  2x + \cos x
+/// ```
+pub fn derivative(
+    ast: &Ast,
+    root: NodeId,
+    var: &str,
+    conf: &Config,
+) -> Result<Option<(Ast, NodeId)>, MyError> {
+    let mut d = Deriv {
+        src: ast,
+        out: Ast::new(),
+        var,
+        degree: conf.trig_func_arg == TrigFuncArg::Degree,
+        log_base: conf.log_base.clone(),
+        copied: HashMap::new(),
+    };
+    match d.d(root) {
+        Some(droot) => optimizer::simplify(&d.out, droot, None).map(Some),
+        None => Ok(None),
+    }
+}
+
+struct Deriv<'a> {
+    src: &'a Ast,
+    out: Ast,
+    var: &'a str,
+    degree: bool,
+    log_base: BigDecimal,
+    // srcのnodeをoutに写したもの
+    copied: HashMap<NodeId, NodeId>,
+}
+
+impl Deriv<'_> {
+    fn push(&mut self, kind: NodeKind, span: Span) -> NodeId {
+        self.out.push(Node {
+            kind,
+            span,
+            op_span: None,
+        })
+    }
+
+    fn num(&mut self, n: BigDecimal, span: Span) -> NodeId {
+        self.push(NodeKind::Num(n), span)
+    }
+
+    fn unary(&mut self, op: UnaryOp, arg: NodeId) -> NodeId {
+        let span = self.out[arg].span;
+        self.push(NodeKind::Unary(op, arg), span)
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: NodeId, rhs: NodeId) -> NodeId {
+        let span = self.out[lhs].span.merge(self.out[rhs].span);
+        self.push(NodeKind::Binary(op, lhs, rhs), span)
+    }
+
+    /// 度数法なら, 弧度法での微分に掛ける π/180 を返す
+    fn deg_factor(&mut self, span: Span, inverse: bool) -> Option<NodeId> {
+        if !self.degree {
+            return None;
+        }
+        let pi = self.num(BigDecimal::from_f64(std::f64::consts::PI)?, span);
+        let deg = self.num(BigDecimal::from(180), span);
+        Some(if inverse {
+            self.binary(BinaryOp::Div, deg, pi)
+        } else {
+            self.binary(BinaryOp::Div, pi, deg)
+        })
+    }
+
+    /// srcのidから下をoutに写す
+    fn copy(&mut self, id: NodeId) -> NodeId {
+        if let Some(copied) = self.copied.get(&id) {
+            return *copied;
+        }
+        let node = &self.src[id];
+        let kind = match &node.kind {
+            NodeKind::Num(_) | NodeKind::Var(_) => node.kind.clone(),
+            NodeKind::Unary(op, arg) => NodeKind::Unary(*op, self.copy(*arg)),
+            NodeKind::Binary(op, lhs, rhs) => {
+                NodeKind::Binary(*op, self.copy(*lhs), self.copy(*rhs))
+            }
+            NodeKind::Call(func, args) => {
+                let args: Vec<NodeId> = self.src.args(*args).to_vec();
+                let args: Vec<NodeId> = args.into_iter().map(|a| self.copy(a)).collect();
+                NodeKind::Call(*func, self.out.push_args(&args))
+            }
+        };
+        let new = self.out.push(Node {
+            kind,
+            span: node.span,
+            op_span: node.op_span,
+        });
+        self.copied.insert(id, new);
+        new
+    }
+
+    /// idの式がvarを含むならtrue
+    fn depends(&self, id: NodeId) -> bool {
+        match &self.src[id].kind {
+            NodeKind::Var(v) => v == self.var,
+            _ => self.src.children(id).any(|c| self.depends(c)),
+        }
+    }
+
+    /// srcのidの式の微分をoutに追加する
+    fn d(&mut self, id: NodeId) -> Option<NodeId> {
+        let span = self.src[id].span;
+        if !self.depends(id) {
+            return Some(self.num(BigDecimal::from(0), span));
+        }
+        let kind = self.src[id].kind.clone();
+        Some(match kind {
+            NodeKind::Num(_) | NodeKind::Var(_) => self.num(BigDecimal::from(1), span),
+            NodeKind::Call(..) => return None,
+            NodeKind::Unary(UnaryOp::Neg, arg) => {
+                let da = self.d(arg)?;
+                self.unary(UnaryOp::Neg, da)
+            }
+            NodeKind::Unary(op, arg) => {
+                let da = self.d(arg)?;
+                let u = self.copy(arg);
+                // f'(u)
+                let df = self.unary_derivative(op, u, span)?;
+                self.binary(BinaryOp::Mul, df, da)
+            }
+            NodeKind::Binary(op @ (BinaryOp::Add | BinaryOp::Sub), lhs, rhs) => {
+                let dl = self.d(lhs)?;
+                let dr = self.d(rhs)?;
+                self.binary(op, dl, dr)
+            }
+            NodeKind::Binary(BinaryOp::Mul, lhs, rhs) => {
+                // (uv)' = u'v + uv'
+                let (dl, dr) = (self.d(lhs)?, self.d(rhs)?);
+                let (u, v) = (self.copy(lhs), self.copy(rhs));
+                let l = self.binary(BinaryOp::Mul, dl, v);
+                let r = self.binary(BinaryOp::Mul, u, dr);
+                self.binary(BinaryOp::Add, l, r)
+            }
+            NodeKind::Binary(BinaryOp::Div, lhs, rhs) => {
+                // (u/v)' = (u'v - uv') / v^2
+                let (dl, dr) = (self.d(lhs)?, self.d(rhs)?);
+                let (u, v) = (self.copy(lhs), self.copy(rhs));
+                let l = self.binary(BinaryOp::Mul, dl, v);
+                let r = self.binary(BinaryOp::Mul, u, dr);
+                let num = self.binary(BinaryOp::Sub, l, r);
+                let two = self.num(BigDecimal::from(2), span);
+                let den = self.binary(BinaryOp::Pow, v, two);
+                self.binary(BinaryOp::Div, num, den)
+            }
+            NodeKind::Binary(BinaryOp::Pow, lhs, rhs) if !self.depends(rhs) => {
+                // (u^n)' = n u^{n-1} u'
+                let dl = self.d(lhs)?;
+                let (u, n) = (self.copy(lhs), self.copy(rhs));
+                let one = self.num(BigDecimal::from(1), span);
+                let n1 = self.binary(BinaryOp::Sub, n, one);
+                let pow = self.binary(BinaryOp::Pow, u, n1);
+                let coef = self.binary(BinaryOp::Mul, n, pow);
+                self.binary(BinaryOp::Mul, coef, dl)
+            }
+            NodeKind::Binary(BinaryOp::Pow, lhs, rhs) => {
+                // (u^v)' = u^v (v' \ln u + v u' / u)
+                let (dl, dr) = (self.d(lhs)?, self.d(rhs)?);
+                let (u, v) = (self.copy(lhs), self.copy(rhs));
+                let pow = self.copy(id);
+                let ln = self.unary(UnaryOp::Ln, u);
+                let l = self.binary(BinaryOp::Mul, dr, ln);
+                let vdu = self.binary(BinaryOp::Mul, v, dl);
+                let r = self.binary(BinaryOp::Div, vdu, u);
+                let sum = self.binary(BinaryOp::Add, l, r);
+                self.binary(BinaryOp::Mul, pow, sum)
+            }
+        })
+    }
+
+    /// 1引数の演算opのuでの微分係数の式を返す
+    fn unary_derivative(&mut self, op: UnaryOp, u: NodeId, span: Span) -> Option<NodeId> {
+        let one = self.num(BigDecimal::from(1), span);
+        let two = self.num(BigDecimal::from(2), span);
+        let df = match op {
+            UnaryOp::Neg => self.unary(UnaryOp::Neg, one),
+            UnaryOp::Sqrt => {
+                // 1 / (2\sqrt{u})
+                let sqrt = self.unary(UnaryOp::Sqrt, u);
+                let den = self.binary(BinaryOp::Mul, two, sqrt);
+                self.binary(BinaryOp::Div, one, den)
+            }
+            UnaryOp::Ln => self.binary(BinaryOp::Div, one, u),
+            UnaryOp::Log => {
+                // 1 / (u \ln b)
+                let base = self.num(self.log_base.clone(), span);
+                let ln = self.unary(UnaryOp::Ln, base);
+                let den = self.binary(BinaryOp::Mul, u, ln);
+                self.binary(BinaryOp::Div, one, den)
+            }
+            UnaryOp::Exp => self.unary(UnaryOp::Exp, u),
+            UnaryOp::Abs => {
+                let abs = self.unary(UnaryOp::Abs, u);
+                self.binary(BinaryOp::Div, u, abs)
+            }
+            UnaryOp::Sin => self.unary(UnaryOp::Cos, u),
+            UnaryOp::Cos => {
+                let sin = self.unary(UnaryOp::Sin, u);
+                self.unary(UnaryOp::Neg, sin)
+            }
+            UnaryOp::Tan => {
+                // 1 / \cos^2 u
+                let cos = self.unary(UnaryOp::Cos, u);
+                let den = self.binary(BinaryOp::Pow, cos, two);
+                self.binary(BinaryOp::Div, one, den)
+            }
+            UnaryOp::Csc => {
+                // -\csc u \cot u
+                let csc = self.unary(UnaryOp::Csc, u);
+                let cot = self.unary(UnaryOp::Cot, u);
+                let prod = self.binary(BinaryOp::Mul, csc, cot);
+                self.unary(UnaryOp::Neg, prod)
+            }
+            UnaryOp::Sec => {
+                let sec = self.unary(UnaryOp::Sec, u);
+                let tan = self.unary(UnaryOp::Tan, u);
+                self.binary(BinaryOp::Mul, sec, tan)
+            }
+            UnaryOp::Cot => {
+                // -1 / \sin^2 u
+                let sin = self.unary(UnaryOp::Sin, u);
+                let den = self.binary(BinaryOp::Pow, sin, two);
+                let div = self.binary(BinaryOp::Div, one, den);
+                self.unary(UnaryOp::Neg, div)
+            }
+            UnaryOp::AcSin | UnaryOp::AcCos => {
+                // ±1 / \sqrt{1 - u^2}
+                let sq = self.binary(BinaryOp::Pow, u, two);
+                let one_ = self.num(BigDecimal::from(1), span);
+                let diff = self.binary(BinaryOp::Sub, one_, sq);
+                let sqrt = self.unary(UnaryOp::Sqrt, diff);
+                let div = self.binary(BinaryOp::Div, one, sqrt);
+                if op == UnaryOp::AcCos {
+                    self.unary(UnaryOp::Neg, div)
+                } else {
+                    div
+                }
+            }
+            UnaryOp::AcTan => {
+                // 1 / (1 + u^2)
+                let sq = self.binary(BinaryOp::Pow, u, two);
+                let one_ = self.num(BigDecimal::from(1), span);
+                let den = self.binary(BinaryOp::Add, one_, sq);
+                self.binary(BinaryOp::Div, one, den)
+            }
+        };
+        // 度数法では, 三角関数の微分に π/180, 逆三角関数の微分に 180/π が掛かる
+        let factor = match op {
+            UnaryOp::Sin
+            | UnaryOp::Cos
+            | UnaryOp::Tan
+            | UnaryOp::Csc
+            | UnaryOp::Sec
+            | UnaryOp::Cot => self.deg_factor(span, false),
+            UnaryOp::AcSin | UnaryOp::AcCos | UnaryOp::AcTan => self.deg_factor(span, true),
+            _ => None,
+        };
+        Some(match factor {
+            Some(k) => self.binary(BinaryOp::Mul, k, df),
+            None => df,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compile::Program;
+    use crate::parser::Parser;
+    use crate::tex_printer;
+
+    fn parse(form: &str) -> (Ast, NodeId) {
+        Parser::new(form.to_string(), false)
+            .unwrap()
+            .build_expr()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_derivative_tex() {
+        let conf = Config::default();
+        let cases = [
+            ("x^{2}", "2x"),
+            ("3x + 2", "3"),
+            ("y x", "y"),
+            ("\\sin x", "\\cos x"),
+            ("\\exp(2x)", "2\\exp(2x)"),
+        ];
+        for (form, expected) in cases {
+            let (ast, root) = parse(form);
+            let (d, droot) = derivative(&ast, root, "x", &conf).unwrap().unwrap();
+            assert_eq!(tex_printer::to_tex(&d, droot).unwrap(), expected, "{form}");
+        }
+    }
+
+    #[test]
+    fn test_derivative_value() {
+        let forms = [
+            "\\frac{x^{3} - 1}{x + 2}",
+            "\\sqrt{x^{2} + 1} \\ln x",
+            "x^{x} + 2^{x}",
+            "\\tan x - \\csc x + \\sec x - \\cot x",
+            "\\arcsin \\frac{x}{2} + \\arccos \\frac{x}{3} + \\arctan x",
+            "\\log x - \\abs(-x) + \\exp(\\cos x)",
+        ];
+        let mut conf = Config::default();
+        for degree in [false, true] {
+            if degree {
+                conf.trig_func_arg = TrigFuncArg::Degree;
+            }
+            for form in forms {
+                let (ast, root) = parse(form);
+                let (d, droot) = derivative(&ast, root, "x", &conf).unwrap().unwrap();
+                let f = Program::compile(&ast, root, form);
+                let df = Program::compile(&d, droot, form);
+                let eval = |prog: &Program, x: f64| {
+                    let mut slots = vec![0.0; prog.slots().len()];
+                    if let Some(i) = prog.slot("x") {
+                        slots[i] = x;
+                    }
+                    prog.eval_f64(&slots, &conf).unwrap()
+                };
+                let (x, h) = (0.7, 1e-6);
+                let numeric = (eval(&f, x + h) - eval(&f, x - h)) / (2.0 * h);
+                let exact = eval(&df, x);
+                assert!(
+                    (numeric - exact).abs() < 1e-5 * (1.0 + exact.abs()),
+                    "{form}: {numeric} {exact}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_derivative_call() {
+        use crate::registry::{register, ArgStyle, Command};
+        let conf = Config::default();
+        register(Command::native("derivid", 1, ArgStyle::Paren, |args| {
+            Ok(args[0].clone())
+        }))
+        .unwrap();
+        // xを含む引数で呼ぶ関数は微分できない
+        let (ast, root) = parse("\\derivid(x)");
+        assert!(derivative(&ast, root, "x", &conf).unwrap().is_none());
+        let (ast, root) = parse("x \\derivid(y)");
+        assert!(derivative(&ast, root, "x", &conf).unwrap().is_some());
+    }
+}
@@ -0,0 +1,720 @@
+//! 確率分布と乱数
+//! 確率密度関数, 累積分布関数, 分位関数と乱数を特殊関数と同じくf64で求める
+
+use std::f64::consts::{PI, SQRT_2};
+use std::ops::RangeInclusive;
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::special_functions::{erfc, ln_gamma, regularized_beta, regularized_gamma, SpecialErr};
+
+/// 確率分布
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dist {
+    /// 正規分布N(μ, σ). 母数を省くと標準正規分布
+    Normal,
+    /// 二項分布B(n, p)
+    Binomial,
+    /// ポアソン分布Po(λ)
+    Poisson,
+    /// 一様分布U(a, b)
+    Uniform,
+    /// 指数分布Exp(λ). λは率
+    Exponential,
+    /// 自由度νのt分布
+    StudentT,
+    /// 自由度kのカイ二乗分布
+    ChiSquared,
+}
+
+/// 分布についての関数の種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DistKind {
+    /// 確率密度関数. 離散分布では確率質量関数
+    Pdf,
+    /// 累積分布関数
+    Cdf,
+    /// 分位関数(累積分布関数の逆関数)
+    Inv,
+    /// 分布に従う乱数
+    Rnd,
+}
+
+/// 確率分布の関数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DistFn {
+    pub dist: Dist,
+    pub kind: DistKind,
+}
+
+const fn dist_fn(dist: Dist, kind: DistKind) -> DistFn {
+    DistFn { dist, kind }
+}
+
+impl DistFn {
+    /// (コマンド名, TeXでの書き方, 関数). \operatorname{rand}()は[0, 1)の一様乱数
+    pub const ALL: [(&'static str, &'static str, DistFn); 28] = {
+        use Dist::*;
+        use DistKind::*;
+        [
+            ("\\normpdf", "\\operatorname{normpdf}", dist_fn(Normal, Pdf)),
+            ("\\normcdf", "\\operatorname{normcdf}", dist_fn(Normal, Cdf)),
+            ("\\norminv", "\\operatorname{norminv}", dist_fn(Normal, Inv)),
+            ("\\normrnd", "\\operatorname{normrnd}", dist_fn(Normal, Rnd)),
+            (
+                "\\binopdf",
+                "\\operatorname{binopdf}",
+                dist_fn(Binomial, Pdf),
+            ),
+            (
+                "\\binocdf",
+                "\\operatorname{binocdf}",
+                dist_fn(Binomial, Cdf),
+            ),
+            (
+                "\\binoinv",
+                "\\operatorname{binoinv}",
+                dist_fn(Binomial, Inv),
+            ),
+            (
+                "\\binornd",
+                "\\operatorname{binornd}",
+                dist_fn(Binomial, Rnd),
+            ),
+            (
+                "\\poisspdf",
+                "\\operatorname{poisspdf}",
+                dist_fn(Poisson, Pdf),
+            ),
+            (
+                "\\poisscdf",
+                "\\operatorname{poisscdf}",
+                dist_fn(Poisson, Cdf),
+            ),
+            (
+                "\\poissinv",
+                "\\operatorname{poissinv}",
+                dist_fn(Poisson, Inv),
+            ),
+            (
+                "\\poissrnd",
+                "\\operatorname{poissrnd}",
+                dist_fn(Poisson, Rnd),
+            ),
+            (
+                "\\unifpdf",
+                "\\operatorname{unifpdf}",
+                dist_fn(Uniform, Pdf),
+            ),
+            (
+                "\\unifcdf",
+                "\\operatorname{unifcdf}",
+                dist_fn(Uniform, Cdf),
+            ),
+            (
+                "\\unifinv",
+                "\\operatorname{unifinv}",
+                dist_fn(Uniform, Inv),
+            ),
+            ("\\rand", "\\operatorname{rand}", dist_fn(Uniform, Rnd)),
+            (
+                "\\exppdf",
+                "\\operatorname{exppdf}",
+                dist_fn(Exponential, Pdf),
+            ),
+            (
+                "\\expcdf",
+                "\\operatorname{expcdf}",
+                dist_fn(Exponential, Cdf),
+            ),
+            (
+                "\\expinv",
+                "\\operatorname{expinv}",
+                dist_fn(Exponential, Inv),
+            ),
+            (
+                "\\exprnd",
+                "\\operatorname{exprnd}",
+                dist_fn(Exponential, Rnd),
+            ),
+            ("\\tpdf", "\\operatorname{tpdf}", dist_fn(StudentT, Pdf)),
+            ("\\tcdf", "\\operatorname{tcdf}", dist_fn(StudentT, Cdf)),
+            ("\\tinv", "\\operatorname{tinv}", dist_fn(StudentT, Inv)),
+            ("\\trnd", "\\operatorname{trnd}", dist_fn(StudentT, Rnd)),
+            (
+                "\\chi2pdf",
+                "\\operatorname{chi2pdf}",
+                dist_fn(ChiSquared, Pdf),
+            ),
+            (
+                "\\chi2cdf",
+                "\\operatorname{chi2cdf}",
+                dist_fn(ChiSquared, Cdf),
+            ),
+            (
+                "\\chi2inv",
+                "\\operatorname{chi2inv}",
+                dist_fn(ChiSquared, Inv),
+            ),
+            (
+                "\\chi2rnd",
+                "\\operatorname{chi2rnd}",
+                dist_fn(ChiSquared, Rnd),
+            ),
+        ]
+    };
+
+    pub fn tex_name(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(_, _, f)| *f == self)
+            .map_or("", |(_, tex, _)| tex)
+    }
+
+    /// 引数の数. Rnd以外は最初の引数が変数で, 残りが母数
+    pub fn arity(self) -> RangeInclusive<usize> {
+        let params = match self.dist {
+            Dist::Normal => 0..=2,
+            Dist::Uniform if self.kind == DistKind::Rnd => 0..=0,
+            Dist::Binomial | Dist::Uniform => 2..=2,
+            _ => 1..=1,
+        };
+        match self.kind {
+            DistKind::Rnd => params,
+            _ => params.start() + 1..=params.end() + 1,
+        }
+    }
+
+    /// 関数の値を求める. 引数の数はarityの範囲にあること
+    pub fn eval(self, args: &[f64]) -> Result<f64, SpecialErr> {
+        let (x, params) = match self.kind {
+            DistKind::Rnd => (None, args),
+            _ => (Some(args[0]), &args[1..]),
+        };
+        let dist = Params::new(self.dist, params)?;
+        let value = match (self.kind, x) {
+            (DistKind::Pdf, Some(x)) => dist.pdf(x),
+            (DistKind::Cdf, Some(x)) => dist.cdf(x).0,
+            (DistKind::Inv, Some(p)) => {
+                if !(0.0..=1.0).contains(&p) {
+                    return Err(SpecialErr::Domain(format!("p = {p}")));
+                }
+                dist.inv(p)?
+            }
+            (DistKind::Rnd, None) if self.dist == Dist::Uniform => rand(),
+            (DistKind::Rnd, None) => dist.inv(with_rng(Rng::uniform_open))?,
+            _ => unreachable!("argument count is checked by parser"),
+        };
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err(SpecialErr::OutOfRange)
+        }
+    }
+}
+
+/// 母数を確かめた分布
+enum Params {
+    Normal { mu: f64, sigma: f64 },
+    Binomial { n: f64, p: f64 },
+    Poisson { lambda: f64 },
+    Uniform { a: f64, b: f64 },
+    Exponential { lambda: f64 },
+    StudentT { nu: f64 },
+    ChiSquared { k: f64 },
+}
+
+impl Params {
+    fn new(dist: Dist, params: &[f64]) -> Result<Params, SpecialErr> {
+        let domain = |desc: String| SpecialErr::Domain(desc);
+        let positive = |name: &str, v: f64| {
+            if v > 0.0 {
+                Ok(v)
+            } else {
+                Err(domain(format!("{name} = {v}")))
+            }
+        };
+        Ok(match (dist, params) {
+            (Dist::Normal, _) => Params::Normal {
+                mu: params.first().copied().unwrap_or(0.0),
+                sigma: positive("σ", params.get(1).copied().unwrap_or(1.0))?,
+            },
+            (Dist::Binomial, &[n, p]) => {
+                if n < 0.0 || n.fract() != 0.0 || !(0.0..=1.0).contains(&p) {
+                    return Err(domain(format!("n = {n}, p = {p}")));
+                }
+                Params::Binomial { n, p }
+            }
+            (Dist::Poisson, &[lambda]) => Params::Poisson {
+                lambda: positive("λ", lambda)?,
+            },
+            (Dist::Uniform, &[a, b]) => {
+                if a >= b {
+                    return Err(domain(format!("a = {a}, b = {b}")));
+                }
+                Params::Uniform { a, b }
+            }
+            // rand()は母数を持たない
+            (Dist::Uniform, _) => Params::Uniform { a: 0.0, b: 1.0 },
+            (Dist::Exponential, &[lambda]) => Params::Exponential {
+                lambda: positive("λ", lambda)?,
+            },
+            (Dist::StudentT, &[nu]) => Params::StudentT {
+                nu: positive("ν", nu)?,
+            },
+            (Dist::ChiSquared, &[k]) => Params::ChiSquared {
+                k: positive("k", k)?,
+            },
+            _ => unreachable!("argument count is checked by parser"),
+        })
+    }
+
+    fn pdf(&self, x: f64) -> f64 {
+        match *self {
+            Params::Normal { mu, sigma } => {
+                let z = (x - mu) / sigma;
+                (-0.5 * z * z).exp() / (sigma * (2.0 * PI).sqrt())
+            }
+            Params::Binomial { n, p } => {
+                if x < 0.0 || x > n || x.fract() != 0.0 {
+                    return 0.0;
+                }
+                if p == 0.0 || p == 1.0 {
+                    return if (p == 0.0 && x == 0.0) || (p == 1.0 && x == n) {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                }
+                (ln_choose(n, x) + x * p.ln() + (n - x) * (-p).ln_1p()).exp()
+            }
+            Params::Poisson { lambda } => {
+                if x < 0.0 || x.fract() != 0.0 {
+                    return 0.0;
+                }
+                (x * lambda.ln() - lambda - ln_gamma(x + 1.0)).exp()
+            }
+            Params::Uniform { a, b } => {
+                if (a..=b).contains(&x) {
+                    1.0 / (b - a)
+                } else {
+                    0.0
+                }
+            }
+            Params::Exponential { lambda } => {
+                if x < 0.0 {
+                    0.0
+                } else {
+                    lambda * (-lambda * x).exp()
+                }
+            }
+            Params::StudentT { nu } => (ln_gamma((nu + 1.0) / 2.0)
+                - ln_gamma(nu / 2.0)
+                - 0.5 * (nu * PI).ln()
+                - (nu + 1.0) / 2.0 * (x * x / nu).ln_1p())
+            .exp(),
+            Params::ChiSquared { k } => {
+                if x < 0.0 {
+                    return 0.0;
+                }
+                let h = k / 2.0;
+                if x == 0.0 {
+                    // k < 2では発散する
+                    return if h < 1.0 {
+                        f64::INFINITY
+                    } else if h == 1.0 {
+                        0.5
+                    } else {
+                        0.0
+                    };
+                }
+                ((h - 1.0) * x.ln() - x / 2.0 - h * 2f64.ln() - ln_gamma(h)).exp()
+            }
+        }
+    }
+
+    /// (P(X <= x), P(X > x)). 裾で桁落ちしないよう両方を求める
+    fn cdf(&self, x: f64) -> (f64, f64) {
+        let lower = |p: f64| (p, 1.0 - p);
+        match *self {
+            Params::Normal { mu, sigma } => {
+                let z = (x - mu) / (sigma * SQRT_2);
+                (0.5 * erfc(-z), 0.5 * erfc(z))
+            }
+            Params::Binomial { n, p } => {
+                let k = x.floor();
+                if k < 0.0 {
+                    (0.0, 1.0)
+                } else if k >= n {
+                    (1.0, 0.0)
+                } else {
+                    regularized_beta(1.0 - p, n - k, k + 1.0)
+                }
+            }
+            Params::Poisson { lambda } => {
+                let k = x.floor();
+                if k < 0.0 {
+                    (0.0, 1.0)
+                } else {
+                    let (p, q) = regularized_gamma(k + 1.0, lambda);
+                    (q, p)
+                }
+            }
+            Params::Uniform { a, b } => lower(((x - a) / (b - a)).clamp(0.0, 1.0)),
+            Params::Exponential { lambda } => {
+                if x <= 0.0 {
+                    (0.0, 1.0)
+                } else {
+                    (-(-lambda * x).exp_m1(), (-lambda * x).exp())
+                }
+            }
+            Params::StudentT { nu } => {
+                let tail = 0.5 * regularized_beta(nu / (nu + x * x), nu / 2.0, 0.5).0;
+                if x < 0.0 {
+                    (tail, 1.0 - tail)
+                } else {
+                    (1.0 - tail, tail)
+                }
+            }
+            Params::ChiSquared { k } => {
+                if x <= 0.0 {
+                    (0.0, 1.0)
+                } else {
+                    regularized_gamma(k / 2.0, x / 2.0)
+                }
+            }
+        }
+    }
+
+    /// 分位関数. 0 <= p <= 1
+    fn inv(&self, p: f64) -> Result<f64, SpecialErr> {
+        let unbounded = || Err(SpecialErr::Domain(format!("p = {p}")));
+        Ok(match *self {
+            Params::Normal { mu, sigma } => {
+                if p == 0.0 || p == 1.0 {
+                    return unbounded();
+                }
+                mu + sigma * norm_inv(p)
+            }
+            Params::Binomial { n, .. } => self.discrete_inv(p, n),
+            Params::Poisson { lambda } => {
+                if p == 1.0 {
+                    return unbounded();
+                }
+                let mut hi = lambda.ceil().max(1.0);
+                while !self.reaches(hi, p) {
+                    hi *= 2.0;
+                }
+                self.discrete_inv(p, hi)
+            }
+            Params::Uniform { a, b } => a + p * (b - a),
+            Params::Exponential { lambda } => {
+                if p == 1.0 {
+                    return unbounded();
+                }
+                -(-p).ln_1p() / lambda
+            }
+            Params::StudentT { .. } => {
+                if p == 0.0 || p == 1.0 {
+                    return unbounded();
+                }
+                // 0について対称なので上半分で解く
+                if p < 0.5 {
+                    -self.continuous_inv(1.0 - p, 0.0)
+                } else {
+                    self.continuous_inv(p, 0.0)
+                }
+            }
+            Params::ChiSquared { .. } => {
+                if p == 1.0 {
+                    return unbounded();
+                }
+                self.continuous_inv(p, 0.0)
+            }
+        })
+    }
+
+    /// P(X <= x) >= p か
+    fn reaches(&self, x: f64, p: f64) -> bool {
+        let (lower, upper) = self.cdf(x);
+        if p <= 0.5 {
+            lower >= p
+        } else {
+            upper <= 1.0 - p
+        }
+    }
+
+    /// P(X <= k) >= p となる最小の整数k. 0 <= k <= hi を二分探索する
+    fn discrete_inv(&self, p: f64, hi: f64) -> f64 {
+        let (mut lo, mut hi) = (0.0, hi);
+        while lo < hi {
+            let mid = ((lo + hi) / 2.0).floor();
+            if self.reaches(mid, p) {
+                hi = mid;
+            } else {
+                lo = mid + 1.0;
+            }
+        }
+        lo
+    }
+
+    /// 連続分布の分位関数. lo以上で二分法を併用したNewton法で解く
+    fn continuous_inv(&self, p: f64, lo: f64) -> f64 {
+        let f = |x: f64| {
+            let (lower, upper) = self.cdf(x);
+            if p <= 0.5 {
+                lower - p
+            } else {
+                (1.0 - p) - upper
+            }
+        };
+        let (mut lo, mut hi) = (lo, lo + 1.0);
+        while f(hi) < 0.0 {
+            lo = hi;
+            hi *= 2.0;
+        }
+        let mut x = (lo + hi) / 2.0;
+        for _ in 0..MAX_INV_ITER {
+            let fx = f(x);
+            if fx == 0.0 {
+                return x;
+            }
+            if fx < 0.0 {
+                lo = x;
+            } else {
+                hi = x;
+            }
+            let newton = x - fx / self.pdf(x);
+            let next = if lo < newton && newton < hi {
+                newton
+            } else {
+                (lo + hi) / 2.0
+            };
+            if (next - x).abs() <= 2.0 * f64::EPSILON * x.abs() || hi - lo <= f64::MIN_POSITIVE {
+                return next;
+            }
+            x = next;
+        }
+        x
+    }
+}
+
+/// 分位関数を解く反復の上限
+const MAX_INV_ITER: usize = 2000;
+
+/// ln C(n, k)
+fn ln_choose(n: f64, k: f64) -> f64 {
+    ln_gamma(n + 1.0) - ln_gamma(k + 1.0) - ln_gamma(n - k + 1.0)
+}
+
+/// 標準正規分布の分位関数. 0 < p < 1
+/// Acklamの有理近似にHalley法を1回かけて精度を上げる
+fn norm_inv(p: f64) -> f64 {
+    // 上側は対称性から求める
+    if p > 0.5 {
+        return -norm_inv(1.0 - p);
+    }
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    let x = if p < 0.024_25 {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    };
+    let e = 0.5 * erfc(-x / SQRT_2) - p;
+    let u = e * (2.0 * PI).sqrt() * (x * x / 2.0).exp();
+    x - u / (1.0 + x * u / 2.0)
+}
+
+/// xoshiro256**による擬似乱数生成器
+struct Rng([u64; 4]);
+
+impl Rng {
+    /// splitmix64でseedから状態を作る
+    fn new(seed: u64) -> Rng {
+        let mut z = seed;
+        let mut next = || {
+            z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut x = z;
+            x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            x ^ (x >> 31)
+        };
+        Rng([next(), next(), next(), next()])
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = &mut self.0;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// [0, 1)の一様乱数
+    fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// (0, 1)の一様乱数. 分位関数に渡しても発散しない
+    fn uniform_open(&mut self) -> f64 {
+        ((self.next_u64() >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+}
+
+/// 乱数の状態. :seedで指定されるまでは時刻から初期化する
+static RNG: LazyLock<Mutex<Rng>> = LazyLock::new(|| {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    Mutex::new(Rng::new(now))
+});
+
+/// 乱数の種を設定する. 同じ種からは同じ乱数の列ができる
+pub fn seed(seed: u64) {
+    *RNG.lock().unwrap_or_else(|e| e.into_inner()) = Rng::new(seed);
+}
+
+/// 共有の乱数の状態でfを呼ぶ
+fn with_rng<T>(f: impl FnOnce(&mut Rng) -> T) -> T {
+    // 状態は整数だけなので, 途中でpanicしていてもそのまま使える
+    f(&mut RNG.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// [0, 1)の一様乱数
+pub fn rand() -> f64 {
+    with_rng(Rng::uniform)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(actual: f64, expected: f64, tol: f64) {
+        let err = (actual - expected).abs() / expected.abs().max(1.0);
+        assert!(err < tol, "{actual} != {expected} (error {err})");
+    }
+
+    fn eval(name: &str, args: &[f64]) -> Result<f64, SpecialErr> {
+        let (_, _, f) = DistFn::ALL.iter().find(|(n, _, _)| *n == name).unwrap();
+        assert!(f.arity().contains(&args.len()), "{name}");
+        f.eval(args)
+    }
+
+    #[test]
+    fn test_continuous() {
+        let cases: [(&str, &[f64], f64); 17] = [
+            ("\\normpdf", &[0.5], 0.352_065_326_764_299_5),
+            ("\\normcdf", &[1.96], 0.975_002_104_851_779_6),
+            ("\\normcdf", &[-10.0], 7.619_853_024_160_526e-24),
+            ("\\normcdf", &[3.0, 1.0, 2.0], 0.841_344_746_068_542_9),
+            ("\\norminv", &[0.975], 1.959_963_984_540_054),
+            ("\\norminv", &[1e-10, 5.0, 2.0], -7.722_681_804_808_112),
+            ("\\unifcdf", &[0.5, -1.0, 3.0], 0.375),
+            ("\\unifinv", &[0.25, 2.0, 6.0], 3.0),
+            ("\\exppdf", &[1.0, 2.0], 0.270_670_566_473_225_4),
+            ("\\expcdf", &[1.0, 2.0], 0.864_664_716_763_387_3),
+            ("\\expinv", &[0.5, 2.0], 0.346_573_590_279_972_65),
+            ("\\tpdf", &[1.0, 3.0], 0.206_748_335_783_172),
+            ("\\tcdf", &[2.0, 5.0], 0.949_030_260_585_070_8),
+            ("\\tinv", &[0.025, 10.0], -2.228_138_851_986_275),
+            ("\\chi2pdf", &[2.0, 3.0], 0.207_553_748_710_297_35),
+            ("\\chi2cdf", &[3.84, 1.0], 0.949_956_478_751_294_9),
+            ("\\chi2inv", &[0.95, 10.0], 18.307_038_053_275_147),
+        ];
+        for (name, args, expected) in cases {
+            close(eval(name, args).unwrap(), expected, 1e-13);
+        }
+    }
+
+    #[test]
+    fn test_discrete() {
+        let cases: [(&str, &[f64], f64); 9] = [
+            ("\\binopdf", &[3.0, 10.0, 0.3], 0.266_827_932),
+            ("\\binopdf", &[2.5, 10.0, 0.3], 0.0),
+            ("\\binocdf", &[3.0, 10.0, 0.3], 0.649_610_718_4),
+            ("\\binocdf", &[10.0, 10.0, 0.3], 1.0),
+            ("\\binoinv", &[0.5, 10.0, 0.3], 3.0),
+            ("\\poisspdf", &[2.0, 3.0], 0.224_041_807_655_387_74),
+            ("\\poisscdf", &[2.0, 3.0], 0.423_190_081_126_843_5),
+            ("\\poisscdf", &[1000.0, 1000.0], 0.508_409_367_168_506),
+            ("\\poissinv", &[0.99, 3.0], 8.0),
+        ];
+        for (name, args, expected) in cases {
+            close(eval(name, args).unwrap(), expected, 1e-13);
+        }
+    }
+
+    #[test]
+    fn test_domain() {
+        for (name, args) in [
+            ("\\normpdf", &[0.0, 0.0, -1.0][..]),
+            ("\\norminv", &[1.0]),
+            ("\\norminv", &[1.5]),
+            ("\\binopdf", &[1.0, 2.5, 0.5]),
+            ("\\unifpdf", &[0.0, 1.0, 1.0]),
+            ("\\poissinv", &[1.0, 3.0]),
+            ("\\tcdf", &[0.0, 0.0]),
+        ] {
+            assert!(
+                matches!(eval(name, args), Err(SpecialErr::Domain(_))),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rand() {
+        let (mut a, mut b) = (Rng::new(42), Rng::new(42));
+        assert!((0..5).all(|_| a.next_u64() == b.next_u64()));
+        assert!((0..100).all(|_| (0.0..1.0).contains(&a.uniform())));
+        // 標本平均が母平均に近いこと
+        let n = 10000;
+        let exp = Params::new(Dist::Exponential, &[2.0]).unwrap();
+        let mean = (0..n)
+            .map(|_| exp.inv(a.uniform_open()).unwrap())
+            .sum::<f64>()
+            / n as f64;
+        assert!((mean - 0.5).abs() < 0.02, "{mean}");
+        let binomial = Params::new(Dist::Binomial, &[20.0, 0.5]).unwrap();
+        let k = binomial.inv(a.uniform_open()).unwrap();
+        assert!(k.fract() == 0.0 && (0.0..=20.0).contains(&k));
+    }
+}
@@ -0,0 +1,130 @@
+use bigdecimal::{BigDecimal, One, Zero};
+
+use crate::ast::{BinaryOp, Node, UnaryOp};
+use crate::config::Config;
+use crate::error::MyError;
+use crate::parser::format_err_span;
+
+/// 評価前に1引数の演算opの引数が定義域に含まれているかを検査する
+///
+/// * `op` - 演算
+/// * `node` - 評価するnode
+/// * `arg` - 引数
+/// * `conf` - 現在の設定
+/// * `form` - エラー個所の表示に使う数式
+pub fn check_unary(
+    op: UnaryOp,
+    node: &Node,
+    arg: &BigDecimal,
+    conf: &Config,
+    form: &str,
+) -> Result<(), MyError> {
+    let zero = BigDecimal::zero();
+    let one = BigDecimal::one();
+    match op {
+        UnaryOp::Sqrt if *arg < zero => Err(domain_err(node, form, arg.to_string())),
+        UnaryOp::Log if conf.log_base <= zero || conf.log_base == one => {
+            Err(domain_err(node, form, format!("base {}", conf.log_base)))
+        }
+        UnaryOp::Log | UnaryOp::Ln if *arg <= zero => Err(domain_err(node, form, arg.to_string())),
+        UnaryOp::AcSin | UnaryOp::AcCos if arg.abs() > one => {
+            Err(domain_err(node, form, arg.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// 評価前に2引数の演算opの引数が定義域に含まれているかを検査する
+///
+/// * `op` - 演算
+/// * `node` - 評価するnode
+/// * `lop` - 左operand
+/// * `rop` - 右operand
+/// * `form` - エラー個所の表示に使う数式
+pub fn check_binary(
+    op: BinaryOp,
+    node: &Node,
+    lop: &BigDecimal,
+    rop: &BigDecimal,
+    form: &str,
+) -> Result<(), MyError> {
+    let zero = BigDecimal::zero();
+    match op {
+        BinaryOp::Div if rop.is_zero() => Err(domain_err(node, form, "divisor 0".to_string())),
+        BinaryOp::Pow if (*lop < zero && !rop.is_integer()) || (lop.is_zero() && *rop < zero) => {
+            Err(domain_err(
+                node,
+                form,
+                format!("base {lop} and exponent {rop}"),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// check_unaryのf64版
+pub fn check_unary_f64(
+    op: UnaryOp,
+    node: &Node,
+    arg: f64,
+    log_base: f64,
+    form: &str,
+) -> Result<(), MyError> {
+    match op {
+        UnaryOp::Sqrt if arg < 0.0 => Err(domain_err(node, form, arg.to_string())),
+        UnaryOp::Log if log_base <= 0.0 || log_base == 1.0 => {
+            Err(domain_err(node, form, format!("base {log_base}")))
+        }
+        UnaryOp::Log | UnaryOp::Ln if arg <= 0.0 => Err(domain_err(node, form, arg.to_string())),
+        UnaryOp::AcSin | UnaryOp::AcCos if arg.abs() > 1.0 => {
+            Err(domain_err(node, form, arg.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// check_binaryのf64版
+pub fn check_binary_f64(
+    op: BinaryOp,
+    node: &Node,
+    lop: f64,
+    rop: f64,
+    form: &str,
+) -> Result<(), MyError> {
+    match op {
+        BinaryOp::Div if rop == 0.0 => Err(domain_err(node, form, "divisor 0".to_string())),
+        BinaryOp::Pow if (lop < 0.0 && rop.fract() != 0.0) || (lop == 0.0 && rop < 0.0) => Err(
+            domain_err(node, form, format!("base {lop} and exponent {rop}")),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// csc, sec, cotのように逆数を取る関数の値を返す
+/// 分母が0のときは定義域外としてエラーを返す
+///
+/// * `node` - 評価するnode
+/// * `arg` - 関数の引数
+/// * `denom` - 分母(cscならsin(arg))
+/// * `form` - エラー個所の表示に使う数式
+pub fn reciprocal(
+    node: &Node,
+    arg: &BigDecimal,
+    denom: BigDecimal,
+    form: &str,
+) -> Result<BigDecimal, MyError> {
+    if denom.is_zero() {
+        Err(domain_err(node, form, arg.to_string()))
+    } else {
+        Ok(BigDecimal::one() / denom)
+    }
+}
+
+/// nodeの演算が値valueに対して定義されていないことを示すエラーを返す
+pub fn domain_err(node: &Node, form: &str, value: String) -> MyError {
+    let name = node
+        .op_span
+        .and_then(|op| form.get(op.start..op.end))
+        .map_or_else(|| node.kind.to_op_str(), |s| s.to_string());
+    MyError::DomainErr(name, value, format_err_span(form, node.span))
+}
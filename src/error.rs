@@ -12,12 +12,32 @@ pub enum MyError {
     ConfigReadErr(String),
     #[error("couldn't write config: {0}")]
     ConfigWriteErr(String),
+    #[error("couldn't write {0}: {1}")]
+    FileWriteErr(String, String),
+    #[error("couldn't read {0}: {1}")]
+    FileReadErr(String, String),
     #[error("couldn't read consts: {0}")]
     ConstsReadErr(String),
+    #[error("couldn't read registry: {0}")]
+    RegistryReadErr(String),
+    #[error("couldn't write registry: {0}")]
+    RegistryWriteErr(String),
+    #[error("couldn't register '{0}': {1}")]
+    RegisterErr(String, String),
     #[error("broken AST")]
     BrokenAstErr,
     #[error("undiffined command '{0}'")]
     UDcommandErr(String),
+    #[error("undefined function '{0}'\n{1}")]
+    UDfunction(String, String),
+    #[error("'{0}' takes {1} argument(s) but {2} given\n{3}")]
+    ArgCountErr(String, String, usize, String),
+    #[error("unsupported {0}\n{1}")]
+    Unsupported(String, String),
+    #[error("invalid MathML: {0}\n{1}")]
+    MathmlErr(String, String),
+    #[error("undefined TeX command '{0}'{1}\n{2}")]
+    UDtexcommand(String, String, String),
     #[error("un processed token '{0}'\n{1}")]
     UnprocessedToekn(String, String),
     #[error("Invalid hex format '{0}'")]
@@ -38,6 +58,10 @@ pub enum MyError {
     ParseU64Error(String),
     #[error("{0}")]
     TomlDeserializeError(#[from] toml::de::Error),
+    #[error("{0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("invalid AST JSON: {0}")]
+    AstJsonErr(String),
     #[error("There is no token to process")]
     NoToken,
     #[error("invalid input: {0}")]
@@ -50,20 +74,58 @@ pub enum MyError {
     NotTkVariable(String, String),
     #[error("expected TkTscCmd but not")]
     NotTkTscCmd,
-    #[error("undefined variable '{0}'")]
-    UDvariableErr(String),
-    #[error("expected {0} but {1}")]
-    UnexpectedToken(String, String),
+    #[error("undefined variable '{0}'\n{1}")]
+    UDvariableErr(String, String),
+    #[error("undefined list '{0}'\n{1}")]
+    UDlistErr(String, String),
+    #[error("list '{0}' can't be used as a number\n{1}")]
+    ListAsNumErr(String, String),
+    #[error("expected {0} but {1}\n{2}")]
+    UnexpectedToken(String, String, String),
+    #[error("unclosed '{0}'\n{1}")]
+    UnclosedBrace(String, String),
+    #[error("unmatched '{0}'\n{1}")]
+    UnmatchedBrace(String, String),
+    #[error("relation '{0}' can't be evaluated, write an expression instead\n{1}")]
+    RelationErr(String, String),
+    #[error("{} errors found\n{}", .0.len(), join_errors(.0))]
+    MultipleErr(Vec<MyError>),
     #[error("undiffined tsc command {0}")]
     UDtsccommand(String),
     #[error("expected {0} but {1}")]
     UnexpectedInput(String, String),
     #[error("unexpected operation to lexer: {0}")]
     UnexpectedOpToLexer(String),
+    #[error("couldn't generate code: {0}")]
+    CodegenErr(String),
+    #[error("no real root of '{0}' in [{1}, {2}]")]
+    NoRoot(String, String, String),
+    #[error("not linear in {0}\n{1}")]
+    NonLinearErr(String, String),
+    #[error("not a polynomial in {0}\n{1}")]
+    NotPolynomialErr(String, String),
+    #[error("no solution: equation {0} contradicts the others")]
+    InconsistentSystem(usize),
+    #[error("underdetermined system: free variables {0}\n{1}")]
+    UnderdeterminedSystem(String, String),
     #[error("couldn't calculate: {0}")]
     CalcErr(String),
+    #[error("'{0}' is undefined for {1}\n{2}")]
+    DomainErr(String, String, String),
+    #[error("nesting too deep (over {0} levels)\n{1}")]
+    TooDeepNesting(usize, String),
+    #[error("expected an integer but {0}\n{1}")]
+    NotIntegerErr(String, String),
     #[error("couldn't convert BigDecimal to {0}: {1}")]
     ConvertErr(String, BigDecimal),
     #[error("received quit command")]
     Quit,
 }
+
+fn join_errors(errs: &[MyError]) -> String {
+    errs.iter()
+        .enumerate()
+        .map(|(i, e)| format!("[{}] {e}", i + 1))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -0,0 +1,381 @@
+//! TeXSC: TeX Scientific Calculator
+//!
+//! TeX形式の数式を字句解析, 構文解析して評価する
+
+use bigdecimal::{BigDecimal, FromPrimitive};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+
+use ast::{Ast, BinaryOp, Node, NodeId, NodeKind, UnaryOp};
+use parser::{NodeOrCmd, TscCmd};
+
+pub mod ast;
+pub mod ast_json;
+mod ast_printer;
+pub mod codegen;
+pub mod compile;
+pub mod config;
+pub mod derivative;
+pub mod distributions;
+mod domain;
+pub mod error;
+mod math_functions;
+pub mod num_formatter;
+pub mod optimizer;
+pub mod parser;
+mod poly;
+mod rational;
+pub mod registry;
+pub mod special_functions;
+pub mod stats;
+mod str2num;
+pub mod syntax;
+pub mod tex_doc;
+pub mod tex_printer;
+pub mod tokenizer;
+mod tsc_cmd;
+#[macro_use]
+mod macros;
+#[cfg(test)]
+mod test;
+
+use config::*;
+use error::*;
+use num_formatter::{num_bin_formatter, num_formatter, num_hex_formatter, num_oct_formatter};
+use registry::{registry_reader, Evaluator, Registry};
+use special_functions::SpecialErr;
+
+pub static CONFIG: LazyLock<RwLock<Config>> = LazyLock::new(|| RwLock::new(Config::default()));
+
+pub static REGISTRY: LazyLock<RwLock<Registry>> =
+    LazyLock::new(|| RwLock::new(Registry::default()));
+
+pub static CONSTS: LazyLock<RwLock<HashMap<String, Value>>> = LazyLock::new(|| {
+    RwLock::new({
+        let mut consts = HashMap::new();
+        consts.insert(
+            "e".to_string(),
+            BigDecimal::from_f64(std::f64::consts::E).unwrap().into(),
+        );
+        consts.insert(
+            "\\pi".to_string(),
+            BigDecimal::from_f64(std::f64::consts::PI).unwrap().into(),
+        );
+        consts
+    })
+});
+
+/// 変数の値. :loadで読み込んだリストも数値の変数と同じ表に入れる
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Num(BigDecimal),
+    List(Vec<BigDecimal>),
+}
+
+impl Value {
+    /// 数値ならその値. リストならNone
+    pub fn num(&self) -> Option<&BigDecimal> {
+        match self {
+            Value::Num(n) => Some(n),
+            Value::List(_) => None,
+        }
+    }
+}
+
+impl From<BigDecimal> for Value {
+    fn from(n: BigDecimal) -> Value {
+        Value::Num(n)
+    }
+}
+
+/// 結果の表示形式. :hex等のTSCコマンドで切り替える
+#[derive(Clone, Copy)]
+pub(crate) enum OutpuFormat {
+    Default,
+    Hex,
+    Dec,
+    Bin,
+    Oct,
+}
+
+impl OutpuFormat {
+    pub(crate) fn format(self, num: &BigDecimal, num_of_digit: u32) -> String {
+        match self {
+            OutpuFormat::Default => num_formatter(num, num_of_digit),
+            OutpuFormat::Hex => num_hex_formatter(num, num_of_digit),
+            OutpuFormat::Dec => num_formatter(num, 0),
+            OutpuFormat::Bin => num_bin_formatter(num, num_of_digit),
+            OutpuFormat::Oct => num_oct_formatter(num, num_of_digit),
+        }
+    }
+}
+
+impl From<TscCmd> for OutpuFormat {
+    fn from(cmd: TscCmd) -> OutpuFormat {
+        match cmd {
+            TscCmd::Hex => OutpuFormat::Hex,
+            TscCmd::Dec => OutpuFormat::Dec,
+            TscCmd::Bin => OutpuFormat::Bin,
+            TscCmd::Oct => OutpuFormat::Oct,
+        }
+    }
+}
+
+/// 1行分の数式を評価して結果を表示する
+/// 評価した値を数式の順に返す
+pub fn process_form(
+    form: String,
+    vars: &mut HashMap<String, Value>,
+) -> Result<Vec<BigDecimal>, MyError> {
+    let syntax = config_reader()?.syntax;
+    process_form_as(form, syntax, vars)
+}
+
+/// 1行分の数式をsyntaxの形式で読んで評価し, 結果を表示する
+pub(crate) fn process_form_as(
+    form: String,
+    syntax: Syntax,
+    vars: &mut HashMap<String, Value>,
+) -> Result<Vec<BigDecimal>, MyError> {
+    debugln!("form: '{}'", form);
+    let form: String = form.replace("\n", "").replace("\t", "").replace("\r", "");
+    for i in vars.iter() {
+        debugln!("{:?}", i);
+    }
+    let (ast, ast_or_cmd_vec) = syntax::parse(&form, syntax, vars)?;
+    let num_of_digit = match config_reader() {
+        Ok(c) => c.num_of_digit,
+        Err(e) => {
+            return Err(e);
+        }
+    };
+    let mut res = Vec::new();
+    let mut out_from = OutpuFormat::Default;
+    for ast_or_cmd in ast_or_cmd_vec {
+        match ast_or_cmd {
+            NodeOrCmd::Node(root) => match eval_root(&ast, root, vars, &form) {
+                Ok(result) => {
+                    debugln!("resutl: {}", result);
+                    res.push(result.clone());
+                    println!("{}", out_from.format(&result, num_of_digit));
+                }
+                Err(e) => return Err(e),
+            },
+            parser::NodeOrCmd::TscCmd(cmd) => out_from = cmd.into(),
+        }
+    }
+    Ok(res)
+}
+
+/// 構文解析したままのASTを表示し, 式を簡約してから評価する
+fn eval_root(
+    ast: &Ast,
+    root: NodeId,
+    vars: &HashMap<String, Value>,
+    form: &str,
+) -> Result<BigDecimal, MyError> {
+    ast_printer::show_ast(ast, root, vars)?;
+    ast_printer::show_ast_in_s_expr_rec(ast, root, vars)?;
+    ast_printer::show_ast_in_tex(ast, root)?;
+    ast_printer::show_ast_in_dot(ast, root, vars)?;
+    ast_printer::show_ast_in_json(ast, root)?;
+    let (ast, root) = optimizer::simplify(ast, root, Some(vars))?;
+    calc(&ast, root, vars, form)
+}
+
+/// ASTのidのnodeを評価する
+pub fn calc(
+    ast: &Ast,
+    id: NodeId,
+    vars: &HashMap<String, Value>,
+    form: &str,
+) -> Result<BigDecimal, MyError> {
+    let node = &ast[id];
+    match &node.kind {
+        NodeKind::Num(n) => Ok(n.clone()),
+        NodeKind::Var(v) => match vars.get(v) {
+            Some(Value::Num(n)) => Ok(n.clone()),
+            Some(Value::List(_)) => Err(MyError::ListAsNumErr(
+                v.clone(),
+                parser::format_err_span(form, node.span),
+            )),
+            None => Err(MyError::UDvariableErr(
+                v.clone(),
+                parser::format_err_span(form, node.span),
+            )),
+        },
+        NodeKind::Unary(op, arg) => {
+            let arg = calc(ast, *arg, vars, form)?;
+            let conf = config_reader()?;
+            calc_unary(*op, node, arg, &conf, form)
+        }
+        NodeKind::Binary(op, lhs, rhs) => {
+            let loperand = calc(ast, *lhs, vars, form)?;
+            let roperand = calc(ast, *rhs, vars, form)?;
+            calc_binary(*op, node, loperand, roperand, form)
+        }
+        NodeKind::Call(func, args) => {
+            let mut values = Vec::with_capacity(args.len());
+            for arg in ast.args(*args) {
+                values.push(calc(ast, *arg, vars, form)?);
+            }
+            call_func(*func, node, values, form)
+        }
+    }
+}
+
+/// 2引数の演算opを評価する
+///
+/// * `node` - 評価するnode. エラー個所の表示に使う
+pub(crate) fn calc_binary(
+    op: BinaryOp,
+    node: &Node,
+    loperand: BigDecimal,
+    roperand: BigDecimal,
+    form: &str,
+) -> Result<BigDecimal, MyError> {
+    domain::check_binary(op, node, &loperand, &roperand, form)?;
+    Ok(match op {
+        BinaryOp::Add => loperand + roperand,
+        BinaryOp::Sub => loperand - roperand,
+        BinaryOp::Mul => loperand * roperand,
+        BinaryOp::Div => loperand / roperand,
+        BinaryOp::Pow => math_functions::pow(loperand, roperand)?,
+    })
+}
+
+/// 1引数の演算opを評価する
+///
+/// * `node` - 評価するnode. エラー個所の表示に使う
+pub(crate) fn calc_unary(
+    op: UnaryOp,
+    node: &Node,
+    loperand: BigDecimal,
+    conf: &Config,
+    form: &str,
+) -> Result<BigDecimal, MyError> {
+    domain::check_unary(op, node, &loperand, conf, form)?;
+
+    fn radian2degree(rad: BigDecimal) -> BigDecimal {
+        rad * BigDecimal::from(180) / BigDecimal::from_f64(std::f64::consts::PI).unwrap()
+    }
+
+    fn degree2radian(deg: BigDecimal) -> BigDecimal {
+        deg * BigDecimal::from_f64(std::f64::consts::PI).unwrap() / BigDecimal::from(180)
+    }
+
+    match op {
+        UnaryOp::Sqrt => loperand.sqrt().ok_or(MyError::CalcErr(format!(
+            "failed calc \\sqrt{{{loperand}}}"
+        ))),
+        UnaryOp::Log => Ok(math_functions::log(conf.log_base.clone(), loperand)?),
+        UnaryOp::Ln => Ok(math_functions::log(
+            BigDecimal::from_f64(std::f64::consts::E).unwrap(),
+            loperand,
+        )?),
+        UnaryOp::Abs => Ok(loperand.abs()),
+        UnaryOp::Exp => Ok(math_functions::exp(loperand)?),
+        UnaryOp::Sin => match conf.trig_func_arg {
+            TrigFuncArg::Radian => Ok(math_functions::sin(loperand)?),
+            TrigFuncArg::Degree => Ok(math_functions::sin(degree2radian(loperand))?),
+        },
+        UnaryOp::Cos => match conf.trig_func_arg {
+            TrigFuncArg::Radian => Ok(math_functions::cos(loperand)?),
+            TrigFuncArg::Degree => Ok(math_functions::cos(degree2radian(loperand))?),
+        },
+        UnaryOp::Tan => match conf.trig_func_arg {
+            TrigFuncArg::Radian => Ok(math_functions::tan(loperand)?),
+            TrigFuncArg::Degree => Ok(math_functions::tan(degree2radian(loperand))?),
+        },
+        UnaryOp::Csc => {
+            let denom = match conf.trig_func_arg {
+                TrigFuncArg::Radian => math_functions::sin(loperand.clone())?,
+                TrigFuncArg::Degree => math_functions::sin(degree2radian(loperand.clone()))?,
+            };
+            domain::reciprocal(node, &loperand, denom, form)
+        }
+        UnaryOp::Sec => {
+            let denom = match conf.trig_func_arg {
+                TrigFuncArg::Radian => math_functions::cos(loperand.clone())?,
+                TrigFuncArg::Degree => math_functions::cos(degree2radian(loperand.clone()))?,
+            };
+            domain::reciprocal(node, &loperand, denom, form)
+        }
+        UnaryOp::Cot => {
+            let denom = match conf.trig_func_arg {
+                TrigFuncArg::Radian => math_functions::tan(loperand.clone())?,
+                TrigFuncArg::Degree => math_functions::tan(degree2radian(loperand.clone()))?,
+            };
+            domain::reciprocal(node, &loperand, denom, form)
+        }
+        UnaryOp::AcSin => match conf.trig_func_arg {
+            TrigFuncArg::Radian => Ok(math_functions::asin(loperand)?),
+            TrigFuncArg::Degree => Ok(radian2degree(math_functions::asin(loperand)?)),
+        },
+        UnaryOp::AcCos => match conf.trig_func_arg {
+            TrigFuncArg::Radian => Ok(math_functions::acos(loperand)?),
+            TrigFuncArg::Degree => Ok(radian2degree(math_functions::acos(loperand)?)),
+        },
+        UnaryOp::AcTan => match conf.trig_func_arg {
+            TrigFuncArg::Radian => Ok(math_functions::atan(loperand)?),
+            TrigFuncArg::Degree => Ok(radian2degree(math_functions::atan(loperand)?)),
+        },
+        UnaryOp::Neg => Ok(-loperand),
+    }
+}
+
+/// 定義された関数の呼び出しのネストの上限
+const MAX_CALL_DEPTH: usize = 64;
+
+thread_local! {
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// registryに登録された関数を評価する
+///
+/// * `args` - 評価済みの引数
+pub(crate) fn call_func(
+    id: usize,
+    node: &Node,
+    args: Vec<BigDecimal>,
+    form: &str,
+) -> Result<BigDecimal, MyError> {
+    // 関数の中で他の関数を呼べるように, 評価する前にlockを外す
+    let eval = match registry_reader()?.get(id) {
+        Some(cmd) => cmd.eval.clone(),
+        None => return Err(MyError::BrokenAstErr),
+    };
+    match eval {
+        Evaluator::Native(f) => f(&args),
+        Evaluator::Defined { params, body } => {
+            let depth = CALL_DEPTH.get();
+            if depth >= MAX_CALL_DEPTH {
+                return Err(MyError::TooDeepNesting(
+                    MAX_CALL_DEPTH,
+                    parser::format_err_span(form, node.span),
+                ));
+            }
+            let mut local = match CONSTS.read() {
+                Ok(consts) => consts.clone(),
+                Err(e) => return Err(MyError::ConstsReadErr(e.to_string())),
+            };
+            local.extend(params.into_iter().zip(args.into_iter().map(Value::Num)));
+            let (ast, root) = body.ast()?;
+            CALL_DEPTH.set(depth + 1);
+            let res = calc(ast, *root, &local, &body.form);
+            CALL_DEPTH.set(depth);
+            res
+        }
+        Evaluator::List(f) => f.eval(&args),
+        Evaluator::Special(f) => f.eval(&args).map_err(|e| match e {
+            SpecialErr::Domain(value) => domain::domain_err(node, form, value),
+            SpecialErr::OutOfRange => MyError::CalcErr(format!(
+                "{} is out of range\n{}",
+                f.tex_name(),
+                parser::format_err_span(form, node.span)
+            )),
+        }),
+        Evaluator::Builtin(_) => Err(MyError::BrokenAstErr),
+    }
+}
@@ -1,53 +1,18 @@
 // TeX Scientific Calculator
 
-use self::parser::{NumOrVar, Parser};
-use bigdecimal::{BigDecimal, FromPrimitive};
-use clap::{value_parser, Arg, Command};
+use clap::{value_parser, Arg, ArgAction, Command};
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
 use std::collections::HashMap;
-use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::sync::{LazyLock, RwLock};
 
-use parser::{NodeKind, NodeOrCmd, TscCmd};
 use text_colorizer::*;
-
-mod ast_printer;
-mod config;
-mod error;
-mod math_functions;
-mod num_formatter;
-mod parser;
-mod str2num;
-mod tokenizer;
-mod tsc_cmd;
-#[macro_use]
-mod macros;
-#[cfg(test)]
-mod test;
-
-use config::*;
-use error::*;
-use num_formatter::{num_bin_formatter, num_formatter, num_hex_formatter, num_oct_formatter};
-
-pub static CONFIG: LazyLock<RwLock<Config>> = LazyLock::new(|| RwLock::new(Config::default()));
-
-pub static CONSTS: LazyLock<RwLock<HashMap<String, BigDecimal>>> = LazyLock::new(|| {
-    RwLock::new({
-        let mut consts = HashMap::new();
-        consts.insert(
-            "e".to_string(),
-            BigDecimal::from_f64(std::f64::consts::E).unwrap(),
-        );
-        consts.insert(
-            "\\pi".to_string(),
-            BigDecimal::from_f64(std::f64::consts::PI).unwrap(),
-        );
-        consts
-    })
-});
+use tsc::codegen::{codegen, Lang};
+use tsc::config::*;
+use tsc::error::*;
+use tsc::tex_doc::{process_tex_annotation, process_tex_doc};
+use tsc::{eprintlnc, process_form, syntax, Value};
 
 fn main() {
     let app = Command::new("tsc")
@@ -60,6 +25,64 @@ fn main() {
                 .short('f')
                 .value_parser(value_parser!(String)),
         )
+        .arg(
+            Arg::new("tex")
+                .help("evaluate formulas embedded in a LaTeX document")
+                .long("tex")
+                .value_name("file")
+                .conflicts_with_all(["file", "tex formulas", "to", "codegen"])
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            Arg::new("write")
+                .help("write computed values to '% tsc: =' markers and \\tsc{...} in the --tex file")
+                .long("write")
+                .requires("tex")
+                .conflicts_with("check")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("check")
+                .help("show lines --write would change and fail if any, without writing")
+                .long("check")
+                .requires("tex")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("emit-ast")
+                .help("show AST in the format: tree|sexpr|both|tex|dot|json|none")
+                .long("emit-ast")
+                .value_name("fmt")
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            Arg::new("codegen")
+                .help("print formulas as functions in the language: python|numpy|c|rust|js")
+                .long("codegen")
+                .value_name("lang")
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            Arg::new("syntax")
+                .help("read formulas in the syntax: tex|sexpr|plain|mathml|asciimath")
+                .long("syntax")
+                .value_name("syntax")
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            Arg::new("fast")
+                .help("evaluate :table with f64 instead of exact decimals (faster, less precise); :plot and :solve always use f64")
+                .long("fast")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("to")
+                .help("print formulas in the syntax instead of evaluating them: tex|sexpr|plain|mathml|asciimath")
+                .long("to")
+                .value_name("syntax")
+                .conflicts_with("codegen")
+                .value_parser(value_parser!(String)),
+        )
         .arg(
             Arg::new("tex formulas")
                 .help("tex formulas")
@@ -69,13 +92,11 @@ fn main() {
 
     let matches = app.get_matches();
 
-    let is_repl = !matches.args_present();
+    let is_repl = matches.get_one::<String>("tex formulas").is_none()
+        && matches.get_one::<String>("file").is_none()
+        && matches.get_one::<String>("tex").is_none();
 
-    match CONFIG
-        .write()
-        .expect("couldn't write CONFIG")
-        .load_from_file()
-    {
+    match load_config() {
         Ok(conf_file) => {
             if is_repl {
                 eprintln!("config loaded from {conf_file:?}")
@@ -88,32 +109,140 @@ fn main() {
         }
     }
 
+    // -f, 引数の数式ではASTを表示しない
+    let ast_format = match matches.get_one::<String>("emit-ast") {
+        Some(fmt) => match fmt.parse::<AstFormat>() {
+            Ok(f) => Some(f),
+            Err(e) => {
+                eprintlnc!(e);
+                return;
+            }
+        },
+        None if is_repl => None,
+        None => Some(AstFormat::None),
+    };
+    if let Some(f) = ast_format {
+        let mut conf = config_writer().expect("couldn't change ast_format config");
+        conf.ast_format = f;
+    }
+
+    if let Some(syntax) = matches.get_one::<String>("syntax") {
+        match syntax.parse::<Syntax>() {
+            Ok(s) => {
+                config_writer()
+                    .expect("couldn't change syntax config")
+                    .syntax = s
+            }
+            Err(e) => {
+                eprintlnc!(e);
+                return;
+            }
+        }
+    }
+
+    if matches.get_flag("fast") {
+        config_writer()
+            .expect("couldn't change fast_eval config")
+            .fast_eval = true;
+    }
+
+    let to = match matches.get_one::<String>("to") {
+        Some(to) => match to.parse::<Syntax>() {
+            Ok(s) => Some(s),
+            Err(e) => {
+                eprintlnc!(e);
+                return;
+            }
+        },
+        None => None,
+    };
+    if to.is_some() && is_repl {
+        eprintlnc!("--to requires tex formulas or -f {file}");
+        return;
+    }
+
+    let lang = match matches.get_one::<String>("codegen") {
+        Some(lang) => match lang.parse::<Lang>() {
+            Ok(l) => Some(l),
+            Err(e) => {
+                eprintlnc!(e);
+                return;
+            }
+        },
+        None => None,
+    };
+    if lang.is_some() && is_repl {
+        eprintlnc!("--codegen requires tex formulas or -f {file}");
+        return;
+    }
+
     // formulas from command line arg
     if let Some(form) = matches.get_one::<String>("tex formulas") {
-        {
-            let mut conf = config_writer().expect("couldn't change ast_format config");
-            conf.ast_format = AstFormat::None;
-        }
-        let mut vars: HashMap<String, BigDecimal> = HashMap::new();
+        let mut vars: HashMap<String, Value> = HashMap::new();
         for line in form.split('\n') {
-            if let Err(e) = process_form(line.replace("\r", ""), &mut vars) {
+            let line = line.replace("\r", "");
+            let res = match (lang, to) {
+                (Some(lang), _) => codegen(&line, lang).map(|code| println!("{code}")),
+                (_, Some(to)) => convert_form(&line, to),
+                _ => process_form(line, &mut vars).map(|_| ()),
+            };
+            if let Err(e) = res {
                 eprintlnc!(e);
             }
         }
         return;
     }
 
+    // formulas in LaTeX document
+    if let Some(file_name) = matches.get_one::<String>("tex") {
+        let doc = match std::fs::read_to_string(file_name) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintlnc!(format!("couldn't read {file_name}: {e}"));
+                return;
+            }
+        };
+        let mut vars: HashMap<String, Value> = HashMap::new();
+        let write = matches.get_flag("write");
+        let res = if write || matches.get_flag("check") {
+            process_tex_annotation(file_name, &doc, &mut vars, write)
+        } else {
+            process_tex_doc(file_name, &doc, &mut vars).map(|_| false)
+        };
+        match res {
+            // --checkで書き換える個所があれば失敗にする
+            Ok(true) if !write => std::process::exit(1),
+            Ok(_) => (),
+            Err(e) => eprintlnc!(e),
+        }
+        return;
+    }
+
     // formulas from file
     if let Some(file_name) = matches.get_one::<String>("file") {
-        {
-            let mut conf = config_writer().expect("couldn't change ast_format config");
-            conf.ast_format = AstFormat::None;
-        }
-        let f: File = File::open(file_name).expect(file_name);
+        let f: File = match File::open(file_name) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintlnc!(format!("couldn't open {file_name}: {e}"));
+                return;
+            }
+        };
         let reader: BufReader<File> = BufReader::new(f);
-        let mut vars: HashMap<String, BigDecimal> = HashMap::new();
+        let mut vars: HashMap<String, Value> = HashMap::new();
         for line in reader.lines() {
-            if let Err(e) = process_form(line.unwrap(), &mut vars) {
+            let line = match line {
+                Ok(l) => l,
+                Err(e) => {
+                    eprintlnc!(format!("couldn't read {file_name}: {e}"));
+                    return;
+                }
+            };
+            let res = match (lang, to) {
+                (Some(lang), _) => codegen(&line, lang).map(|code| println!("{code}")),
+                (_, Some(to)) => convert_form(&line, to),
+                _ => process_form(line, &mut vars).map(|_| ()),
+            };
+            if let Err(e) = res {
                 eprintlnc!(e);
             }
         }
@@ -121,7 +250,7 @@ fn main() {
     }
 
     // REPL
-    let mut vars: HashMap<String, BigDecimal> = HashMap::new();
+    let mut vars: HashMap<String, Value> = HashMap::new();
     let mut rl = match DefaultEditor::new() {
         Ok(r) => r,
         Err(_) => panic!("Can't readline!"),
@@ -150,192 +279,9 @@ fn main() {
     }
 }
 
-enum OutpuFormat {
-    Default,
-    Hex,
-    Dec,
-    Bin,
-    Oct,
-}
-
-fn process_form(
-    form: String,
-    vars: &mut HashMap<String, BigDecimal>,
-) -> Result<Vec<BigDecimal>, MyError> {
-    debugln!("form: '{}'", form);
-    let form: String = form.replace("\n", "").replace("\t", "").replace("\r", "");
-    let mut pars = Parser::new(form.clone())?;
-    for i in vars.iter() {
-        debugln!("{:?}", i);
-    }
-    let ast_or_cmd_vec = pars.build_ast(vars)?;
-    let num_of_digit = match config_reader() {
-        Ok(c) => c.num_of_digit,
-        Err(e) => {
-            return Err(e);
-        }
-    };
-    let mut res = Vec::new();
-    let mut out_from = OutpuFormat::Default;
-    for ast_or_cmd in ast_or_cmd_vec {
-        match ast_or_cmd {
-            NodeOrCmd::Node(ast_root) => match calc(*ast_root, vars) {
-                Ok(result) => {
-                    debugln!("resutl: {}", result);
-                    res.push(result.clone());
-                    match out_from {
-                        OutpuFormat::Default => {
-                            println!("{}", num_formatter(&result, num_of_digit));
-                        }
-                        OutpuFormat::Hex => {
-                            println!("{}", num_hex_formatter(&result, num_of_digit));
-                        }
-                        OutpuFormat::Dec => {
-                            println!("{}", num_formatter(&result, 0));
-                        }
-                        OutpuFormat::Bin => {
-                            println!("{}", num_bin_formatter(&result, num_of_digit));
-                        }
-                        OutpuFormat::Oct => {
-                            println!("{}", num_oct_formatter(&result, num_of_digit));
-                        }
-                    }
-                }
-                Err(e) => return Err(e),
-            },
-            parser::NodeOrCmd::TscCmd(cmd) => match cmd {
-                TscCmd::Hex => out_from = OutpuFormat::Hex,
-                TscCmd::Dec => out_from = OutpuFormat::Dec,
-                TscCmd::Bin => out_from = OutpuFormat::Bin,
-                TscCmd::Oct => out_from = OutpuFormat::Oct,
-            },
-        }
-    }
-    Ok(res)
-}
-
-fn calc(node: parser::Node, vars: &HashMap<String, BigDecimal>) -> Result<BigDecimal, MyError> {
-    match node.node_kind {
-        NodeKind::Num | NodeKind::Var => {
-            return Ok(match node.val.unwrap() {
-                NumOrVar::Num(n) => n,
-                NumOrVar::Var(v) => match vars.get(&v) {
-                    Some(n) => n.clone(),
-                    None => return Err(MyError::UDvariableErr(v)),
-                },
-            })
-        }
-        _ => (),
-    }
-
-    let loperand: BigDecimal;
-    let mut roperand: BigDecimal = BigDecimal::from(1);
-
-    if let Some(left) = node.left_node {
-        loperand = getoperand(*left, vars)?;
-    } else {
-        // Num, Var以外でleftがNoneはエラー
-        // ここに到達した => 不正なAST
-        return Err(MyError::BrokenAstErr);
-    }
-
-    if let Some(right) = node.right_node {
-        roperand = getoperand(*right, vars)?;
-    } else {
-        // Num, Var以外でrightがNoneはありえる
-        // 前置, 1引数のノードの場合 => 正常
-        // それ以外 => 不正なAST
-        match node.node_kind {
-            NodeKind::Add => return Err(MyError::BrokenAstErr),
-            NodeKind::Sub => return Err(MyError::BrokenAstErr),
-            NodeKind::Div => return Err(MyError::BrokenAstErr),
-            NodeKind::Mul => return Err(MyError::BrokenAstErr),
-            _ => (),
-        }
-    }
-
-    let conf = config_reader()?;
-
-    fn radian2degree(rad: BigDecimal) -> BigDecimal {
-        rad * BigDecimal::from(180) / BigDecimal::from_f64(std::f64::consts::PI).unwrap()
-    }
-
-    fn degree2radian(deg: BigDecimal) -> BigDecimal {
-        deg * BigDecimal::from_f64(std::f64::consts::PI).unwrap() / BigDecimal::from(180)
-    }
-
-    match node.node_kind {
-        NodeKind::Add => Ok(loperand + roperand),
-        NodeKind::Sub => Ok(loperand - roperand),
-        NodeKind::Mul => Ok(loperand * roperand),
-        NodeKind::Div => Ok(loperand / roperand),
-        NodeKind::Sqrt => loperand
-            .sqrt()
-            .ok_or(MyError::CalcErr("failed calc sqrt".to_string())),
-        NodeKind::Log => Ok(math_functions::log(conf.log_base.clone(), loperand)?),
-        NodeKind::Ln => Ok(math_functions::log(
-            BigDecimal::from_f64(std::f64::consts::E).unwrap(),
-            loperand,
-        )?),
-        NodeKind::Abs => Ok(loperand.abs()),
-        NodeKind::Exp => Ok(loperand.exp()),
-        NodeKind::Sin => match conf.trig_func_arg {
-            TrigFuncArg::Radian => Ok(math_functions::sin(loperand)?),
-            TrigFuncArg::Degree => Ok(math_functions::sin(degree2radian(loperand))?),
-        },
-        NodeKind::Cos => match conf.trig_func_arg {
-            TrigFuncArg::Radian => Ok(math_functions::cos(loperand)?),
-            TrigFuncArg::Degree => Ok(math_functions::cos(degree2radian(loperand))?),
-        },
-        NodeKind::Tan => match conf.trig_func_arg {
-            TrigFuncArg::Radian => Ok(math_functions::tan(loperand)?),
-            TrigFuncArg::Degree => Ok(math_functions::tan(degree2radian(loperand))?),
-        },
-        NodeKind::Csc => match conf.trig_func_arg {
-            TrigFuncArg::Radian => Ok(1.0 / math_functions::sin(loperand)?),
-            TrigFuncArg::Degree => Ok(1.0 / math_functions::sin(degree2radian(loperand))?),
-        },
-        NodeKind::Sec => match conf.trig_func_arg {
-            TrigFuncArg::Radian => Ok(1.0 / math_functions::cos(loperand)?),
-            TrigFuncArg::Degree => Ok(1.0 / math_functions::cos(degree2radian(loperand))?),
-        },
-        NodeKind::Cot => match conf.trig_func_arg {
-            TrigFuncArg::Radian => Ok(1.0 / math_functions::tan(loperand)?),
-            TrigFuncArg::Degree => Ok(1.0 / math_functions::tan(degree2radian(loperand))?),
-        },
-        NodeKind::AcSin => match conf.trig_func_arg {
-            TrigFuncArg::Radian => Ok(math_functions::asin(loperand)?),
-            TrigFuncArg::Degree => Ok(radian2degree(math_functions::asin(loperand)?)),
-        },
-        NodeKind::AcCos => match conf.trig_func_arg {
-            TrigFuncArg::Radian => Ok(math_functions::acos(loperand)?),
-            TrigFuncArg::Degree => Ok(radian2degree(math_functions::acos(loperand)?)),
-        },
-        NodeKind::AcTan => match conf.trig_func_arg {
-            TrigFuncArg::Radian => Ok(math_functions::atan(loperand)?),
-            TrigFuncArg::Degree => Ok(radian2degree(math_functions::atan(loperand)?)),
-        },
-        NodeKind::Pow => Ok(math_functions::pow(loperand, roperand)?),
-        NodeKind::Neg => Ok(-loperand),
-        _ => Err(MyError::UDcommandErr(node.node_kind.to_string())),
-    }
-}
-
-fn getoperand(
-    node: parser::Node,
-    vars: &HashMap<String, BigDecimal>,
-) -> Result<BigDecimal, MyError> {
-    match &node.node_kind {
-        NodeKind::Num | NodeKind::Var => {
-            return Ok(match node.val.unwrap() {
-                NumOrVar::Num(n) => n,
-                NumOrVar::Var(v) => match vars.get(&v) {
-                    Some(n) => n.clone(),
-                    None => return Err(MyError::UDvariableErr(v)),
-                },
-            })
-        }
-        _ => (),
-    }
-    calc(node, vars)
+/// 設定の入力形式の数式をtoの形式に書き換えて表示する
+fn convert_form(form: &str, to: Syntax) -> Result<(), MyError> {
+    let from = config_reader()?.syntax;
+    println!("{}", syntax::convert(form, from, to)?);
+    Ok(())
 }
@@ -1,5 +1,8 @@
 use crate::MyError;
-use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use bigdecimal::{BigDecimal, FromPrimitive, One, ToPrimitive};
+
+/// BigDecimal::expは引数が大きいと計算が終わらないので引数の絶対値を制限する
+const EXP_ARG_LIMIT: i64 = 1000;
 
 pub fn pow(base: BigDecimal, exp: BigDecimal) -> Result<BigDecimal, MyError> {
     let base_f64 = base
@@ -28,6 +31,18 @@ pub fn log(base: BigDecimal, antilog: BigDecimal) -> Result<BigDecimal, MyError>
         .ok_or(MyError::CalcErr(format!("\\log_{base_f64} {antilog_f64}")))
 }
 
+pub fn exp(x: BigDecimal) -> Result<BigDecimal, MyError> {
+    if x.abs() > BigDecimal::from(EXP_ARG_LIMIT) {
+        return Err(MyError::CalcErr(format!("\\exp({x}) is out of range")));
+    }
+    // BigDecimal::expは負の引数で誤った値を返すので逆数で計算する
+    if x < BigDecimal::from(0) {
+        Ok(BigDecimal::one() / (-x).exp())
+    } else {
+        Ok(x.exp())
+    }
+}
+
 pub fn sin(x: BigDecimal) -> Result<BigDecimal, MyError> {
     let x_f64 = x
         .to_f64()
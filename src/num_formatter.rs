@@ -1,6 +1,5 @@
-use crate::math_functions::pow;
 use bigdecimal::BigDecimal;
-use num_bigint::{Sign, ToBigInt};
+use num_bigint::{BigInt, Sign, ToBigInt};
 use num_traits::Signed;
 
 pub fn num_formatter(num: &BigDecimal, significant_figure: u32) -> String {
@@ -13,7 +12,7 @@ pub fn num_formatter(num: &BigDecimal, significant_figure: u32) -> String {
             num.to_string()
         } else if *num < BigDecimal::from(1) {
             let sift_digit = get_num_of_zero(num) + 1;
-            let rsifted = num * pow(BigDecimal::from(10), BigDecimal::from(sift_digit)).unwrap();
+            let rsifted = num * pow10(sift_digit);
             format!(
                 "{} * 10^{{-{}}}",
                 round_n(&rsifted, significant_figure - 1),
@@ -24,8 +23,8 @@ pub fn num_formatter(num: &BigDecimal, significant_figure: u32) -> String {
             round_n(num, significant_figure - a).to_string()
         }
     } else {
-        let rounded = num.round(0) / 10.0_f64.powf((a - significant_figure) as f64);
-        let fraction = rounded / 10.0_f64.powf((significant_figure - 1) as f64);
+        let rounded = (num / pow10(a - significant_figure)).round(0);
+        let fraction = rounded / pow10(significant_figure - 1);
         format!("{} * 10^{{{}}}", fraction, a - 1).to_string()
     }
 }
@@ -89,7 +88,8 @@ fn get_num_of_digit(num: &BigDecimal) -> (u32, u32) {
     let mut state = State::Seisu;
     let mut a = 0;
     let mut b = 0;
-    for c in num.to_string().chars() {
+    // to_stringは桁が多いと1E-45のように指数で表すので, 指数を使わない表記で数える
+    for c in num.to_plain_string().chars() {
         if c != '.' {
             match state {
                 State::Seisu => a += 1,
@@ -106,7 +106,7 @@ fn get_num_of_zero(num: &BigDecimal) -> u32 {
     // num: 0.00012 -> 3
     assert!(*num < BigDecimal::from(1));
     let mut num_of_zero = 0;
-    for c in num.to_string().replace("0.", "").chars() {
+    for c in num.to_plain_string().replace("0.", "").chars() {
         if c == '0' {
             num_of_zero += 1;
         } else {
@@ -118,8 +118,12 @@ fn get_num_of_zero(num: &BigDecimal) -> u32 {
 
 fn round_n(num: &BigDecimal, n: u32) -> BigDecimal {
     // num: 123.4567, n: 2 -> 123.45
-    (num * pow(BigDecimal::from(10), BigDecimal::from(n)).unwrap()).round(0)
-        / pow(BigDecimal::from(10), BigDecimal::from(n)).unwrap()
+    (num * pow10(n)).round(0) / pow10(n)
+}
+
+/// 10^n. f64で求めると10^{308}を超えたとき溢れる
+fn pow10(n: u32) -> BigDecimal {
+    BigDecimal::from(BigInt::from(10).pow(n))
 }
 
 #[cfg(test)]
@@ -127,6 +131,7 @@ mod test {
     use super::num_formatter;
     use bigdecimal::{BigDecimal, FromPrimitive};
     use std::io::Write;
+    use std::str::FromStr;
     use text_colorizer::*;
 
     struct TestCase {
@@ -177,6 +182,7 @@ mod test {
         assert_eq!(test_success, test_cases.len());
     }
 
+    #[allow(clippy::vec_init_then_push)]
     fn get_testcases() -> Vec<TestCase> {
         let mut test_cases: Vec<TestCase> = Vec::new();
         test_cases.push(TestCase {
@@ -184,6 +190,18 @@ mod test {
             sf: 4,
             result: "12.35".to_string(),
         });
+        // 1E-45のように指数で表される数
+        test_cases.push(TestCase {
+            num: BigDecimal::from_str("2.08848758376255E-45").unwrap(),
+            sf: 4,
+            result: "2.088 * 10^{-45}".to_string(),
+        });
+        // f64で表せない大きさの数
+        test_cases.push(TestCase {
+            num: BigDecimal::from_str("3.94E+372").unwrap(),
+            sf: 3,
+            result: "3.94 * 10^{372}".to_string(),
+        });
         test_cases
     }
 }
@@ -0,0 +1,426 @@
+use bigdecimal::{BigDecimal, One, Signed, ToPrimitive, Zero};
+use std::collections::HashMap;
+
+use crate::ast::{Ast, BinaryOp, Node, NodeId, NodeKind, UnaryOp};
+use crate::error::*;
+use crate::Value;
+
+/// 累乗を畳み込む指数の絶対値の上限
+const MAX_FOLD_EXP: i64 = 64;
+
+/// ASTを簡約した新しいAstとその根を返す
+/// 値が厳密に求まる定数の部分式を畳み込み, *1, +0を除き, 同類項をまとめ, 符号を外に出す
+/// 簡約しても式の値は変わらない. 評価でエラーになる部分式は残すので, エラーの個所も変わらない
+///
+/// * `vars` - 評価に使う変数. Someなら定義されていない変数を含む項は消さない. Noneなら全ての変数を定義済みとして扱う
+///
+/// ex)
+/// ```text
+/// x \cdot 1 + 0 + 2x  ->  3x
+/// ```
+pub fn simplify(
+    ast: &Ast,
+    root: NodeId,
+    vars: Option<&HashMap<String, Value>>,
+) -> Result<(Ast, NodeId), MyError> {
+    run(ast, root, vars, false)
+}
+
+/// 表示するためにASTを簡約した新しいAstとその根を返す
+/// simplifyと違い, 係数が0の項は定義されていない変数を含んでいても消す. 0で割る等の項は残す
+///
+/// ex)
+/// ```text
+/// x - x + \frac{a}{a}  ->  1 (aが0でない値に定義済みのとき)
+/// ```
+pub fn simplify_to_show(
+    ast: &Ast,
+    root: NodeId,
+    vars: &HashMap<String, Value>,
+) -> Result<(Ast, NodeId), MyError> {
+    run(ast, root, Some(vars), true)
+}
+
+fn run(
+    ast: &Ast,
+    root: NodeId,
+    vars: Option<&HashMap<String, Value>>,
+    drop_zero_terms: bool,
+) -> Result<(Ast, NodeId), MyError> {
+    let mut opt = Optimizer {
+        src: ast,
+        out: Ast::new(),
+        vars,
+        drop_zero_terms,
+    };
+    let root = opt.simplify(root)?;
+    Ok((opt.out, root))
+}
+
+struct Optimizer<'a> {
+    src: &'a Ast,
+    out: Ast,
+    vars: Option<&'a HashMap<String, Value>>,
+    /// trueなら係数が0の項を, 定義されていない変数を含んでいても消す
+    drop_zero_terms: bool,
+}
+
+impl Optimizer<'_> {
+    /// srcのidのnodeを子から順に簡約し, outに追加する
+    fn simplify(&mut self, id: NodeId) -> Result<NodeId, MyError> {
+        let node = &self.src[id];
+        match &node.kind {
+            NodeKind::Num(_) | NodeKind::Var(_) => Ok(self.out.push(node.clone())),
+            NodeKind::Unary(op, arg) => {
+                let arg = self.simplify(*arg)?;
+                Ok(self.unary(*op, node, arg))
+            }
+            NodeKind::Binary(op, lhs, rhs) => {
+                let lhs = self.simplify(*lhs)?;
+                let rhs = self.simplify(*rhs)?;
+                Ok(self.binary(*op, node, lhs, rhs))
+            }
+            NodeKind::Call(func, args) => {
+                let mut new_args = Vec::with_capacity(args.len());
+                for arg in self.src.args(*args) {
+                    new_args.push(self.simplify(*arg)?);
+                }
+                let args = self.out.push_args(&new_args);
+                Ok(self.push(NodeKind::Call(*func, args), node))
+            }
+        }
+    }
+
+    /// orgの位置にnodeを追加する
+    fn push(&mut self, kind: NodeKind, org: &Node) -> NodeId {
+        self.out.push(Node {
+            kind,
+            span: org.span,
+            op_span: org.op_span,
+        })
+    }
+
+    fn num(&self, id: NodeId) -> Option<&BigDecimal> {
+        match &self.out[id].kind {
+            NodeKind::Num(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn unary(&mut self, op: UnaryOp, org: &Node, arg: NodeId) -> NodeId {
+        if let Some(n) = self.num(arg) {
+            let folded = match op {
+                UnaryOp::Neg => Some(-n),
+                UnaryOp::Abs => Some(n.abs()),
+                UnaryOp::Sqrt if !n.is_negative() => n.sqrt().filter(|r| r * r == *n),
+                _ => None,
+            };
+            if let Some(n) = folded {
+                return self.push(NodeKind::Num(n), org);
+            }
+        }
+        match (op, &self.out[arg].kind) {
+            // --a -> a
+            (UnaryOp::Neg, NodeKind::Unary(UnaryOp::Neg, a)) => *a,
+            // -(a - b) -> b - a
+            (UnaryOp::Neg, NodeKind::Binary(BinaryOp::Sub, a, b)) => {
+                let (a, b) = (*a, *b);
+                self.push(NodeKind::Binary(BinaryOp::Sub, b, a), org)
+            }
+            // |-a| -> |a|
+            (UnaryOp::Abs, NodeKind::Unary(UnaryOp::Neg, a)) => {
+                let a = *a;
+                self.push(NodeKind::Unary(UnaryOp::Abs, a), org)
+            }
+            _ => self.push(NodeKind::Unary(op, arg), org),
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, org: &Node, lhs: NodeId, rhs: NodeId) -> NodeId {
+        match op {
+            BinaryOp::Add | BinaryOp::Sub => {
+                let mut terms = Vec::new();
+                let mut constant = BigDecimal::zero();
+                self.collect_terms(lhs, true, &mut terms, &mut constant);
+                self.collect_terms(rhs, op == BinaryOp::Add, &mut terms, &mut constant);
+                self.sum(org, terms, constant)
+            }
+            BinaryOp::Mul => {
+                let mut factors = Vec::new();
+                let mut coef = BigDecimal::one();
+                self.collect_factors(lhs, &mut factors, &mut coef);
+                self.collect_factors(rhs, &mut factors, &mut coef);
+                self.product(org, factors, coef)
+            }
+            BinaryOp::Div => self.div(org, lhs, rhs),
+            BinaryOp::Pow => self.pow(org, lhs, rhs),
+        }
+    }
+
+    /// 和を項に分ける. 項は係数と数以外の部分の組. 数の項はconstantに足す
+    fn collect_terms(
+        &self,
+        id: NodeId,
+        positive: bool,
+        terms: &mut Vec<(BigDecimal, NodeId)>,
+        constant: &mut BigDecimal,
+    ) {
+        let sign = |n: BigDecimal| if positive { n } else { -n };
+        match &self.out[id].kind {
+            NodeKind::Num(n) => *constant += sign(n.clone()),
+            NodeKind::Binary(BinaryOp::Add, lhs, rhs) => {
+                self.collect_terms(*lhs, positive, terms, constant);
+                self.collect_terms(*rhs, positive, terms, constant);
+            }
+            NodeKind::Binary(BinaryOp::Sub, lhs, rhs) => {
+                self.collect_terms(*lhs, positive, terms, constant);
+                self.collect_terms(*rhs, !positive, terms, constant);
+            }
+            NodeKind::Unary(UnaryOp::Neg, arg) => {
+                self.collect_terms(*arg, !positive, terms, constant)
+            }
+            NodeKind::Binary(BinaryOp::Mul, lhs, rhs) if self.num(*lhs).is_some() => {
+                let coef = sign(self.num(*lhs).unwrap().clone());
+                self.add_term(terms, coef, *rhs);
+            }
+            _ => self.add_term(terms, sign(BigDecimal::one()), id),
+        }
+    }
+
+    /// 同類項があれば係数を足し, なければ項を追加する
+    fn add_term(&self, terms: &mut Vec<(BigDecimal, NodeId)>, coef: BigDecimal, id: NodeId) {
+        match terms.iter_mut().find(|(_, t)| self.same(*t, id)) {
+            Some((c, _)) => *c += coef,
+            None => terms.push((coef, id)),
+        }
+    }
+
+    /// 項を和に戻す. 数の項は最後に置く
+    fn sum(
+        &mut self,
+        org: &Node,
+        terms: Vec<(BigDecimal, NodeId)>,
+        constant: BigDecimal,
+    ) -> NodeId {
+        let mut acc: Option<NodeId> = None;
+        for (coef, term) in terms {
+            // 0x は xが評価できるときだけ消す
+            if coef.is_zero() && self.can_drop(term) {
+                continue;
+            }
+            acc = Some(match acc {
+                None => self.scale(org, coef, term),
+                Some(acc) => {
+                    let op = if coef.is_negative() {
+                        BinaryOp::Sub
+                    } else {
+                        BinaryOp::Add
+                    };
+                    let abs = self.scale(org, coef.abs(), term);
+                    self.push(NodeKind::Binary(op, acc, abs), org)
+                }
+            });
+        }
+        match acc {
+            None => self.push(NodeKind::Num(constant), org),
+            Some(acc) if constant.is_zero() => acc,
+            Some(acc) => {
+                let (op, abs) = if constant.is_negative() {
+                    (BinaryOp::Sub, -constant)
+                } else {
+                    (BinaryOp::Add, constant)
+                };
+                let num = self.push(NodeKind::Num(abs), org);
+                self.push(NodeKind::Binary(op, acc, num), org)
+            }
+        }
+    }
+
+    /// coef * id. 係数-1は単項マイナスにする
+    fn scale(&mut self, org: &Node, coef: BigDecimal, id: NodeId) -> NodeId {
+        if coef.is_one() {
+            id
+        } else if (-&coef).is_one() {
+            self.push(NodeKind::Unary(UnaryOp::Neg, id), org)
+        } else {
+            let num = self.push(NodeKind::Num(coef), org);
+            self.push(NodeKind::Binary(BinaryOp::Mul, num, id), org)
+        }
+    }
+
+    /// 積を因子に分ける. 数の因子と符号はcoefに掛ける
+    fn collect_factors(&self, id: NodeId, factors: &mut Vec<NodeId>, coef: &mut BigDecimal) {
+        match &self.out[id].kind {
+            NodeKind::Num(n) => *coef *= n,
+            NodeKind::Binary(BinaryOp::Mul, lhs, rhs) => {
+                self.collect_factors(*lhs, factors, coef);
+                self.collect_factors(*rhs, factors, coef);
+            }
+            NodeKind::Unary(UnaryOp::Neg, arg) => {
+                *coef = -coef.clone();
+                self.collect_factors(*arg, factors, coef);
+            }
+            _ => factors.push(id),
+        }
+    }
+
+    /// 因子を積に戻す. 係数は先頭に置く
+    fn product(&mut self, org: &Node, factors: Vec<NodeId>, coef: BigDecimal) -> NodeId {
+        if coef.is_zero() && factors.iter().all(|f| self.can_drop(*f)) {
+            return self.push(NodeKind::Num(coef), org);
+        }
+        let mut acc: Option<NodeId> = None;
+        for f in factors {
+            acc = Some(match acc {
+                None => f,
+                Some(acc) => self.push(NodeKind::Binary(BinaryOp::Mul, acc, f), org),
+            });
+        }
+        let Some(acc) = acc else {
+            return self.push(NodeKind::Num(coef), org);
+        };
+        self.scale(org, coef, acc)
+    }
+
+    fn div(&mut self, org: &Node, lhs: NodeId, rhs: NodeId) -> NodeId {
+        if let (Some(a), Some(b)) = (self.num(lhs), self.num(rhs))
+            && !b.is_zero()
+        {
+            // 割り切れるときだけ畳み込む
+            let q = a / b;
+            if &q * b == *a {
+                return self.push(NodeKind::Num(q), org);
+            }
+        }
+        if self.num(rhs).is_some_and(|b| b.is_one()) {
+            return lhs;
+        }
+        if self.is_nonzero(rhs) && self.is_total(rhs) {
+            // a/a -> 1
+            if self.same(lhs, rhs) && self.is_total(lhs) {
+                return self.push(NodeKind::Num(BigDecimal::one()), org);
+            }
+            // 0/a -> 0
+            if self.num(lhs).is_some_and(|a| a.is_zero()) {
+                return lhs;
+            }
+        }
+        // -a/b, a/-b -> -(a/b)
+        let (lhs, lneg) = self.strip_neg(lhs);
+        let (rhs, rneg) = self.strip_neg(rhs);
+        let div = self.push(NodeKind::Binary(BinaryOp::Div, lhs, rhs), org);
+        if lneg != rneg {
+            self.push(NodeKind::Unary(UnaryOp::Neg, div), org)
+        } else {
+            div
+        }
+    }
+
+    /// 単項マイナスを外したnodeと, 外したかを返す
+    fn strip_neg(&self, id: NodeId) -> (NodeId, bool) {
+        match self.out[id].kind {
+            NodeKind::Unary(UnaryOp::Neg, arg) => (arg, true),
+            _ => (id, false),
+        }
+    }
+
+    fn pow(&mut self, org: &Node, base: NodeId, exp: NodeId) -> NodeId {
+        if let (Some(a), Some(n)) = (self.num(base), self.num(exp))
+            && let Some(folded) = exact_pow(a, n)
+        {
+            return self.push(NodeKind::Num(folded), org);
+        }
+        if let Some(n) = self.num(exp) {
+            // a^1 -> a
+            if n.is_one() {
+                return base;
+            }
+            // a^0 -> 1
+            if n.is_zero() && self.is_total(base) {
+                return self.push(NodeKind::Num(BigDecimal::one()), org);
+            }
+        }
+        // 1^a -> 1
+        if self.num(base).is_some_and(|a| a.is_one()) && self.is_total(exp) {
+            return base;
+        }
+        self.push(NodeKind::Binary(BinaryOp::Pow, base, exp), org)
+    }
+
+    /// 2つのnodeが同じ式を表すか. 関数は副作用があるかもしれないので同じとみなさない
+    fn same(&self, a: NodeId, b: NodeId) -> bool {
+        match (&self.out[a].kind, &self.out[b].kind) {
+            (NodeKind::Num(x), NodeKind::Num(y)) => x == y,
+            (NodeKind::Var(x), NodeKind::Var(y)) => x == y,
+            (NodeKind::Unary(op1, x), NodeKind::Unary(op2, y)) => op1 == op2 && self.same(*x, *y),
+            (NodeKind::Binary(op1, l1, r1), NodeKind::Binary(op2, l2, r2)) => {
+                op1 == op2 && self.same(*l1, *l2) && self.same(*r1, *r2)
+            }
+            _ => false,
+        }
+    }
+
+    /// 評価してもエラーにならない式か
+    fn is_total(&self, id: NodeId) -> bool {
+        self.is_total_with(id, false)
+    }
+
+    /// 係数が0の項として消してよい式か
+    fn can_drop(&self, id: NodeId) -> bool {
+        self.is_total_with(id, self.drop_zero_terms)
+    }
+
+    /// free_varsなら定義されていない変数も, 数が入れば評価できるとみなす
+    fn is_total_with(&self, id: NodeId, free_vars: bool) -> bool {
+        match &self.out[id].kind {
+            NodeKind::Num(_) => true,
+            NodeKind::Var(v) => self.vars.is_none_or(|vars| match vars.get(v) {
+                Some(v) => v.num().is_some(),
+                None => free_vars,
+            }),
+            NodeKind::Unary(UnaryOp::Neg | UnaryOp::Abs, arg) => {
+                self.is_total_with(*arg, free_vars)
+            }
+            NodeKind::Binary(BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul, lhs, rhs) => {
+                self.is_total_with(*lhs, free_vars) && self.is_total_with(*rhs, free_vars)
+            }
+            _ => false,
+        }
+    }
+
+    /// 値が0でないと分かっている式か
+    fn is_nonzero(&self, id: NodeId) -> bool {
+        match &self.out[id].kind {
+            NodeKind::Num(n) => !n.is_zero(),
+            NodeKind::Var(v) => self
+                .vars
+                .and_then(|vars| vars.get(v)?.num())
+                .is_some_and(|n| !n.is_zero()),
+            NodeKind::Unary(UnaryOp::Neg | UnaryOp::Abs, arg) => self.is_nonzero(*arg),
+            NodeKind::Binary(BinaryOp::Mul, lhs, rhs) => {
+                self.is_nonzero(*lhs) && self.is_nonzero(*rhs)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// 指数が整数のときの累乗を厳密に求める. 求まらなければNone
+fn exact_pow(base: &BigDecimal, exp: &BigDecimal) -> Option<BigDecimal> {
+    if !exp.is_integer() {
+        return None;
+    }
+    let n = exp.to_i64()?;
+    if n.abs() > MAX_FOLD_EXP || (base.is_zero() && n < 0) {
+        return None;
+    }
+    let mut p = BigDecimal::one();
+    for _ in 0..n.abs() {
+        p *= base;
+    }
+    if n >= 0 {
+        return Some(p);
+    }
+    let q = BigDecimal::one() / &p;
+    (&q * &p).is_one().then_some(q)
+}
@@ -1,142 +1,27 @@
-use bigdecimal::BigDecimal;
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 
-use crate::ast_printer::{show_ast, show_ast_in_s_expr_rec};
+use crate::ast::{Ast, AstMark, BinaryOp, Node, NodeId, NodeKind, UnaryOp};
 use crate::error::*;
+use crate::registry::{
+    infix_op, postfix_op, prefix_op, registry_reader, ArgStyle, Builtin, Evaluator,
+    PREC_IMPLICIT_MUL,
+};
+use crate::special_functions::SpecialFn;
+use crate::stats::ListFn;
 use crate::str2num::*;
-use crate::tokenizer::tokenize;
+use crate::tokenizer::{tokenize, tokenize_recovering};
 use crate::tokenizer::{NumstrOrVar, Token, TokenKind};
 use crate::tsc_cmd;
-use crate::CONSTS;
+use crate::{Value, CONSTS};
 
 use text_colorizer::*;
+use unicode_width::UnicodeWidthStr;
 
 use crate::debugln;
 
-#[derive(Clone, Copy)]
-pub enum NodeKind {
-    // 1引数
-    Sin,
-    Cos,
-    Tan,
-    Csc,
-    Sec,
-    Cot,
-    AcSin,
-    AcCos,
-    AcTan,
-    Sqrt,
-    Log,
-    Ln,
-    Abs,
-    Exp,
-    // 2引数
-    Add,
-    Sub,
-    Mul,
-    Div,
-    // 前置1引数
-    Neg,
-    // 後置1引数
-    Pow,
-    // 数字
-    Num,
-    // 変数
-    Var,
-}
-
-impl fmt::Display for NodeKind {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            NodeKind::Sin => write!(f, "Sin"),
-            NodeKind::Cos => write!(f, "Cos"),
-            NodeKind::Tan => write!(f, "Tan"),
-            NodeKind::Csc => write!(f, "Csc"),
-            NodeKind::Sec => write!(f, "Sec"),
-            NodeKind::Cot => write!(f, "Cot"),
-            NodeKind::AcSin => write!(f, "AcSin"),
-            NodeKind::AcCos => write!(f, "AcCos"),
-            NodeKind::AcTan => write!(f, "AcTan"),
-            NodeKind::Sqrt => write!(f, "Sqrt"),
-            NodeKind::Log => write!(f, "Log"),
-            NodeKind::Ln => write!(f, "Ln"),
-            NodeKind::Abs => write!(f, "Abs"),
-            NodeKind::Exp => write!(f, "Exp"),
-            NodeKind::Add => write!(f, "Add"),
-            NodeKind::Sub => write!(f, "Sub"),
-            NodeKind::Mul => write!(f, "Mul"),
-            NodeKind::Div => write!(f, "Div"),
-            NodeKind::Neg => write!(f, "Neg"),
-            NodeKind::Pow => write!(f, "Pow"),
-            NodeKind::Num => write!(f, "Num"),
-            NodeKind::Var => write!(f, "Var"),
-        }
-    }
-}
-
-impl NodeKind {
-    pub fn to_op_str(self) -> String {
-        match self {
-            NodeKind::Sin => "Sin".to_string(),
-            NodeKind::Cos => "Cos".to_string(),
-            NodeKind::Tan => "Tan".to_string(),
-            NodeKind::Csc => "Csc".to_string(),
-            NodeKind::Sec => "Sec".to_string(),
-            NodeKind::Cot => "Cot".to_string(),
-            NodeKind::AcSin => "AcSin".to_string(),
-            NodeKind::AcCos => "AcCos".to_string(),
-            NodeKind::AcTan => "AcTan".to_string(),
-            NodeKind::Sqrt => "Sqrt".to_string(),
-            NodeKind::Log => "Log".to_string(),
-            NodeKind::Ln => "Ln".to_string(),
-            NodeKind::Abs => "Abs".to_string(),
-            NodeKind::Exp => "exp".to_string(),
-            NodeKind::Add => "+".to_string(),
-            NodeKind::Sub => "-".to_string(),
-            NodeKind::Mul => "*".to_string(),
-            NodeKind::Div => "/".to_string(),
-            NodeKind::Neg => "-".to_string(),
-            NodeKind::Pow => "Pow".to_string(),
-            NodeKind::Num => "Num".to_string(),
-            NodeKind::Var => "Var".to_string(),
-        }
-    }
-
-    pub fn to_lisp_op_str(self) -> String {
-        match self {
-            NodeKind::Sin => "sin".to_string(),
-            NodeKind::Cos => "cos".to_string(),
-            NodeKind::Tan => "tan".to_string(),
-            NodeKind::Csc => "csc".to_string(),
-            NodeKind::Sec => "sec".to_string(),
-            NodeKind::Cot => "cot".to_string(),
-            NodeKind::AcSin => "asin".to_string(),
-            NodeKind::AcCos => "acos".to_string(),
-            NodeKind::AcTan => "atan".to_string(),
-            NodeKind::Sqrt => "sqrt".to_string(),
-            NodeKind::Log => "log".to_string(),
-            NodeKind::Ln => "log".to_string(),
-            NodeKind::Abs => "abs".to_string(),
-            NodeKind::Exp => "exp".to_string(),
-            NodeKind::Add => "+".to_string(),
-            NodeKind::Sub => "-".to_string(),
-            NodeKind::Mul => "*".to_string(),
-            NodeKind::Div => "/".to_string(),
-            NodeKind::Neg => "-".to_string(),
-            NodeKind::Pow => "expt".to_string(),
-            NodeKind::Num => "Num".to_string(),
-            NodeKind::Var => "Var".to_string(),
-        }
-    }
-}
-
-#[derive(Clone, Debug)]
-pub enum NumOrVar {
-    Num(BigDecimal),
-    Var(String),
-}
-
 pub enum TscCmd {
     Hex,
     Dec,
@@ -154,16 +39,106 @@ impl fmt::Display for TscCmd {
     }
 }
 
+impl TscCmd {
+    /// 出力形式を切り替えるTSCコマンドならそのTscCmdを返す
+    pub fn from_name(name: &str) -> Option<TscCmd> {
+        match name {
+            ":hex" => Some(TscCmd::Hex),
+            ":dec" => Some(TscCmd::Dec),
+            ":bin" => Some(TscCmd::Bin),
+            ":oct" => Some(TscCmd::Oct),
+            _ => None,
+        }
+    }
+}
+
 pub enum NodeOrCmd {
-    Node(Box<Node>),
+    Node(NodeId),
     TscCmd(TscCmd),
 }
 
-pub struct Node {
-    pub node_kind: NodeKind,
-    pub right_node: Option<Box<Node>>,
-    pub left_node: Option<Box<Node>>,
-    pub val: Option<NumOrVar>,
+/// 数式中のバイト位置の範囲 [start, end)
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// selfとotherの両方を含む最小のspanを返す
+    pub fn merge(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+/// 数式formのspanの個所を示す文字列を返す
+/// ex)
+/// ```text
+/// \log 0
+/// ^~~~
+/// ```
+/// * `form` - 数式
+/// * `span` - エラーの原因となった個所
+pub fn format_err_span(form: &str, span: Span) -> String {
+    // spanはbyte単位なので, 表示幅に直して揃える
+    let start = form.get(..span.start).map_or(span.start, |s| s.width());
+    let len = form
+        .get(span.start..span.end)
+        .map_or(span.end.saturating_sub(span.start), |s| s.width());
+    let mut pad: String = String::with_capacity(start);
+    for _i in 0..start {
+        pad += " ";
+    }
+    let err_indicator: String = format!("{}{}", pad, "^".red());
+    let mut nami: String = String::new();
+    for _i in 0..len.saturating_sub(1) {
+        nami += &format!("{}", "~".red());
+    }
+    format!("{}\n{}{}", form, err_indicator, nami)
+}
+
+/// varsに定数をプッシュする
+pub(crate) fn insert_consts(vars: &mut HashMap<String, Value>) -> Result<(), MyError> {
+    match CONSTS.read() {
+        Ok(consts) => {
+            for (name, value) in consts.iter() {
+                vars.insert(name.to_string(), value.clone());
+            }
+            Ok(())
+        }
+        Err(e) => Err(MyError::ConstsReadErr(e.to_string())),
+    }
+}
+
+fn is_closing_brace(br: &str) -> bool {
+    matches!(br, ")" | "]" | "}")
+}
+
+fn closing_brace(br: &str) -> &'static str {
+    match br {
+        "(" => ")",
+        "[" => "]",
+        _ => "}",
+    }
+}
+
+/// 括弧や関数のネストの上限
+const MAX_NEST_DEPTH: usize = 256;
+/// ASTの深さの上限. 評価や表示は再帰するので, 1 + 1 + ...のような長い式もここで止める
+const MAX_AST_DEPTH: usize = 400;
+
+/// ASTが深すぎれば, 上限を超えたnodeの位置を示すエラーにする
+pub(crate) fn check_ast_depth(ast: &Ast, form: &str) -> Result<(), MyError> {
+    match ast.too_deep(MAX_AST_DEPTH) {
+        Some(id) => Err(MyError::TooDeepNesting(
+            MAX_AST_DEPTH,
+            format_err_span(form, ast[id].op_span.unwrap_or(ast[id].span)),
+        )),
+        None => Ok(()),
+    }
 }
 
 /*
@@ -176,24 +151,58 @@ struct NodeInfo {
 }
 */
 
+/// エラーの表示順. 小さいものほど根本的な原因である可能性が高い
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ErrRank {
+    UnbalancedBrace,
+    Lexical,
+    Syntax,
+}
+
 // pub struct Parser<'a> {
 pub struct Parser {
     form: String,
     tokens: Vec<Token>,
-    token_loc: Vec<usize>,
+    token_loc: Vec<Span>,
     token_idx: usize,
-    ctx_stack: Vec<usize>,
+    ctx_stack: Vec<(usize, usize, AstMark)>,
+    ast: Ast,
+    depth: usize,
+    recovery: bool,
+    errors: Vec<(ErrRank, usize, MyError)>,
+    // 括弧の対応のエラーを報告した括弧の位置
+    brace_err_locs: Vec<usize>,
+    // 関数の引数で要素に展開するリスト変数
+    lists: HashMap<String, Vec<BigDecimal>>,
 }
 
 impl Parser {
-    pub fn new(form: String) -> Result<Parser, MyError> {
-        let (tokens, token_loc) = tokenize(&form)?;
+    /// * `form` - 数式
+    /// * `recovery` - trueならエラーから回復しながら解析し, 見つかったエラーをbuild_astでまとめて返す
+    pub fn new(form: String, recovery: bool) -> Result<Parser, MyError> {
+        let (tokens, token_loc, errors) = if recovery {
+            let (tokens, token_loc, lex_errors) = tokenize_recovering(&form);
+            let errors = lex_errors
+                .into_iter()
+                .map(|(loc, e)| (ErrRank::Lexical, loc, e))
+                .collect();
+            (tokens, token_loc, errors)
+        } else {
+            let (tokens, token_loc) = tokenize(&form)?;
+            (tokens, token_loc, Vec::new())
+        };
         Ok(Parser {
             form,
             tokens,
             token_loc,
             token_idx: 0,
             ctx_stack: Vec::new(),
+            ast: Ast::new(),
+            depth: 0,
+            recovery,
+            errors,
+            brace_err_locs: Vec::new(),
+            lists: HashMap::new(),
         })
     }
 
@@ -201,13 +210,52 @@ impl Parser {
         &self.tokens[idx]
     }
 
+    /// idx番目のtokenから区切り文字かTSCコマンドの手前までの数式と, そのtoken数を返す
+    /// 引数に数式をとるTSCコマンドが使う
+    pub fn cmd_arg_form(&self, idx: usize) -> Result<(String, usize), MyError> {
+        let len = self.tokens[idx..]
+            .iter()
+            .take_while(|t| {
+                !matches!(
+                    t.token_kind,
+                    TokenKind::TkSeparaotr | TokenKind::TkTscCommand | TokenKind::TkEOT
+                )
+            })
+            .count();
+        Ok((self.tokens_form(idx, len)?, len))
+    }
+
+    /// idx番目からlen個のtokenの数式を返す
+    pub fn tokens_form(&self, idx: usize, len: usize) -> Result<String, MyError> {
+        if len == 0 {
+            return Err(MyError::NoToken);
+        }
+        let span = self.token_span(idx).merge(self.token_span(idx + len - 1));
+        Ok(self.form[span.start..span.end].to_string())
+    }
+
+    /// idx番目のtokenの直後からend番目のtokenの手前までの入力をそのまま返す
+    /// ファイル名のように数式として字句解析できない引数を読むのに使う
+    pub fn raw_form(&self, idx: usize, end: usize) -> String {
+        let start = self.token_loc[idx].end;
+        let end = self.token_loc[end].start.max(start);
+        self.form[start..end].trim().to_string()
+    }
+
+    /// 1行分の数式を構文解析する
+    /// nodeを格納したAstと, 式とTSCコマンドの列を返す
     pub fn build_ast(
         &mut self,
-        vars: &mut HashMap<String, BigDecimal>,
-    ) -> Result<Vec<NodeOrCmd>, MyError> {
+        vars: &mut HashMap<String, Value>,
+    ) -> Result<(Ast, Vec<NodeOrCmd>), MyError> {
         // から varsを構築
         let mut to_delete_el = Vec::<usize>::new();
+        // TSCコマンドが消費したtokenは変数定義として読まない
+        let mut skip_to = 0;
         for i in 0..self.tokens.len() {
+            if i < skip_to {
+                continue;
+            }
             if self.tokens[i].token == ";" {
                 match self.tokens[i + 1].token_kind {
                     TokenKind::TkVariable => {
@@ -226,71 +274,52 @@ impl Parser {
                         continue;
                     }
                 }
-                match self.tokens[i + 3].token_kind {
-                    TokenKind::TkNum(f) => {
-                        match bigdecimal_from_str(f, &self.tokens[i + 3].token) {
-                            Ok(num) => {
-                                vars.insert(self.tokens[i + 1].token.clone(), num);
-                            }
-                            Err(e) => return Err(e),
+                match self.var_def_value(i + 3) {
+                    Ok((num, consumed)) => {
+                        vars.insert(self.tokens[i + 1].token.clone(), num.into());
+                        for n in 0..consumed {
+                            to_delete_el.push(i + 3 + n);
                         }
                     }
-                    TokenKind::TkOperator => {
-                        if self.tokens[i + 3].token == "-" {
-                            match self.tokens[i + 4].token_kind {
-                                TokenKind::TkNum(f) => {
-                                    match bigdecimal_from_str(f, &self.tokens[i + 4].token) {
-                                        Ok(num) => {
-                                            vars.insert(self.tokens[i + 1].token.clone(), -num);
-                                            to_delete_el.push(i + 4);
-                                        }
-                                        Err(e) => return Err(e),
-                                    }
-                                }
-                                tk => {
-                                    return Err(MyError::NotTkNumber(
-                                        tk.to_string(),
-                                        self.format_err_loc_idx(i + 4),
-                                    ))
-                                }
-                            }
-                        } else {
-                            return Err(MyError::NotTkNumber(
-                                TokenKind::TkOperator.to_string(),
-                                self.format_err_loc_idx(i + 3),
-                            ));
-                        }
-                    }
-                    tk => {
-                        return Err(MyError::NotTkNumber(
-                            tk.to_string(),
-                            self.format_err_loc_idx(i + 3),
-                        ))
+                    Err(e) => {
+                        self.record_err_at(ErrRank::Syntax, self.token_loc[i + 3].start, e)?;
+                        to_delete_el.push(i + 3);
                     }
                 }
-                to_delete_el.push(i + 3);
             } else if let TokenKind::TkTscCommand = self.tokens[i].token_kind {
                 let consumed = tsc_cmd::process_tsccommand(self, i, vars)?;
+                // コマンドが読んだ範囲は入力をそのまま読むので, 字句解析のエラーを取り消す
+                let start = self.token_loc[i].start;
+                let end = self.token_loc[(i + consumed).min(self.tokens.len() - 1)].start;
+                self.errors.retain(|(rank, loc, _)| {
+                    *rank != ErrRank::Lexical || !(start..end).contains(loc)
+                });
                 for n in 0..consumed {
                     to_delete_el.push(i + n)
                 }
+                skip_to = i + consumed;
             }
         }
         to_delete_el.sort_by(|a, b| b.cmp(a));
+        to_delete_el.dedup();
         for i in to_delete_el.into_iter() {
-            self.del_token(i);
-        }
-        // varsに定数をプッシュする
-        match CONSTS.read() {
-            Ok(consts) => {
-                for (name, value) in consts.iter() {
-                    vars.insert(name.to_string(), value.clone());
-                }
+            // EOTは消さない
+            if i < self.tokens.len() - 1 {
+                self.del_token(i);
             }
-            Err(e) => return Err(MyError::ConstsReadErr(e.to_string())),
         }
+        insert_consts(vars)?;
+        self.lists = vars
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Value::List(values) => Some((name.clone(), values.clone())),
+                Value::Num(_) => None,
+            })
+            .collect();
 
+        self.check_braces()?;
         if self.is_eot() {
+            self.take_errors()?;
             return Err(MyError::NoToken);
         }
         let ast_or_cmd_vec = self.expr_vec()?;
@@ -300,16 +329,28 @@ impl Parser {
                 self.format_err_loc(),
             ));
         }
-        for ast_or_cmd in &ast_or_cmd_vec {
-            match ast_or_cmd {
-                NodeOrCmd::Node(ast) => {
-                    show_ast(ast, vars)?;
-                    show_ast_in_s_expr_rec(ast, vars)?;
-                }
-                NodeOrCmd::TscCmd(_) => {}
-            }
+        self.take_errors()?;
+        check_ast_depth(&self.ast, &self.form)?;
+        Ok((std::mem::take(&mut self.ast), ast_or_cmd_vec))
+    }
+
+    /// 変数定義, TSCコマンドを含まない1つの式を構文解析する
+    /// 設定ファイルで定義された関数の本体を読むのに使う
+    pub fn build_expr(&mut self) -> Result<(Ast, NodeId), MyError> {
+        self.check_braces()?;
+        if self.is_eot() {
+            return Err(MyError::NoToken);
+        }
+        let node = self.expr()?;
+        if !self.is_eot() {
+            return Err(MyError::UnprocessedToekn(
+                self.now_token().to_string(),
+                self.format_err_loc(),
+            ));
         }
-        Ok(ast_or_cmd_vec)
+        self.take_errors()?;
+        check_ast_depth(&self.ast, &self.form)?;
+        Ok((std::mem::take(&mut self.ast), node))
     }
 
     fn is_eot(&self) -> bool {
@@ -328,47 +369,204 @@ impl Parser {
         self.token_loc.remove(idx);
     }
 
+    /// 変数定義"; {var} = {value}"のvalueを読む
+    /// 値と{value}のtoken数を返す
+    ///
+    /// * `idx` - {value}の先頭のtokenのindex
+    fn var_def_value(&self, idx: usize) -> Result<(BigDecimal, usize), MyError> {
+        match self.tokens[idx].token_kind {
+            TokenKind::TkNum(f) => Ok((bigdecimal_from_str(f, &self.tokens[idx].token)?, 1)),
+            TokenKind::TkOperator if self.tokens[idx].token == "-" => {
+                match self.tokens[idx + 1].token_kind {
+                    TokenKind::TkNum(f) => {
+                        Ok((-bigdecimal_from_str(f, &self.tokens[idx + 1].token)?, 2))
+                    }
+                    tk => Err(MyError::NotTkNumber(
+                        tk.to_string(),
+                        self.format_err_loc_idx(idx + 1),
+                    )),
+                }
+            }
+            tk => Err(MyError::NotTkNumber(
+                tk.to_string(),
+                self.format_err_loc_idx(idx),
+            )),
+        }
+    }
+
+    /// エラーを記録する
+    /// 回復モードでなければエラーをそのまま返す
+    fn record_err_at(&mut self, rank: ErrRank, loc: usize, e: MyError) -> Result<(), MyError> {
+        if self.recovery {
+            self.errors.push((rank, loc, e));
+            Ok(())
+        } else {
+            Err(e)
+        }
+    }
+
+    /// 現在のtokenで起こった構文エラーを記録する
+    /// 括弧の対応のエラーで起こる, その括弧やEOTでのエラーは重ねて報告しない
+    fn record_err(&mut self, e: MyError) -> Result<(), MyError> {
+        // 深すぎる式は読み進めても同じエラーになるので, そこで止める
+        if matches!(e, MyError::TooDeepNesting(..)) {
+            return Err(e);
+        }
+        let loc = self.token_loc[self.token_idx].start;
+        if self.has_brace_err() && (self.is_eot() || self.brace_err_locs.contains(&loc)) {
+            return Ok(());
+        }
+        // 字句のエラーで読み飛ばした文字の直後も, そのエラーに続くものなので重ねない
+        if self.follows_lex_err() {
+            return Ok(());
+        }
+        self.record_err_at(ErrRank::Syntax, loc, e)
+    }
+
+    /// 直前のtokenとの間に字句のエラーがあればtrue
+    fn follows_lex_err(&self) -> bool {
+        let start = match self.token_idx {
+            0 => 0,
+            i => self.token_loc[i - 1].end,
+        };
+        let end = self.token_loc[self.token_idx].start;
+        self.errors
+            .iter()
+            .any(|(rank, loc, _)| *rank == ErrRank::Lexical && (start..=end).contains(loc))
+    }
+
+    /// 括弧の対応のエラーを記録済みならtrue
+    fn has_brace_err(&self) -> bool {
+        self.errors
+            .iter()
+            .any(|(rank, _, _)| *rank == ErrRank::UnbalancedBrace)
+    }
+
+    /// 記録されたエラーを重要なものから順に並べて返す
+    fn take_errors(&mut self) -> Result<(), MyError> {
+        self.errors.sort_by_key(|(rank, loc, _)| (*rank, *loc));
+        let mut errors: Vec<MyError> = self.errors.drain(..).map(|(_, _, e)| e).collect();
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(MyError::MultipleErr(errors)),
+        }
+    }
+
+    /// 括弧の対応を検査する
+    /// 閉じられていない括弧は開き括弧の位置で報告する
+    fn check_braces(&mut self) -> Result<(), MyError> {
+        let mut stack: Vec<usize> = Vec::new();
+        for i in 0..self.tokens.len() {
+            if self.tokens[i].token_kind != TokenKind::TkBrace {
+                continue;
+            }
+            match &*self.tokens[i].token {
+                "(" | "[" | "{" => stack.push(i),
+                close => match stack.pop() {
+                    Some(open) if closing_brace(&self.tokens[open].token) == close => (),
+                    Some(open) => {
+                        let loc = self.token_loc[open].start;
+                        self.brace_err_locs.extend([loc, self.token_loc[i].start]);
+                        let e = MyError::UnclosedBrace(
+                            self.tokens[open].token.clone(),
+                            self.format_err_loc_idx(open),
+                        );
+                        self.record_err_at(ErrRank::UnbalancedBrace, loc, e)?;
+                    }
+                    None => {
+                        self.brace_err_locs.push(self.token_loc[i].start);
+                        let e =
+                            MyError::UnmatchedBrace(close.to_string(), self.format_err_loc_idx(i));
+                        self.record_err_at(ErrRank::UnbalancedBrace, self.token_loc[i].start, e)?;
+                    }
+                },
+            }
+        }
+        for open in stack {
+            self.brace_err_locs.push(self.token_loc[open].start);
+            let e = MyError::UnclosedBrace(
+                self.tokens[open].token.clone(),
+                self.format_err_loc_idx(open),
+            );
+            self.record_err_at(ErrRank::UnbalancedBrace, self.token_loc[open].start, e)?;
+        }
+        Ok(())
+    }
+
+    /// expr_vecでエラーが起こったとき, 次の式の先頭まで読み飛ばす
+    /// セパレータの手前か閉じ括弧の直後で止まる
+    fn synchronize(&mut self) {
+        let start = self.token_idx;
+        loop {
+            match self.tokens[self.token_idx].token_kind {
+                TokenKind::TkEOT | TokenKind::TkSeparaotr => break,
+                TokenKind::TkBrace if is_closing_brace(self.now_token()) => {
+                    self.token_idx += 1;
+                    break;
+                }
+                _ => self.token_idx += 1,
+            }
+        }
+        if self.token_idx == start && !self.is_eot() {
+            self.token_idx += 1;
+        }
+    }
+
+    /// 括弧の中でエラーが起こったとき, 対応する閉じ括弧closeの直後まで読み飛ばす
+    /// 対応する閉じ括弧がなければセパレータかEOTの手前で止まる
+    fn skip_to_close(&mut self, close: &str) {
+        let mut level = 0;
+        loop {
+            match self.tokens[self.token_idx].token_kind {
+                TokenKind::TkEOT | TokenKind::TkSeparaotr => return,
+                TokenKind::TkBrace if is_closing_brace(self.now_token()) => {
+                    if level == 0 {
+                        if self.now_token() == close {
+                            self.token_idx += 1;
+                        }
+                        return;
+                    }
+                    level -= 1;
+                }
+                TokenKind::TkBrace => level += 1,
+                _ => (),
+            }
+            self.token_idx += 1;
+        }
+    }
+
     /// parser内でエラーが起こっており、r.token_idxにエラーの原因となる
     /// tokenが入っているときに、エラーが数式のどの個所で起こったかを示す文字列を返す
     /// ex)
-    /// ```
+    /// ```text
     /// \frac {3} {
     ///            ^~~
     /// ```
     pub fn format_err_loc(&self) -> String {
-        let mut pad: String = String::with_capacity(self.token_loc[self.token_idx]);
-        for _i in 0..self.token_loc[self.token_idx] {
-            pad += " ";
-        }
-        let err_indicator: String = format!("{}{}", pad, "^".red());
-        let mut nami: String = String::with_capacity(self.tokens[self.token_idx].token.len());
-        for _i in 0..self.tokens[self.token_idx].token.len() - 1 {
-            nami += &format!("{}", "~".red());
-        }
-        let res: String = format!("{}\n{}{}", self.form, err_indicator, nami);
-        res
+        self.format_err_loc_idx(self.token_idx)
     }
 
     /// 変数やTSC Commandの処理中等のparser外でエラーが起こっており、r.token_idxにエラーの
     /// 原因個所のtoken indexが入っていないときにエラーが数式のどの個所で起こったかを示す文字列を返す
     /// ex)
-    /// ```
+    /// ```text
     /// , x = a
     ///       ^
     ///       ```
     /// * `token_idx` - token_idx: エラーが発生したtokenのindex
     pub fn format_err_loc_idx(&self, token_idx: usize) -> String {
-        let mut pad: String = String::with_capacity(self.token_loc[token_idx]);
-        for _i in 0..self.token_loc[token_idx] {
-            pad += " ";
-        }
-        let err_indicator: String = format!("{}{}", pad, "^".red());
-        let mut nami: String = String::with_capacity(self.tokens[token_idx].token.len());
-        for _i in 0..self.tokens[token_idx].token.len() - 1 {
-            nami += &format!("{}", "~".red());
-        }
-        let res: String = format!("{}\n{}{}", self.form, err_indicator, nami);
-        res
+        format_err_span(&self.form, self.token_span(token_idx))
+    }
+
+    /// idx番目のtokenの数式中の位置を返す
+    fn token_span(&self, idx: usize) -> Span {
+        self.token_loc[idx]
+    }
+
+    /// 直前に消費したtokenの数式中の位置を返す
+    fn prev_span(&self) -> Span {
+        self.token_span(self.token_idx - 1)
     }
 
     /*
@@ -389,13 +587,17 @@ impl Parser {
     */
 
     pub fn save_ctx(&mut self) {
-        self.ctx_stack.push(self.token_idx);
+        self.ctx_stack
+            .push((self.token_idx, self.errors.len(), self.ast.mark()));
     }
 
     pub fn revert_ctx(&mut self) -> Result<(), MyError> {
         match self.ctx_stack.pop() {
-            Some(i) => {
+            Some((i, num_of_err, mark)) => {
                 self.token_idx = i;
+                self.errors.truncate(num_of_err);
+                // 読み直す部分のnodeは使われないので捨てる
+                self.ast.rollback(mark);
             }
             None => {
                 return Err(MyError::UnexpectedOpToLexer(
@@ -448,13 +650,7 @@ impl Parser {
             TokenKind::TkTscCommand => {
                 let token = &*self.tokens[self.token_idx].token;
                 self.token_idx += 1;
-                Ok(match token {
-                    ":hex" => TscCmd::Hex,
-                    ":dec" => TscCmd::Dec,
-                    ":bin" => TscCmd::Bin,
-                    ":oct" => TscCmd::Oct,
-                    cmd => return Err(MyError::UDcommandErr(cmd.to_string())),
-                })
+                TscCmd::from_name(token).ok_or(MyError::UDcommandErr(token.to_string()))
             }
             _ => Err(MyError::NotTkTscCmd),
         }
@@ -470,6 +666,7 @@ impl Parser {
                     Err(MyError::UnexpectedToken(
                         br,
                         self.tokens[self.token_idx].token.to_string(),
+                        self.format_err_loc(),
                     ))
                 }
             }
@@ -503,73 +700,54 @@ impl Parser {
         }
     }
 
-    fn new_node(kind: NodeKind, left: Box<Node>, right: Box<Node>) -> Box<Node> {
-        Box::new(Node {
-            node_kind: kind,
-            right_node: Some(right),
-            left_node: Some(left),
-            val: None,
+    fn push_node(&mut self, kind: NodeKind, span: Span, op_span: Option<Span>) -> NodeId {
+        self.ast.push(Node {
+            kind,
+            span,
+            op_span,
         })
     }
 
-    fn new_unary_node(kind: NodeKind, left: Box<Node>) -> Box<Node> {
-        Box::new(Node {
-            node_kind: kind,
-            right_node: None,
-            left_node: Some(left),
-            val: None,
-        })
+    fn new_binary_node(
+        &mut self,
+        op: BinaryOp,
+        lhs: NodeId,
+        rhs: NodeId,
+        op_span: Option<Span>,
+    ) -> NodeId {
+        let mut span = self.ast[lhs].span.merge(self.ast[rhs].span);
+        if let Some(op_span) = op_span {
+            span = span.merge(op_span);
+        }
+        self.push_node(NodeKind::Binary(op, lhs, rhs), span, op_span)
     }
 
-    fn new_node_num(val: BigDecimal) -> Box<Node> {
-        Box::new(Node {
-            node_kind: NodeKind::Num,
-            right_node: None,
-            left_node: None,
-            val: Some(NumOrVar::Num(val)),
-        })
+    fn new_unary_node(&mut self, op: UnaryOp, arg: NodeId, op_span: Span) -> NodeId {
+        let span = self.ast[arg].span.merge(op_span);
+        self.push_node(NodeKind::Unary(op, arg), span, Some(op_span))
     }
 
-    fn new_node_var(var: String) -> Box<Node> {
-        Box::new(Node {
-            node_kind: NodeKind::Var,
-            right_node: None,
-            left_node: None,
-            val: Some(NumOrVar::Var(var)),
-        })
+    fn new_call_node(&mut self, id: usize, args: &[NodeId], op_span: Span) -> NodeId {
+        let span = args
+            .iter()
+            .fold(op_span, |span, arg| span.merge(self.ast[*arg].span));
+        let args = self.ast.push_args(args);
+        self.push_node(NodeKind::Call(id, args), span, Some(op_span))
     }
 
-    fn show_node(place: String, node: &Node) {
-        debugln!(
-            "{}: create {{ Kind: {}, Val: {:?} }}",
-            place,
-            node.node_kind.to_string(),
-            node.val
-        );
+    fn new_node_num(&mut self, val: BigDecimal, span: Span) -> NodeId {
+        self.push_node(NodeKind::Num(val), span, None)
     }
 
-    /*
-    expr_vec  = ","? (expr | tsc_cmd) (","? expr | ","? tsc_cmd)*
-    tsc_cmd   = ":hex" | ":dec" | ":bin"
-    expr      = mul ("+" mul | "-" mul)*
-    mul       = noobmul  ("*" noobmul | "/" noobmul | "\cdto" noobmul | "\times" noobmul | "\div" noobmul)*
-    noobmul   = sigend (expo)*
-    signed    = "-"? expo
-    expo      = primary ("^" "{" expr "}")*
-    primary   = num | "(" expr ")" | "\frac" "{" expr "}" "{" expr "}" | "\sqrt" "{" expr "} | "\exp" "(" expr ")" | "\abs" "(" expr ")"
-                | "\log"  signed | "\ln" signed | "\sin" signed | "\cos" signed | "\tan" signed | "\csc" signed | "\sec" signed | "\cot" signed
-    */
-    /*
-    fn signed(&mut self) -> Result<Box<Node>, MyError> {
-        if self.consume("-".to_string()) {
-            let mut node = self.expo()?;
-            node = Parser::new_unary_node(NodeKind::Neg, node);
-            Ok(node)
-        } else {
-            Ok(self.expo()?)
-        }
+    fn new_node_var(&mut self, var: String, span: Span) -> NodeId {
+        self.push_node(NodeKind::Var(var), span, None)
     }
-    */
+
+    fn show_node(&self, place: &str, id: NodeId) {
+        debugln!("{}: create {{ Kind: {} }}", place, self.ast[id].kind);
+    }
+
+    // 文法はregistryの演算子とコマンドの表で決まる
 
     fn expr_vec(&mut self) -> Result<Vec<NodeOrCmd>, MyError> {
         let mut res = Vec::new();
@@ -584,10 +762,13 @@ impl Parser {
                     res.push(NodeOrCmd::TscCmd(tsc_cmd));
                 }
                 Err(e) => match e {
-                    MyError::NotTkTscCmd => {
-                        let node = self.expr()?;
-                        res.push(NodeOrCmd::Node(node));
-                    }
+                    MyError::NotTkTscCmd => match self.expr() {
+                        Ok(node) => res.push(NodeOrCmd::Node(node)),
+                        Err(e) => {
+                            self.record_err(e)?;
+                            self.synchronize();
+                        }
+                    },
                     MyError::UDcommandErr(e) => {
                         return Err(MyError::UDcommandErr(e));
                     }
@@ -601,191 +782,373 @@ impl Parser {
         Ok(res)
     }
 
-    fn expr(&mut self) -> Result<Box<Node>, MyError> {
-        let mut node: Box<Node> = self.mul()?;
-        loop {
-            if self.consume("+".to_string()) {
-                node = Parser::new_node(NodeKind::Add, node, self.mul()?);
-            } else if self.consume("-".to_string()) {
-                node = Parser::new_node(NodeKind::Sub, node, self.mul()?);
-            } else {
-                Parser::show_node("expr".to_string(), &node);
-                return Ok(node);
-            }
-        }
+    fn expr(&mut self) -> Result<NodeId, MyError> {
+        let node = self.expr_bp(0)?;
+        self.show_node("expr", node);
+        Ok(node)
     }
 
-    fn mul(&mut self) -> Result<Box<Node>, MyError> {
-        let mut node: Box<Node> = self.noobmul()?;
-        Parser::show_node("noobmul".to_string(), &node);
-        loop {
-            if self.consume("*".to_string())
-                || self.consume("\\times".to_string())
-                || self.consume("\\cdot".to_string())
-            {
-                node = Parser::new_node(NodeKind::Mul, node, self.noobmul()?);
-            } else if self.consume("\\div".to_string()) || self.consume("/".to_string()) {
-                node = Parser::new_node(NodeKind::Div, node, self.noobmul()?);
-            } else {
-                Parser::show_node("mul".to_string(), &node);
-                return Ok(node);
-            }
+    /// 結合力がmin_prec以上の演算子だけを読む
+    fn expr_bp(&mut self, min_prec: u8) -> Result<NodeId, MyError> {
+        // 深すぎるネストによるstack overflowを防ぐ
+        if self.depth >= MAX_NEST_DEPTH {
+            return Err(MyError::TooDeepNesting(
+                MAX_NEST_DEPTH,
+                self.format_err_loc(),
+            ));
         }
+        self.depth += 1;
+        let res = self.expr_bp_inner(min_prec);
+        self.depth -= 1;
+        res
     }
 
-    fn noobmul(&mut self) -> Result<Box<Node>, MyError> {
-        let mut node: Box<Node> = self.signed()?;
-        Parser::show_node("signed".to_string(), &node);
+    fn expr_bp_inner(&mut self, min_prec: u8) -> Result<NodeId, MyError> {
+        let mut node = self.prefix()?;
         loop {
-            self.save_ctx();
-            match self.expo() {
-                Ok(n) => {
-                    match n.node_kind {
-                        NodeKind::Num => {
-                            /*
-                            return Err(MyError::InvalidInput(
-                                "don't allowed nulmber literal on right operand of noobvious mul"
-                                    .to_string(),
-                            ));
-                            */
+            let is_op = self.tokens[self.token_idx].token_kind == TokenKind::TkOperator;
+            if let Some(op) = infix_op(self.now_token()).filter(|_| is_op) {
+                if op.prec < min_prec {
+                    break;
+                }
+                self.token_idx += 1;
+                let span = self.prev_span();
+                let rnode = self.expr_bp(op.prec + 1)?;
+                node = self.new_binary_node(op.op, node, rnode, Some(span));
+            } else if let Some(op) = postfix_op(self.now_token()).filter(|_| is_op) {
+                if op.prec < min_prec {
+                    break;
+                }
+                self.token_idx += 1;
+                let span = self.prev_span();
+                let cnode = self.carg_node()?;
+                node = self.new_binary_node(op.op, node, cnode, Some(span));
+                self.ast[node].span.end = self.prev_span().end;
+            } else if PREC_IMPLICIT_MUL >= min_prec && !is_op {
+                // 暗黙の乗算: 右の被演算子が読めなければ終わる
+                self.save_ctx();
+                match self.expr_bp(PREC_IMPLICIT_MUL + 1) {
+                    Ok(n) => match self.ast[n].kind {
+                        NodeKind::Num(_) => {
+                            // 数値リテラルは暗黙の乗算の右の被演算子にしない
                             self.revert_ctx()?;
-                            return Ok(node);
+                            break;
                         }
                         _ => {
                             self.discard_ctx()?;
-                            node = Parser::new_node(NodeKind::Mul, node, n);
+                            // 暗黙の乗算には演算子のtokenがない
+                            node = self.new_binary_node(BinaryOp::Mul, node, n, None);
                         }
-                    }
-                }
-                Err(e) => {
-                    self.revert_ctx()?;
-                    match e {
-                        MyError::NotTkNumber(_, _) => {
-                            Parser::show_node("noobmul".to_string(), &node);
-                            return Ok(node);
+                    },
+                    Err(e) => {
+                        self.revert_ctx()?;
+                        match e {
+                            MyError::NotTkNumber(_, _) => break,
+                            _ => return Err(e),
                         }
-                        _ => return Err(e),
                     }
                 }
+            } else {
+                break;
             }
         }
+        Ok(node)
     }
 
-    fn signed(&mut self) -> Result<Box<Node>, MyError> {
-        if self.consume("-".to_string()) {
-            let mut node = self.expo()?;
-            node = Parser::new_unary_node(NodeKind::Neg, node);
-            Ok(node)
-        } else {
-            Ok(self.expo()?)
+    fn prefix(&mut self) -> Result<NodeId, MyError> {
+        let is_op = self.tokens[self.token_idx].token_kind == TokenKind::TkOperator;
+        if let Some(op) = prefix_op(self.now_token()).filter(|_| is_op) {
+            self.token_idx += 1;
+            let span = self.prev_span();
+            let node = self.expr_bp(op.prec)?;
+            return Ok(self.new_unary_node(op.op, node, span));
         }
+        self.primary()
     }
 
-    fn expo(&mut self) -> Result<Box<Node>, MyError> {
-        let mut node: Box<Node> = self.primary()?;
-        Parser::show_node("primary".to_string(), &node);
-        loop {
-            if self.consume("^".to_string()) {
-                self.expect_br("{".to_string())?;
-                let cnode: Box<Node> = self.expr()?;
-                self.expect_br("}".to_string())?;
-                node = Parser::new_node(NodeKind::Pow, node, cnode);
-            } else {
-                Parser::show_node("mul".to_string(), &node);
-                return Ok(node);
-            }
-        }
+    fn primary(&mut self) -> Result<NodeId, MyError> {
+        let start = self.token_loc[self.token_idx].start;
+        let node = self.primary_inner()?;
+        // 閉じ括弧を含めた範囲をnodeの位置とする
+        self.ast[node].span = Span {
+            start,
+            end: self.prev_span().end,
+        };
+        self.show_node("primary", node);
+        Ok(node)
     }
 
-    fn primary(&mut self) -> Result<Box<Node>, MyError> {
+    fn primary_inner(&mut self) -> Result<NodeId, MyError> {
         if self.consume("(".to_string()) {
-            let node: Box<Node> = self.expr()?;
-            self.expect_br(")".to_string())?;
-            return Ok(node);
-        }
-        if self.consume("\\frac".to_string()) {
-            self.expect_br("{".to_string())?;
-            let lnode: Box<Node> = self.expr()?;
-            self.expect_br("}".to_string())?;
-            self.expect_br("{".to_string())?;
-            let rnode: Box<Node> = self.expr()?;
-            self.expect_br("}".to_string())?;
-            let node = Parser::new_node(NodeKind::Div, lnode, rnode);
-            return Ok(node);
+            return self.close_braced_expr(")");
         }
-
-        if self.consume("\\sqrt".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::Sqrt, self.carg_node()?));
+        if self.tokens[self.token_idx].token_kind == TokenKind::TkTexCommand {
+            return self.command();
         }
-        if self.consume("\\abs".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::Abs, self.parg_node()?));
-        }
-        if self.consume("\\exp".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::Exp, self.parg_node()?));
-        }
-        if self.consume("\\log".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::Log, self.signed()?));
-        }
-        if self.consume("\\ln".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::Ln, self.signed()?));
+        if let Some((id, f)) = self.var_func()? {
+            self.token_idx += 1;
+            let span = self.prev_span();
+            let args = self.special_args(f, span)?;
+            return Ok(self.new_call_node(id, &args, span));
         }
-        if self.consume("\\sin".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::Sin, self.signed()?));
+        let num_node = self.num()?;
+        Ok(num_node)
+    }
+
+    /// registryに登録されたTeX コマンドを読む
+    fn command(&mut self) -> Result<NodeId, MyError> {
+        let (id, cmd) = match registry_reader()?.lookup(self.now_token()) {
+            Some((id, cmd)) => (id, cmd.clone()),
+            None => {
+                return Err(MyError::UDtexcommand(
+                    self.now_token().to_string(),
+                    String::new(),
+                    self.format_err_loc(),
+                ));
+            }
+        };
+        self.token_idx += 1;
+        let span = self.prev_span();
+        match cmd.eval {
+            // 最後の引数pはリストとして展開しない
+            Evaluator::List(ListFn::Quantile) => {
+                let n = match self.tokens.get(self.token_idx + 1) {
+                    Some(t) if t.token == ")" => 0,
+                    _ => self.paren_arg_count(self.token_idx).unwrap_or(0),
+                };
+                let args = self.paren_args(n.saturating_sub(1))?;
+                if n < 2 {
+                    return Err(MyError::ArgCountErr(
+                        ListFn::Quantile.tex_name(),
+                        "2 or more".to_string(),
+                        n,
+                        format_err_span(&self.form, span.merge(self.prev_span())),
+                    ));
+                }
+                return Ok(self.new_call_node(id, &args, span));
+            }
+            Evaluator::List(_) => {
+                let args = self.paren_args(usize::MAX)?;
+                return Ok(self.new_call_node(id, &args, span));
+            }
+            Evaluator::Special(f) => {
+                let args = self.special_args(f, span)?;
+                return Ok(self.new_call_node(id, &args, span));
+            }
+            // \ln\Gamma(x)は値が大きくても溢れないよう, まとめてln Γ(x)として求める
+            Evaluator::Builtin(Builtin::Unary(UnaryOp::Ln))
+                if self.now_token() == "\\Gamma"
+                    && self.paren_arg_count(self.token_idx + 1) == Some(1) =>
+            {
+                let Some((id, _)) = registry_reader()?.lookup("\\lnGamma") else {
+                    return Err(MyError::BrokenAstErr);
+                };
+                self.token_idx += 1;
+                let span = span.merge(self.prev_span());
+                let args = self.special_args(SpecialFn::LnGamma, span)?;
+                return Ok(self.new_call_node(id, &args, span));
+            }
+            _ => (),
         }
-        if self.consume("\\cos".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::Cos, self.signed()?));
+        let mut args = Vec::with_capacity(cmd.arity);
+        for _ in 0..cmd.arity {
+            let arg = match cmd.arg_style {
+                // \sqrt 2のように括弧が無いときは直後のprimaryを引数にする
+                ArgStyle::Brace if self.now_token() == "{" => self.carg_node()?,
+                ArgStyle::Brace => self.primary()?,
+                ArgStyle::Paren => self.parg_node()?,
+                ArgStyle::Signed => self.expr_bp(cmd.prec)?,
+            };
+            args.push(arg);
         }
-        if self.consume("\\tan".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::Tan, self.signed()?));
+        if cmd.arity == 0 && cmd.arg_style == ArgStyle::Paren {
+            self.expect_br("(".to_string())?;
+            self.expect_br(")".to_string())?;
         }
-        if self.consume("\\csc".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::Csc, self.signed()?));
+        Ok(match (cmd.eval, args.as_slice()) {
+            (Evaluator::Builtin(Builtin::Unary(op)), [arg]) => self.new_unary_node(op, *arg, span),
+            (Evaluator::Builtin(Builtin::Binary(op)), [lhs, rhs]) => {
+                self.new_binary_node(op, *lhs, *rhs, Some(span))
+            }
+            _ => self.new_call_node(id, &args, span),
+        })
+    }
+
+    /// 括弧で囲んだ引数 "(" (arg ("," arg)*)? ")" を読む
+    /// 先頭からexpand個の引数のうち, リスト変数だけのものはその要素に展開する
+    fn paren_args(&mut self, expand: usize) -> Result<Vec<NodeId>, MyError> {
+        self.expect_br("(".to_string())?;
+        let mut args = Vec::new();
+        // \operatorname{rand}()のように引数が無い
+        if self.now_token() == ")" {
+            self.token_idx += 1;
+            return Ok(args);
         }
-        if self.consume("\\sec".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::Sec, self.signed()?));
+        for n in 0.. {
+            let token = &self.tokens[self.token_idx];
+            let is_single = self
+                .tokens
+                .get(self.token_idx + 1)
+                .is_some_and(|next| next.token == "," || next.token == ")");
+            let values = match token.token_kind {
+                TokenKind::TkVariable if n < expand && is_single => {
+                    self.lists.get(&token.token).cloned()
+                }
+                _ => None,
+            };
+            match values {
+                Some(values) => {
+                    let span = self.token_span(self.token_idx);
+                    self.token_idx += 1;
+                    for v in values {
+                        args.push(self.new_node_num(v, span));
+                    }
+                }
+                None => args.push(self.expr()?),
+            }
+            if !self.consume(",".to_string()) {
+                break;
+            }
         }
-        if self.consume("\\cot".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::Cot, self.signed()?));
+        self.expect_br(")".to_string())?;
+        Ok(args)
+    }
+
+    /// 特殊関数の引数を読む. J_{n}(x)のように添字で書く関数は添字を最初の引数にする
+    ///
+    /// * `span` - 関数名の位置
+    fn special_args(&mut self, f: SpecialFn, span: Span) -> Result<Vec<NodeId>, MyError> {
+        let mut args = Vec::new();
+        if f.subscripted() {
+            if !self.consume("_".to_string()) {
+                return Err(MyError::UnexpectedToken(
+                    "'_'".to_string(),
+                    format!("'{}'", self.now_token()),
+                    self.format_err_loc(),
+                ));
+            }
+            args.push(if self.now_token() == "{" {
+                self.carg_node()?
+            } else {
+                self.primary()?
+            });
         }
-        if self.consume("\\arcsin".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::AcSin, self.signed()?));
+        args.extend(self.paren_args(0)?);
+        if !f.arity().contains(&args.len()) {
+            return Err(MyError::ArgCountErr(
+                f.tex_name().to_string(),
+                f.arity_text(),
+                args.len(),
+                format_err_span(&self.form, span.merge(self.prev_span())),
+            ));
         }
-        if self.consume("\\arccos".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::AcCos, self.signed()?));
+        Ok(args)
+    }
+
+    /// idx番目が "(" なら, 対応する ")" までの最も外側の引数の数を返す
+    fn paren_arg_count(&self, idx: usize) -> Option<usize> {
+        if self.tokens.get(idx)?.token != "(" {
+            return None;
         }
-        if self.consume("\\arctan".to_string()) {
-            return Ok(Parser::new_unary_node(NodeKind::AcTan, self.signed()?));
+        let mut depth = 0;
+        let mut count = 1;
+        for t in &self.tokens[idx..] {
+            match (&*t.token, t.token_kind) {
+                ("(" | "{", TokenKind::TkBrace) => depth += 1,
+                (")" | "}", TokenKind::TkBrace) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(count);
+                    }
+                }
+                (",", TokenKind::TkOperator) if depth == 1 => count += 1,
+                (_, TokenKind::TkSeparaotr | TokenKind::TkEOT) => return None,
+                _ => (),
+            }
         }
+        None
+    }
 
-        let num_node = self.num()?;
-        Ok(num_node)
+    /// \Gamma(x), J_{n}(x)のように変数の名前で書く特殊関数なら, そのidと関数を返す
+    /// ギリシャ文字は必要な数以上の引数の括弧が続くときだけ関数とし, それ以外は変数のままにする
+    fn var_func(&self) -> Result<Option<(usize, SpecialFn)>, MyError> {
+        let t = &self.tokens[self.token_idx];
+        if t.token_kind != TokenKind::TkVariable {
+            return Ok(None);
+        }
+        let is_greek = t.token.starts_with('\\');
+        let name = if is_greek {
+            t.token.clone()
+        } else {
+            format!("\\{}", t.token)
+        };
+        let Some((id, f)) = registry_reader()?
+            .lookup(&name)
+            .and_then(|(id, cmd)| match cmd.eval {
+                Evaluator::Special(f) => Some((id, f)),
+                _ => None,
+            })
+        else {
+            return Ok(None);
+        };
+        let next = self.token_idx + 1;
+        let is_func = if f.subscripted() {
+            self.tokens[next].token == "_"
+        } else {
+            is_greek
+                && self
+                    .paren_arg_count(next)
+                    .is_some_and(|n| n >= *f.arity().start())
+        };
+        Ok(is_func.then_some((id, f)))
     }
 
-    fn num(&mut self) -> Result<Box<Node>, MyError> {
-        match self.expect_number() {
-            Ok(v) => match v {
-                NumstrOrVar::Num((format, num)) => {
-                    Ok(Parser::new_node_num(bigdecimal_from_str(format, &num)?))
-                }
-                NumstrOrVar::Var(var) => Ok(Parser::new_node_var(var)),
-            },
-            Err(e) => Err(e),
+    fn num(&mut self) -> Result<NodeId, MyError> {
+        match self.expect_number()? {
+            NumstrOrVar::Num((format, num)) => {
+                let val = bigdecimal_from_str(format, &num)?;
+                Ok(self.new_node_num(val, self.prev_span()))
+            }
+            NumstrOrVar::Var(var) => Ok(self.new_node_var(var, self.prev_span())),
         }
     }
 
     // parentheses "()" arg node
-    fn parg_node(&mut self) -> Result<Box<Node>, MyError> {
+    fn parg_node(&mut self) -> Result<NodeId, MyError> {
         self.expect_br("(".to_string())?;
-        let node: Box<Node> = self.expr()?;
-        self.expect_br(")".to_string())?;
-        Ok(node)
+        self.close_braced_expr(")")
     }
 
     // curly brackets "{}" arg node
-    fn carg_node(&mut self) -> Result<Box<Node>, MyError> {
+    fn carg_node(&mut self) -> Result<NodeId, MyError> {
         self.expect_br("{".to_string())?;
-        let node: Box<Node> = self.expr()?;
-        self.expect_br("}".to_string())?;
-        Ok(node)
+        self.close_braced_expr("}")
+    }
+
+    /// 開き括弧の後の "expr 閉じ括弧" を読む
+    /// 回復モードではエラーを記録して閉じ括弧まで読み飛ばし, 代わりに0のnodeを返す
+    fn close_braced_expr(&mut self, close: &str) -> Result<NodeId, MyError> {
+        let start = self.token_loc[self.token_idx].start;
+        let res = match self.expr() {
+            Ok(node) => match self.expect_br(close.to_string()) {
+                Ok(()) => Ok(node),
+                // 括弧の対応のエラーを報告済みなら, 閉じ括弧が無いことは重ねて報告しない
+                Err(_) if self.has_brace_err() => return Ok(node),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok(node) => Ok(node),
+            Err(e) => {
+                self.record_err(e)?;
+                self.skip_to_close(close);
+                let span = Span {
+                    start,
+                    end: self.token_loc[self.token_idx].start.max(start),
+                };
+                Ok(self.new_node_num(BigDecimal::zero(), span))
+            }
+        }
     }
 }
@@ -0,0 +1,733 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use std::collections::HashMap;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::ast::{Ast, BinaryOp, NodeId, NodeKind, UnaryOp};
+use crate::config::Config;
+use crate::domain::domain_err;
+use crate::error::MyError;
+use crate::parser::format_err_span;
+use crate::rational::{sum_to_tex, ExactEval, Rational};
+use crate::Value;
+
+/// 多項式の累乗で展開する指数の上限
+const MAX_POW_EXP: u32 = 256;
+/// 因数分解で試す根の組み合わせの数の上限
+const MAX_SUBSETS: u64 = 100_000;
+/// Aberth法の反復の上限
+const MAX_ITER: usize = 1000;
+
+/// 有理数係数の1変数多項式
+/// coefs[i]はx^iの係数で, 最高次の係数は0でない. 0はcoefsが空
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Poly {
+    coefs: Vec<Rational>,
+}
+
+/// 根
+#[derive(Clone, Debug)]
+pub enum Root {
+    Exact(Rational), // 有理数の根
+    Real(f64),
+    Complex(Complex),
+}
+
+/// 有理数 * 整数係数の既約多項式の積
+pub struct Factorization {
+    pub content: Rational,
+    pub factors: Vec<(Poly, usize)>, // 因数と重複度
+}
+
+impl Poly {
+    pub fn new(mut coefs: Vec<Rational>) -> Poly {
+        while coefs.last().is_some_and(|c| c.is_zero()) {
+            coefs.pop();
+        }
+        Poly { coefs }
+    }
+
+    pub fn constant(c: Rational) -> Poly {
+        Poly::new(vec![c])
+    }
+
+    pub fn x() -> Poly {
+        Poly::new(vec![Rational::zero(), Rational::one()])
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coefs.is_empty()
+    }
+
+    /// 次数. 0の次数も0とする
+    pub fn degree(&self) -> usize {
+        self.coefs.len().saturating_sub(1)
+    }
+
+    pub fn coefs(&self) -> &[Rational] {
+        &self.coefs
+    }
+
+    /// 最高次の係数
+    pub fn lead(&self) -> Rational {
+        self.coefs.last().cloned().unwrap_or_else(Rational::zero)
+    }
+
+    pub fn scale(&self, k: &Rational) -> Poly {
+        Poly::new(self.coefs.iter().map(|c| c * k).collect())
+    }
+
+    pub fn pow(&self, exp: u32) -> Poly {
+        let mut res = Poly::constant(Rational::one());
+        for _ in 0..exp {
+            res = &res * self;
+        }
+        res
+    }
+
+    /// 商と余り. dは0でないこと
+    pub fn div_rem(&self, d: &Poly) -> (Poly, Poly) {
+        assert!(!d.is_zero(), "divisor 0");
+        let mut rem = self.coefs.clone();
+        if rem.len() < d.coefs.len() {
+            return (Poly::new(Vec::new()), self.clone());
+        }
+        let lead = d.lead();
+        let mut quot = vec![Rational::zero(); rem.len() - d.coefs.len() + 1];
+        for i in (0..quot.len()).rev() {
+            let q = &rem[i + d.degree()] / &lead;
+            for (j, c) in d.coefs.iter().enumerate() {
+                rem[i + j] = &rem[i + j] - &(&q * c);
+            }
+            quot[i] = q;
+        }
+        (Poly::new(quot), Poly::new(rem))
+    }
+
+    pub fn derivative(&self) -> Poly {
+        Poly::new(
+            self.coefs
+                .iter()
+                .enumerate()
+                .skip(1)
+                .map(|(i, c)| c * &Rational::from_int(i as i64))
+                .collect(),
+        )
+    }
+
+    /// 最高次の係数を1にする
+    pub fn monic(&self) -> Poly {
+        match self.lead().recip() {
+            Some(k) => self.scale(&k),
+            None => self.clone(),
+        }
+    }
+
+    /// 最大公約式. 最高次の係数は1
+    pub fn gcd(&self, other: &Poly) -> Poly {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        while !b.is_zero() {
+            let r = a.div_rem(&b).1;
+            (a, b) = (b, r);
+        }
+        a.monic()
+    }
+
+    /// 内容と原始多項式に分ける
+    /// 原始多項式は係数が互いに素な整数で, 最高次の係数が正
+    pub fn primitive(&self) -> (Rational, Poly) {
+        if self.is_zero() {
+            return (Rational::zero(), self.clone());
+        }
+        let den = self
+            .coefs
+            .iter()
+            .fold(BigInt::one(), |l, c| l.lcm(c.denom()));
+        let nums: Vec<BigInt> = self
+            .coefs
+            .iter()
+            .map(|c| c.numer() * (&den / c.denom()))
+            .collect();
+        let mut g = nums.iter().fold(BigInt::zero(), |g, n| g.gcd(n));
+        if self.lead().is_negative() {
+            g = -g;
+        }
+        let prim = Poly::new(
+            nums.into_iter()
+                .map(|n| Rational::from_int(n / &g))
+                .collect(),
+        );
+        (Rational::new(g, den), prim)
+    }
+
+    /// Yunの方法で無平方分解する
+    /// 最高次の係数が1の無平方な多項式と重複度の組を返す. 定数倍は除く
+    pub fn square_free(&self) -> Vec<(Poly, usize)> {
+        let mut res = Vec::new();
+        if self.degree() == 0 {
+            return res;
+        }
+        let d = self.derivative();
+        let a0 = self.gcd(&d);
+        let mut b = self.div_rem(&a0).0;
+        let mut c = d.div_rem(&a0).0;
+        let mut dd = &c - &b.derivative();
+        let mut i = 1;
+        while b.degree() > 0 {
+            let a = b.gcd(&dd);
+            b = b.div_rem(&a).0;
+            c = dd.div_rem(&a).0;
+            dd = &c - &b.derivative();
+            if a.degree() > 0 {
+                res.push((a, i));
+            }
+            i += 1;
+        }
+        res
+    }
+
+    /// 有理数の範囲で因数分解する
+    pub fn factor(&self) -> Factorization {
+        let (content, prim) = self.primitive();
+        let mut factors = Vec::new();
+        for (g, m) in prim.square_free() {
+            for f in factor_square_free(&g.primitive().1) {
+                factors.push((f, m));
+            }
+        }
+        // 1次の因数を根の絶対値の小さい順(同じなら正の根が先)に並べ, 高次の因数を後にする
+        factors.sort_by(|(a, _), (b, _)| {
+            let key = |p: &Poly| match p.degree() {
+                1 => {
+                    let root = (-&p.coefs[0] / p.coefs[1].clone()).to_f64();
+                    (1, root.abs(), root < 0.0)
+                }
+                d => (d, 0.0, false),
+            };
+            let (ka, kb) = (key(a), key(b));
+            ka.0.cmp(&kb.0)
+                .then(ka.1.total_cmp(&kb.1))
+                .then(ka.2.cmp(&kb.2))
+        });
+        Factorization { content, factors }
+    }
+
+    /// 実数と複素数の全ての根を重複度とともに返す
+    /// 有理数の根は厳密に, それ以外はf64で求める. 実数の根を小さい順に並べ, 複素数の根を後にする
+    pub fn roots(&self) -> Vec<(Root, usize)> {
+        let mut roots = Vec::new();
+        for (f, m) in self.factor().factors {
+            if f.degree() == 1 {
+                roots.push((Root::Exact(-&f.coefs[0] / f.coefs[1].clone()), m));
+                continue;
+            }
+            for z in aberth(&f) {
+                if z.im.abs() <= 1e-9 * (1.0 + z.abs()) {
+                    roots.push((Root::Real(z.re), m));
+                } else {
+                    roots.push((Root::Complex(z), m));
+                }
+            }
+        }
+        let key = |r: &Root| match r {
+            Root::Exact(q) => (0, q.to_f64(), 0.0),
+            Root::Real(x) => (0, *x, 0.0),
+            Root::Complex(z) => (1, z.re, z.im),
+        };
+        roots.sort_by(|(a, _), (b, _)| {
+            let (ka, kb) = (key(a), key(b));
+            ka.0.cmp(&kb.0)
+                .then(ka.1.total_cmp(&kb.1))
+                .then(ka.2.total_cmp(&kb.2))
+        });
+        roots
+    }
+
+    /// varの多項式としてTeXにする
+    /// ex) x^{2} - \frac{1}{2}x + 3
+    pub fn to_tex(&self, var: &str) -> String {
+        let terms: Vec<(Rational, String)> = self
+            .coefs
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, c)| {
+                let mono = match i {
+                    0 => String::new(),
+                    1 => var.to_string(),
+                    _ => format!("{var}^{{{i}}}"),
+                };
+                (c.clone(), mono)
+            })
+            .collect();
+        sum_to_tex(&terms)
+    }
+
+    /// 項が1つならtrue
+    fn is_monomial(&self) -> bool {
+        self.coefs.iter().filter(|c| !c.is_zero()).count() <= 1
+    }
+}
+
+impl Factorization {
+    /// varの多項式としてTeXにする
+    /// ex) -2x(x - 1)^{2}(x^{2} + 1)
+    pub fn to_tex(&self, var: &str) -> String {
+        if self.factors.is_empty() {
+            return self.content.to_tex();
+        }
+        // 因数が1つだけなら括弧を付けない
+        if let [(f, 1)] = self.factors.as_slice()
+            && self.content.is_one()
+        {
+            return f.to_tex(var);
+        }
+        let mut tex = if self.content.is_one() {
+            String::new()
+        } else if (-&self.content).is_one() {
+            "-".to_string()
+        } else {
+            self.content.to_tex()
+        };
+        for (f, m) in &self.factors {
+            if f.is_monomial() {
+                tex += &f.to_tex(var);
+            } else {
+                tex += &format!("({})", f.to_tex(var));
+            }
+            if *m > 1 {
+                tex += &format!("^{{{m}}}");
+            }
+        }
+        tex
+    }
+}
+
+impl Add<&Poly> for &Poly {
+    type Output = Poly;
+    fn add(self, rhs: &Poly) -> Poly {
+        let n = self.coefs.len().max(rhs.coefs.len());
+        let zero = Rational::zero();
+        Poly::new(
+            (0..n)
+                .map(|i| {
+                    let a = self.coefs.get(i).unwrap_or(&zero);
+                    a + rhs.coefs.get(i).unwrap_or(&zero)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Sub<&Poly> for &Poly {
+    type Output = Poly;
+    fn sub(self, rhs: &Poly) -> Poly {
+        self + &-rhs
+    }
+}
+
+impl Mul<&Poly> for &Poly {
+    type Output = Poly;
+    fn mul(self, rhs: &Poly) -> Poly {
+        if self.is_zero() || rhs.is_zero() {
+            return Poly::new(Vec::new());
+        }
+        let mut coefs = vec![Rational::zero(); self.coefs.len() + rhs.coefs.len() - 1];
+        for (i, a) in self.coefs.iter().enumerate() {
+            for (j, b) in rhs.coefs.iter().enumerate() {
+                coefs[i + j] = &coefs[i + j] + &(a * b);
+            }
+        }
+        Poly::new(coefs)
+    }
+}
+
+impl Neg for &Poly {
+    type Output = Poly;
+    fn neg(self) -> Poly {
+        Poly::new(self.coefs.iter().map(|c| -c).collect())
+    }
+}
+
+/// rootの式をvarの多項式にする
+/// var以外の変数と定数は値を係数にする
+pub fn from_ast(
+    ast: &Ast,
+    root: NodeId,
+    var: &str,
+    form: &str,
+    vars: &HashMap<String, Value>,
+    conf: &Config,
+) -> Result<Poly, MyError> {
+    PolyReader {
+        var,
+        exact: ExactEval {
+            ast,
+            form,
+            vars,
+            conf,
+        },
+    }
+    .read(root)
+}
+
+struct PolyReader<'a> {
+    var: &'a str,
+    exact: ExactEval<'a>,
+}
+
+impl PolyReader<'_> {
+    fn has_var(&self, id: NodeId) -> bool {
+        match &self.exact.ast[id].kind {
+            NodeKind::Var(v) => v == self.var,
+            _ => self.exact.ast.children(id).any(|c| self.has_var(c)),
+        }
+    }
+
+    fn not_polynomial(&self, id: NodeId) -> MyError {
+        MyError::NotPolynomialErr(
+            self.var.to_string(),
+            format_err_span(self.exact.form, self.exact.ast[id].span),
+        )
+    }
+
+    fn read(&self, id: NodeId) -> Result<Poly, MyError> {
+        if !self.has_var(id) {
+            return Ok(Poly::constant(self.exact.value(id)?));
+        }
+        let node = &self.exact.ast[id];
+        match &node.kind {
+            NodeKind::Var(_) => Ok(Poly::x()),
+            NodeKind::Unary(UnaryOp::Neg, arg) => Ok(-&self.read(*arg)?),
+            NodeKind::Binary(BinaryOp::Add, lhs, rhs) => Ok(&self.read(*lhs)? + &self.read(*rhs)?),
+            NodeKind::Binary(BinaryOp::Sub, lhs, rhs) => Ok(&self.read(*lhs)? - &self.read(*rhs)?),
+            NodeKind::Binary(BinaryOp::Mul, lhs, rhs) => Ok(&self.read(*lhs)? * &self.read(*rhs)?),
+            NodeKind::Binary(BinaryOp::Div, lhs, rhs) if !self.has_var(*rhs) => {
+                match self.exact.value(*rhs)?.recip() {
+                    Some(k) => Ok(self.read(*lhs)?.scale(&k)),
+                    None => Err(domain_err(node, self.exact.form, "divisor 0".to_string())),
+                }
+            }
+            NodeKind::Binary(BinaryOp::Pow, lhs, rhs) if !self.has_var(*rhs) => {
+                let exp = self.exact.value(*rhs)?;
+                match exp.numer().to_u32() {
+                    Some(e) if exp.is_integer() && e <= MAX_POW_EXP => Ok(self.read(*lhs)?.pow(e)),
+                    _ => Err(self.not_polynomial(id)),
+                }
+            }
+            _ => Err(self.not_polynomial(id)),
+        }
+    }
+}
+
+/// 無平方な原始多項式を既約な原始多項式の積に分ける
+/// 数値的に求めた根の組から整数係数の因数の候補を作り, 割り切れるかを厳密に確かめる
+fn factor_square_free(g: &Poly) -> Vec<Poly> {
+    let mut factors = Vec::new();
+    let mut h = g.clone();
+    if h.degree() <= 1 {
+        return vec![h];
+    }
+    if h.coefs[0].is_zero() {
+        factors.push(Poly::x());
+        h = h.div_rem(&Poly::x()).0;
+    }
+    let mut roots = if h.degree() > 0 {
+        aberth(&h)
+    } else {
+        Vec::new()
+    };
+    let mut k = 1;
+    'size: while 2 * k <= h.degree() {
+        if binomial(roots.len() as u64, k as u64) > MAX_SUBSETS {
+            break;
+        }
+        let leads = divisors(h.lead().numer());
+        let mut subset: Vec<usize> = (0..k).collect();
+        loop {
+            if let Some(f) = candidate(&subset, &roots, &leads)
+                && h.div_rem(&f).1.is_zero()
+            {
+                h = h.div_rem(&f).0;
+                for &i in subset.iter().rev() {
+                    roots.remove(i);
+                }
+                factors.push(f);
+                // 残りの根で同じ次数の因数を探す
+                continue 'size;
+            }
+            if !next_combination(&mut subset, roots.len()) {
+                break;
+            }
+        }
+        k += 1;
+    }
+    if h.degree() > 0 {
+        factors.push(h);
+    }
+    factors
+}
+
+/// 根の組subsetを根に持ち, 最高次の係数がleadsのどれかの整数係数の多項式
+fn candidate(subset: &[usize], roots: &[Complex], leads: &[BigInt]) -> Option<Poly> {
+    // Π(x - r)
+    let mut prod = vec![Complex::new(1.0, 0.0)];
+    for &i in subset {
+        let mut next = vec![Complex::new(0.0, 0.0); prod.len() + 1];
+        for (j, c) in prod.iter().enumerate() {
+            next[j + 1] = next[j + 1] + *c;
+            next[j] = next[j] - *c * roots[i];
+        }
+        prod = next;
+    }
+    'lead: for lead in leads {
+        let lead = lead.to_f64()?;
+        let mut coefs = Vec::with_capacity(prod.len());
+        for c in &prod {
+            let (re, im) = (c.re * lead, c.im * lead);
+            let tol = 1e-6 * (1.0 + re.abs());
+            if im.abs() > tol || (re - re.round()).abs() > tol {
+                continue 'lead;
+            }
+            coefs.push(Rational::from_int(BigInt::from(re.round() as i64)));
+        }
+        return Some(Poly::new(coefs));
+    }
+    None
+}
+
+/// n個から選ぶ添字の組を辞書順で次に進める. 最後の組ならfalse
+fn next_combination(subset: &mut [usize], n: usize) -> bool {
+    let k = subset.len();
+    for i in (0..k).rev() {
+        if subset[i] < n - k + i {
+            subset[i] += 1;
+            for j in i + 1..k {
+                subset[j] = subset[j - 1] + 1;
+            }
+            return true;
+        }
+    }
+    false
+}
+
+fn binomial(n: u64, k: u64) -> u64 {
+    (0..k).fold(1u64, |acc, i| acc.saturating_mul(n - i) / (i + 1))
+}
+
+/// nの正の約数. 大きすぎて試し割りできないときは1と|n|だけ
+fn divisors(n: &BigInt) -> Vec<BigInt> {
+    let n = n.abs();
+    let Some(m) = n.to_u64().filter(|m| *m <= 1_000_000_000_000) else {
+        return vec![BigInt::one(), n];
+    };
+    let mut small = Vec::new();
+    let mut large = Vec::new();
+    let mut d = 1;
+    while d * d <= m {
+        if m % d == 0 {
+            small.push(BigInt::from(d));
+            if d * d != m {
+                large.push(BigInt::from(m / d));
+            }
+        }
+        d += 1;
+    }
+    small.extend(large.into_iter().rev());
+    small
+}
+
+/// Aberth法で無平方な多項式の全ての根をf64で求める
+fn aberth(p: &Poly) -> Vec<Complex> {
+    let n = p.degree();
+    let lead = p.lead().to_f64();
+    let c: Vec<f64> = p.coefs.iter().map(|c| c.to_f64() / lead).collect();
+    // Cauchyの上界の円周上に初期値を並べる
+    let radius = 1.0 + c[..n].iter().fold(0.0_f64, |m, c| m.max(c.abs()));
+    let mut z: Vec<Complex> = (0..n)
+        .map(|k| {
+            let t = 2.0 * std::f64::consts::PI * k as f64 / n as f64 + 0.4;
+            Complex::new(radius * t.cos(), radius * t.sin())
+        })
+        .collect();
+    let horner = |x: Complex| {
+        let mut v = Complex::new(0.0, 0.0);
+        let mut dv = Complex::new(0.0, 0.0);
+        for coef in c.iter().rev() {
+            dv = dv * x + v;
+            v = v * x + Complex::new(*coef, 0.0);
+        }
+        (v, dv)
+    };
+    for _ in 0..MAX_ITER {
+        let mut max_step = 0.0_f64;
+        for k in 0..n {
+            let (v, dv) = horner(z[k]);
+            if v.abs() == 0.0 {
+                continue;
+            }
+            let ratio = v / dv;
+            let mut sum = Complex::new(0.0, 0.0);
+            for (j, zj) in z.iter().enumerate() {
+                if j != k {
+                    sum = sum + Complex::new(1.0, 0.0) / (z[k] - *zj);
+                }
+            }
+            let w = ratio / (Complex::new(1.0, 0.0) - ratio * sum);
+            if !w.re.is_finite() || !w.im.is_finite() {
+                continue;
+            }
+            z[k] = z[k] - w;
+            max_step = max_step.max(w.abs() / (1.0 + z[k].abs()));
+        }
+        if max_step < 1e-15 {
+            break;
+        }
+    }
+    z
+}
+
+/// f64の複素数
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, rhs: Complex) -> Complex {
+        let d = rhs.re * rhs.re + rhs.im * rhs.im;
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / d,
+            (self.im * rhs.re - self.re * rhs.im) / d,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+    use bigdecimal::BigDecimal;
+
+    fn poly(form: &str) -> Poly {
+        let (ast, root) = Parser::new(form.to_string(), false)
+            .unwrap()
+            .build_expr()
+            .unwrap();
+        let mut vars = HashMap::new();
+        vars.insert("a".to_string(), BigDecimal::from(3).into());
+        from_ast(&ast, root, "x", form, &vars, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_expand() {
+        let cases = [
+            ("(x - 1)(x + 2)", "x^{2} + x - 2"),
+            ("(x + 1)^{3} - x^{3}", "3x^{2} + 3x + 1"),
+            ("\\frac{x^{2}}{2} - a x", "\\frac{1}{2}x^{2} - 3x"),
+            ("-(x - 1)", "-x + 1"),
+            ("x - x", "0"),
+        ];
+        for (form, expected) in cases {
+            assert_eq!(poly(form).to_tex("x"), expected, "{form}");
+        }
+    }
+
+    #[test]
+    fn test_factor() {
+        let cases = [
+            ("x^{2} + x - 2", "(x - 1)(x + 2)"),
+            ("2x^{3} - 2x", "2x(x - 1)(x + 1)"),
+            ("x^{4} - 1", "(x - 1)(x + 1)(x^{2} + 1)"),
+            ("(x - 1)^{2}(x^{2} - 2)", "(x - 1)^{2}(x^{2} - 2)"),
+            ("x^{4} + 4", "(x^{2} - 2x + 2)(x^{2} + 2x + 2)"),
+            (
+                "\\frac{1}{2}x^{2} - \\frac{1}{8}",
+                "\\frac{1}{8}(2x - 1)(2x + 1)",
+            ),
+            ("-x^{2} - 1", "-(x^{2} + 1)"),
+            ("x^{2} + 1", "x^{2} + 1"),
+            ("6", "6"),
+        ];
+        for (form, expected) in cases {
+            assert_eq!(poly(form).factor().to_tex("x"), expected, "{form}");
+        }
+    }
+
+    #[test]
+    fn test_roots() {
+        let roots = poly("(x - 1)^{2}(x^{2} + 1)(2x + 1)").roots();
+        assert_eq!(roots.len(), 4);
+        assert!(matches!(&roots[0], (Root::Exact(r), 1) if r.to_tex() == "-\\frac{1}{2}"));
+        assert!(matches!(&roots[1], (Root::Exact(r), 2) if r.is_one()));
+        for (root, im) in roots[2..].iter().zip([-1.0, 1.0]) {
+            match root {
+                (Root::Complex(z), 1) => {
+                    assert!(z.re.abs() < 1e-12 && (z.im - im).abs() < 1e-12, "{z:?}")
+                }
+                r => panic!("expected complex root but {r:?}"),
+            }
+        }
+        let roots = poly("x^{3} - 2").roots();
+        match roots[0] {
+            (Root::Real(r), 1) => assert!((r - 2.0_f64.cbrt()).abs() < 1e-14),
+            ref r => panic!("expected real root but {r:?}"),
+        }
+    }
+
+    #[test]
+    fn test_div_rem() {
+        let (q, r) = poly("x^{3} - 2x + 1").div_rem(&poly("x + 2"));
+        assert_eq!(q.to_tex("x"), "x^{2} - 2x + 2");
+        assert_eq!(r.to_tex("x"), "-3");
+    }
+
+    #[test]
+    fn test_not_polynomial() {
+        for form in ["\\sin x", "x^{\\frac{1}{2}}", "\\frac{1}{x}", "2^{x}"] {
+            let (ast, root) = Parser::new(form.to_string(), false)
+                .unwrap()
+                .build_expr()
+                .unwrap();
+            let res = from_ast(&ast, root, "x", form, &HashMap::new(), &Config::default());
+            assert!(matches!(res, Err(MyError::NotPolynomialErr(..))), "{form}");
+        }
+    }
+}
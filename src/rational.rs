@@ -0,0 +1,327 @@
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::ast::{Ast, BinaryOp, NodeId, NodeKind, UnaryOp};
+use crate::compile::Program;
+use crate::config::Config;
+use crate::error::MyError;
+use crate::Value;
+
+/// 誤差なく計算する累乗の指数の絶対値の上限
+const MAX_EXACT_EXP: i32 = 1024;
+
+/// 既約分数で表した有理数
+/// 分母は常に正
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rational {
+    num: BigInt,
+    den: BigInt,
+}
+
+impl Rational {
+    /// num/denを約分して返す. denは0でないこと
+    pub fn new(num: BigInt, den: BigInt) -> Rational {
+        assert!(!den.is_zero(), "denominator 0");
+        let g = num.gcd(&den);
+        let (mut num, mut den) = (num / &g, den / &g);
+        if den.is_negative() {
+            num = -num;
+            den = -den;
+        }
+        Rational { num, den }
+    }
+
+    pub fn from_int(n: impl Into<BigInt>) -> Rational {
+        Rational {
+            num: n.into(),
+            den: BigInt::one(),
+        }
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        &self.num
+    }
+
+    pub fn denom(&self) -> &BigInt {
+        &self.den
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.den.is_one()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.num.is_negative()
+    }
+
+    pub fn abs(&self) -> Rational {
+        Rational {
+            num: self.num.abs(),
+            den: self.den.clone(),
+        }
+    }
+
+    /// 逆数. 0ならNone
+    pub fn recip(&self) -> Option<Rational> {
+        if self.is_zero() {
+            None
+        } else {
+            Some(Rational::new(self.den.clone(), self.num.clone()))
+        }
+    }
+
+    /// 整数乗. 0の負の整数乗はNone
+    pub fn pow(&self, exp: i32) -> Option<Rational> {
+        let base = if exp < 0 { self.recip()? } else { self.clone() };
+        let exp = exp.unsigned_abs();
+        Some(Rational {
+            num: base.num.pow(exp),
+            den: base.den.pow(exp),
+        })
+    }
+
+    /// 10進の有限小数で表せるならtrue
+    pub fn is_terminating(&self) -> bool {
+        let mut den = self.den.clone();
+        for p in [2, 5] {
+            let p = BigInt::from(p);
+            while (&den % &p).is_zero() {
+                den /= &p;
+            }
+        }
+        den.is_one()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_bigdecimal().to_f64().unwrap_or(f64::NAN)
+    }
+
+    /// BigDecimalの精度で割った値
+    pub fn to_bigdecimal(&self) -> BigDecimal {
+        BigDecimal::from(self.num.clone()) / BigDecimal::from(self.den.clone())
+    }
+
+    /// TeXの数式にする
+    /// ex) 3, -\frac{1}{2}
+    pub fn to_tex(&self) -> String {
+        if self.is_integer() {
+            self.num.to_string()
+        } else {
+            let sign = if self.is_negative() { "-" } else { "" };
+            format!("{sign}\\frac{{{}}}{{{}}}", self.num.abs(), self.den)
+        }
+    }
+}
+
+/// Σ coef monomial をTeXにする. monomialが空の項は定数項
+/// ex) -2z + \frac{1}{2}
+pub fn sum_to_tex(terms: &[(Rational, String)]) -> String {
+    let mut tex = String::new();
+    for (coef, mono) in terms.iter().filter(|(c, _)| !c.is_zero()) {
+        tex += match (tex.is_empty(), coef.is_negative()) {
+            (true, true) => "-",
+            (true, false) => "",
+            (false, true) => " - ",
+            (false, false) => " + ",
+        };
+        let abs = coef.abs();
+        if mono.is_empty() || !abs.is_one() {
+            tex += &abs.to_tex();
+        }
+        tex += mono;
+    }
+    if tex.is_empty() {
+        tex.push('0');
+    }
+    tex
+}
+
+impl From<&BigDecimal> for Rational {
+    /// 有限小数なので誤差なく変換できる
+    fn from(n: &BigDecimal) -> Rational {
+        let (digits, scale) = n.as_bigint_and_exponent();
+        let ten = BigInt::from(10);
+        if scale >= 0 {
+            Rational::new(digits, ten.pow(scale as u32))
+        } else {
+            Rational::from_int(digits * ten.pow((-scale) as u32))
+        }
+    }
+}
+
+impl Zero for Rational {
+    fn zero() -> Rational {
+        Rational::from_int(0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.num.is_zero()
+    }
+}
+
+impl One for Rational {
+    fn one() -> Rational {
+        Rational::from_int(1)
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+impl Add<&Rational> for &Rational {
+    type Output = Rational;
+    fn add(self, rhs: &Rational) -> Rational {
+        Rational::new(
+            &self.num * &rhs.den + &rhs.num * &self.den,
+            &self.den * &rhs.den,
+        )
+    }
+}
+
+impl Sub<&Rational> for &Rational {
+    type Output = Rational;
+    fn sub(self, rhs: &Rational) -> Rational {
+        Rational::new(
+            &self.num * &rhs.den - &rhs.num * &self.den,
+            &self.den * &rhs.den,
+        )
+    }
+}
+
+impl Mul<&Rational> for &Rational {
+    type Output = Rational;
+    fn mul(self, rhs: &Rational) -> Rational {
+        Rational::new(&self.num * &rhs.num, &self.den * &rhs.den)
+    }
+}
+
+impl Div<&Rational> for &Rational {
+    type Output = Rational;
+    /// rhsは0でないこと
+    fn div(self, rhs: &Rational) -> Rational {
+        Rational::new(&self.num * &rhs.den, &self.den * &rhs.num)
+    }
+}
+
+impl Neg for &Rational {
+    type Output = Rational;
+    fn neg(self) -> Rational {
+        Rational {
+            num: -&self.num,
+            den: self.den.clone(),
+        }
+    }
+}
+
+macro_rules! forward_owned {
+    ($($trait:ident $method:ident),*) => {$(
+        impl $trait<Rational> for Rational {
+            type Output = Rational;
+            fn $method(self, rhs: Rational) -> Rational {
+                (&self).$method(&rhs)
+            }
+        }
+    )*};
+}
+forward_owned!(Add add, Sub sub, Mul mul, Div div);
+
+impl Neg for Rational {
+    type Output = Rational;
+    fn neg(self) -> Rational {
+        -&self
+    }
+}
+
+/// ASTの変数を含まない部分式を評価する
+pub struct ExactEval<'a> {
+    pub ast: &'a Ast,
+    pub form: &'a str,
+    pub vars: &'a HashMap<String, Value>,
+    pub conf: &'a Config,
+}
+
+impl ExactEval<'_> {
+    /// idの式の値. 有理数の演算で済む所は誤差なく求める
+    pub fn value(&self, id: NodeId) -> Result<Rational, MyError> {
+        match &self.ast[id].kind {
+            NodeKind::Num(n) => Ok(Rational::from(n)),
+            NodeKind::Unary(UnaryOp::Neg, arg) => Ok(-self.value(*arg)?),
+            NodeKind::Binary(op @ (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul), lhs, rhs) => {
+                let (l, r) = (self.value(*lhs)?, self.value(*rhs)?);
+                Ok(match op {
+                    BinaryOp::Add => l + r,
+                    BinaryOp::Sub => l - r,
+                    _ => l * r,
+                })
+            }
+            NodeKind::Binary(BinaryOp::Div, lhs, rhs) => match self.value(*rhs)?.recip() {
+                Some(k) => Ok(self.value(*lhs)? * k),
+                None => self.eval(id),
+            },
+            NodeKind::Binary(BinaryOp::Pow, lhs, rhs) => {
+                let exp = self.value(*rhs)?;
+                let exact = match i32::try_from(exp.numer()) {
+                    Ok(e) if exp.is_integer() && e.abs() <= MAX_EXACT_EXP => {
+                        self.value(*lhs)?.pow(e)
+                    }
+                    _ => None,
+                };
+                match exact {
+                    Some(v) => Ok(v),
+                    None => self.eval(id),
+                }
+            }
+            _ => self.eval(id),
+        }
+    }
+
+    /// idの式をBigDecimalで評価する
+    fn eval(&self, id: NodeId) -> Result<Rational, MyError> {
+        let prog = Program::compile(self.ast, id, self.form);
+        let val = prog.eval(&prog.bind(self.vars)?, self.conf)?;
+        Ok(Rational::from(&val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn r(num: i64, den: i64) -> Rational {
+        Rational::new(BigInt::from(num), BigInt::from(den))
+    }
+
+    #[test]
+    fn test_rational() {
+        assert_eq!(r(2, -4), r(-1, 2));
+        assert_eq!(r(1, 2) + r(1, 3), r(5, 6));
+        assert_eq!(r(1, 2) - r(1, 3), r(1, 6));
+        assert_eq!(r(2, 3) * r(3, 4), r(1, 2));
+        assert_eq!(r(2, 3) / r(4, 3), r(1, 2));
+        assert_eq!(r(2, 3).pow(-2), Some(r(9, 4)));
+        assert_eq!(Rational::zero().pow(-1), None);
+        let d = BigDecimal::from_str("-1.25").unwrap();
+        assert_eq!(Rational::from(&d), r(-5, 4));
+        assert_eq!(
+            Rational::from(&BigDecimal::from_str("3e2").unwrap()),
+            r(300, 1)
+        );
+        assert_eq!(r(-5, 4).to_tex(), "-\\frac{5}{4}");
+        assert_eq!(r(6, 3).to_tex(), "2");
+        assert!(r(7, 20).is_terminating());
+        assert!(!r(1, 3).is_terminating());
+    }
+}
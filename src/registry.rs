@@ -0,0 +1,449 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use crate::ast::{Ast, BinaryOp, NodeId, UnaryOp};
+use crate::distributions::DistFn;
+use crate::error::*;
+use crate::parser::Parser;
+use crate::special_functions::SpecialFn;
+use crate::stats::ListFn;
+
+/*
+文法は演算子と TeX コマンドの表から決まる (Pratt parser)
+
+expr_vec  = ";"? (expr | tsc_cmd) (";"? expr | ";"? tsc_cmd)*
+expr(p)   = prefix (infix expr(q + 1) | postfix "{" expr "}" | implicit_mul expr(PREC_IMPLICIT_MUL + 1))*
+            ただし演算子の結合力q >= pのものだけを読む
+prefix    = PREFIX_OPS expr(q) | primary
+primary   = num | var | "(" expr ")" | command
+command   = ArgStyle::Brace  : "\name" ("{" expr "}" | primary){arity}
+          | ArgStyle::Paren  : "\name" "(" expr ")"
+          | ArgStyle::Signed : "\name" expr(prec)
+*/
+
+/// 加減算の結合力
+pub const PREC_SUM: u8 = 10;
+/// 乗除算の結合力
+pub const PREC_PRODUCT: u8 = 20;
+/// 暗黙の乗算(2x)の結合力. 明示的な乗除算より強く結合する
+pub const PREC_IMPLICIT_MUL: u8 = 30;
+/// 累乗, 単項マイナスの結合力
+pub const PREC_POWER: u8 = 40;
+/// 括弧が要らない式の結合力
+pub const PREC_ATOM: u8 = u8::MAX;
+
+/// 演算子の定義
+pub struct OpDef<Op> {
+    pub token: &'static str,
+    pub op: Op,
+    pub prec: u8,
+}
+
+/// 左結合の中置演算子
+pub const INFIX_OPS: [OpDef<BinaryOp>; 7] = [
+    OpDef {
+        token: "+",
+        op: BinaryOp::Add,
+        prec: PREC_SUM,
+    },
+    OpDef {
+        token: "-",
+        op: BinaryOp::Sub,
+        prec: PREC_SUM,
+    },
+    OpDef {
+        token: "*",
+        op: BinaryOp::Mul,
+        prec: PREC_PRODUCT,
+    },
+    OpDef {
+        token: "\\times",
+        op: BinaryOp::Mul,
+        prec: PREC_PRODUCT,
+    },
+    OpDef {
+        token: "\\cdot",
+        op: BinaryOp::Mul,
+        prec: PREC_PRODUCT,
+    },
+    OpDef {
+        token: "/",
+        op: BinaryOp::Div,
+        prec: PREC_PRODUCT,
+    },
+    OpDef {
+        token: "\\div",
+        op: BinaryOp::Div,
+        prec: PREC_PRODUCT,
+    },
+];
+
+/// 前置演算子. 被演算子を結合力precで読む
+pub const PREFIX_OPS: [OpDef<UnaryOp>; 1] = [OpDef {
+    token: "-",
+    op: UnaryOp::Neg,
+    prec: PREC_POWER,
+}];
+
+/// 後置演算子. "{" expr "}" を右の被演算子とする
+pub const POSTFIX_OPS: [OpDef<BinaryOp>; 1] = [OpDef {
+    token: "^",
+    op: BinaryOp::Pow,
+    prec: PREC_POWER,
+}];
+
+pub fn infix_op(token: &str) -> Option<&'static OpDef<BinaryOp>> {
+    INFIX_OPS.iter().find(|op| op.token == token)
+}
+
+pub fn prefix_op(token: &str) -> Option<&'static OpDef<UnaryOp>> {
+    PREFIX_OPS.iter().find(|op| op.token == token)
+}
+
+pub fn postfix_op(token: &str) -> Option<&'static OpDef<BinaryOp>> {
+    POSTFIX_OPS.iter().find(|op| op.token == token)
+}
+
+/// TeX コマンドの引数の書き方
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgStyle {
+    /// \frac{a}{b}. 括弧を省略した\sqrt 2のようにprimaryも引数にできる
+    Brace,
+    /// \abs(a)
+    Paren,
+    /// \sin a. 結合力precで被演算子を1つ読む
+    Signed,
+}
+
+impl fmt::Display for ArgStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgStyle::Brace => write!(f, "brace"),
+            ArgStyle::Paren => write!(f, "paren"),
+            ArgStyle::Signed => write!(f, "signed"),
+        }
+    }
+}
+
+/// Rustで書かれた関数
+pub type NativeFn = Arc<dyn Fn(&[BigDecimal]) -> Result<BigDecimal, MyError> + Send + Sync>;
+
+/// 組み込みのコマンドが表す演算
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Builtin {
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+}
+
+/// TeX コマンドの評価方法
+#[derive(Clone)]
+pub enum Evaluator {
+    /// calcで評価する組み込みの演算
+    Builtin(Builtin),
+    /// crateの利用者が登録した関数
+    Native(NativeFn),
+    /// 設定ファイルで定義された関数: 引数名と本体の数式
+    Defined { params: Vec<String>, body: Body },
+    /// 可変個の引数をリストとして受け取る組み込みの統計関数
+    List(ListFn),
+    /// 組み込みの特殊関数
+    Special(SpecialFn),
+}
+
+/// 設定ファイルで定義された関数の本体
+/// 構文解析したASTを持っておき, 呼び出すたびに解析し直さない
+#[derive(Clone)]
+pub struct Body {
+    pub form: String,
+    ast: Arc<OnceLock<(Ast, NodeId)>>,
+}
+
+impl Body {
+    pub fn new(form: &str) -> Body {
+        Body {
+            form: form.to_string(),
+            ast: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// 本体のASTを返す. まだ構文解析していなければ解析する
+    pub fn ast(&self) -> Result<&(Ast, NodeId), MyError> {
+        if let Some(ast) = self.ast.get() {
+            return Ok(ast);
+        }
+        let parsed = Parser::new(self.form.clone(), false)?.build_expr()?;
+        Ok(self.ast.get_or_init(|| parsed))
+    }
+}
+
+/// 登録された TeX コマンド
+#[derive(Clone)]
+pub struct Command {
+    pub name: String, // "\sin"のように\から始まる名前
+    pub arity: usize,
+    pub arg_style: ArgStyle,
+    pub prec: u8,
+    pub eval: Evaluator,
+}
+
+impl Command {
+    /// Rustの関数をTeX コマンドにする
+    ///
+    /// * `name` - コマンド名. \は省略できる
+    /// * `arity` - 引数の数
+    /// * `arg_style` - 引数の書き方
+    /// * `f` - 引数を受け取って値を返す関数
+    pub fn native<F>(name: &str, arity: usize, arg_style: ArgStyle, f: F) -> Command
+    where
+        F: Fn(&[BigDecimal]) -> Result<BigDecimal, MyError> + Send + Sync + 'static,
+    {
+        Command {
+            name: command_name(name),
+            arity,
+            arg_style,
+            prec: PREC_POWER,
+            eval: Evaluator::Native(Arc::new(f)),
+        }
+    }
+}
+
+/// 設定ファイルの[[functions]]
+/// ```toml
+/// [[functions]]
+/// name = "sinh"
+/// params = ["x"]
+/// style = "signed"
+/// prec = 40
+/// body = "\\frac{\\exp(x) - \\exp(-x)}{2}"
+/// ```
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FuncDef {
+    pub name: String,
+    pub params: Vec<String>,
+    #[serde(default = "default_style")]
+    pub style: ArgStyle,
+    /// signedのとき被演算子を読む結合力
+    #[serde(default = "default_prec")]
+    pub prec: u8,
+    pub body: String,
+}
+
+fn default_style() -> ArgStyle {
+    ArgStyle::Brace
+}
+
+fn default_prec() -> u8 {
+    PREC_POWER
+}
+
+impl From<&FuncDef> for Command {
+    fn from(def: &FuncDef) -> Self {
+        Command {
+            name: command_name(&def.name),
+            arity: def.params.len(),
+            arg_style: def.style,
+            prec: def.prec,
+            eval: Evaluator::Defined {
+                params: def.params.clone(),
+                body: Body::new(&def.body),
+            },
+        }
+    }
+}
+
+/// nameに\が無ければ付ける
+fn command_name(name: &str) -> String {
+    if name.starts_with('\\') {
+        name.to_string()
+    } else {
+        format!("\\{name}")
+    }
+}
+
+pub struct Registry {
+    commands: Vec<Command>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let builtin = |name: &str, arity, arg_style, kind| Command {
+            name: name.to_string(),
+            arity,
+            arg_style,
+            prec: PREC_POWER,
+            eval: Evaluator::Builtin(kind),
+        };
+        let commands = vec![
+            builtin("\\frac", 2, ArgStyle::Brace, Builtin::Binary(BinaryOp::Div)),
+            builtin("\\sqrt", 1, ArgStyle::Brace, Builtin::Unary(UnaryOp::Sqrt)),
+            builtin("\\abs", 1, ArgStyle::Paren, Builtin::Unary(UnaryOp::Abs)),
+            builtin("\\exp", 1, ArgStyle::Paren, Builtin::Unary(UnaryOp::Exp)),
+            builtin("\\log", 1, ArgStyle::Signed, Builtin::Unary(UnaryOp::Log)),
+            builtin("\\ln", 1, ArgStyle::Signed, Builtin::Unary(UnaryOp::Ln)),
+            builtin("\\sin", 1, ArgStyle::Signed, Builtin::Unary(UnaryOp::Sin)),
+            builtin("\\cos", 1, ArgStyle::Signed, Builtin::Unary(UnaryOp::Cos)),
+            builtin("\\tan", 1, ArgStyle::Signed, Builtin::Unary(UnaryOp::Tan)),
+            builtin("\\csc", 1, ArgStyle::Signed, Builtin::Unary(UnaryOp::Csc)),
+            builtin("\\sec", 1, ArgStyle::Signed, Builtin::Unary(UnaryOp::Sec)),
+            builtin("\\cot", 1, ArgStyle::Signed, Builtin::Unary(UnaryOp::Cot)),
+            builtin(
+                "\\arcsin",
+                1,
+                ArgStyle::Signed,
+                Builtin::Unary(UnaryOp::AcSin),
+            ),
+            builtin(
+                "\\arccos",
+                1,
+                ArgStyle::Signed,
+                Builtin::Unary(UnaryOp::AcCos),
+            ),
+            builtin(
+                "\\arctan",
+                1,
+                ArgStyle::Signed,
+                Builtin::Unary(UnaryOp::AcTan),
+            ),
+        ];
+        let commands = commands
+            .into_iter()
+            .chain(ListFn::ALL.iter().map(|&(name, f)| Command {
+                name: name.to_string(),
+                arity: 1,
+                arg_style: ArgStyle::Paren,
+                prec: PREC_POWER,
+                eval: Evaluator::List(f),
+            }))
+            .chain(
+                SpecialFn::ALL
+                    .iter()
+                    .copied()
+                    .chain(
+                        DistFn::ALL
+                            .iter()
+                            .map(|&(name, _, f)| (name, SpecialFn::Dist(f))),
+                    )
+                    .map(|(name, f)| Command {
+                        name: name.to_string(),
+                        arity: *f.arity().end(),
+                        arg_style: ArgStyle::Paren,
+                        prec: PREC_POWER,
+                        eval: Evaluator::Special(f),
+                    }),
+            )
+            .collect();
+        Registry { commands }
+    }
+}
+
+impl Registry {
+    /// 名前がnameのコマンドのidとコマンドを返す
+    pub fn lookup(&self, name: &str) -> Option<(usize, &Command)> {
+        self.commands
+            .iter()
+            .enumerate()
+            .find(|(_, cmd)| cmd.name == name)
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Command> {
+        self.commands.get(id)
+    }
+
+    /// 組み込みの演算bを表すコマンドを返す
+    pub fn builtin(&self, b: Builtin) -> Option<&Command> {
+        self.commands
+            .iter()
+            .find(|cmd| matches!(cmd.eval, Evaluator::Builtin(c) if c == b))
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &Command> {
+        self.commands.iter()
+    }
+
+    /// コマンドを登録し, idを返す
+    /// 同じ名前の組み込みでないコマンドがあれば置き換える
+    pub fn register(&mut self, cmd: Command) -> Result<usize, MyError> {
+        let err = |msg: &str| Err(MyError::RegisterErr(cmd.name.clone(), msg.to_string()));
+        let name = &cmd.name[1..];
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+            return err("name must consist of ASCII letters");
+        }
+        if infix_op(&cmd.name).is_some() {
+            return err("name is an operator");
+        }
+        match cmd.arg_style {
+            ArgStyle::Signed if cmd.arity != 1 => return err("signed style takes 1 argument"),
+            ArgStyle::Paren if cmd.arity > 1 => return err("paren style takes at most 1 argument"),
+            _ => (),
+        }
+        match self.lookup(&cmd.name) {
+            Some((
+                _,
+                Command {
+                    eval: Evaluator::Builtin(_) | Evaluator::List(_) | Evaluator::Special(_),
+                    ..
+                },
+            )) => err("builtin command"),
+            Some((id, _)) => {
+                self.commands[id] = cmd;
+                Ok(id)
+            }
+            None => {
+                self.commands.push(cmd);
+                Ok(self.commands.len() - 1)
+            }
+        }
+    }
+}
+
+/// TeX コマンドを登録する
+///
+/// ex)
+/// ```
+/// use tsc::registry::{register, ArgStyle, Command};
+/// register(Command::native("double", 1, ArgStyle::Paren, |args| {
+///     Ok(&args[0] + &args[0])
+/// }))
+/// .unwrap();
+/// ```
+pub fn register(cmd: Command) -> Result<usize, MyError> {
+    registry_writer()?.register(cmd)
+}
+
+/// 設定ファイルで定義された関数を登録する
+/// 本体は全ての関数を登録してから構文解析するので, 後に定義した関数も呼べる
+pub fn register_functions(defs: &[FuncDef]) -> Result<(), MyError> {
+    let mut bodies = Vec::new();
+    {
+        let mut registry = registry_writer()?;
+        for def in defs {
+            let cmd = Command::from(def);
+            if let Evaluator::Defined { body, .. } = &cmd.eval {
+                bodies.push(body.clone());
+            }
+            registry.register(cmd)?;
+        }
+    }
+    for body in bodies {
+        body.ast()?;
+    }
+    Ok(())
+}
+
+pub fn registry_reader() -> Result<std::sync::RwLockReadGuard<'static, Registry>, MyError> {
+    // 登録中でなければすぐに読めるので, 待ってもよい
+    match crate::REGISTRY.read() {
+        Ok(r) => Ok(r),
+        Err(e) => Err(MyError::RegistryReadErr(e.to_string())),
+    }
+}
+
+pub fn registry_writer() -> Result<std::sync::RwLockWriteGuard<'static, Registry>, MyError> {
+    match crate::REGISTRY.write() {
+        Ok(r) => Ok(r),
+        Err(e) => Err(MyError::RegistryWriteErr(e.to_string())),
+    }
+}
@@ -0,0 +1,360 @@
+//! リストの統計量と最小二乗法によるあてはめ
+
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use num_traits::{One, Zero};
+
+use crate::error::MyError;
+use crate::rational::Rational;
+
+/// リストを引数にとる組み込みの関数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListFn {
+    Sum,
+    Mean,
+    Median,
+    /// 不偏分散
+    Var,
+    /// 不偏分散の平方根
+    Sd,
+    Min,
+    Max,
+    /// 最後の引数pに対する分位数
+    Quantile,
+}
+
+impl ListFn {
+    /// コマンド名と関数
+    pub const ALL: [(&'static str, ListFn); 8] = [
+        ("\\sum", ListFn::Sum),
+        ("\\mean", ListFn::Mean),
+        ("\\median", ListFn::Median),
+        ("\\var", ListFn::Var),
+        ("\\sd", ListFn::Sd),
+        ("\\min", ListFn::Min),
+        ("\\max", ListFn::Max),
+        ("\\quantile", ListFn::Quantile),
+    ];
+
+    /// TeXでの書き方. LaTeXに無いコマンドは\operatornameで書く
+    pub fn tex_name(self) -> String {
+        let name = ListFn::ALL.iter().find(|(_, f)| *f == self).unwrap().0;
+        match self {
+            ListFn::Sum | ListFn::Min | ListFn::Max => name.to_string(),
+            _ => format!("\\operatorname{{{}}}", &name[1..]),
+        }
+    }
+
+    /// 引数の値から関数の値を求める
+    pub fn eval(self, args: &[BigDecimal]) -> Result<BigDecimal, MyError> {
+        let (data, p) = match self {
+            ListFn::Quantile => match args.split_last() {
+                Some((p, data)) => (data, Some(p)),
+                None => (args, None),
+            },
+            _ => (args, None),
+        };
+        let name = self.tex_name();
+        if data.is_empty() {
+            return Err(MyError::CalcErr(format!("{name} of no values")));
+        }
+        let n = BigDecimal::from(data.len() as u64);
+        Ok(match self {
+            ListFn::Sum => data.iter().sum(),
+            ListFn::Mean => mean(data),
+            ListFn::Median => quantile(data, &BigDecimal::new(5.into(), 1)),
+            ListFn::Var | ListFn::Sd => {
+                if data.len() < 2 {
+                    return Err(MyError::CalcErr(format!("{name} needs at least 2 values")));
+                }
+                let m = mean(data);
+                let ss: BigDecimal = data.iter().map(|x| (x - &m) * (x - &m)).sum();
+                let var = ss / (n - BigDecimal::one());
+                match self {
+                    ListFn::Var => var,
+                    _ => var
+                        .sqrt()
+                        .ok_or(MyError::CalcErr(format!("\\sqrt{{{var}}}")))?,
+                }
+            }
+            ListFn::Min => data.iter().min().unwrap().clone(),
+            ListFn::Max => data.iter().max().unwrap().clone(),
+            ListFn::Quantile => {
+                let p = p.unwrap();
+                if *p < BigDecimal::zero() || *p > BigDecimal::one() {
+                    return Err(MyError::CalcErr(format!(
+                        "{name} for p = {p} not in [0, 1]"
+                    )));
+                }
+                quantile(data, p)
+            }
+        })
+    }
+}
+
+fn mean(data: &[BigDecimal]) -> BigDecimal {
+    data.iter().sum::<BigDecimal>() / BigDecimal::from(data.len() as u64)
+}
+
+/// 順位を線形補間したp分位数. dataは空でないこと
+fn quantile(data: &[BigDecimal], p: &BigDecimal) -> BigDecimal {
+    let mut sorted = data.to_vec();
+    sorted.sort();
+    let h = p * BigDecimal::from((sorted.len() - 1) as u64);
+    let lo = h.with_scale_round(0, bigdecimal::RoundingMode::Floor);
+    let i = lo.to_usize().unwrap_or(0);
+    match sorted.get(i + 1) {
+        Some(next) => (&sorted[i] + (h - lo) * (next - &sorted[i])).normalized(),
+        None => sorted[i].clone(),
+    }
+}
+
+/// 区間の数をbinsとした度数分布. (下端, 上端, 度数)の列
+/// 最後の区間だけは上端を含む
+pub fn histogram(data: &[f64], bins: usize) -> Vec<(f64, f64, usize)> {
+    let lo = data.iter().copied().fold(f64::INFINITY, f64::min);
+    let hi = data.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let width = if hi > lo {
+        (hi - lo) / bins as f64
+    } else {
+        1.0
+    };
+    let mut counts = vec![0; bins];
+    for &x in data {
+        let i = ((x - lo) / width) as usize;
+        counts[i.min(bins - 1)] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| (lo + width * i as f64, lo + width * (i + 1) as f64, c))
+        .collect()
+}
+
+/// Sturgesの公式による区間の数
+pub fn sturges(n: usize) -> usize {
+    (n.max(1) as f64).log2().ceil() as usize + 1
+}
+
+/// あてはめるモデル
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+    /// n次の多項式 a_0 + a_1 x + ... + a_n x^n. 1次は直線
+    Poly(usize),
+    /// a e^{b x}
+    Exp,
+}
+
+/// あてはめた係数と決定係数
+pub struct Fit {
+    pub coefs: Vec<BigDecimal>,
+    pub r2: BigDecimal,
+}
+
+/// 最小二乗法でモデルをあてはめる
+/// 多項式は正規方程式を有理数のまま解くので, 係数に丸め誤差が入らない
+pub fn fit(model: Model, xs: &[BigDecimal], ys: &[BigDecimal]) -> Result<Fit, MyError> {
+    if xs.len() != ys.len() {
+        return Err(MyError::CalcErr(format!(
+            "lists have different lengths {} and {}",
+            xs.len(),
+            ys.len()
+        )));
+    }
+    match model {
+        Model::Poly(deg) => {
+            let xs: Vec<Rational> = xs.iter().map(Rational::from).collect();
+            let ys: Vec<Rational> = ys.iter().map(Rational::from).collect();
+            let coefs = poly_least_squares(&xs, &ys, deg)?;
+            let predict = |x: &Rational| {
+                coefs
+                    .iter()
+                    .rev()
+                    .fold(Rational::zero(), |acc, c| &(&acc * x) + c)
+            };
+            let n = Rational::from_int(ys.len() as i64);
+            let m = &ys.iter().fold(Rational::zero(), |acc, y| &acc + y) / &n;
+            let (mut ss_res, mut ss_tot) = (Rational::zero(), Rational::zero());
+            for (x, y) in xs.iter().zip(&ys) {
+                let r = y - &predict(x);
+                ss_res = &ss_res + &(&r * &r);
+                let d = y - &m;
+                ss_tot = &ss_tot + &(&d * &d);
+            }
+            let r2 = match ss_tot.recip() {
+                Some(inv) => &Rational::one() - &(&ss_res * &inv),
+                None => Rational::one(),
+            };
+            Ok(Fit {
+                coefs: coefs.iter().map(Rational::to_bigdecimal).collect(),
+                r2: r2.to_bigdecimal(),
+            })
+        }
+        Model::Exp => {
+            // ln y = ln a + b x を直線であてはめる
+            let to_f64 = |v: &BigDecimal| v.to_f64().unwrap_or(f64::NAN);
+            let xs: Vec<f64> = xs.iter().map(to_f64).collect();
+            let ys: Vec<f64> = ys.iter().map(to_f64).collect();
+            if let Some(y) = ys.iter().find(|y| y.is_nan() || **y <= 0.0) {
+                return Err(MyError::CalcErr(format!(
+                    "exponential fit needs positive values but {y}"
+                )));
+            }
+            let to_rational = |v: f64| BigDecimal::from_f64(v).map(|v| Rational::from(&v));
+            let lx: Option<Vec<Rational>> = xs.iter().map(|&x| to_rational(x)).collect();
+            let ly: Option<Vec<Rational>> = ys.iter().map(|&y| to_rational(y.ln())).collect();
+            let (Some(lx), Some(ly)) = (lx, ly) else {
+                return Err(MyError::CalcErr("values are not finite".to_string()));
+            };
+            let c = poly_least_squares(&lx, &ly, 1)?;
+            let (a, b) = (c[0].to_f64().exp(), c[1].to_f64());
+            let m = ys.iter().sum::<f64>() / ys.len() as f64;
+            let ss_res: f64 = xs
+                .iter()
+                .zip(&ys)
+                .map(|(x, y)| (y - a * (b * x).exp()).powi(2))
+                .sum();
+            let ss_tot: f64 = ys.iter().map(|y| (y - m).powi(2)).sum();
+            let r2 = if ss_tot > 0.0 {
+                1.0 - ss_res / ss_tot
+            } else {
+                1.0
+            };
+            let dec = |v: f64| {
+                BigDecimal::from_f64(v)
+                    .map(|v| v.with_prec(15).normalized())
+                    .ok_or(MyError::CalcErr(format!("{v} is not a number")))
+            };
+            Ok(Fit {
+                coefs: vec![dec(a)?, dec(b)?],
+                r2: dec(r2)?,
+            })
+        }
+    }
+}
+
+/// deg次の多項式の係数を, 正規方程式を掃き出して求める
+fn poly_least_squares(
+    xs: &[Rational],
+    ys: &[Rational],
+    deg: usize,
+) -> Result<Vec<Rational>, MyError> {
+    let n = deg + 1;
+    // 係数行列 Σx^{i+j} と右辺 Σx^i y を拡大行列にする
+    let mut moments = vec![Rational::zero(); 2 * deg + 1];
+    let mut rhs = vec![Rational::zero(); n];
+    for (x, y) in xs.iter().zip(ys) {
+        let mut pow = Rational::one();
+        for (k, m) in moments.iter_mut().enumerate() {
+            *m = &*m + &pow;
+            if k < n {
+                rhs[k] = &rhs[k] + &(&pow * y);
+            }
+            pow = &pow * x;
+        }
+    }
+    let mut rows: Vec<Vec<Rational>> = (0..n)
+        .map(|i| {
+            let mut row = moments[i..i + n].to_vec();
+            row.push(rhs[i].clone());
+            row
+        })
+        .collect();
+    for col in 0..n {
+        let Some(pivot) = (col..n).find(|&r| !rows[r][col].is_zero()) else {
+            return Err(MyError::CalcErr(format!(
+                "degree {deg} needs at least {n} distinct x values"
+            )));
+        };
+        rows.swap(col, pivot);
+        let inv = rows[col][col].recip().unwrap();
+        for v in rows[col].iter_mut() {
+            *v = &*v * &inv;
+        }
+        let pivot_row = rows[col].clone();
+        for (r, row) in rows.iter_mut().enumerate() {
+            if r != col && !row[col].is_zero() {
+                let k = row[col].clone();
+                for (v, p) in row.iter_mut().zip(&pivot_row).skip(col) {
+                    *v = &*v - &(&k * p);
+                }
+            }
+        }
+    }
+    Ok(rows.into_iter().map(|row| row[n].clone()).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn decs(v: &[&str]) -> Vec<BigDecimal> {
+        v.iter().map(|s| BigDecimal::from_str(s).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_list_fn() {
+        let data = decs(&["2", "4", "4", "4", "5", "5", "7", "9"]);
+        let eval = |f: ListFn| f.eval(&data).unwrap();
+        assert_eq!(eval(ListFn::Sum), BigDecimal::from(40));
+        assert_eq!(eval(ListFn::Mean), BigDecimal::from(5));
+        assert_eq!(eval(ListFn::Median), BigDecimal::from_str("4.5").unwrap());
+        assert_eq!(
+            eval(ListFn::Var),
+            BigDecimal::from(32) / BigDecimal::from(7)
+        );
+        assert_eq!(eval(ListFn::Min), BigDecimal::from(2));
+        assert_eq!(eval(ListFn::Max), BigDecimal::from(9));
+        let q = |p: &str| {
+            let mut args = data.clone();
+            args.push(BigDecimal::from_str(p).unwrap());
+            ListFn::Quantile.eval(&args).unwrap()
+        };
+        assert_eq!(q("0"), BigDecimal::from(2));
+        assert_eq!(q("0.25"), BigDecimal::from(4));
+        assert_eq!(q("1"), BigDecimal::from(9));
+        assert!(ListFn::Quantile.eval(&decs(&["1", "0.5"])).is_ok());
+        assert!(ListFn::Quantile.eval(&decs(&["1", "2"])[..1]).is_err());
+        assert!(ListFn::Var.eval(&decs(&["1"])).is_err());
+        assert_eq!(ListFn::Sd.tex_name(), "\\operatorname{sd}");
+    }
+
+    #[test]
+    fn test_histogram() {
+        let h = histogram(&[0.0, 1.0, 1.5, 2.0, 4.0], 2);
+        assert_eq!(h, [(0.0, 2.0, 3), (2.0, 4.0, 2)]);
+        assert_eq!(sturges(8), 4);
+    }
+
+    #[test]
+    fn test_fit() {
+        // y = 1 + 2x + 3x^2 は厳密に求まる
+        let xs = decs(&["0", "1", "2", "3", "4"]);
+        let ys = decs(&["1", "6", "17", "34", "57"]);
+        let fit = super::fit(Model::Poly(2), &xs, &ys).unwrap();
+        assert_eq!(fit.coefs, decs(&["1", "2", "3"]));
+        assert_eq!(fit.r2, BigDecimal::one());
+        let fit = super::fit(
+            Model::Poly(1),
+            &decs(&["0", "1", "2"]),
+            &decs(&["0", "2", "1"]),
+        )
+        .unwrap();
+        assert_eq!(fit.coefs, decs(&["0.5", "0.5"]));
+        assert_eq!(fit.r2, BigDecimal::from_str("0.25").unwrap());
+        // y = 2e^{0.5x}
+        let ys: Vec<BigDecimal> = [0.0f64, 1.0, 2.0, 3.0, 4.0]
+            .iter()
+            .map(|x| BigDecimal::from_f64(2.0 * (0.5 * x).exp()).unwrap())
+            .collect();
+        let fit = super::fit(Model::Exp, &xs, &ys).unwrap();
+        assert_eq!(fit.coefs, decs(&["2", "0.5"]));
+        assert!(super::fit(Model::Exp, &xs, &decs(&["1", "0", "1", "1", "1"])).is_err());
+        assert!(super::fit(
+            Model::Poly(2),
+            &decs(&["1", "1", "1"]),
+            &decs(&["1", "2", "3"])
+        )
+        .is_err());
+    }
+}
@@ -33,12 +33,10 @@ fn hex2dec_f64(num_str: &str) -> Result<f64, MyError> {
 
 fn hex2dec_u64(num_str: &str) -> Result<u64, MyError> {
     let mut num: u64 = 0;
-    let mut figure: u32 = 1;
     for i in num_str.chars() {
         match u64::from_str(&i.to_string()) {
             Ok(n) => {
-                num += n * 16_u64.pow(num_str.len() as u32 - figure);
-                figure += 1;
+                num = push_digit_u64(num, 16, n, num_str)?;
             }
             Err(_) => {
                 let n: u64 = match &i.to_string()[0..1] {
@@ -50,14 +48,21 @@ fn hex2dec_u64(num_str: &str) -> Result<u64, MyError> {
                     "f" | "F" => 15,
                     _ => return Err(MyError::InvalidHexFormat(num_str.to_string())),
                 };
-                num += n * 16_u64.pow(num_str.len() as u32 - figure);
-                figure += 1;
+                num = push_digit_u64(num, 16, n, num_str)?;
             }
         }
     }
     Ok(num)
 }
 
+/// numの末尾にradix進数の1桁nを追加した値を返す
+/// u64に収まらない場合はエラー
+fn push_digit_u64(num: u64, radix: u64, n: u64, num_str: &str) -> Result<u64, MyError> {
+    num.checked_mul(radix)
+        .and_then(|num| num.checked_add(n))
+        .ok_or(MyError::ParseU64Error(num_str.to_string()))
+}
+
 fn bin2dec_f64(num_str: &str) -> Result<f64, MyError> {
     let mut num: f64 = 0.0;
     let mut figure: f64 = 1.0;
@@ -78,15 +83,13 @@ fn bin2dec_f64(num_str: &str) -> Result<f64, MyError> {
 
 fn bin2dec_u64(num_str: &str) -> Result<u64, MyError> {
     let mut num: u64 = 0;
-    let mut figure: u32 = 1;
     for i in num_str.chars() {
         match u64::from_str(&i.to_string()) {
             Ok(n) => {
                 if n > 1 {
                     return Err(MyError::InvalidBinFormat(num_str.to_string()));
                 }
-                num += n * 2_u64.pow(num_str.len() as u32 - figure);
-                figure += 1;
+                num = push_digit_u64(num, 2, n, num_str)?;
             }
             Err(e) => return Err(MyError::ParseIntError(e)),
         }
@@ -114,15 +117,13 @@ fn oct2dec_f64(num_str: &str) -> Result<f64, MyError> {
 
 fn oct2dec_u64(num_str: &str) -> Result<u64, MyError> {
     let mut num: u64 = 0;
-    let mut figure: u32 = 1;
     for i in num_str.chars() {
         match u64::from_str(&i.to_string()) {
             Ok(n) => {
                 if n > 7 {
                     return Err(MyError::InvalidOctalFormat(num_str.to_string()));
                 }
-                num += n * 8_u64.pow(num_str.len() as u32 - figure);
-                figure += 1;
+                num = push_digit_u64(num, 8, n, num_str)?;
             }
             Err(e) => return Err(MyError::ParseIntError(e)),
         }
@@ -289,6 +290,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::vec_init_then_push)]
     fn test_bigdecimal_from_str() {
         let mut test_cases = Vec::new();
         test_cases.push(TestCaseBigDecimal {
@@ -325,6 +327,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::vec_init_then_push)]
     fn test_f64_from_str() {
         let mut test_cases = Vec::new();
         test_cases.push(TestCaseF64 {
@@ -361,6 +364,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::vec_init_then_push)]
     fn test_u64_from_str() {
         let mut test_cases = Vec::new();
         test_cases.push(TestCaseU64 {
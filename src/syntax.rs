@@ -0,0 +1,652 @@
+//! TeX以外の入力形式
+//!
+//! - sexpr: `(+ (sin x) (expt 2 3))`
+//! - plain: `sin(x) + 2^3`
+//! - mathml: `<math><msqrt><mi>x</mi></msqrt></math>` (Presentation MathML)
+//! - asciimath: `sqrt x + 2^3`
+//!
+//! どの形式もTeXと同じAstを作るので, 評価, 表示, :hex等の出力形式の切り替えは入力形式によらない
+
+use bigdecimal::BigDecimal;
+use std::collections::HashMap;
+use std::fmt;
+use unicode_width::UnicodeWidthStr;
+
+use crate::ast::{Ast, Node, NodeId, NodeKind, UnaryOp};
+use crate::config::Syntax;
+use crate::error::*;
+use crate::parser::{
+    check_ast_depth, format_err_span, insert_consts, NodeOrCmd, Parser, Span, TscCmd,
+};
+use crate::registry::{registry_reader, Builtin, Evaluator};
+use crate::str2num::bigdecimal_from_str;
+use crate::tex_printer;
+use crate::tokenizer::{is_greek_letter, scan_num};
+use crate::Value;
+
+mod asciimath;
+mod mathml;
+mod plain;
+mod sexpr;
+
+pub use asciimath::to_asciimath;
+pub use mathml::to_mathml;
+pub use plain::to_plain;
+pub use sexpr::to_sexpr;
+
+/// 括弧や関数のネストの上限
+const MAX_NEST_DEPTH: usize = 256;
+
+/// TeXのコマンド名と異なる関数名
+const FUNC_ALIASES: [(&str, &str); 10] = [
+    ("asin", "\\arcsin"),
+    ("acos", "\\arccos"),
+    ("atan", "\\arctan"),
+    ("gamma", "\\Gamma"),
+    ("lgamma", "\\lnGamma"),
+    ("lowergamma", "\\gamma"),
+    ("beta", "\\B"),
+    ("besselj", "\\J"),
+    ("bessely", "\\Y"),
+    ("lambertw", "\\W"),
+];
+
+/// 1行分の数式をsyntaxの形式で構文解析する
+/// nodeを格納したAstと, 式とTSCコマンドの列を返す
+///
+/// 出力形式を切り替えるコマンド以外のTSCコマンドで始まる行は, 入力形式によらずTeXとして読む
+pub fn parse(
+    form: &str,
+    syntax: Syntax,
+    vars: &mut HashMap<String, Value>,
+) -> Result<(Ast, Vec<NodeOrCmd>), MyError> {
+    let first = form.split_whitespace().next().unwrap_or("");
+    let is_tsc_line = first.starts_with(':') && TscCmd::from_name(first).is_none();
+    let (ast, nodes) = match syntax {
+        Syntax::Tex => return Parser::new(form.to_string(), true)?.build_ast(vars),
+        _ if is_tsc_line => return Parser::new(form.to_string(), true)?.build_ast(vars),
+        Syntax::Sexpr => sexpr::parse_line(form, vars)?,
+        Syntax::Plain => plain::parse_line(form, vars)?,
+        Syntax::Mathml => mathml::parse_line(form, vars)?,
+        Syntax::Asciimath => asciimath::parse_line(form, vars)?,
+    };
+    check_ast_depth(&ast, form)?;
+    Ok((ast, nodes))
+}
+
+/// 変数定義, TSCコマンドを含まない1つの式をsyntaxの形式で構文解析する
+pub fn parse_expr(form: &str, syntax: Syntax) -> Result<(Ast, NodeId), MyError> {
+    let (ast, root) = match syntax {
+        Syntax::Tex => return Parser::new(form.to_string(), false)?.build_expr(),
+        Syntax::Sexpr => sexpr::parse_expr(form)?,
+        Syntax::Plain => plain::parse_expr(form)?,
+        Syntax::Mathml => mathml::parse_expr(form)?,
+        Syntax::Asciimath => asciimath::parse_expr(form)?,
+    };
+    check_ast_depth(&ast, form)?;
+    Ok((ast, root))
+}
+
+/// ASTをsyntaxの形式の数式にする. 出力はparse_exprで読み直せる
+pub fn print(ast: &Ast, root: NodeId, syntax: Syntax) -> Result<String, MyError> {
+    match syntax {
+        Syntax::Tex => tex_printer::to_tex(ast, root),
+        Syntax::Sexpr => to_sexpr(ast, root),
+        Syntax::Plain => to_plain(ast, root),
+        Syntax::Mathml => to_mathml(ast, root),
+        Syntax::Asciimath => to_asciimath(ast, root),
+    }
+}
+
+/// fromの形式の数式をtoの形式に書き換える
+pub fn convert(form: &str, from: Syntax, to: Syntax) -> Result<String, MyError> {
+    let (ast, root) = parse_expr(form, from)?;
+    print(&ast, root, to)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Num(BigDecimal),
+    Ident(String),
+    Punct(&'static str),
+    TscCmd(String),
+    Eot,
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tok::Num(n) => write!(f, "{n}"),
+            Tok::Ident(s) | Tok::TscCmd(s) => write!(f, "{s}"),
+            Tok::Punct(p) => write!(f, "{p}"),
+            Tok::Eot => write!(f, "EOT"),
+        }
+    }
+}
+
+/// 字句解析したtoken列と読んでいる位置
+struct TokenStream<'a> {
+    form: &'a str,
+    toks: Vec<Tok>,
+    spans: Vec<Span>,
+    idx: usize,
+    depth: usize,
+}
+
+impl<'a> TokenStream<'a> {
+    /// formを字句解析する
+    /// * `puncts` - 記号のtoken. 長いものから順に並べる
+    /// * `comment` - この文字から行末までを読み飛ばす
+    /// * `ident` - 英字で始まる文字列の先頭の識別子の長さ
+    fn new(
+        form: &'a str,
+        puncts: &[&'static str],
+        comment: Option<char>,
+        ident: fn(&str) -> usize,
+    ) -> Result<TokenStream<'a>, MyError> {
+        let bytes = form.as_bytes();
+        let mut toks = Vec::new();
+        let mut spans = Vec::new();
+        let mut idx = 0;
+        while let Some(c) = form[idx..].chars().next() {
+            let rest = &form[idx..];
+            let len = if c.is_whitespace() {
+                c.len_utf8()
+            } else if Some(c) == comment {
+                rest.len()
+            } else if let Some(p) = puncts.iter().find(|p| rest.starts_with(**p)) {
+                toks.push(Tok::Punct(p));
+                p.len()
+            } else if c.is_ascii_digit() {
+                // ,は引数の区切りなので桁区切りに使わない
+                let end = rest.find(',').unwrap_or(rest.len());
+                let (len, format) = scan_num(&bytes[idx..idx + end]);
+                toks.push(Tok::Num(bigdecimal_from_str(format, &rest[..len])?));
+                len
+            } else if c.is_ascii_alphabetic() {
+                let len = ident(rest);
+                toks.push(Tok::Ident(rest[..len].to_string()));
+                len
+            } else if c == ':' {
+                let len = 1 + rest[1..]
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(rest.len() - 1);
+                toks.push(Tok::TscCmd(rest[..len].to_string()));
+                len
+            } else {
+                let span = Span {
+                    start: idx,
+                    end: idx + c.len_utf8(),
+                };
+                let column = form[..idx].width() + 1;
+                return Err(MyError::InvalidInput(format!(
+                    "'{c}' at column {column}\n{}",
+                    format_err_span(form, span)
+                )));
+            };
+            if spans.len() < toks.len() {
+                spans.push(Span {
+                    start: idx,
+                    end: idx + len,
+                });
+            }
+            idx += len;
+        }
+        toks.push(Tok::Eot);
+        spans.push(Span {
+            start: form.len(),
+            end: form.len(),
+        });
+        Ok(TokenStream {
+            form,
+            toks,
+            spans,
+            idx: 0,
+            depth: 0,
+        })
+    }
+
+    fn peek(&self) -> &Tok {
+        &self.toks[self.idx]
+    }
+
+    fn peek_at(&self, n: usize) -> &Tok {
+        &self.toks[(self.idx + n).min(self.toks.len() - 1)]
+    }
+
+    fn span(&self) -> Span {
+        self.spans[self.idx]
+    }
+
+    fn prev_span(&self) -> Span {
+        self.spans[self.idx - 1]
+    }
+
+    fn bump(&mut self) {
+        if self.idx + 1 < self.toks.len() {
+            self.idx += 1;
+        }
+    }
+
+    fn is_punct(&self, p: &str) -> bool {
+        matches!(self.peek(), Tok::Punct(q) if *q == p)
+    }
+
+    /// 記号pを読む
+    fn expect(&mut self, p: &str) -> Result<(), MyError> {
+        if self.is_punct(p) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{p}'")))
+        }
+    }
+
+    /// 今のtokenがexpectedでないことを表すエラー
+    fn unexpected(&self, expected: &str) -> MyError {
+        MyError::UnexpectedToken(
+            expected.to_string(),
+            self.peek().to_string(),
+            self.format_err_loc(self.span()),
+        )
+    }
+
+    fn format_err_loc(&self, span: Span) -> String {
+        format_err_span(self.form, span)
+    }
+
+    /// 文の後には区切り文字かTSCコマンドが続く
+    fn end_of_stmt(&self) -> Result<(), MyError> {
+        match self.peek() {
+            Tok::Eot | Tok::Punct(";") | Tok::TscCmd(_) => Ok(()),
+            tok => Err(MyError::UnprocessedToekn(
+                tok.to_string(),
+                self.format_err_loc(self.span()),
+            )),
+        }
+    }
+
+    /// 出力形式を切り替えるTSCコマンドを読む
+    fn tsc_cmd(&mut self) -> Result<TscCmd, MyError> {
+        let name = self.peek().to_string();
+        let cmd = TscCmd::from_name(&name).ok_or(MyError::UDcommandErr(name))?;
+        self.bump();
+        Ok(cmd)
+    }
+
+    /// 符号付きの数値リテラルを読む. 変数定義の値に使う
+    fn signed_num(&mut self) -> Result<BigDecimal, MyError> {
+        let neg = self.is_punct("-");
+        if neg {
+            self.bump();
+        }
+        match self.peek().clone() {
+            Tok::Num(n) => {
+                self.bump();
+                Ok(if neg { -n } else { n })
+            }
+            tok => Err(MyError::NotTkNumber(
+                tok.to_string(),
+                self.format_err_loc(self.span()),
+            )),
+        }
+    }
+
+    /// 深すぎるネストによるstack overflowを防ぐ
+    fn enter(&mut self) -> Result<(), MyError> {
+        if self.depth >= MAX_NEST_DEPTH {
+            return Err(MyError::TooDeepNesting(
+                MAX_NEST_DEPTH,
+                self.format_err_loc(self.span()),
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+}
+
+/// 英数字と_が続く識別子の長さ
+fn alnum_ident(s: &str) -> usize {
+    s.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(s.len())
+}
+
+/// ";"で区切った文を読む構文解析器
+trait StmtParser<'a> {
+    fn ts(&mut self) -> &mut TokenStream<'a>;
+    /// 1つの式を読む
+    fn expr(&mut self) -> Result<NodeId, MyError>;
+    fn into_ast(self) -> Ast;
+}
+
+/// 1行分の文を構文解析する
+/// ```text
+/// line = stmt (";" stmt)*
+/// stmt = tsc_cmd* (ident "=" signed_num | expr)?
+/// ```
+fn parse_stmts<'a, P: StmtParser<'a>>(
+    mut p: P,
+    vars: &mut HashMap<String, Value>,
+) -> Result<(Ast, Vec<NodeOrCmd>), MyError> {
+    let mut res = Vec::new();
+    loop {
+        let ts = p.ts();
+        match ts.peek() {
+            Tok::Eot => break,
+            Tok::Punct(";") => ts.bump(),
+            Tok::TscCmd(_) => res.push(NodeOrCmd::TscCmd(ts.tsc_cmd()?)),
+            Tok::Ident(name) if ts.peek_at(1) == &Tok::Punct("=") => {
+                let name = var_of_ident(name);
+                ts.bump();
+                ts.bump();
+                let value = ts.signed_num()?;
+                vars.insert(name, value.into());
+                ts.end_of_stmt()?;
+            }
+            _ => {
+                res.push(NodeOrCmd::Node(p.expr()?));
+                p.ts().end_of_stmt()?;
+            }
+        }
+    }
+    insert_consts(vars)?;
+    if res.is_empty() {
+        return Err(MyError::NoToken);
+    }
+    Ok((p.into_ast(), res))
+}
+
+/// 1つの式だけを構文解析する
+fn parse_single<'a, P: StmtParser<'a>>(mut p: P) -> Result<(Ast, NodeId), MyError> {
+    if p.ts().peek() == &Tok::Eot {
+        return Err(MyError::NoToken);
+    }
+    let root = p.expr()?;
+    let ts = p.ts();
+    if ts.peek() != &Tok::Eot {
+        return Err(MyError::UnprocessedToekn(
+            ts.peek().to_string(),
+            ts.format_err_loc(ts.span()),
+        ));
+    }
+    Ok((p.into_ast(), root))
+}
+
+fn push_node(ast: &mut Ast, kind: NodeKind, span: Span, op_span: Option<Span>) -> NodeId {
+    ast.push(Node {
+        kind,
+        span,
+        op_span,
+    })
+}
+
+/// 関数名nameの呼び出しのnodeを作る
+/// nameは\を除いたregistryのコマンド名かasin等の別名
+fn push_func(
+    form: &str,
+    ast: &mut Ast,
+    name: &str,
+    args: &[NodeId],
+    op_span: Span,
+    span: Span,
+) -> Result<NodeId, MyError> {
+    let cmd_name = match FUNC_ALIASES.iter().find(|(alias, _)| *alias == name) {
+        Some((_, cmd)) => cmd.to_string(),
+        None => format!("\\{name}"),
+    };
+    let registry = registry_reader()?;
+    let Some((id, cmd)) = registry.lookup(&cmd_name) else {
+        return Err(MyError::UDfunction(
+            name.to_string(),
+            format_err_span(form, op_span),
+        ));
+    };
+    let arity_ok = match &cmd.eval {
+        Evaluator::List(_) => true,
+        Evaluator::Special(f) => f.arity().contains(&args.len()),
+        _ => cmd.arity == args.len(),
+    };
+    if !arity_ok {
+        return Err(MyError::ArgCountErr(
+            name.to_string(),
+            cmd.arity.to_string(),
+            args.len(),
+            format_err_span(form, span),
+        ));
+    }
+    let kind = match (&cmd.eval, args) {
+        (Evaluator::Builtin(Builtin::Unary(op)), [arg]) => NodeKind::Unary(*op, *arg),
+        (Evaluator::Builtin(Builtin::Binary(op)), [lhs, rhs]) => NodeKind::Binary(*op, *lhs, *rhs),
+        _ => NodeKind::Call(id, ast.push_args(args)),
+    };
+    Ok(push_node(ast, kind, span, Some(op_span)))
+}
+
+/// 識別子を変数名にする. alpha, pi等はTeXのギリシャ文字の変数と同じ名前にする
+fn var_of_ident(ident: &str) -> String {
+    let tex = format!("\\{ident}");
+    if is_greek_letter(&tex) {
+        tex
+    } else {
+        ident.to_string()
+    }
+}
+
+/// 変数名を識別子にする. var_of_identの逆
+fn ident_of_var(var: &str) -> &str {
+    var.trim_start_matches('\\')
+}
+
+/// Unary, Call nodeの関数名. parse_exprで読み直せる
+fn func_ident(ast: &Ast, id: NodeId) -> Result<String, MyError> {
+    match &ast[id].kind {
+        NodeKind::Unary(UnaryOp::Ln, _) => Ok("ln".to_string()),
+        NodeKind::Unary(op, _) => Ok(op.to_lisp_op_str().to_string()),
+        NodeKind::Call(func, _) => match registry_reader()?.get(*func) {
+            Some(cmd) => match FUNC_ALIASES.iter().find(|(_, name)| *name == cmd.name) {
+                Some((alias, _)) => Ok(alias.to_string()),
+                None => Ok(cmd.name.trim_start_matches('\\').to_string()),
+            },
+            None => Err(MyError::BrokenAstErr),
+        },
+        _ => Err(MyError::BrokenAstErr),
+    }
+}
+
+/// Unary, Call nodeのTeXのコマンド名から\を除いたもの
+fn tex_func_name(ast: &Ast, id: NodeId) -> Result<String, MyError> {
+    let registry = registry_reader()?;
+    let cmd = match &ast[id].kind {
+        NodeKind::Unary(op, _) => registry.builtin(Builtin::Unary(*op)),
+        NodeKind::Call(func, _) => registry.get(*func),
+        _ => None,
+    };
+    match cmd {
+        Some(cmd) => Ok(cmd.name.trim_start_matches('\\').to_string()),
+        None => Err(MyError::BrokenAstErr),
+    }
+}
+
+fn num_str(n: &BigDecimal) -> String {
+    n.normalized().to_plain_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::CONSTS;
+
+    fn tex_ast(form: &str) -> (Ast, NodeId) {
+        parse_expr(form, Syntax::Tex).unwrap()
+    }
+
+    #[test]
+    fn test_print() {
+        let cases = [
+            ("1 + 2 \\cdot 3 - 4", "(- (+ 1 (* 2 3)) 4)", "1 + 2 * 3 - 4"),
+            (
+                "\\frac{x}{2} - \\sqrt{x}",
+                "(- (/ x 2) (sqrt x))",
+                "x / 2 - sqrt(x)",
+            ),
+            ("-2^{2}", "(- (expt 2 2))", "-2^2"),
+            ("(-2)^{2}", "(expt (- 2) 2)", "(-2)^2"),
+            ("2^{3}^{2}", "(expt (expt 2 3) 2)", "(2^3)^2"),
+            ("2^{3^{2}}", "(expt 2 (expt 3 2))", "2^3^2"),
+            ("a - (b - c)", "(- a (- b c))", "a - (b - c)"),
+            ("a \\cdot (-b)", "(* a (- b))", "a * (-b)"),
+            (
+                "\\sin \\alpha \\arccos x",
+                "(* (sin alpha) (acos x))",
+                "sin(alpha) * acos(x)",
+            ),
+            (
+                "\\log x + \\ln \\pi + \\abs(-x) + \\exp(0x10)",
+                "(+ (+ (+ (log x) (ln pi)) (abs (- x))) (exp 16))",
+                "log(x) + ln(pi) + abs(-x) + exp(16)",
+            ),
+            (
+                "\\Gamma(x) + \\ln\\Gamma(x) + J_{0}(x) + \\operatorname{B}(a, b)",
+                "(+ (+ (+ (gamma x) (lgamma x)) (besselj 0 x)) (beta a b))",
+                "gamma(x) + lgamma(x) + besselj(0, x) + beta(a, b)",
+            ),
+            (
+                "\\operatorname{normcdf}(x, 0, 1) - \\operatorname{rand}()",
+                "(- (normcdf x 0 1) (rand))",
+                "normcdf(x, 0, 1) - rand()",
+            ),
+        ];
+        for (tex, sexpr, plain) in cases {
+            let (ast, root) = tex_ast(tex);
+            assert_eq!(to_sexpr(&ast, root).unwrap(), sexpr, "{tex}");
+            assert_eq!(to_plain(&ast, root).unwrap(), plain, "{tex}");
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let forms = [
+            "1 + 2 \\cdot 3 - \\frac{4}{5}",
+            "-x^{2} + (-x)^{2} - -x",
+            "\\frac{a}{2x} + \\sqrt{\\frac{x}{y}} - \\abs(-x)^{3}",
+            "\\sin 2 x \\cos \\pi + \\log 8 - \\ln e + \\exp(1)",
+            "\\arcsin \\frac{1}{2} + \\arctan(\\theta - 1) + \\csc \\Omega",
+            "2^{3}^{2} - 2^{3^{2}} + 1.5 \\cdot 0.25",
+            "\\operatorname{B}(2, 3) + J_{0}(1) - Y_{1}(x + 1) \\operatorname{normcdf}(x, 0, 1)",
+            "\\sum(1, 2) + \\max(x, 3)",
+        ];
+        for form in forms {
+            let (ast, root) = tex_ast(form);
+            let tex = tex_printer::to_tex(&ast, root).unwrap();
+            if form.contains("J_{0}") {
+                assert!(to_mathml(&ast, root)
+                    .unwrap()
+                    .contains("<msub><mi>J</mi><mn>0</mn></msub>"));
+            }
+            for syntax in [
+                Syntax::Sexpr,
+                Syntax::Plain,
+                Syntax::Mathml,
+                Syntax::Asciimath,
+            ] {
+                let s = print(&ast, root, syntax).unwrap();
+                assert_eq!(convert(&s, syntax, Syntax::Tex).unwrap(), tex, "{s}");
+                assert_eq!(convert(&s, syntax, syntax).unwrap(), s);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut vars = CONSTS.read().unwrap().clone();
+        vars.insert("x".to_string(), BigDecimal::from(3).into());
+        let cases = [
+            (Syntax::Sexpr, "(+ 1 2 3 (* 2 x))", "12"),
+            (Syntax::Sexpr, "(- 10 2 3)", "5"),
+            (Syntax::Sexpr, "(expt 2 (- x))", "0.125"),
+            (Syntax::Sexpr, "(abs -7)", "7"),
+            (Syntax::Sexpr, "(frac 9 x)", "3"),
+            (Syntax::Plain, "2**3 + 2^-1 * 2", "9"),
+            (Syntax::Plain, "-x^2 + abs(-x) * 3", "0"),
+            (Syntax::Plain, "0x10 / (1 + 3)", "4"),
+            (Syntax::Plain, "sqrt(frac(16, 0b100))", "2"),
+            (Syntax::Asciimath, "sqrt 4 + 2^3 - |-3| x", "1"),
+            (Syntax::Asciimath, "frac 1 2 + root(3)(8) + 1/2/2", "2.75"),
+            (Syntax::Asciimath, "sin(0) + 2^-1 (x + 1)", "2"),
+            (
+                Syntax::Mathml,
+                "<math><mfrac><mn>1</mn><mn>2</mn></mfrac><mo>+</mo>\
+                 <mroot><mn>8</mn><mn>3</mn></mroot></math>",
+                "2.5",
+            ),
+            (
+                Syntax::Mathml,
+                "<?xml version=\"1.0\"?><m:math xmlns:m=\"http://www.w3.org/1998/Math/MathML\">\
+                 <m:mn>2</m:mn><m:mo>&InvisibleTimes;</m:mo><m:msup><m:mi>x</m:mi><m:mn>2</m:mn>\
+                 </m:msup><m:mo>&minus;</m:mo><m:msqrt><m:mn>4</m:mn></m:msqrt></m:math>",
+                "16",
+            ),
+            (
+                Syntax::Mathml,
+                "<math><mrow><mo>|</mo><mo>-</mo><mi>x</mi><mo>|</mo><mi>x</mi></mrow></math>",
+                "9",
+            ),
+            (
+                Syntax::Mathml,
+                "<math><msub><mi>J</mi><mn>0</mn></msub><mo>&ApplyFunction;</mo>\
+                 <mrow><mo>(</mo><mn>0</mn><mo>)</mo></mrow><mo>+</mo><mi>max</mi>\
+                 <mrow><mo>(</mo><mn>1</mn><mo>,</mo><mi>x</mi><mo>)</mo></mrow></math>",
+                "4",
+            ),
+        ];
+        for (syntax, form, expected) in cases {
+            let (ast, root) = parse_expr(form, syntax).unwrap();
+            let res = crate::calc(&ast, root, &vars, form).unwrap();
+            assert_eq!(res, expected.parse::<BigDecimal>().unwrap(), "{form}");
+        }
+    }
+
+    #[test]
+    fn test_parse_err() {
+        let cases = [
+            (Syntax::Sexpr, "(+ 1 2"),
+            (Syntax::Sexpr, "(1 2)"),
+            (Syntax::Sexpr, "(sin 1 2)"),
+            (Syntax::Sexpr, "(undefined 1)"),
+            (Syntax::Sexpr, "(+ 1 2))"),
+            (Syntax::Plain, "2x"),
+            (Syntax::Plain, "sin x"),
+            (Syntax::Plain, "1 +"),
+            (Syntax::Plain, "frac(1)"),
+            (Syntax::Plain, "1 $ 2"),
+            (Syntax::Asciimath, "sum_(i=1)^n i"),
+            (Syntax::Asciimath, "x_1"),
+            (Syntax::Asciimath, "3!"),
+            (Syntax::Asciimath, "int x dx"),
+            (Syntax::Asciimath, "(1 + 2"),
+            (
+                Syntax::Mathml,
+                "<math><msub><mi>x</mi><mn>1</mn></msub></math>",
+            ),
+            (
+                Syntax::Mathml,
+                "<math><mi>x</mi><mo>&le;</mo><mn>1</mn></math>",
+            ),
+            (
+                Syntax::Mathml,
+                "<math><mi>x</mi><mo>≤</mo><mn>1</mn></math>",
+            ),
+            (Syntax::Mathml, "<math><mrow><mi>x</mi></math>"),
+            (Syntax::Mathml, "<math><mfrac><mn>1</mn></mfrac></math>"),
+            (Syntax::Mathml, "<mrow><mn>1</mn></mrow>"),
+            (Syntax::Mathml, "<math><mtext>x</mtext></math>"),
+        ];
+        for (syntax, form) in cases {
+            assert!(parse_expr(form, syntax).is_err(), "{form}");
+        }
+    }
+}
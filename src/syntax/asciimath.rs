@@ -0,0 +1,400 @@
+//! AsciiMathの入力形式
+//!
+//! ```text
+//! expr    = product (("+" | "-") product)*
+//! product = unary ((("*" | "**" | "xx" | "cdot" | "-:" | "div")? unary)*
+//! unary   = "-" unary | frac
+//! frac    = inter ("/" inter)*
+//! inter   = simple ("^" "-"? simple)?
+//! simple  = num | var | "(" expr ")" | "[" expr "]" | "{" expr "}" | "|" expr "|"
+//!         | func simple | func "(" (expr ("," expr)*)? ")"
+//!         | "frac" simple simple | "root" simple simple
+//! ```
+//!
+//! AsciiMathと同じく, 並べた式(暗黙の乗算)より/が, /より^が強く結合する.
+//! 変数は1文字かギリシャ文字の名前. 総和のsum, int, 下付き文字等は扱えない. sum(a, b)はリストの和
+
+use std::collections::HashMap;
+
+use super::*;
+use crate::ast::BinaryOp;
+use crate::registry::PREC_ATOM;
+use crate::tokenizer::GREEK_LETTERS;
+
+const PUNCTS: [&str; 19] = [
+    "-:", "**", "+", "-", "*", "/", "^", "_", "!", "(", ")", "[", "]", "{", "}", "|", ",", ";", "=",
+];
+
+/// 演算子や構文を表す単語
+const WORDS: [&str; 5] = ["xx", "cdot", "div", "frac", "root"];
+
+/// 計算できないAsciiMathの単語
+const UNSUPPORTED_WORDS: [&str; 16] = [
+    "sum", "prod", "int", "oint", "lim", "oo", "text", "mbox", "hat", "bar", "vec", "dot", "ddot",
+    "stackrel", "overset", "underset",
+];
+
+const PREC_SUM: u8 = 10;
+const PREC_PRODUCT: u8 = 20;
+const PREC_NEG: u8 = 25;
+const PREC_FRAC: u8 = 30;
+const PREC_POWER: u8 = 40;
+
+/// 1行分のAsciiMathを構文解析する
+pub(super) fn parse_line(
+    form: &str,
+    vars: &mut HashMap<String, Value>,
+) -> Result<(Ast, Vec<NodeOrCmd>), MyError> {
+    parse_stmts(AsciimathParser::new(form)?, vars)
+}
+
+/// 1つのAsciiMathの式を構文解析する
+pub(super) fn parse_expr(form: &str) -> Result<(Ast, NodeId), MyError> {
+    parse_single(AsciimathParser::new(form)?)
+}
+
+/// 識別子の長さ. 関数名, ギリシャ文字等の単語のうち最も長いもの, 無ければ1文字
+fn asciimath_ident(s: &str) -> usize {
+    let registry = registry_reader();
+    let commands = registry
+        .iter()
+        .flat_map(|r| r.commands())
+        .map(|cmd| &cmd.name[1..]);
+    let greek = GREEK_LETTERS.iter().map(|(_, name)| &name[1..]);
+    WORDS
+        .into_iter()
+        .chain(UNSUPPORTED_WORDS)
+        .chain(FUNC_ALIASES.iter().map(|(alias, _)| *alias))
+        .chain(greek)
+        .chain(commands)
+        .filter(|w| s.starts_with(w))
+        .map(str::len)
+        .max()
+        .unwrap_or(1)
+}
+
+/// ASTをAsciiMathにする
+pub fn to_asciimath(ast: &Ast, root: NodeId) -> Result<String, MyError> {
+    Ok(print(ast, root)?.0)
+}
+
+/// 式と, その式の結合力を返す
+fn print(ast: &Ast, id: NodeId) -> Result<(String, u8), MyError> {
+    // 結合力がmin_prec未満か, -で始まる被演算子を括弧で囲む
+    let operand = |id: NodeId, min_prec: u8| -> Result<String, MyError> {
+        let (s, prec) = print(ast, id)?;
+        if prec < min_prec || s.starts_with('-') {
+            Ok(format!("({s})"))
+        } else {
+            Ok(s)
+        }
+    };
+    let lhs_operand = |id: NodeId, min_prec: u8| -> Result<String, MyError> {
+        let (s, prec) = print(ast, id)?;
+        if prec < min_prec {
+            Ok(format!("({s})"))
+        } else {
+            Ok(s)
+        }
+    };
+    Ok(match &ast[id].kind {
+        NodeKind::Num(n) if n.sign() == bigdecimal::num_bigint::Sign::Minus => {
+            (num_str(n), PREC_NEG)
+        }
+        NodeKind::Num(n) => (num_str(n), PREC_ATOM),
+        NodeKind::Var(v) => {
+            let name = ident_of_var(v);
+            if name.len() > 1 && !v.starts_with('\\') {
+                return Err(MyError::Unsupported(
+                    format!("multi-letter variable '{v}' in AsciiMath"),
+                    String::new(),
+                ));
+            }
+            (name.to_string(), PREC_ATOM)
+        }
+        NodeKind::Unary(UnaryOp::Neg, arg) => (format!("-{}", operand(*arg, PREC_FRAC)?), PREC_NEG),
+        NodeKind::Binary(op @ (BinaryOp::Add | BinaryOp::Sub), lhs, rhs) => {
+            let l = lhs_operand(*lhs, PREC_SUM)?;
+            let r = operand(*rhs, PREC_SUM + 1)?;
+            (format!("{l} {} {r}", op.to_op_str()), PREC_SUM)
+        }
+        NodeKind::Binary(BinaryOp::Mul, lhs, rhs) => {
+            let l = lhs_operand(*lhs, PREC_PRODUCT)?;
+            let r = operand(*rhs, PREC_PRODUCT + 1)?;
+            // 数の並びは1つの数と区別できないので*を書く
+            let sep = if r.starts_with(|c: char| c.is_ascii_digit()) {
+                " * "
+            } else {
+                " "
+            };
+            (format!("{l}{sep}{r}"), PREC_PRODUCT)
+        }
+        NodeKind::Binary(BinaryOp::Div, lhs, rhs) => {
+            let l = lhs_operand(*lhs, PREC_POWER)?;
+            (format!("{l}/{}", operand(*rhs, PREC_POWER)?), PREC_FRAC)
+        }
+        NodeKind::Binary(BinaryOp::Pow, lhs, rhs) => {
+            let l = operand(*lhs, PREC_ATOM)?;
+            (format!("{l}^{}", operand(*rhs, PREC_ATOM)?), PREC_POWER)
+        }
+        NodeKind::Unary(..) | NodeKind::Call(..) => {
+            let mut args = Vec::new();
+            for child in ast.children(id) {
+                args.push(print(ast, child)?.0);
+            }
+            let name = tex_func_name(ast, id)?;
+            (format!("{name}({})", args.join(", ")), PREC_ATOM)
+        }
+    })
+}
+
+struct AsciimathParser<'a> {
+    ts: TokenStream<'a>,
+    ast: Ast,
+    abs_depth: usize,
+}
+
+impl<'a> AsciimathParser<'a> {
+    fn new(form: &'a str) -> Result<AsciimathParser<'a>, MyError> {
+        Ok(AsciimathParser {
+            ts: TokenStream::new(form, &PUNCTS, None, asciimath_ident)?,
+            ast: Ast::new(),
+            abs_depth: 0,
+        })
+    }
+}
+
+impl<'a> StmtParser<'a> for AsciimathParser<'a> {
+    fn ts(&mut self) -> &mut TokenStream<'a> {
+        &mut self.ts
+    }
+
+    fn expr(&mut self) -> Result<NodeId, MyError> {
+        self.ts.enter()?;
+        let res = self.sum();
+        self.ts.leave();
+        res
+    }
+
+    fn into_ast(self) -> Ast {
+        self.ast
+    }
+}
+
+impl AsciimathParser<'_> {
+    fn binary(&mut self, op: BinaryOp, lhs: NodeId, rhs: NodeId, op_span: Option<Span>) -> NodeId {
+        let span = self.ast[lhs].span.merge(self.ast[rhs].span);
+        push_node(&mut self.ast, NodeKind::Binary(op, lhs, rhs), span, op_span)
+    }
+
+    fn sum(&mut self) -> Result<NodeId, MyError> {
+        let mut node = self.product()?;
+        loop {
+            let op = match self.ts.peek() {
+                Tok::Punct("+") => BinaryOp::Add,
+                Tok::Punct("-") => BinaryOp::Sub,
+                _ => break,
+            };
+            let op_span = self.ts.span();
+            self.ts.bump();
+            let rhs = self.product()?;
+            node = self.binary(op, node, rhs, Some(op_span));
+        }
+        Ok(node)
+    }
+
+    /// 次のtokenが暗黙の乗算の右の被演算子になれるならtrue
+    fn starts_operand(&self) -> bool {
+        match self.ts.peek() {
+            Tok::Num(_) => true,
+            Tok::Ident(w) => !matches!(&**w, "xx" | "cdot" | "div"),
+            Tok::Punct("(" | "[" | "{") => true,
+            Tok::Punct("|") => self.abs_depth == 0,
+            _ => false,
+        }
+    }
+
+    fn product(&mut self) -> Result<NodeId, MyError> {
+        let mut node = self.unary()?;
+        loop {
+            let op = match self.ts.peek() {
+                Tok::Punct("*" | "**") => Some(BinaryOp::Mul),
+                Tok::Ident(w) if w == "xx" || w == "cdot" => Some(BinaryOp::Mul),
+                Tok::Punct("-:") => Some(BinaryOp::Div),
+                Tok::Ident(w) if w == "div" => Some(BinaryOp::Div),
+                _ => None,
+            };
+            let (op, op_span) = match op {
+                Some(op) => {
+                    let op_span = self.ts.span();
+                    self.ts.bump();
+                    (op, Some(op_span))
+                }
+                None if self.starts_operand() => (BinaryOp::Mul, None),
+                None => break,
+            };
+            let rhs = self.unary()?;
+            node = self.binary(op, node, rhs, op_span);
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<NodeId, MyError> {
+        if self.ts.is_punct("-") {
+            let op_span = self.ts.span();
+            self.ts.bump();
+            self.ts.enter()?;
+            let arg = self.unary();
+            self.ts.leave();
+            let arg = arg?;
+            let span = op_span.merge(self.ast[arg].span);
+            let kind = NodeKind::Unary(UnaryOp::Neg, arg);
+            return Ok(push_node(&mut self.ast, kind, span, Some(op_span)));
+        }
+        self.frac()
+    }
+
+    fn frac(&mut self) -> Result<NodeId, MyError> {
+        let mut node = self.inter()?;
+        while self.ts.is_punct("/") {
+            let op_span = self.ts.span();
+            self.ts.bump();
+            let rhs = self.inter()?;
+            node = self.binary(BinaryOp::Div, node, rhs, Some(op_span));
+        }
+        Ok(node)
+    }
+
+    fn inter(&mut self) -> Result<NodeId, MyError> {
+        let base = self.simple()?;
+        if self.ts.is_punct("_") {
+            return Err(self.unsupported("subscript '_'"));
+        }
+        if !self.ts.is_punct("^") {
+            return Ok(base);
+        }
+        let op_span = self.ts.span();
+        self.ts.bump();
+        // x^-1 は x^(-1)
+        let exp = if self.ts.is_punct("-") {
+            let neg_span = self.ts.span();
+            self.ts.bump();
+            let arg = self.simple()?;
+            let span = neg_span.merge(self.ast[arg].span);
+            let kind = NodeKind::Unary(UnaryOp::Neg, arg);
+            push_node(&mut self.ast, kind, span, Some(neg_span))
+        } else {
+            self.simple()?
+        };
+        Ok(self.binary(BinaryOp::Pow, base, exp, Some(op_span)))
+    }
+
+    fn unsupported(&self, what: &str) -> MyError {
+        MyError::Unsupported(
+            format!("AsciiMath {what}"),
+            self.ts.format_err_loc(self.ts.span()),
+        )
+    }
+
+    fn simple(&mut self) -> Result<NodeId, MyError> {
+        self.ts.enter()?;
+        let res = self.simple_inner();
+        self.ts.leave();
+        res
+    }
+
+    fn simple_inner(&mut self) -> Result<NodeId, MyError> {
+        let span = self.ts.span();
+        match self.ts.peek().clone() {
+            Tok::Num(n) => {
+                self.ts.bump();
+                Ok(push_node(&mut self.ast, NodeKind::Num(n), span, None))
+            }
+            Tok::Punct(open @ ("(" | "[" | "{")) => {
+                self.ts.bump();
+                let node = self.sum()?;
+                self.ts.expect(match open {
+                    "(" => ")",
+                    "[" => "]",
+                    _ => "}",
+                })?;
+                // 閉じ括弧を含めた範囲をnodeの位置とする
+                self.ast[node].span = span.merge(self.ts.prev_span());
+                Ok(node)
+            }
+            Tok::Punct("|") => {
+                self.ts.bump();
+                self.abs_depth += 1;
+                let arg = self.sum();
+                self.abs_depth -= 1;
+                let arg = arg?;
+                self.ts.expect("|")?;
+                let span = span.merge(self.ts.prev_span());
+                let kind = NodeKind::Unary(UnaryOp::Abs, arg);
+                Ok(push_node(&mut self.ast, kind, span, Some(span)))
+            }
+            Tok::Punct("!") => Err(self.unsupported("factorial '!'")),
+            // sum(a, b)はリストの和のコマンドとして読む
+            Tok::Ident(w) if w == "sum" && matches!(self.ts.peek_at(1), Tok::Punct("(")) => {
+                self.func(&w)
+            }
+            Tok::Ident(w) if UNSUPPORTED_WORDS.contains(&&*w) => {
+                Err(self.unsupported(&format!("symbol '{w}'")))
+            }
+            Tok::Ident(w) if w == "frac" || w == "root" => {
+                self.ts.bump();
+                let a = self.simple()?;
+                let b = self.simple()?;
+                let span = span.merge(self.ts.prev_span());
+                Ok(if w == "frac" {
+                    let kind = NodeKind::Binary(BinaryOp::Div, a, b);
+                    push_node(&mut self.ast, kind, span, Some(span))
+                } else {
+                    // root(n)(x) は x^(1/n)
+                    let one = push_node(&mut self.ast, NodeKind::Num(1.into()), span, None);
+                    let exp = self.binary(BinaryOp::Div, one, a, None);
+                    let kind = NodeKind::Binary(BinaryOp::Pow, b, exp);
+                    push_node(&mut self.ast, kind, span, Some(span))
+                })
+            }
+            Tok::Ident(w) if is_func(&w) => self.func(&w),
+            Tok::Ident(w) => {
+                self.ts.bump();
+                let kind = NodeKind::Var(var_of_ident(&w));
+                Ok(push_node(&mut self.ast, kind, span, None))
+            }
+            _ => Err(self.ts.unexpected("number, variable or '('")),
+        }
+    }
+
+    /// func simple | func "(" (expr ("," expr)*)? ")" を読む
+    fn func(&mut self, name: &str) -> Result<NodeId, MyError> {
+        let op_span = self.ts.span();
+        self.ts.bump();
+        if self.ts.is_punct("^") || self.ts.is_punct("_") {
+            return Err(self.unsupported(&format!("power or subscript of function '{name}'")));
+        }
+        let mut args = Vec::new();
+        if self.ts.is_punct("(") {
+            self.ts.bump();
+            if !self.ts.is_punct(")") {
+                args.push(self.sum()?);
+                while self.ts.is_punct(",") {
+                    self.ts.bump();
+                    args.push(self.sum()?);
+                }
+            }
+            self.ts.expect(")")?;
+        } else {
+            args.push(self.simple()?);
+        }
+        let span = op_span.merge(self.ts.prev_span());
+        push_func(self.ts.form, &mut self.ast, name, &args, op_span, span)
+    }
+}
+
+/// registryに登録された関数名か別名ならtrue
+fn is_func(name: &str) -> bool {
+    FUNC_ALIASES.iter().any(|(alias, _)| *alias == name)
+        || registry_reader().is_ok_and(|r| r.lookup(&format!("\\{name}")).is_some())
+}
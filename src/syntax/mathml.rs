@@ -0,0 +1,921 @@
+//! Presentation MathMLの入力形式
+//!
+//! 扱える要素は math, mrow, mstyle, mpadded, semantics(最初の子だけ), mn, mi, mo,
+//! mfrac, msqrt, mroot, msup, msub(J_{n}(x)のように添字で書く関数だけ), mspace.
+//! それ以外の要素やmoの演算子はエラーにする
+//!
+//! mrow等の子は次の文法で読む. 関数は\<mo>&amp;#x2061;\</mo>(ApplyFunction)を挟んでもよい
+//! 関数の直後の "(" で始まり ")" で終わるmrowは, 引数を並べたものとして読む
+//!
+//! ```text
+//! sum     = product (("+" | "−") product)*
+//! product = unary (("⋅" | "×" | "*" | "&#x2062;" | "/" | "÷")? unary)*
+//! unary   = "−" unary | primary
+//! primary = mn | mi | 要素 | "(" sum ")" | "|" sum "|" | func ("(" (sum ("," sum)*)? ")" | unary)
+//! func    = mi | msub(mi, 添字)
+//! ```
+
+use bigdecimal::BigDecimal;
+use std::collections::HashMap;
+
+use super::*;
+use crate::ast::BinaryOp;
+use crate::registry::PREC_ATOM;
+use crate::tokenizer::GREEK_LETTERS;
+
+const MATHML_NS: &str = "http://www.w3.org/1998/Math/MathML";
+/// 関数適用 (&ApplyFunction;)
+const APPLY_FUNCTION: char = '\u{2061}';
+/// 見えない乗算記号 (&InvisibleTimes;)
+const INVISIBLE_TIMES: char = '\u{2062}';
+/// 見えない区切り (&InvisibleComma;)
+const INVISIBLE_COMMA: char = '\u{2063}';
+const MINUS: char = '\u{2212}';
+
+const PREC_SUM: u8 = 10;
+const PREC_PRODUCT: u8 = 20;
+const PREC_NEG: u8 = 30;
+const PREC_FUNC: u8 = 35;
+
+/// 1行分のMathMLを構文解析する
+/// 行には:hex等の出力形式を切り替えるTSCコマンドと, 1つ以上の\<math>要素を並べられる
+pub(super) fn parse_line(
+    form: &str,
+    vars: &mut HashMap<String, Value>,
+) -> Result<(Ast, Vec<NodeOrCmd>), MyError> {
+    let mut reader = XmlReader { form, pos: 0 };
+    let mut conv = Converter {
+        form,
+        ast: Ast::new(),
+        depth: 0,
+    };
+    let mut res = Vec::new();
+    loop {
+        reader.skip_misc()?;
+        let rest = &form[reader.pos..];
+        if rest.is_empty() {
+            break;
+        }
+        if rest.starts_with(':') {
+            let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let cmd = TscCmd::from_name(&rest[..len])
+                .ok_or(MyError::UDcommandErr(rest[..len].to_string()))?;
+            res.push(NodeOrCmd::TscCmd(cmd));
+            reader.pos += len;
+        } else {
+            let el = reader.element()?;
+            res.push(NodeOrCmd::Node(conv.root(&el)?));
+        }
+    }
+    insert_consts(vars)?;
+    if res.is_empty() {
+        return Err(MyError::NoToken);
+    }
+    Ok((conv.ast, res))
+}
+
+/// 1つの\<math>要素を構文解析する
+pub(super) fn parse_expr(form: &str) -> Result<(Ast, NodeId), MyError> {
+    let mut reader = XmlReader { form, pos: 0 };
+    reader.skip_misc()?;
+    if reader.pos == form.len() {
+        return Err(MyError::NoToken);
+    }
+    let el = reader.element()?;
+    reader.skip_misc()?;
+    if reader.pos != form.len() {
+        return Err(reader.err("unexpected content after the root element", reader.pos));
+    }
+    let mut conv = Converter {
+        form,
+        ast: Ast::new(),
+        depth: 0,
+    };
+    let root = conv.root(&el)?;
+    Ok((conv.ast, root))
+}
+
+/// ASTをPresentation MathMLにする
+pub fn to_mathml(ast: &Ast, root: NodeId) -> Result<String, MyError> {
+    Ok(format!(
+        "<math xmlns=\"{MATHML_NS}\">{}</math>",
+        emit(ast, root)?.0
+    ))
+}
+
+fn mrow(items: &[String]) -> String {
+    format!("<mrow>{}</mrow>", items.concat())
+}
+
+fn mo(op: &str) -> String {
+    format!("<mo>{op}</mo>")
+}
+
+fn paren(s: String) -> String {
+    mrow(&[mo("("), s, mo(")")])
+}
+
+/// 1つの要素と, その式の結合力を返す
+fn emit(ast: &Ast, id: NodeId) -> Result<(String, u8), MyError> {
+    // 結合力がmin_prec未満か, −で始まる被演算子を括弧で囲む
+    let operand = |id: NodeId, min_prec: u8| -> Result<String, MyError> {
+        match emit(ast, id)? {
+            (s, prec) if prec < min_prec || prec == PREC_NEG => Ok(paren(s)),
+            (s, _) => Ok(s),
+        }
+    };
+    let lhs_operand = |id: NodeId, min_prec: u8| -> Result<String, MyError> {
+        match emit(ast, id)? {
+            (s, prec) if prec < min_prec => Ok(paren(s)),
+            (s, _) => Ok(s),
+        }
+    };
+    Ok(match &ast[id].kind {
+        NodeKind::Num(n) if n.sign() == bigdecimal::num_bigint::Sign::Minus => (
+            mrow(&[mo(&MINUS.to_string()), format!("<mn>{}</mn>", num_str(&-n))]),
+            PREC_NEG,
+        ),
+        NodeKind::Num(n) => (format!("<mn>{}</mn>", num_str(n)), PREC_ATOM),
+        NodeKind::Var(v) => {
+            let name = match GREEK_LETTERS.iter().find(|(_, name)| name == v) {
+                Some((c, _)) => c.to_string(),
+                None => v.clone(),
+            };
+            (format!("<mi>{name}</mi>"), PREC_ATOM)
+        }
+        NodeKind::Unary(UnaryOp::Neg, arg) => (
+            mrow(&[mo(&MINUS.to_string()), operand(*arg, PREC_FUNC)?]),
+            PREC_NEG,
+        ),
+        NodeKind::Unary(UnaryOp::Sqrt, arg) => {
+            (format!("<msqrt>{}</msqrt>", emit(ast, *arg)?.0), PREC_ATOM)
+        }
+        NodeKind::Unary(UnaryOp::Abs, arg) => {
+            (mrow(&[mo("|"), emit(ast, *arg)?.0, mo("|")]), PREC_ATOM)
+        }
+        NodeKind::Binary(op @ (BinaryOp::Add | BinaryOp::Sub), lhs, rhs) => {
+            let op = if *op == BinaryOp::Add {
+                "+".to_string()
+            } else {
+                MINUS.to_string()
+            };
+            let l = lhs_operand(*lhs, PREC_SUM)?;
+            let r = operand(*rhs, PREC_SUM + 1)?;
+            (mrow(&[l, mo(&op), r]), PREC_SUM)
+        }
+        NodeKind::Binary(BinaryOp::Mul, lhs, rhs) => {
+            let l = lhs_operand(*lhs, PREC_PRODUCT)?;
+            let r = operand(*rhs, PREC_PRODUCT + 1)?;
+            // 数が並ぶときは乗算記号を見えるようにする
+            let op = if starts_with_num(ast, *rhs) {
+                mo("\u{22c5}")
+            } else {
+                mo("&#x2062;")
+            };
+            (mrow(&[l, op, r]), PREC_PRODUCT)
+        }
+        NodeKind::Binary(BinaryOp::Div, lhs, rhs) => (
+            format!(
+                "<mfrac>{}{}</mfrac>",
+                emit(ast, *lhs)?.0,
+                emit(ast, *rhs)?.0
+            ),
+            PREC_ATOM,
+        ),
+        NodeKind::Binary(BinaryOp::Pow, lhs, rhs) => (
+            format!(
+                "<msup>{}{}</msup>",
+                operand(*lhs, PREC_ATOM)?,
+                emit(ast, *rhs)?.0
+            ),
+            PREC_ATOM,
+        ),
+        NodeKind::Unary(..) | NodeKind::Call(..) => {
+            let name = tex_func_name(ast, id)?;
+            // J_{n}(x)の添字は<msub>にし, 残りを引数にする
+            let sub = match &ast[id].kind {
+                NodeKind::Call(_, args) if is_subscripted(&name) => Some(ast.args(*args)[0]),
+                _ => None,
+            };
+            let mut args = Vec::new();
+            for child in ast.children(id).skip(sub.iter().len()) {
+                args.push(emit(ast, child)?);
+            }
+            let arg = match args.as_slice() {
+                [(s, PREC_ATOM)] => s.clone(),
+                _ => {
+                    let mut items = vec![mo("(")];
+                    for (i, (s, _)) in args.into_iter().enumerate() {
+                        if i > 0 {
+                            items.push(mo(","));
+                        }
+                        items.push(s);
+                    }
+                    items.push(mo(")"));
+                    mrow(&items)
+                }
+            };
+            let name = match sub {
+                Some(sub) => format!("<msub><mi>{name}</mi>{}</msub>", emit(ast, sub)?.0),
+                None => format!("<mi>{name}</mi>"),
+            };
+            (mrow(&[name, mo("&#x2061;"), arg]), PREC_FUNC)
+        }
+    })
+}
+
+/// 式を表示したとき, 数字で始まるならtrue
+fn starts_with_num(ast: &Ast, id: NodeId) -> bool {
+    match &ast[id].kind {
+        NodeKind::Num(_) => true,
+        NodeKind::Binary(BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Pow, lhs, _) => {
+            starts_with_num(ast, *lhs)
+        }
+        _ => false,
+    }
+}
+
+/// XMLの要素
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Element>,
+    /// 子の要素の間の文字列をつなげたもの. 前後の空白は除く
+    text: String,
+    span: Span,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// MathMLに必要な分だけのXMLの読み取り
+struct XmlReader<'a> {
+    form: &'a str,
+    pos: usize,
+}
+
+impl<'a> XmlReader<'a> {
+    fn err(&self, msg: &str, pos: usize) -> MyError {
+        let end = self.form[pos..]
+            .chars()
+            .next()
+            .map_or(pos, |c| pos + c.len_utf8());
+        MyError::MathmlErr(
+            msg.to_string(),
+            format_err_span(self.form, Span { start: pos, end }),
+        )
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.form[self.pos..]
+    }
+
+    /// 空白, XML宣言, コメント, DOCTYPEを読み飛ばす
+    fn skip_misc(&mut self) -> Result<(), MyError> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            let close = if trimmed.starts_with("<?") {
+                "?>"
+            } else if trimmed.starts_with("<!--") {
+                "-->"
+            } else if trimmed.starts_with("<!") {
+                ">"
+            } else {
+                return Ok(());
+            };
+            match trimmed.find(close) {
+                Some(i) => self.pos += i + close.len(),
+                None => return Err(self.err(&format!("missing '{close}'"), self.pos)),
+            }
+        }
+    }
+
+    /// 名前を読む. 名前空間の接頭辞(m:math)は除く
+    fn name(&mut self) -> Result<String, MyError> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '='))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.err("expected a name", self.pos));
+        }
+        let name = &rest[..len];
+        self.pos += len;
+        Ok(name.rsplit(':').next().unwrap_or(name).to_string())
+    }
+
+    fn element(&mut self) -> Result<Element, MyError> {
+        let start = self.pos;
+        if !self.rest().starts_with('<') {
+            return Err(self.err("expected '<'", self.pos));
+        }
+        self.pos += 1;
+        let name = self.name()?;
+        let mut attrs = Vec::new();
+        loop {
+            let rest = self.rest();
+            self.pos += rest.len() - rest.trim_start().len();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(Element {
+                    name,
+                    attrs,
+                    children: Vec::new(),
+                    text: String::new(),
+                    span: Span {
+                        start,
+                        end: self.pos,
+                    },
+                });
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            if rest.is_empty() {
+                return Err(self.err(&format!("unclosed tag <{name}>"), start));
+            }
+            let attr = self.name()?;
+            let rest = self.rest();
+            let quote = match rest.strip_prefix('=') {
+                Some(v) if v.starts_with(['"', '\'']) => v.chars().next().unwrap_or('"'),
+                _ => return Err(self.err("expected '=\"...\"'", self.pos)),
+            };
+            let value_start = self.pos + 2;
+            let Some(len) = self.form[value_start..].find(quote) else {
+                return Err(self.err("unclosed attribute value", self.pos));
+            };
+            let value = decode_entities(
+                self,
+                &self.form[value_start..value_start + len],
+                value_start,
+            )?;
+            attrs.push((attr, value));
+            self.pos = value_start + len + 1;
+        }
+        let mut children = Vec::new();
+        let mut text = String::new();
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                let close_start = self.pos;
+                self.pos += 2;
+                let close = self.name()?;
+                if close != name || !self.rest().starts_with('>') {
+                    return Err(self.err(&format!("expected </{name}>"), close_start));
+                }
+                self.pos += 1;
+                break;
+            } else if rest.starts_with("<!--") || rest.starts_with("<?") {
+                self.skip_misc()?;
+            } else if rest.starts_with('<') {
+                children.push(self.element()?);
+            } else if rest.is_empty() {
+                return Err(self.err(&format!("unclosed tag <{name}>"), start));
+            } else {
+                let len = rest.find('<').unwrap_or(rest.len());
+                text += &decode_entities(self, &rest[..len], self.pos)?;
+                self.pos += len;
+            }
+        }
+        Ok(Element {
+            name,
+            attrs,
+            children,
+            text: text.trim().to_string(),
+            span: Span {
+                start,
+                end: self.pos,
+            },
+        })
+    }
+}
+
+/// 文字参照を文字に直す
+/// * `pos` - sの数式中の位置
+fn decode_entities(reader: &XmlReader, s: &str, pos: usize) -> Result<String, MyError> {
+    let mut res = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        res += &rest[..i];
+        let at = pos + (s.len() - rest.len()) + i;
+        let Some(len) = rest[i..].find(';') else {
+            return Err(reader.err("unterminated entity", at));
+        };
+        let name = &rest[i + 1..i + len];
+        let c = if let Some(hex) = name.strip_prefix("#x").or(name.strip_prefix("#X")) {
+            u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+        } else if let Some(dec) = name.strip_prefix('#') {
+            dec.parse().ok().and_then(char::from_u32)
+        } else {
+            named_entity(name)
+        };
+        match c {
+            Some(c) => res.push(c),
+            None => return Err(reader.err(&format!("unknown entity '&{name};'"), at)),
+        }
+        rest = &rest[i + len + 1..];
+    }
+    Ok(res + rest)
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    let c = match name {
+        "lt" => '<',
+        "gt" => '>',
+        "amp" => '&',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "minus" => MINUS,
+        "times" => '×',
+        "sdot" | "middot" | "centerdot" => '⋅',
+        "divide" | "div" => '÷',
+        "InvisibleTimes" | "it" => INVISIBLE_TIMES,
+        "ApplyFunction" | "af" => APPLY_FUNCTION,
+        "InvisibleComma" | "ic" => INVISIBLE_COMMA,
+        "VerticalBar" | "verbar" | "vert" => '|',
+        "lpar" => '(',
+        "rpar" => ')',
+        _ => {
+            return GREEK_LETTERS
+                .iter()
+                .find(|(_, tex)| &tex[1..] == name)
+                .map(|(c, _)| *c);
+        }
+    };
+    Some(c)
+}
+
+/// mrow等の子を並べたもの
+#[derive(Clone, Debug, PartialEq)]
+enum Item {
+    Node(NodeId),
+    /// 関数名と, J_{n}(x)のように添字で書いたときの添字
+    Func(String, Option<NodeId>),
+    Add,
+    Minus,
+    Mul,
+    Div,
+    Open,
+    Close,
+    Bar,
+    Comma,
+}
+
+struct Converter<'a> {
+    form: &'a str,
+    ast: Ast,
+    depth: usize,
+}
+
+impl Converter<'_> {
+    fn loc(&self, span: Span) -> String {
+        format_err_span(self.form, span)
+    }
+
+    fn unsupported(&self, what: String, span: Span) -> MyError {
+        MyError::Unsupported(what, self.loc(span))
+    }
+
+    fn root(&mut self, el: &Element) -> Result<NodeId, MyError> {
+        if el.name != "math" {
+            return Err(MyError::MathmlErr(
+                format!("expected <math> but <{}>", el.name),
+                self.loc(el.span),
+            ));
+        }
+        self.expr(el)
+    }
+
+    /// 1つの要素を式として読む
+    fn expr(&mut self, el: &Element) -> Result<NodeId, MyError> {
+        if self.depth >= MAX_NEST_DEPTH {
+            return Err(MyError::TooDeepNesting(MAX_NEST_DEPTH, self.loc(el.span)));
+        }
+        self.depth += 1;
+        let res = self.expr_inner(el);
+        self.depth -= 1;
+        res
+    }
+
+    fn expr_inner(&mut self, el: &Element) -> Result<NodeId, MyError> {
+        let layout = matches!(
+            &*el.name,
+            "math" | "mrow" | "mstyle" | "mpadded" | "mfrac" | "msqrt" | "mroot" | "msup"
+        );
+        if layout && !el.text.is_empty() {
+            return Err(MyError::MathmlErr(
+                format!("text '{}' directly in <{}>", el.text, el.name),
+                self.loc(el.span),
+            ));
+        }
+        let arity = |n: usize| -> Result<(), MyError> {
+            if el.children.len() == n {
+                Ok(())
+            } else {
+                Err(MyError::MathmlErr(
+                    format!("<{}> takes {n} children but {}", el.name, el.children.len()),
+                    self.loc(el.span),
+                ))
+            }
+        };
+        let (kind, span) = match &*el.name {
+            "math" | "mrow" | "mstyle" | "mpadded" | "mn" | "mi" | "mo" => {
+                let children = if layout {
+                    &el.children[..]
+                } else {
+                    std::slice::from_ref(el)
+                };
+                return self.seq(children, el.span);
+            }
+            "semantics" => match el.children.first() {
+                Some(child) => return self.expr(child),
+                None => {
+                    return Err(MyError::MathmlErr(
+                        "empty <semantics>".to_string(),
+                        self.loc(el.span),
+                    ))
+                }
+            },
+            "mfrac" => {
+                arity(2)?;
+                if el
+                    .attr("linethickness")
+                    .is_some_and(|t| t.trim_end_matches(|c: char| c.is_ascii_alphabetic()) == "0")
+                {
+                    return Err(self.unsupported(
+                        "MathML <mfrac linethickness=\"0\"> (binomial)".to_string(),
+                        el.span,
+                    ));
+                }
+                let num = self.expr(&el.children[0])?;
+                let den = self.expr(&el.children[1])?;
+                (NodeKind::Binary(BinaryOp::Div, num, den), el.span)
+            }
+            "msqrt" => {
+                let arg = self.seq(&el.children, el.span)?;
+                (NodeKind::Unary(UnaryOp::Sqrt, arg), el.span)
+            }
+            "mroot" => {
+                // n乗根は x^(1/n)
+                arity(2)?;
+                let base = self.expr(&el.children[0])?;
+                let index = self.expr(&el.children[1])?;
+                let one = push_node(
+                    &mut self.ast,
+                    NodeKind::Num(1.into()),
+                    el.children[1].span,
+                    None,
+                );
+                let exp = push_node(
+                    &mut self.ast,
+                    NodeKind::Binary(BinaryOp::Div, one, index),
+                    el.children[1].span,
+                    None,
+                );
+                (NodeKind::Binary(BinaryOp::Pow, base, exp), el.span)
+            }
+            "msup" => {
+                arity(2)?;
+                let base_el = &el.children[0];
+                if base_el.name == "mi" && is_func(&base_el.text) {
+                    return Err(self.unsupported(
+                        format!("power of function '{}' in MathML", base_el.text),
+                        base_el.span,
+                    ));
+                }
+                let base = self.expr(base_el)?;
+                let exp = self.expr(&el.children[1])?;
+                (NodeKind::Binary(BinaryOp::Pow, base, exp), el.span)
+            }
+            name => {
+                return Err(self.unsupported(format!("MathML element <{name}>"), el.span));
+            }
+        };
+        Ok(push_node(&mut self.ast, kind, span, Some(span)))
+    }
+
+    /// mrow等の子を並べた式を読む
+    fn seq(&mut self, children: &[Element], span: Span) -> Result<NodeId, MyError> {
+        let mut items = Vec::new();
+        self.items(children, &mut items)?;
+        if items.is_empty() {
+            return Err(MyError::MathmlErr(
+                "empty expression".to_string(),
+                self.loc(span),
+            ));
+        }
+        let mut p = ItemParser {
+            conv: self,
+            items,
+            pos: 0,
+            abs_depth: 0,
+            span,
+        };
+        let node = p.sum()?;
+        if p.pos < p.items.len() {
+            let (item, span) = &p.items[p.pos];
+            return Err(MyError::MathmlErr(
+                format!("unexpected {item:?}"),
+                p.conv.loc(*span),
+            ));
+        }
+        Ok(node)
+    }
+
+    /// mrow等の子を読んでitemsに追加する
+    fn items(
+        &mut self,
+        children: &[Element],
+        items: &mut Vec<(Item, Span)>,
+    ) -> Result<(), MyError> {
+        for el in children {
+            let item = match &*el.name {
+                "mspace" => continue,
+                "mn" => match el.text.parse::<BigDecimal>() {
+                    Ok(n) if !el.text.starts_with(['-', '+']) => {
+                        Item::Node(push_node(&mut self.ast, NodeKind::Num(n), el.span, None))
+                    }
+                    _ => {
+                        return Err(MyError::MathmlErr(
+                            format!("invalid number '{}'", el.text),
+                            self.loc(el.span),
+                        ));
+                    }
+                },
+                "mi" => self.identifier(el)?,
+                "mo" => match self.operator(el)? {
+                    Some(item) => item,
+                    None => continue,
+                },
+                "msub" if sub_func_name(el).is_some() => self.sub_func(el)?,
+                // 関数の引数の括弧は, 中の "," で引数を区切れるよう並びに加える
+                "mrow" if matches!(items.last(), Some((Item::Func(..), _))) && is_paren_row(el) => {
+                    self.items(&el.children, items)?;
+                    continue;
+                }
+                _ => Item::Node(self.expr(el)?),
+            };
+            items.push((item, el.span));
+        }
+        Ok(())
+    }
+
+    /// \<msub>\<mi>J\</mi>添字\</msub>を読む
+    fn sub_func(&mut self, el: &Element) -> Result<Item, MyError> {
+        let name = sub_func_name(el).unwrap_or_default().to_string();
+        let sub = self.expr(&el.children[1])?;
+        Ok(Item::Func(name, Some(sub)))
+    }
+
+    fn identifier(&mut self, el: &Element) -> Result<Item, MyError> {
+        let name = &*el.text;
+        if is_func(name) {
+            return Ok(Item::Func(name.to_string(), None));
+        }
+        let mut chars = name.chars();
+        let var = match (chars.next(), chars.next()) {
+            (Some(c), None) if !c.is_ascii() => match GREEK_LETTERS.iter().find(|(g, _)| *g == c) {
+                Some((_, tex)) => tex.to_string(),
+                None => {
+                    return Err(self.unsupported(format!("MathML identifier '{name}'"), el.span));
+                }
+            },
+            (Some(c), _)
+                if c.is_ascii_alphabetic() && name.chars().all(|c| c.is_ascii_alphanumeric()) =>
+            {
+                name.to_string()
+            }
+            _ => {
+                return Err(self.unsupported(format!("MathML identifier '{name}'"), el.span));
+            }
+        };
+        Ok(Item::Node(push_node(
+            &mut self.ast,
+            NodeKind::Var(var),
+            el.span,
+            None,
+        )))
+    }
+
+    /// moを読む. 関数適用等の読み飛ばす演算子ならNone
+    fn operator(&mut self, el: &Element) -> Result<Option<Item>, MyError> {
+        let mut chars = el.text.chars();
+        let c = match (chars.next(), chars.next()) {
+            (None, _) => return Ok(None),
+            (Some(c), None) => c,
+            _ => {
+                return Err(self.unsupported(format!("MathML operator '{}'", el.text), el.span));
+            }
+        };
+        Ok(Some(match c {
+            APPLY_FUNCTION => return Ok(None),
+            '+' => Item::Add,
+            '-' | MINUS => Item::Minus,
+            '*' | '×' | '·' | '⋅' | '∗' | INVISIBLE_TIMES => Item::Mul,
+            '/' | '÷' | '∕' => Item::Div,
+            '(' | '[' | '{' => Item::Open,
+            ')' | ']' | '}' => Item::Close,
+            '|' => Item::Bar,
+            ',' | INVISIBLE_COMMA => Item::Comma,
+            _ => {
+                return Err(self.unsupported(format!("MathML operator '{}'", el.text), el.span));
+            }
+        }))
+    }
+}
+
+/// registryに登録された関数名か別名ならtrue
+fn is_func(name: &str) -> bool {
+    FUNC_ALIASES.iter().any(|(alias, _)| *alias == name)
+        || registry_reader().is_ok_and(|r| r.lookup(&format!("\\{name}")).is_some())
+}
+
+/// J_{n}(x)のように最初の引数を添字で書く関数ならtrue
+fn is_subscripted(name: &str) -> bool {
+    let cmd = match FUNC_ALIASES.iter().find(|(alias, _)| *alias == name) {
+        Some((_, cmd)) => cmd.to_string(),
+        None => format!("\\{name}"),
+    };
+    registry_reader().is_ok_and(|r| {
+        r.lookup(&cmd)
+            .is_some_and(|(_, c)| matches!(c.eval, Evaluator::Special(f) if f.subscripted()))
+    })
+}
+
+/// 添字で書く関数の\<msub>なら, その関数名を返す
+fn sub_func_name(el: &Element) -> Option<&str> {
+    match el.children.as_slice() {
+        [base, _] if base.name == "mi" && is_subscripted(&base.text) => Some(&base.text),
+        _ => None,
+    }
+}
+
+/// "(" で始まり ")" で終わるmrowならtrue
+fn is_paren_row(el: &Element) -> bool {
+    let is_mo =
+        |el: Option<&Element>, op: &str| el.is_some_and(|el| el.name == "mo" && el.text == op);
+    is_mo(el.children.first(), "(") && is_mo(el.children.last(), ")")
+}
+
+/// mrow等の子の並びを読む
+struct ItemParser<'a, 'b> {
+    conv: &'a mut Converter<'b>,
+    items: Vec<(Item, Span)>,
+    pos: usize,
+    abs_depth: usize,
+    /// 並びを囲む要素の位置. 並びの途中で終わったときのエラーに使う
+    span: Span,
+}
+
+impl ItemParser<'_, '_> {
+    fn peek(&self) -> Option<&Item> {
+        self.items.get(self.pos).map(|(item, _)| item)
+    }
+
+    fn item_span(&self) -> Span {
+        self.items
+            .get(self.pos)
+            .map_or(self.span, |(_, span)| *span)
+    }
+
+    fn unexpected(&self, expected: &str) -> MyError {
+        let got = match self.peek() {
+            Some(item) => format!("{item:?}"),
+            None => "end of element".to_string(),
+        };
+        MyError::UnexpectedToken(expected.to_string(), got, self.conv.loc(self.item_span()))
+    }
+
+    fn expect(&mut self, item: Item) -> Result<(), MyError> {
+        if self.peek() == Some(&item) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("{item:?}")))
+        }
+    }
+
+    fn push(&mut self, kind: NodeKind, span: Span, op_span: Option<Span>) -> NodeId {
+        push_node(&mut self.conv.ast, kind, span, op_span)
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: NodeId, rhs: NodeId, op_span: Span) -> NodeId {
+        let span = self.conv.ast[lhs].span.merge(self.conv.ast[rhs].span);
+        self.push(NodeKind::Binary(op, lhs, rhs), span, Some(op_span))
+    }
+
+    fn sum(&mut self) -> Result<NodeId, MyError> {
+        let mut node = self.product()?;
+        loop {
+            let op = match self.peek() {
+                Some(Item::Add) => BinaryOp::Add,
+                Some(Item::Minus) => BinaryOp::Sub,
+                _ => break,
+            };
+            let op_span = self.item_span();
+            self.pos += 1;
+            let rhs = self.product()?;
+            node = self.binary(op, node, rhs, op_span);
+        }
+        Ok(node)
+    }
+
+    fn product(&mut self) -> Result<NodeId, MyError> {
+        let mut node = self.unary()?;
+        loop {
+            let op_span = self.item_span();
+            let op = match self.peek() {
+                Some(Item::Mul) => {
+                    self.pos += 1;
+                    BinaryOp::Mul
+                }
+                Some(Item::Div) => {
+                    self.pos += 1;
+                    BinaryOp::Div
+                }
+                Some(Item::Node(_) | Item::Func(..) | Item::Open) => BinaryOp::Mul,
+                Some(Item::Bar) if self.abs_depth == 0 => BinaryOp::Mul,
+                _ => break,
+            };
+            let rhs = self.unary()?;
+            node = self.binary(op, node, rhs, op_span);
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<NodeId, MyError> {
+        if self.peek() == Some(&Item::Minus) {
+            let op_span = self.item_span();
+            self.pos += 1;
+            let arg = self.unary()?;
+            let span = op_span.merge(self.conv.ast[arg].span);
+            return Ok(self.push(NodeKind::Unary(UnaryOp::Neg, arg), span, Some(op_span)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<NodeId, MyError> {
+        let span = self.item_span();
+        match self.peek().cloned() {
+            Some(Item::Node(id)) => {
+                self.pos += 1;
+                Ok(id)
+            }
+            Some(Item::Open) => {
+                self.pos += 1;
+                let node = self.sum()?;
+                self.expect(Item::Close)?;
+                Ok(node)
+            }
+            Some(Item::Bar) => {
+                self.pos += 1;
+                self.abs_depth += 1;
+                let arg = self.sum();
+                self.abs_depth -= 1;
+                let arg = arg?;
+                self.expect(Item::Bar)?;
+                let span = span.merge(self.items[self.pos - 1].1);
+                Ok(self.push(NodeKind::Unary(UnaryOp::Abs, arg), span, Some(span)))
+            }
+            Some(Item::Func(name, sub)) => {
+                self.pos += 1;
+                let mut args = Vec::from_iter(sub);
+                if self.peek() == Some(&Item::Open) {
+                    self.pos += 1;
+                    if self.peek() != Some(&Item::Close) {
+                        args.push(self.sum()?);
+                        while self.peek() == Some(&Item::Comma) {
+                            self.pos += 1;
+                            args.push(self.sum()?);
+                        }
+                    }
+                    self.expect(Item::Close)?;
+                } else {
+                    args.push(self.unary()?);
+                }
+                let end = self.items[self.pos - 1].1;
+                let call_span = span.merge(end);
+                push_func(
+                    self.conv.form,
+                    &mut self.conv.ast,
+                    &name,
+                    &args,
+                    span,
+                    call_span,
+                )
+            }
+            _ => Err(self.unexpected("number, identifier or '('")),
+        }
+    }
+}
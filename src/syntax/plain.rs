@@ -0,0 +1,215 @@
+//! 通常の数式記法の入力形式
+//!
+//! ```text
+//! expr(p) = prefix (("+" | "-") expr(11) | ("*" | "/") expr(21) | ("^" | "**") expr(40))*
+//!           ただし演算子の結合力q >= pのものだけを読む
+//! prefix  = "-" expr(31) | primary
+//! primary = num | ident | ident "(" (expr ("," expr)*)? ")" | "(" expr ")"
+//! ```
+//!
+//! ^と**は右結合. 暗黙の乗算は無い
+
+use std::collections::HashMap;
+
+use super::*;
+use crate::ast::BinaryOp;
+use crate::registry::{PREC_ATOM, PREC_POWER, PREC_PRODUCT, PREC_SUM};
+
+const PUNCTS: [&str; 11] = ["**", "+", "-", "*", "/", "^", "(", ")", ",", ";", "="];
+
+/// 単項マイナスの結合力. 乗除算より強く, 累乗より弱い
+const PREC_NEG: u8 = 30;
+
+/// 中置演算子と結合力. 右結合ならtrue
+fn infix(tok: &Tok) -> Option<(BinaryOp, u8, bool)> {
+    match tok {
+        Tok::Punct("+") => Some((BinaryOp::Add, PREC_SUM, false)),
+        Tok::Punct("-") => Some((BinaryOp::Sub, PREC_SUM, false)),
+        Tok::Punct("*") => Some((BinaryOp::Mul, PREC_PRODUCT, false)),
+        Tok::Punct("/") => Some((BinaryOp::Div, PREC_PRODUCT, false)),
+        Tok::Punct("^" | "**") => Some((BinaryOp::Pow, PREC_POWER, true)),
+        _ => None,
+    }
+}
+
+/// 1行分の数式を構文解析する
+pub(super) fn parse_line(
+    form: &str,
+    vars: &mut HashMap<String, Value>,
+) -> Result<(Ast, Vec<NodeOrCmd>), MyError> {
+    parse_stmts(PlainParser::new(form)?, vars)
+}
+
+/// 1つの式を構文解析する
+pub(super) fn parse_expr(form: &str) -> Result<(Ast, NodeId), MyError> {
+    parse_single(PlainParser::new(form)?)
+}
+
+/// ASTを通常の数式記法にする
+pub fn to_plain(ast: &Ast, root: NodeId) -> Result<String, MyError> {
+    Ok(print(ast, root)?.0)
+}
+
+/// 式と, その式の結合力を返す
+fn print(ast: &Ast, id: NodeId) -> Result<(String, u8), MyError> {
+    // 結合力がmin_prec未満か, -で始まる被演算子を括弧で囲む
+    let operand = |id: NodeId, min_prec: u8| -> Result<String, MyError> {
+        let (s, prec) = print(ast, id)?;
+        if prec < min_prec || s.starts_with('-') {
+            Ok(format!("({s})"))
+        } else {
+            Ok(s)
+        }
+    };
+    Ok(match &ast[id].kind {
+        NodeKind::Num(n) if n.sign() == bigdecimal::num_bigint::Sign::Minus => {
+            (num_str(n), PREC_NEG)
+        }
+        NodeKind::Num(n) => (num_str(n), PREC_ATOM),
+        NodeKind::Var(v) => (ident_of_var(v).to_string(), PREC_ATOM),
+        NodeKind::Unary(UnaryOp::Neg, arg) => {
+            (format!("-{}", operand(*arg, PREC_NEG + 1)?), PREC_NEG)
+        }
+        NodeKind::Binary(op, lhs, rhs) => {
+            let (prec, right_assoc) = match op {
+                BinaryOp::Add | BinaryOp::Sub => (PREC_SUM, false),
+                BinaryOp::Mul | BinaryOp::Div => (PREC_PRODUCT, false),
+                BinaryOp::Pow => (PREC_POWER, true),
+            };
+            let (lmin, rmin) = if right_assoc {
+                (prec + 1, prec)
+            } else {
+                (prec, prec + 1)
+            };
+            let l = match print(ast, *lhs)? {
+                (s, p) if p < lmin => format!("({s})"),
+                (s, _) => s,
+            };
+            let r = operand(*rhs, rmin)?;
+            let s = match op {
+                BinaryOp::Pow => format!("{l}^{r}"),
+                op => format!("{l} {} {r}", op.to_op_str()),
+            };
+            (s, prec)
+        }
+        NodeKind::Unary(..) | NodeKind::Call(..) => {
+            let mut args = Vec::new();
+            for child in ast.children(id) {
+                args.push(print(ast, child)?.0);
+            }
+            (
+                format!("{}({})", func_ident(ast, id)?, args.join(", ")),
+                PREC_ATOM,
+            )
+        }
+    })
+}
+
+struct PlainParser<'a> {
+    ts: TokenStream<'a>,
+    ast: Ast,
+}
+
+impl<'a> PlainParser<'a> {
+    fn new(form: &'a str) -> Result<PlainParser<'a>, MyError> {
+        Ok(PlainParser {
+            ts: TokenStream::new(form, &PUNCTS, None, alnum_ident)?,
+            ast: Ast::new(),
+        })
+    }
+}
+
+impl<'a> StmtParser<'a> for PlainParser<'a> {
+    fn ts(&mut self) -> &mut TokenStream<'a> {
+        &mut self.ts
+    }
+
+    fn expr(&mut self) -> Result<NodeId, MyError> {
+        self.expr_bp(0)
+    }
+
+    fn into_ast(self) -> Ast {
+        self.ast
+    }
+}
+
+impl PlainParser<'_> {
+    /// 結合力がmin_prec以上の演算子だけを読む
+    fn expr_bp(&mut self, min_prec: u8) -> Result<NodeId, MyError> {
+        self.ts.enter()?;
+        let res = self.expr_bp_inner(min_prec);
+        self.ts.leave();
+        res
+    }
+
+    fn expr_bp_inner(&mut self, min_prec: u8) -> Result<NodeId, MyError> {
+        let mut node = self.prefix()?;
+        while let Some((op, prec, right_assoc)) = infix(self.ts.peek()) {
+            if prec < min_prec {
+                break;
+            }
+            let op_span = self.ts.span();
+            self.ts.bump();
+            let rhs = self.expr_bp(if right_assoc { prec } else { prec + 1 })?;
+            let span = self.ast[node].span.merge(self.ast[rhs].span);
+            node = push_node(
+                &mut self.ast,
+                NodeKind::Binary(op, node, rhs),
+                span,
+                Some(op_span),
+            );
+        }
+        Ok(node)
+    }
+
+    fn prefix(&mut self) -> Result<NodeId, MyError> {
+        if self.ts.is_punct("-") {
+            let op_span = self.ts.span();
+            self.ts.bump();
+            let arg = self.expr_bp(PREC_NEG + 1)?;
+            let span = op_span.merge(self.ast[arg].span);
+            let kind = NodeKind::Unary(UnaryOp::Neg, arg);
+            return Ok(push_node(&mut self.ast, kind, span, Some(op_span)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<NodeId, MyError> {
+        let span = self.ts.span();
+        match self.ts.peek().clone() {
+            Tok::Num(n) => {
+                self.ts.bump();
+                Ok(push_node(&mut self.ast, NodeKind::Num(n), span, None))
+            }
+            Tok::Ident(name) if self.ts.peek_at(1) == &Tok::Punct("(") => {
+                self.ts.bump();
+                self.ts.bump();
+                let mut args = Vec::new();
+                if !self.ts.is_punct(")") {
+                    args.push(self.expr_bp(0)?);
+                    while self.ts.is_punct(",") {
+                        self.ts.bump();
+                        args.push(self.expr_bp(0)?);
+                    }
+                }
+                self.ts.expect(")")?;
+                let call_span = span.merge(self.ts.prev_span());
+                push_func(self.ts.form, &mut self.ast, &name, &args, span, call_span)
+            }
+            Tok::Ident(name) => {
+                self.ts.bump();
+                let kind = NodeKind::Var(var_of_ident(&name));
+                Ok(push_node(&mut self.ast, kind, span, None))
+            }
+            Tok::Punct("(") => {
+                self.ts.bump();
+                let node = self.expr_bp(0)?;
+                self.ts.expect(")")?;
+                // 閉じ括弧を含めた範囲をnodeの位置とする
+                self.ast[node].span = span.merge(self.ts.prev_span());
+                Ok(node)
+            }
+            _ => Err(self.ts.unexpected("number, variable or '('")),
+        }
+    }
+}
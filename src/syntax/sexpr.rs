@@ -0,0 +1,212 @@
+//! S式の入力形式
+//!
+//! ```text
+//! line  = (tsc_cmd | "(" ("defvar" | "setq") ident signed_num ")" | form)*
+//! form  = num | "-"num | ident | "(" (op | ident) form* ")"
+//! op    = "+" | "-" | "*" | "/"
+//! ```
+//!
+//! ";"から行末まではコメント. +, -, *, /は3つ以上の引数を左から畳み込む
+
+use bigdecimal::BigDecimal;
+use std::collections::HashMap;
+
+use super::*;
+use crate::ast::BinaryOp;
+use crate::parser::insert_consts;
+
+const PUNCTS: [&str; 6] = ["(", ")", "+", "-", "*", "/"];
+
+/// 1行分のS式を構文解析する
+pub(super) fn parse_line(
+    form: &str,
+    vars: &mut HashMap<String, Value>,
+) -> Result<(Ast, Vec<NodeOrCmd>), MyError> {
+    let mut p = SexprParser::new(form)?;
+    let mut res = Vec::new();
+    loop {
+        match p.ts.peek() {
+            Tok::Eot => break,
+            Tok::TscCmd(_) => res.push(NodeOrCmd::TscCmd(p.ts.tsc_cmd()?)),
+            _ if p.is_var_def() => {
+                let (name, value) = p.var_def()?;
+                vars.insert(name, value.into());
+            }
+            _ => res.push(NodeOrCmd::Node(p.form()?)),
+        }
+    }
+    insert_consts(vars)?;
+    if res.is_empty() {
+        return Err(MyError::NoToken);
+    }
+    Ok((p.ast, res))
+}
+
+/// 1つのS式を構文解析する
+pub(super) fn parse_expr(form: &str) -> Result<(Ast, NodeId), MyError> {
+    let mut p = SexprParser::new(form)?;
+    if p.ts.peek() == &Tok::Eot {
+        return Err(MyError::NoToken);
+    }
+    let root = p.form()?;
+    if p.ts.peek() != &Tok::Eot {
+        return Err(MyError::UnprocessedToekn(
+            p.ts.peek().to_string(),
+            p.ts.format_err_loc(p.ts.span()),
+        ));
+    }
+    Ok((p.ast, root))
+}
+
+/// ASTをS式にする
+pub fn to_sexpr(ast: &Ast, root: NodeId) -> Result<String, MyError> {
+    let node = &ast[root];
+    let (head, children): (String, Vec<NodeId>) = match &node.kind {
+        NodeKind::Num(n) => return Ok(num_str(n)),
+        NodeKind::Var(v) => return Ok(ident_of_var(v).to_string()),
+        NodeKind::Unary(UnaryOp::Neg, _) => ("-".to_string(), ast.children(root).collect()),
+        NodeKind::Binary(op, _, _) => (
+            op.to_lisp_op_str().to_string(),
+            ast.children(root).collect(),
+        ),
+        NodeKind::Unary(..) | NodeKind::Call(..) => {
+            (func_ident(ast, root)?, ast.children(root).collect())
+        }
+    };
+    let mut s = format!("({head}");
+    for child in children {
+        s += " ";
+        s += &to_sexpr(ast, child)?;
+    }
+    Ok(s + ")")
+}
+
+struct SexprParser<'a> {
+    ts: TokenStream<'a>,
+    ast: Ast,
+}
+
+impl<'a> SexprParser<'a> {
+    fn new(form: &'a str) -> Result<SexprParser<'a>, MyError> {
+        Ok(SexprParser {
+            ts: TokenStream::new(form, &PUNCTS, Some(';'), alnum_ident)?,
+            ast: Ast::new(),
+        })
+    }
+
+    fn is_var_def(&self) -> bool {
+        self.ts.is_punct("(")
+            && matches!(self.ts.peek_at(1), Tok::Ident(s) if s == "defvar" || s == "setq")
+    }
+
+    /// (defvar name value) を読む
+    fn var_def(&mut self) -> Result<(String, BigDecimal), MyError> {
+        self.ts.expect("(")?;
+        self.ts.bump();
+        let name = match self.ts.peek() {
+            Tok::Ident(name) => var_of_ident(name),
+            _ => return Err(self.ts.unexpected("variable name")),
+        };
+        self.ts.bump();
+        let value = self.ts.signed_num()?;
+        self.ts.expect(")")?;
+        Ok((name, value))
+    }
+
+    fn form(&mut self) -> Result<NodeId, MyError> {
+        self.ts.enter()?;
+        let res = self.form_inner();
+        self.ts.leave();
+        res
+    }
+
+    fn form_inner(&mut self) -> Result<NodeId, MyError> {
+        let span = self.ts.span();
+        match self.ts.peek().clone() {
+            Tok::Num(n) => {
+                self.ts.bump();
+                Ok(push_node(&mut self.ast, NodeKind::Num(n), span, None))
+            }
+            // 間を空けずに数値が続く"-"は負の数のリテラル
+            Tok::Punct("-")
+                if matches!(self.ts.peek_at(1), Tok::Num(_))
+                    && self.ts.spans[self.ts.idx + 1].start == span.end =>
+            {
+                let n = self.ts.signed_num()?;
+                let span = span.merge(self.ts.prev_span());
+                Ok(push_node(&mut self.ast, NodeKind::Num(n), span, None))
+            }
+            Tok::Ident(name) => {
+                self.ts.bump();
+                let kind = NodeKind::Var(var_of_ident(&name));
+                Ok(push_node(&mut self.ast, kind, span, None))
+            }
+            Tok::Punct("(") => self.list(),
+            _ => Err(self.ts.unexpected("number, symbol or '('")),
+        }
+    }
+
+    /// (head args...) を読む
+    fn list(&mut self) -> Result<NodeId, MyError> {
+        let start = self.ts.span();
+        self.ts.bump();
+        let op_span = self.ts.span();
+        let head = match self.ts.peek() {
+            Tok::Punct(p) if *p != "(" && *p != ")" => p.to_string(),
+            Tok::Ident(name) => name.clone(),
+            _ => return Err(self.ts.unexpected("operator or function name")),
+        };
+        self.ts.bump();
+        let mut args = Vec::new();
+        while !self.ts.is_punct(")") && self.ts.peek() != &Tok::Eot {
+            args.push(self.form()?);
+        }
+        self.ts.expect(")")?;
+        let span = start.merge(self.ts.prev_span());
+        let arg_count_err = |expected: &str| {
+            Err(MyError::ArgCountErr(
+                head.clone(),
+                expected.to_string(),
+                args.len(),
+                self.ts.format_err_loc(span),
+            ))
+        };
+        let op = match &*head {
+            "+" => BinaryOp::Add,
+            "-" => BinaryOp::Sub,
+            "*" => BinaryOp::Mul,
+            "/" => BinaryOp::Div,
+            "expt" if args.len() == 2 => BinaryOp::Pow,
+            "expt" => return arg_count_err("2"),
+            _ => return push_func(self.ts.form, &mut self.ast, &head, &args, op_span, span),
+        };
+        match (op, args.as_slice()) {
+            (_, []) => arg_count_err("1 or more"),
+            (BinaryOp::Sub, [arg]) => {
+                let kind = NodeKind::Unary(UnaryOp::Neg, *arg);
+                Ok(push_node(&mut self.ast, kind, span, Some(op_span)))
+            }
+            // (/ x) は 1/x
+            (BinaryOp::Div, [arg]) => {
+                let one = push_node(&mut self.ast, NodeKind::Num(1.into()), op_span, None);
+                let kind = NodeKind::Binary(op, one, *arg);
+                Ok(push_node(&mut self.ast, kind, span, Some(op_span)))
+            }
+            (BinaryOp::Add | BinaryOp::Mul, [arg]) => Ok(*arg),
+            (_, [first, rest @ ..]) => {
+                let mut node = *first;
+                for (i, arg) in rest.iter().enumerate() {
+                    // 最後のnodeはリスト全体, 途中のnodeは畳み込んだ引数の範囲を表す
+                    let span = if i + 2 == args.len() {
+                        span
+                    } else {
+                        self.ast[*first].span.merge(self.ast[*arg].span)
+                    };
+                    let kind = NodeKind::Binary(op, node, *arg);
+                    node = push_node(&mut self.ast, kind, span, Some(op_span));
+                }
+                Ok(node)
+            }
+        }
+    }
+}
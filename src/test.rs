@@ -3,6 +3,8 @@ use std::collections::HashMap;
 use std::io::Write;
 use text_colorizer::*;
 
+use crate::Value;
+
 struct TestCase {
     formula: String,
     result: BigDecimal,
@@ -13,7 +15,7 @@ fn test_calc() {
     let test_cases = get_testcases();
     let mut test_success = 0;
     for (i, tc) in test_cases.iter().enumerate() {
-        let mut vars: HashMap<String, BigDecimal> = HashMap::new();
+        let mut vars: HashMap<String, Value> = HashMap::new();
         for line in tc.formula.split('\n') {
             match crate::process_form(line.replace("\r", ""), &mut vars) {
                 Ok(r) => {
@@ -66,6 +68,7 @@ fn test_calc() {
     assert_eq!(test_success, test_cases.len());
 }
 
+#[allow(clippy::vec_init_then_push)]
 fn get_testcases() -> Vec<TestCase> {
     let mut test_cases: Vec<TestCase> = Vec::new();
     test_cases.push(TestCase {
@@ -80,5 +83,659 @@ fn get_testcases() -> Vec<TestCase> {
         formula: "-\\abs (-2)^{\\frac{1}{4/2}}^{6}".to_string(),
         result: BigDecimal::from_f64(-8.0).unwrap(),
     });
+    test_cases.push(TestCase {
+        formula: "2×3 − √4 + 3²".to_string(),
+        result: BigDecimal::from_f64(13.0).unwrap(),
+    });
+    test_cases.push(TestCase {
+        formula: "½ · ４ ÷ 2".to_string(),
+        result: BigDecimal::from_f64(1.0).unwrap(),
+    });
+    test_cases.push(TestCase {
+        formula: "√16 + 2⁻¹".to_string(),
+        result: BigDecimal::from_f64(4.5).unwrap(),
+    });
     test_cases
 }
+
+#[test]
+fn test_domain_err() {
+    let formulas = [
+        "1/0",
+        "\\frac{1}{2-2}",
+        "\\log 0",
+        "\\ln -1",
+        "\\arcsin 2",
+        "\\arccos -2",
+        "\\sqrt{-1}",
+        "\\csc 0",
+        "\\cot 0",
+        "(-8)^{0.5}",
+        "0^{-1}",
+        "\\frac{x}{x} ; x = 0",
+    ];
+    for form in formulas {
+        let mut vars: HashMap<String, Value> = HashMap::new();
+        match crate::process_form(form.to_string(), &mut vars) {
+            Err(crate::MyError::DomainErr(_, _, _)) => (),
+            Err(e) => panic!("{form}: expected DomainErr but {e}"),
+            Ok(r) => panic!("{form}: expected DomainErr but {r:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_too_deep() {
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    // 括弧が無くても, 長い式は評価する前にエラーにする
+    let forms = [
+        vec!["1"; 30000].join("+"),
+        format!("{}1{}", "(".repeat(300), ")".repeat(300)),
+    ];
+    for form in forms {
+        match crate::process_form(form, &mut vars) {
+            Err(crate::MyError::TooDeepNesting(..)) => (),
+            r => panic!("expected TooDeepNesting but {r:?}"),
+        }
+    }
+    let res = crate::process_form(vec!["1"; 400].join(" + "), &mut vars).unwrap();
+    assert_eq!(res, [BigDecimal::from(400)]);
+    let form = vec!["x"; 30000].join("+");
+    assert!(matches!(
+        crate::syntax::parse_expr(&form, crate::config::Syntax::Plain),
+        Err(crate::MyError::TooDeepNesting(..))
+    ));
+}
+
+#[test]
+fn test_node_span() {
+    use crate::parser::{NodeOrCmd, Parser, Span};
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    let mut pars = Parser::new("1 + \\frac{2}{y} * 3".to_string(), false).unwrap();
+    let (ast, mut nodes) = pars.build_ast(&mut vars).unwrap();
+    let root = match nodes.pop() {
+        Some(NodeOrCmd::Node(root)) => root,
+        _ => panic!("expected Node"),
+    };
+    // 1 + \frac{2}{y} * 3
+    assert_eq!(ast[root].span, Span { start: 0, end: 19 });
+    assert_eq!(ast[root].op_span, Some(Span { start: 2, end: 3 }));
+    let mul = ast.children(root).last().unwrap();
+    assert_eq!(ast[mul].span, Span { start: 4, end: 19 });
+    let frac = ast.children(mul).next().unwrap();
+    assert_eq!(ast[frac].span, Span { start: 4, end: 15 });
+    assert_eq!(ast[frac].op_span, Some(Span { start: 4, end: 9 }));
+    let y = ast.children(frac).last().unwrap();
+    assert_eq!(ast[y].span, Span { start: 13, end: 14 });
+}
+
+#[test]
+fn test_multiple_err() {
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    let form = "\\frac{1+}{2 +* 3} + \\sn x + (4";
+    match crate::process_form(form.to_string(), &mut vars) {
+        Err(crate::MyError::MultipleErr(errs)) => {
+            assert_eq!(errs.len(), 4);
+            // 括弧の対応のエラーが最初に開き括弧の位置で報告される
+            assert!(matches!(&errs[0], crate::MyError::UnclosedBrace(br, _) if br == "("));
+            assert!(matches!(
+                &errs[1],
+                crate::MyError::UDtexcommand(cmd, hint, _) if cmd == "\\sn" && hint.contains("\\sin")
+            ));
+        }
+        Err(e) => panic!("expected MultipleErr but {e}"),
+        Ok(r) => panic!("expected MultipleErr but {r:?}"),
+    }
+    // 閉じられていない括弧はそれぞれ1回だけ報告する
+    match crate::process_form("(((((".to_string(), &mut vars) {
+        Err(crate::MyError::MultipleErr(errs)) => {
+            let unclosed = errs
+                .iter()
+                .filter(|e| matches!(e, crate::MyError::UnclosedBrace(_, _)))
+                .count();
+            assert_eq!(unclosed, 5);
+            assert_eq!(errs.len(), 5, "{errs:?}");
+        }
+        r => panic!("expected MultipleErr but {r:?}"),
+    }
+    // 括弧の対応のエラーの後に, 同じ括弧やEOTでの構文エラーを重ねない
+    for form in ["1 + 2)", "\\frac{1}{2", "{", "(1]", "\\sqrt{2})"] {
+        match crate::process_form(form.to_string(), &mut vars) {
+            Err(crate::MyError::UnclosedBrace(..) | crate::MyError::UnmatchedBrace(..)) => (),
+            r => panic!("{form}: expected one brace error but {r:?}"),
+        }
+    }
+    // 読めない文字のエラーの後に, その直後での構文エラーを重ねない
+    for form in ["1 + ?", "(? + 1)", "\\sqrt{?}"] {
+        match crate::process_form(form.to_string(), &mut vars) {
+            Err(crate::MyError::InvalidInput(..)) => (),
+            r => panic!("{form}: expected one lexical error but {r:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_err_caret_width() {
+    use crate::parser::{format_err_span, Span};
+    // 全角文字は2桁分として^の位置を揃える
+    let form = "１２ + \\sn 3";
+    let start = form.find('\\').unwrap();
+    let msg = format_err_span(
+        form,
+        Span {
+            start,
+            end: start + 3,
+        },
+    );
+    let caret_line = msg.lines().nth(1).unwrap();
+    assert_eq!(caret_line.len() - caret_line.trim_start().len(), 7);
+}
+
+#[test]
+fn test_register_command() {
+    use crate::registry::{register, ArgStyle, Command, FuncDef, PREC_POWER, PREC_SUM};
+    register(Command::native("twice", 1, ArgStyle::Paren, |args| {
+        Ok(&args[0] + &args[0])
+    }))
+    .unwrap();
+    crate::registry::register_functions(&[FuncDef {
+        name: "hypot".to_string(),
+        params: vec!["a".to_string(), "b".to_string()],
+        style: ArgStyle::Brace,
+        prec: PREC_POWER,
+        body: "\\sqrt{a^{2} + b^{2}}".to_string(),
+    }])
+    .unwrap();
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    let res = crate::process_form("\\twice(3) + \\hypot{3}{4}".to_string(), &mut vars).unwrap();
+    assert_eq!(res, [BigDecimal::from(11)]);
+    // 結合力を指定した関数. 後に定義した関数も本体で呼べる
+    let signed = |name: &str, prec, body: &str| FuncDef {
+        name: name.to_string(),
+        params: vec!["x".to_string()],
+        style: ArgStyle::Signed,
+        prec,
+        body: body.to_string(),
+    };
+    crate::registry::register_functions(&[
+        signed("quarter", PREC_POWER, "\\frac{\\halve x}{2}"),
+        signed("halve", PREC_SUM, "\\frac{x}{2}"),
+    ])
+    .unwrap();
+    let res =
+        crate::process_form("\\halve 2 + 4 ; \\quarter 8 + 4".to_string(), &mut vars).unwrap();
+    assert_eq!(res, [BigDecimal::from(3), BigDecimal::from(6)]);
+    // 本体の構文エラーは登録するときに分かる
+    assert!(crate::registry::register_functions(&[signed("broken", PREC_POWER, "x +")]).is_err());
+    // 組み込みのコマンドは置き換えられない
+    assert!(
+        register(Command::native("sin", 1, ArgStyle::Signed, |args| Ok(args
+            [0]
+        .clone())))
+        .is_err()
+    );
+    assert!(
+        register(Command::native("add3", 3, ArgStyle::Signed, |args| Ok(
+            args[0].clone()
+        )))
+        .is_err()
+    );
+    // 3引数以上の関数
+    register(Command::native("sumthree", 3, ArgStyle::Brace, |args| {
+        Ok(args.iter().sum())
+    }))
+    .unwrap();
+    let res = crate::process_form("\\sumthree{1}{2}{3 + 4}".to_string(), &mut vars).unwrap();
+    assert_eq!(res, [BigDecimal::from(10)]);
+}
+
+#[test]
+fn test_simplify() {
+    use crate::optimizer::simplify;
+    use crate::parser::Parser;
+    use crate::tex_printer::to_tex;
+    let cases = [
+        ("x + x", "2x"),
+        ("x \\cdot 1 + 0", "x"),
+        ("2 \\cdot 3x - 4x + 1 - 3", "2x - 2"),
+        ("3 - 5x + 2x", "-3x + 3"),
+        // xが0かもしれないので\frac{2x}{2x}は残す
+        ("\\frac{3}{3} + \\frac{2x}{2x}", "\\frac{2x}{2x} + 1"),
+        ("\\frac{1}{3}", "\\frac{1}{3}"),
+        ("-(a - b)", "b - a"),
+        ("--x", "x"),
+        ("\\frac{-x}{y}", "-\\frac{x}{y}"),
+        ("2^{10} + x^{1} + y^{0}", "x + 1025"),
+        (
+            "\\sin x^{2} + \\sqrt{16} \\cdot \\frac{1}{2}",
+            "\\sin x^{2} + 2",
+        ),
+        ("x \\cdot (-2) y", "-2x y"),
+        ("(x + 1)(x - 1)", "(x + 1) (x - 1)"),
+    ];
+    for (form, expected) in cases {
+        let (ast, root) = Parser::new(form.to_string(), false)
+            .unwrap()
+            .build_expr()
+            .unwrap();
+        let (ast, root) = simplify(&ast, root, None).unwrap();
+        assert_eq!(to_tex(&ast, root).unwrap(), expected, "{form}");
+    }
+    // 評価できない項は消さない
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    match crate::process_form("y - y".to_string(), &mut vars) {
+        Err(crate::MyError::UDvariableErr(v, _)) => assert_eq!(v, "y"),
+        r => panic!("expected UDvariableErr but {r:?}"),
+    }
+    let res = crate::process_form("x - x + \\frac{x}{x} ; x = 3".to_string(), &mut vars).unwrap();
+    assert_eq!(res, [BigDecimal::from(1)]);
+    // :simplifyは定義済みの変数の値を使う
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    let simplify_form = crate::tsc_cmd::simplify_form;
+    assert_eq!(
+        simplify_form("\\frac{a}{a}", &vars).unwrap(),
+        "\\frac{a}{a}"
+    );
+    crate::process_form("1 ; a = 2".to_string(), &mut vars).unwrap();
+    assert_eq!(simplify_form("\\frac{a}{a}", &vars).unwrap(), "1");
+    // 表示では評価できない変数の項も係数が0なら消す
+    assert_eq!(simplify_form("\\frac{a}{a} + b - b", &vars).unwrap(), "1");
+    assert_eq!(simplify_form("x - x", &vars).unwrap(), "0");
+    assert_eq!(simplify_form("0 \\cdot y + x", &vars).unwrap(), "x");
+    // 0で割る項やlog 0の項は係数が0でも消さない
+    for form in ["0 \\cdot \\frac{1}{0}", "(x - x) \\log 0"] {
+        let shown = simplify_form(form, &vars).unwrap();
+        assert_ne!(shown, "0", "{form}");
+        let res = crate::process_form(format!("{shown} ; x = 1"), &mut vars);
+        assert!(res.is_err(), "{form} -> {shown}: {res:?}");
+    }
+}
+
+#[test]
+fn test_syntax() {
+    use crate::config::Syntax;
+    // 同じ式はどの入力形式でも同じ値になる
+    let cases = [
+        (
+            "\\frac{x}{2} + \\sqrt{16} \\cdot 2^{3} ; x = 3",
+            "(+ (/ x 2) (* (sqrt 16) (expt 2 3))) (defvar x 3)",
+            "x / 2 + sqrt(16) * 2**3; x = 3",
+        ),
+        (
+            ":hex 255 ; 0b101 - \\abs(-2)",
+            ":hex 255 (- 0b101 (abs -2))",
+            ":hex 255; 0b101 - abs(-2)",
+        ),
+        (
+            "\\sin \\frac{\\pi}{2} - \\ln e ; \\alpha = -1",
+            "(- (sin (/ pi 2)) (ln e)) (setq alpha -1)",
+            "sin(pi / 2) - ln(e); alpha = -1",
+        ),
+    ];
+    for (tex, sexpr, plain) in cases {
+        let mut vars: HashMap<String, Value> = HashMap::new();
+        let expected = crate::process_form_as(tex.to_string(), Syntax::Tex, &mut vars).unwrap();
+        for (form, syntax) in [(sexpr, Syntax::Sexpr), (plain, Syntax::Plain)] {
+            let mut vars: HashMap<String, Value> = HashMap::new();
+            let res = crate::process_form_as(form.to_string(), syntax, &mut vars).unwrap();
+            assert_eq!(res, expected, "{form}");
+        }
+    }
+    // 変数定義だけの行は値を返さない
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    assert!(matches!(
+        crate::process_form_as("(defvar y 2)".to_string(), Syntax::Sexpr, &mut vars),
+        Err(crate::MyError::NoToken)
+    ));
+    let res = crate::process_form_as("y^y".to_string(), Syntax::Plain, &mut vars).unwrap();
+    assert_eq!(res, [BigDecimal::from(4)]);
+}
+
+#[test]
+fn test_solve() {
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    // 最初の解を変数に代入する
+    let res = crate::process_form(
+        ":solve x x^{3} - 3x^{2} + 2x from 0.5 to 10 bind ; x".to_string(),
+        &mut vars,
+    )
+    .unwrap();
+    assert_eq!(res, [BigDecimal::from(1)]);
+    // 右辺と変数の省略
+    vars.insert("a".to_string(), BigDecimal::from(4).into());
+    assert!(matches!(
+        crate::process_form(":solve t^{2} - a from 0 to a bind".to_string(), &mut vars),
+        Err(crate::MyError::NoToken)
+    ));
+    assert_eq!(vars["t"], BigDecimal::from(2).into());
+    assert!(matches!(
+        crate::process_form(":solve x x^{2} = -1".to_string(), &mut vars),
+        Err(crate::MyError::NoRoot(..))
+    ));
+}
+
+#[test]
+fn test_linsolve() {
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    // ; y = 1 は変数定義ではなく方程式
+    assert!(matches!(
+        crate::process_form(
+            ":linsolve 2x + 3y = 5; x - y = 1 bind".to_string(),
+            &mut vars
+        ),
+        Err(crate::MyError::NoToken)
+    ));
+    assert_eq!(vars["x"], "1.6".parse::<BigDecimal>().unwrap().into());
+    assert_eq!(vars["y"], "0.6".parse::<BigDecimal>().unwrap().into());
+    let res = crate::process_form("x - y".to_string(), &mut vars).unwrap();
+    assert_eq!(res, [BigDecimal::from(1)]);
+    assert!(matches!(
+        crate::process_form(":linsolve a + b = 3; b = 1".to_string(), &mut vars),
+        Err(crate::MyError::NoToken)
+    ));
+    assert!(!vars.contains_key("a") && !vars.contains_key("b"));
+}
+
+#[test]
+fn test_poly() {
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    vars.insert("a".to_string(), BigDecimal::from(2).into());
+    // 出力だけのコマンドは値を返さない
+    for form in [
+        ":expand (x - a)(x + 2)",
+        ":factor x^{4} - 1",
+        ":roots t^{3} - a",
+        ":polydiv x \\frac{x^{3} - 1}{x - 1}",
+    ] {
+        assert!(
+            matches!(
+                crate::process_form(form.to_string(), &mut vars),
+                Err(crate::MyError::NoToken)
+            ),
+            "{form}"
+        );
+    }
+    for (form, var) in [(":factor \\sqrt{x}", "x"), (":roots y \\frac{1}{y}", "y")] {
+        match crate::process_form(form.to_string(), &mut vars) {
+            Err(crate::MyError::NotPolynomialErr(v, _)) => assert_eq!(v, var),
+            r => panic!("expected NotPolynomialErr but {r:?}"),
+        }
+    }
+    assert!(matches!(
+        crate::process_form(":polydiv \\frac{x}{x - x}".to_string(), &mut vars),
+        Err(crate::MyError::DomainErr(..))
+    ));
+}
+
+#[test]
+fn test_plot() {
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    // ファイル名は字句解析せずにそのまま読む
+    let path = std::env::temp_dir().join("tscplot-1.svg");
+    let _ = std::fs::remove_file(&path);
+    // 関数は , で区切り, 後ろの変数の定義は描いた後に処理する
+    assert!(matches!(
+        crate::process_form(
+            format!(
+                ":plot \\tan t, a t t from 0 to \\pi --svg {}; a = 2",
+                path.display()
+            ),
+            &mut vars
+        ),
+        Err(crate::MyError::UDvariableErr(..))
+    ));
+    vars.insert("a".to_string(), BigDecimal::from(2).into());
+    let res = crate::process_form(
+        format!(
+            ":plot \\tan t, a t t from 0 to \\pi --svg {}; a",
+            path.display()
+        ),
+        &mut vars,
+    )
+    .unwrap();
+    assert_eq!(res, [BigDecimal::from(2)]);
+    let svg = std::fs::read_to_string(&path).unwrap();
+    // \tanは\frac{\pi}{2}で途切れる
+    assert_eq!(svg.matches("<polyline").count(), 3);
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        crate::process_form(":plot x from 0".to_string(), &mut vars),
+        Err(crate::MyError::UnexpectedToken(..))
+    ));
+    // 式の中の , はエラー
+    assert!(crate::process_form("1 + 2, 3".to_string(), &mut vars).is_err());
+}
+
+#[test]
+fn test_table() {
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    vars.insert("a".to_string(), BigDecimal::from(2).into());
+    let path = std::env::temp_dir().join("tsc_table-1.csv");
+    let _ = std::fs::remove_file(&path);
+    // 後の変数ほど速く変わる
+    assert!(matches!(
+        crate::process_form(
+            format!(
+                ":table a x + y, x = 0..1 step 0.5, y = 1..2 --csv {}",
+                path.display()
+            ),
+            &mut vars,
+        ),
+        Err(crate::MyError::NoToken)
+    ));
+    let csv = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        csv,
+        "x,y,a x + y\n0,1,1\n0,2,2\n0.5,1,2.0\n0.5,2,3.0\n1,1,3\n1,2,4\n"
+    );
+    assert!(matches!(
+        crate::process_form(":table x, x = 0..1 step -1".to_string(), &mut vars),
+        Err(crate::MyError::InvalidInput(..))
+    ));
+}
+
+#[test]
+fn test_loadast() {
+    use crate::ast_json::to_json;
+    use crate::parser::Parser;
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    vars.insert("a".to_string(), BigDecimal::from(4).into());
+    let path = std::env::temp_dir().join("tsc-loadast_1.json");
+    let (ast, root) = Parser::new(
+        "\\frac{a}{2} + \\sqrt{9} \\cdot \\pi^{0}".to_string(),
+        false,
+    )
+    .unwrap()
+    .build_expr()
+    .unwrap();
+    std::fs::write(&path, to_json(&ast, root).unwrap()).unwrap();
+    let file = path.display().to_string();
+    assert_eq!(
+        crate::tsc_cmd::load_ast(&file, &mut vars).unwrap(),
+        [BigDecimal::from(5)]
+    );
+    assert!(matches!(
+        crate::process_form(format!(":loadast {file}"), &mut vars),
+        Err(crate::MyError::NoToken)
+    ));
+    std::fs::write(&path, "{\"kind\": \"var\"}").unwrap();
+    assert!(crate::tsc_cmd::load_ast(&file, &mut vars).is_err());
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        crate::tsc_cmd::load_ast(&file, &mut vars),
+        Err(crate::MyError::FileReadErr(..))
+    ));
+}
+
+#[test]
+fn test_load() {
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    let dir = std::env::temp_dir().join("tscload");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("my-data_1.csv");
+    std::fs::write(&path, "t,height\n0,1\n1,3\n2,5\n3,7\n").unwrap();
+    assert!(matches!(
+        crate::process_form(format!(":load {} as d", path.display()), &mut vars),
+        Err(crate::MyError::NoToken)
+    ));
+    std::fs::remove_file(&path).unwrap();
+    let res = crate::process_form(
+        "\\operatorname{mean}(d2) + \\sum(d1, 10); \\operatorname{quantile}(d2, 0.5)".to_string(),
+        &mut vars,
+    )
+    .unwrap();
+    assert_eq!(res, [BigDecimal::from(20), BigDecimal::from(4)]);
+    assert!(matches!(
+        crate::process_form(":fit linear d1 d2".to_string(), &mut vars),
+        Err(crate::MyError::NoToken)
+    ));
+    assert!(matches!(
+        crate::process_form(":fit linear d1 nolist".to_string(), &mut vars),
+        Err(crate::MyError::UDlistErr(..))
+    ));
+    assert!(matches!(
+        crate::process_form("\\operatorname{var}(1)".to_string(), &mut vars),
+        Err(crate::MyError::CalcErr(_))
+    ));
+    // pはリストとして展開せず, 省略すると引数の数のエラーにする
+    assert!(matches!(
+        crate::process_form("\\operatorname{quantile}(d1)".to_string(), &mut vars),
+        Err(crate::MyError::ArgCountErr(..))
+    ));
+    assert!(matches!(
+        crate::process_form("\\operatorname{quantile}(d1, d2)".to_string(), &mut vars),
+        Err(crate::MyError::ListAsNumErr(..))
+    ));
+    assert!(matches!(
+        crate::process_form("d1 + 1".to_string(), &mut vars),
+        Err(crate::MyError::ListAsNumErr(..))
+    ));
+    // リスト変数は読み込んだ変数の表にだけある
+    let mut other: HashMap<String, Value> = HashMap::new();
+    assert!(matches!(
+        crate::process_form(":fit linear d1 d2".to_string(), &mut other),
+        Err(crate::MyError::UDlistErr(..))
+    ));
+    // 関数の引数の "," は詰めて書いても桁区切りにしない
+    let res = crate::process_form(
+        "\\operatorname{mean}(1,2,3); \\sum(1,2,3)".to_string(),
+        &mut vars,
+    )
+    .unwrap();
+    assert_eq!(res, [BigDecimal::from(2), BigDecimal::from(6)]);
+}
+
+#[test]
+fn test_special_functions() {
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    let res = crate::process_form(
+        "\\Gamma(5) + J_{0}(0); \\operatorname{erf}(1) + \\operatorname{erfc}(1)".to_string(),
+        &mut vars,
+    )
+    .unwrap();
+    assert_eq!(res, [BigDecimal::from(25), BigDecimal::from(1)]);
+    let res = crate::process_form("\\ln\\Gamma(3) - \\ln 2".to_string(), &mut vars).unwrap();
+    assert!(res[0].abs() < BigDecimal::from_f64(1e-12).unwrap());
+    // 引数が足りなければギリシャ文字の変数として扱う
+    let res = crate::process_form("\\gamma(3) ; \\gamma = 2".to_string(), &mut vars).unwrap();
+    let alpha = crate::process_form("\\alpha(3) ; \\alpha = 2".to_string(), &mut vars).unwrap();
+    assert_eq!(res, alpha);
+    assert!(matches!(
+        crate::process_form("\\Gamma(0)".to_string(), &mut vars),
+        Err(crate::MyError::DomainErr(..))
+    ));
+    for form in ["\\Gamma(1, 2, 3)", "\\Gamma(1,2,3)", "J_{0}(1,5)"] {
+        assert!(
+            matches!(
+                crate::process_form(form.to_string(), &mut vars),
+                Err(crate::MyError::ArgCountErr(..))
+            ),
+            "{form}"
+        );
+    }
+}
+
+#[test]
+fn test_distributions() {
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    let res = crate::process_form(
+        "\\operatorname{binocdf}(10, 10, 0.3); \\operatorname{unifinv}(0.25, 2, 6)".to_string(),
+        &mut vars,
+    )
+    .unwrap();
+    assert_eq!(res, [BigDecimal::from(1), BigDecimal::from(3)]);
+    // 名前に数字を含む関数も\operatorname{}で書ける
+    let res = crate::process_form(
+        "\\operatorname{chi2cdf}(\\operatorname{chi2inv}(0.95, 3), 3)".to_string(),
+        &mut vars,
+    )
+    .unwrap();
+    assert!(
+        (&res[0] - BigDecimal::from_f64(0.95).unwrap()).abs() < BigDecimal::from_f64(1e-9).unwrap()
+    );
+    let tight = crate::process_form("\\operatorname{normcdf}(1,0,1)".to_string(), &mut vars);
+    let spaced = crate::process_form("\\operatorname{normcdf}(1, 0, 1)".to_string(), &mut vars);
+    assert_eq!(tight.unwrap(), spaced.unwrap());
+    // 乱数を使うテストはここだけなので, 種から同じ列ができる
+    let sample =
+        "\\sum(\\operatorname{rand}(), \\operatorname{normrnd}(1, 2), \\operatorname{poissrnd}(3))";
+    let mut runs = Vec::new();
+    for _ in 0..2 {
+        assert!(matches!(
+            crate::process_form(":seed 42".to_string(), &mut vars),
+            Err(crate::MyError::NoToken)
+        ));
+        runs.push(crate::process_form(sample.to_string(), &mut vars).unwrap());
+    }
+    assert_eq!(runs[0], runs[1]);
+    assert!(matches!(
+        crate::process_form("\\operatorname{norminv}(1)".to_string(), &mut vars),
+        Err(crate::MyError::DomainErr(..))
+    ));
+    assert!(matches!(
+        crate::process_form("\\operatorname{normpdf}()".to_string(), &mut vars),
+        Err(crate::MyError::ArgCountErr(..))
+    ));
+}
+
+#[test]
+fn test_integer_cmds() {
+    let mut vars: HashMap<String, Value> = HashMap::new();
+    vars.insert("n".to_string(), BigDecimal::from(60).into());
+    for form in [
+        ":fact 2^{64}+1",
+        ":gcd 12 42 66",
+        ":gcd 2^{100}, 6^{50}",
+        ":lcm 4, 6, 10",
+        ":isprime 2^{61}-1",
+        ":nextprime 10^{12}",
+        ":divisors n",
+        ":modpow 2, 10^{18}, 10^{9}+7",
+        ":crt 2, 3, 3, 5, 2, 7",
+    ] {
+        assert!(
+            matches!(
+                crate::process_form(form.to_string(), &mut vars),
+                Err(crate::MyError::NoToken)
+            ),
+            "{form}"
+        );
+    }
+    assert!(matches!(
+        crate::process_form(":fact \\frac{1}{2}".to_string(), &mut vars),
+        Err(crate::MyError::NotIntegerErr(..))
+    ));
+    assert!(matches!(
+        crate::process_form(":gcd 12".to_string(), &mut vars),
+        Err(crate::MyError::ArgCountErr(..))
+    ));
+    assert!(matches!(
+        crate::process_form(":modinv 4, 10".to_string(), &mut vars),
+        Err(crate::MyError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        crate::process_form(":crt 1, 4, 2, 6".to_string(), &mut vars),
+        Err(crate::MyError::InconsistentSystem(2))
+    ));
+}
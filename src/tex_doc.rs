@@ -0,0 +1,753 @@
+//! LaTeX文書に埋め込まれた数式の評価
+//!
+//! `$...$`, `$$...$$`, `\(...\)`, `\[...\]`, equation, alignの各環境から数式を取り出して評価する
+//!
+//! * `%`から行末まで, verbatim環境は読まない
+//! * `\label`, `\tag`, `\nonumber`, `\left`, `\,`等の評価に関係ないコマンドと`&`は無視する
+//! * `\text{...}`, `\quad`, 行区切りの`\\`で数式を分けて, それぞれを評価する
+//! * `a = b = c`は各辺を評価して比べる. 左辺が変数1つなら右辺の値をその変数に代入する
+//! * `&= c`のように`=`で始まる行は, 前の行の最後の辺に続けて比べる
+//!
+//! 書き戻しでは, 数式の後の`% tsc: =`の後ろと, `\tsc{式}`の後の`{値}`に値を書き込む.
+//! `\tsc{式}`はLaTeXでは`\newcommand{\tsc}[2]{#2}`として値だけを表示する
+
+use bigdecimal::BigDecimal;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use text_colorizer::*;
+
+use crate::ast::NodeKind;
+use crate::config::*;
+use crate::error::*;
+use crate::num_formatter::num_formatter;
+use crate::parser::{format_err_span, insert_consts, Span};
+use crate::{eprintlnc, eval_root, syntax, Value, CONSTS};
+
+/// 数式を取り出す環境. *付きも同じように扱う
+const MATH_ENVS: [&str; 2] = ["equation", "align"];
+
+/// 読み飛ばす環境
+const VERBATIM_ENVS: [&str; 2] = ["verbatim", "comment"];
+
+/// 無視するコマンド
+const IGNORED_CMDS: [&str; 8] = [
+    "nonumber",
+    "notag",
+    "left",
+    "right",
+    "displaystyle",
+    "textstyle",
+    "limits",
+    "nolimits",
+];
+
+/// 引数ごと無視するコマンド
+const IGNORED_GROUP_CMDS: [&str; 4] = ["label", "tag", "begin", "end"];
+
+/// 数式の区切りになるコマンド
+const SEP_CMDS: [&str; 2] = ["quad", "qquad"];
+
+/// 引数ごと数式の区切りになるコマンド
+const SEP_GROUP_CMDS: [&str; 7] = [
+    "text",
+    "textrm",
+    "textit",
+    "textbf",
+    "mathrm",
+    "mbox",
+    "intertext",
+];
+
+/// 数式の区切りを表す文字. 文書中の位置を保つため, 無視する部分は空白で, 区切りはこの文字と空白で置き換える
+const SEP: u8 = 0x1f;
+
+/// 値を書き込むコメント
+const MARKER: &str = "tsc:";
+
+/// 値を書き込むコマンド
+const TSC_CMD: &str = "\\tsc{";
+
+/// 関係記号
+const RELATIONS: [(&str, &str); 2] = [("=", "="), ("\\approx", "\\approx")];
+
+/// 1つの数式の評価結果
+#[derive(Debug)]
+pub struct Report {
+    /// 文書中の行番号(1から)
+    pub line: usize,
+    /// 文書中の桁(1から, 文字単位)
+    pub col: usize,
+    /// 空白をまとめた数式
+    pub form: String,
+    /// 値. `a = b`は`1 = 1`, `1 != 2`のように各辺の値を並べる
+    pub result: Result<String, MyError>,
+    /// 数式の終わりの文書中の位置
+    end: usize,
+    /// 最後の辺の値. 書き戻しに使う
+    value: Option<String>,
+}
+
+/// 数式の本体の範囲
+struct MathBlock {
+    start: usize,
+    end: usize,
+    /// `\tsc{...}`なら, 閉じ括弧の次の位置
+    tsc: Option<usize>,
+}
+
+/// LaTeX文書の数式を評価して, 位置と値かエラーを表示する
+pub fn process_tex_doc(
+    file_name: &str,
+    doc: &str,
+    vars: &mut HashMap<String, Value>,
+) -> Result<(), MyError> {
+    for r in eval_tex_doc(doc, vars)? {
+        match r.result {
+            Ok(value) => println!("{file_name}:{}:{}: {} => {value}", r.line, r.col, r.form),
+            Err(e) => eprintlnc!(format!("{file_name}:{}:{}: {e}", r.line, r.col)),
+        }
+    }
+    Ok(())
+}
+
+/// LaTeX文書の`% tsc: =`と`\tsc{...}`に値を書き込み, 書き換えた行を表示する
+/// writeなら書き換えた文書をファイルに書き戻す. 書き換える個所があればtrueを返す
+pub fn process_tex_annotation(
+    file_name: &str,
+    doc: &str,
+    vars: &mut HashMap<String, Value>,
+    write: bool,
+) -> Result<bool, MyError> {
+    let (new_doc, reports) = annotate_tex_doc(doc, vars)?;
+    for r in reports {
+        if let Err(e) = r.result {
+            eprintlnc!(format!("{file_name}:{}:{}: {e}", r.line, r.col));
+        }
+    }
+    let diffs = diff_lines(doc, &new_doc);
+    for (line, old, new) in &diffs {
+        println!("{file_name}:{line}\n- {old}\n+ {new}");
+    }
+    if write && !diffs.is_empty() {
+        write_atomic(Path::new(file_name), &new_doc)
+            .map_err(|e| MyError::FileWriteErr(file_name.to_string(), e.to_string()))?;
+    }
+    Ok(!diffs.is_empty())
+}
+
+/// LaTeX文書の数式を文書の順に評価する
+/// 変数への代入は後の数式に引き継ぐ
+pub fn eval_tex_doc(doc: &str, vars: &mut HashMap<String, Value>) -> Result<Vec<Report>, MyError> {
+    Ok(annotate_tex_doc(doc, vars)?.1)
+}
+
+/// LaTeX文書の数式を評価し, `% tsc: =`と`\tsc{...}`に値を書き込んだ文書と評価結果を返す
+pub fn annotate_tex_doc(
+    doc: &str,
+    vars: &mut HashMap<String, Value>,
+) -> Result<(String, Vec<Report>), MyError> {
+    insert_consts(vars)?;
+    let num_of_digit = config_reader()?.num_of_digit;
+    let mut buf = doc.as_bytes().to_vec();
+    let markers = blank_comments(&mut buf);
+    let mut reports = Vec::new();
+    // 書き換える範囲と書き込む文字列
+    let mut edits = Vec::new();
+    for block in find_math(&buf) {
+        let MathBlock { start, end, tsc } = match block {
+            Ok(block) => block,
+            Err((pos, open)) => {
+                let (line, col, line_span) = line_col(doc, pos);
+                let span = Span {
+                    start: pos - line_span.start,
+                    end: pos - line_span.start + open.len(),
+                };
+                let line_str = &doc[line_span.start..line_span.end];
+                reports.push(Report {
+                    line,
+                    col,
+                    form: open.to_string(),
+                    result: Err(MyError::UnclosedBrace(
+                        open.to_string(),
+                        format_err_span(line_str, span),
+                    )),
+                    end: pos,
+                    value: None,
+                });
+                continue;
+            }
+        };
+        clean_body(&mut buf, start, end);
+        // 無視した部分は空白にしているので, 文字の境界は変わらない
+        let body = String::from_utf8_lossy(&buf[start..end]).into_owned();
+        let mut prev = None;
+        let mut offset = start;
+        let first_report = reports.len();
+        for part in body.split(SEP as char) {
+            let part_start = offset;
+            offset += part.len() + 1;
+            let trimmed = part.trim_start();
+            let part_start = part_start + part.len() - trimmed.len();
+            let trimmed = trimmed.trim_end().trim_end_matches([',', '.', ';']);
+            if trimmed.trim().is_empty() {
+                continue;
+            }
+            let Some(result) = eval_part(trimmed, vars, &mut prev, num_of_digit) else {
+                continue;
+            };
+            let (line, col, _) = line_col(doc, part_start);
+            let (result, value) = match result {
+                Ok((s, value)) => (Ok(s), Some(value)),
+                Err(e) => (Err(e), None),
+            };
+            reports.push(Report {
+                line,
+                col,
+                form: collapse_ws(trimmed),
+                result,
+                end: part_start + trimmed.len(),
+                value,
+            });
+        }
+        // \tsc{式}{値}の値を書き換える. 値が無ければ足す
+        if let Some(pos) = tsc
+            && let Some(value) = reports[first_report..].last().and_then(|r| r.value.clone())
+        {
+            let value_end = find_group_end(&buf, pos, buf.len()).unwrap_or(pos);
+            edits.push((pos, value_end, format!("{{{value}}}")));
+        }
+    }
+    // 1つの数式に書き込むコメントは1つだけ
+    let mut annotated = None;
+    let mut marker_errs = Vec::new();
+    for (pct, start, end) in markers {
+        match reports.iter().rposition(|r| r.end <= start) {
+            Some(i) if annotated != Some(i) => {
+                annotated = Some(i);
+                if let Some(value) = &reports[i].value {
+                    edits.push((start, end, format!(" {value}")));
+                }
+            }
+            _ => {
+                let (line, col, _) = line_col(doc, pct);
+                marker_errs.push(Report {
+                    line,
+                    col,
+                    form: MARKER.to_string(),
+                    result: Err(MyError::UnexpectedInput(
+                        format!("formula before '% {MARKER} ='"),
+                        "nothing".to_string(),
+                    )),
+                    end: start,
+                    value: None,
+                });
+            }
+        }
+    }
+    reports.append(&mut marker_errs);
+    reports.sort_by_key(|r| (r.line, r.col));
+    edits.sort_by_key(|(start, ..)| *start);
+    let mut new_doc = String::with_capacity(doc.len());
+    let mut pos = 0;
+    for (start, end, text) in edits {
+        new_doc += &doc[pos..start];
+        new_doc += &text;
+        pos = end;
+    }
+    new_doc += &doc[pos..];
+    Ok((new_doc, reports))
+}
+
+/// 書き換えた行の行番号と, 書き換える前後の行を返す
+/// 書き換えで行の数は変わらない
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<(usize, &'a str, &'a str)> {
+    old.lines()
+        .zip(new.lines())
+        .enumerate()
+        .filter(|(_, (o, n))| o != n)
+        .map(|(i, (o, n))| (i + 1, o, n))
+        .collect()
+}
+
+/// 同じディレクトリの一時ファイルに書いてから置き換える
+/// 書き込みの途中で失敗しても元のファイルは壊れない
+fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{file_name}.tsc~"));
+    let res = (|| {
+        fs::write(&tmp, contents)?;
+        fs::File::open(&tmp)?.sync_all()?;
+        fs::set_permissions(&tmp, fs::metadata(path)?.permissions())?;
+        fs::rename(&tmp, path)
+    })();
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    res
+}
+
+/// 連続する空白を1つにまとめる
+fn collapse_ws(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// posの行番号, 桁と, posを含む行の範囲を返す
+fn line_col(doc: &str, pos: usize) -> (usize, usize, Span) {
+    let line_start = doc[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = doc[pos..].find('\n').map_or(doc.len(), |i| pos + i);
+    let line = doc[..pos].matches('\n').count() + 1;
+    let col = doc[line_start..pos].chars().count() + 1;
+    (
+        line,
+        col,
+        Span {
+            start: line_start,
+            end: line_end,
+        },
+    )
+}
+
+fn blank(buf: &mut [u8], start: usize, end: usize) {
+    buf[start..end].fill(b' ');
+}
+
+/// `%`のコメントとverbatim環境を空白にする
+/// `% tsc: =`のコメントの`%`の位置と, `=`の後ろから行末までの範囲を返す
+fn blank_comments(buf: &mut [u8]) -> Vec<(usize, usize, usize)> {
+    let mut markers = Vec::new();
+    let mut i = 0;
+    while i < buf.len() {
+        match buf[i] {
+            b'\\' => {
+                if let Some(env) = env_at(buf, i, "begin")
+                    && VERBATIM_ENVS.contains(&env.trim_end_matches('*'))
+                {
+                    let close = format!("\\end{{{env}}}");
+                    let end = find(buf, i, close.as_bytes()).map_or(buf.len(), |j| j + close.len());
+                    blank(buf, i, end);
+                    i = end;
+                } else {
+                    i += 2;
+                }
+            }
+            b'%' => {
+                let mut end = buf[i..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(buf.len(), |j| i + j);
+                if buf[end - 1] == b'\r' {
+                    end -= 1;
+                }
+                let comment = String::from_utf8_lossy(&buf[i + 1..end]);
+                if let Some(rest) = comment.trim_start().strip_prefix(MARKER)
+                    && rest.trim_start().starts_with('=')
+                {
+                    let eq = end - rest.trim_start().len();
+                    markers.push((i, eq + 1, end));
+                }
+                blank(buf, i, end);
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+    markers
+}
+
+/// buf[i]から始まる`\begin{env}`や`\end{env}`のenvを返す
+fn env_at(buf: &[u8], i: usize, cmd: &str) -> Option<String> {
+    let rest = &buf[i..];
+    let head = format!("\\{cmd}{{");
+    if !rest.starts_with(head.as_bytes()) {
+        return None;
+    }
+    let len = rest.iter().position(|&b| b == b'}')?;
+    Some(String::from_utf8_lossy(&rest[head.len()..len]).into_owned())
+}
+
+/// fromから後の, エスケープされていないpatの位置を返す
+fn find(buf: &[u8], from: usize, pat: &[u8]) -> Option<usize> {
+    let mut j = from;
+    while j < buf.len() {
+        if buf[j..].starts_with(pat) {
+            return Some(j);
+        }
+        j += if buf[j] == b'\\' { 2 } else { 1 };
+    }
+    None
+}
+
+/// 数式の本体の範囲を返す. 閉じていなければ開始位置と開始の記号を返す
+fn find_math(buf: &[u8]) -> Vec<Result<MathBlock, (usize, String)>> {
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < buf.len() {
+        if buf[i..].starts_with(TSC_CMD.as_bytes()) {
+            let open = i + TSC_CMD.len() - 1;
+            match find_group_end(buf, open, buf.len()) {
+                Some(close) => {
+                    res.push(Ok(MathBlock {
+                        start: open + 1,
+                        end: close - 1,
+                        tsc: Some(close),
+                    }));
+                    // \tsc{式}{値}の値は読まない
+                    i = find_group_end(buf, close, buf.len()).unwrap_or(close);
+                }
+                None => {
+                    res.push(Err((i, TSC_CMD.to_string())));
+                    i = open + 1;
+                }
+            }
+            continue;
+        }
+        let (open, close) = match buf[i] {
+            b'\\' => match buf.get(i + 1) {
+                Some(b'(') => ("\\(".to_string(), "\\)".to_string()),
+                Some(b'[') => ("\\[".to_string(), "\\]".to_string()),
+                _ => match env_at(buf, i, "begin") {
+                    Some(env) if MATH_ENVS.contains(&env.trim_end_matches('*')) => {
+                        (format!("\\begin{{{env}}}"), format!("\\end{{{env}}}"))
+                    }
+                    _ => {
+                        i += 2;
+                        continue;
+                    }
+                },
+            },
+            b'$' if buf.get(i + 1) == Some(&b'$') => ("$$".to_string(), "$$".to_string()),
+            b'$' => ("$".to_string(), "$".to_string()),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let start = i + open.len();
+        match find(buf, start, close.as_bytes()) {
+            Some(end) => {
+                res.push(Ok(MathBlock {
+                    start,
+                    end,
+                    tsc: None,
+                }));
+                i = end + close.len();
+            }
+            None => {
+                res.push(Err((i, open)));
+                i = start;
+            }
+        }
+    }
+    res
+}
+
+/// {...}の閉じ括弧の次の位置を返す. iが{でなければiを返す
+fn group_end(buf: &[u8], i: usize, end: usize) -> usize {
+    if buf.get(i) != Some(&b'{') {
+        return i;
+    }
+    find_group_end(buf, i, end).unwrap_or(end)
+}
+
+/// buf[i]の{に対応する閉じ括弧の次の位置を返す. iが{でないか閉じていなければNone
+fn find_group_end(buf: &[u8], i: usize, end: usize) -> Option<usize> {
+    if buf.get(i) != Some(&b'{') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut j = i;
+    while j < end {
+        match buf[j] {
+            b'\\' => j += 1,
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(j + 1);
+                }
+            }
+            _ => (),
+        }
+        j += 1;
+    }
+    None
+}
+
+/// 数式の本体から評価に関係ない部分を空白にし, 区切りをSEPにする
+/// alignの行の偶数番目の&は次の列の組の始まりなので区切りにする
+fn clean_body(buf: &mut [u8], start: usize, end: usize) {
+    // 行の中の&の数
+    let mut amps = 0;
+    let mut i = start;
+    while i < end {
+        match buf[i] {
+            b'\\' => {
+                let name_len = buf[i + 1..end]
+                    .iter()
+                    .take_while(|b| b.is_ascii_alphabetic())
+                    .count();
+                if name_len == 0 {
+                    // 1文字のコマンド
+                    let cmd_end = (i + 2).min(end);
+                    match buf.get(i + 1) {
+                        Some(b'\\') => {
+                            blank(buf, i, cmd_end);
+                            buf[i] = SEP;
+                            amps = 0;
+                        }
+                        Some(b',' | b';' | b':' | b'!' | b' ') => blank(buf, i, cmd_end),
+                        _ => (),
+                    }
+                    i = cmd_end;
+                    continue;
+                }
+                let name = String::from_utf8_lossy(&buf[i + 1..i + 1 + name_len]).into_owned();
+                let mut cmd_end = i + 1 + name_len;
+                if buf.get(cmd_end) == Some(&b'*') {
+                    cmd_end += 1;
+                }
+                let name = name.as_str();
+                if IGNORED_CMDS.contains(&name) {
+                    blank(buf, i, cmd_end);
+                } else if IGNORED_GROUP_CMDS.contains(&name) {
+                    cmd_end = group_end(buf, cmd_end, end);
+                    blank(buf, i, cmd_end);
+                } else if SEP_CMDS.contains(&name) {
+                    blank(buf, i, cmd_end);
+                    buf[i] = SEP;
+                } else if SEP_GROUP_CMDS.contains(&name) {
+                    cmd_end = group_end(buf, cmd_end, end);
+                    blank(buf, i, cmd_end);
+                    buf[i] = SEP;
+                }
+                i = cmd_end;
+            }
+            b'&' => {
+                amps += 1;
+                buf[i] = if amps % 2 == 0 { SEP } else { b' ' };
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// 括弧の外の関係記号で式を分ける. 最初の辺は関係記号無し
+fn split_relations(form: &str) -> Vec<(&'static str, &str)> {
+    let mut sides = Vec::new();
+    let mut depth = 0i32;
+    let mut rel = "";
+    let mut side_start = 0;
+    let mut i = 0;
+    let bytes = form.as_bytes();
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' | b'(' | b'[' => depth += 1,
+            b'}' | b')' | b']' => depth -= 1,
+            _ if depth == 0 => {
+                if let Some((token, name)) = RELATIONS
+                    .iter()
+                    .find(|(token, _)| form[i..].starts_with(token))
+                    // \approxeq等の別のコマンドの一部でない
+                    && !form[i + token.len()..].starts_with(|c: char| {
+                        token.starts_with('\\') && c.is_ascii_alphabetic()
+                    })
+                {
+                    sides.push((rel, &form[side_start..i]));
+                    rel = name;
+                    i += token.len();
+                    side_start = i;
+                    continue;
+                }
+                if bytes[i] == b'\\' {
+                    i += 1;
+                }
+            }
+            b'\\' => i += 1,
+            _ => (),
+        }
+        i += 1;
+    }
+    sides.push((rel, &form[side_start..]));
+    sides
+}
+
+/// 関係記号でつながった式を評価して, 各辺の値を並べたものと最後の辺の値を返す
+/// 評価しない式(未定義の変数1つだけ)ならNone
+/// * `prev` - 前の式の最後の辺の値. `= c`のように続く式はprevと比べる
+fn eval_part(
+    form: &str,
+    vars: &mut HashMap<String, Value>,
+    prev: &mut Option<String>,
+    num_of_digit: u32,
+) -> Option<Result<(String, String), MyError>> {
+    let sides = split_relations(form);
+    let first = sides[0].1.trim();
+    let continued = first.is_empty() && sides.len() > 1;
+    // 左辺が変数1つの式は代入
+    let def_var = if continued {
+        None
+    } else {
+        match syntax::parse_expr(first, Syntax::Tex) {
+            Ok((ast, root)) => match &ast[root].kind {
+                NodeKind::Var(v) => Some(v.clone()),
+                _ => None,
+            },
+            Err(_) => None,
+        }
+    };
+    if let Some(v) = &def_var
+        && sides.len() == 1
+        && !vars.contains_key(v)
+    {
+        return None;
+    }
+    let is_const = |v: &String| CONSTS.read().is_ok_and(|c| c.contains_key(v));
+    let def_var = def_var.filter(|v| sides.len() > 1 && !is_const(v));
+    let rhs = if continued || def_var.is_some() {
+        &sides[1..]
+    } else {
+        &sides[..]
+    };
+    let mut out: Vec<String> = def_var.iter().cloned().collect();
+    let mut last: Option<String> = None;
+    let mut value = None;
+    for (i, (rel, side)) in rhs.iter().enumerate() {
+        let side = collapse_ws(side);
+        let v = match eval_side(&side, vars) {
+            Ok(v) => v,
+            Err(e) => return Some(Err(e)),
+        };
+        let s = num_formatter(&v, num_of_digit);
+        // 比べる相手は直前の辺. 続く式の最初の辺は前の式と比べる
+        let other = if i == 0 && continued {
+            prev.as_deref()
+        } else {
+            last.as_deref()
+        };
+        match (*rel, other) {
+            ("", _) => (),
+            ("=", Some(other)) if other != s => out.push("!=".to_string()),
+            (rel, _) => out.push(rel.to_string()),
+        }
+        out.push(s.clone());
+        last = Some(s);
+        value = Some(v);
+    }
+    if let (Some(v), Some(value)) = (def_var, value) {
+        vars.insert(v, value.into());
+    }
+    *prev = last.clone();
+    Some(Ok((out.join(" "), last.unwrap_or_default())))
+}
+
+/// TeXの式1つを評価する
+fn eval_side(form: &str, vars: &HashMap<String, Value>) -> Result<BigDecimal, MyError> {
+    let (ast, root) = syntax::parse_expr(form, Syntax::Tex)?;
+    eval_root(&ast, root, vars, form)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn eval(doc: &str) -> Vec<(usize, usize, String, String)> {
+        let mut vars = HashMap::new();
+        eval_tex_doc(doc, &mut vars)
+            .unwrap()
+            .into_iter()
+            .map(|r| {
+                let result = match r.result {
+                    Ok(v) => v,
+                    Err(e) => format!("error: {}", e.to_string().lines().next().unwrap_or("")),
+                };
+                (r.line, r.col, r.form, result)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_inline() {
+        let doc = "Let $x = 3$ and \\(y = \\frac{x}{2}\\). Then $2x + y$ is the answer, \
+                   costs \\$5 % $1 + 1$ is a comment\n$\\sqrt{4}$, $z$ and $z + 1$.";
+        let res = eval(doc);
+        let expected = [
+            (1, 6, "x = 3", "x = 3"),
+            (1, 19, "y = \\frac{x}{2}", "y = 1.5"),
+            (1, 44, "2x + y", "7.5"),
+            (2, 2, "\\sqrt{4}", "2"),
+            (2, 22, "z + 1", "error: undefined variable 'z'"),
+        ];
+        assert_eq!(res.len(), expected.len(), "{res:?}");
+        for (r, (line, col, form, value)) in res.iter().zip(expected) {
+            assert_eq!(r, &(line, col, form.to_string(), value.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_multiline_inline() {
+        // 行をまたぐ数式の中のコメントは読まない
+        let res = eval("$a = 3 % c\n + 1$\n$b = 3\n + 1$");
+        let expected = [(1, 2, "a = 3 + 1", "a = 4"), (3, 2, "b = 3 + 1", "b = 4")];
+        assert_eq!(res.len(), expected.len(), "{res:?}");
+        for (r, (line, col, form, value)) in res.iter().zip(expected) {
+            assert_eq!(r, &(line, col, form.to_string(), value.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let doc = "\\begin{equation}\\label{eq:1}\n  1 + 2 = 3 \\nonumber\n\\end{equation}\n\
+                   \\begin{align*}\n  a &= 2 \\cdot 3 \\\\\n    &= 6 \\\\\n    &= 7 \\text{ (wrong)} \\\\\n  b &= a \\cdot 2 & c &= 7\n\
+                   \\end{align*}\n\\[ \\left( 1 + 1 \\right)^{2} \\quad 2 \\approx 2.0, \\]\n\
+                   $$ 2^{3} = 2 \\cdot 4 = 9 $$\n\\begin{verbatim}$1 + 1$\\end{verbatim}\n\\[ 1 +";
+        let res = eval(doc);
+        let expected = [
+            (2, 3, "1 + 2 = 3", "3 = 3"),
+            (5, 3, "a = 2 \\cdot 3", "a = 6"),
+            (6, 6, "= 6", "= 6"),
+            (7, 6, "= 7", "!= 7"),
+            // 次の列の組の&は区切り
+            (8, 3, "b = a \\cdot 2", "b = 12"),
+            (8, 20, "c = 7", "c = 7"),
+            (10, 9, "( 1 + 1 )^{2}", "4"),
+            (10, 35, "2 \\approx 2.0", "2 \\approx 2.0"),
+            (11, 4, "2^{3} = 2 \\cdot 4 = 9", "8 = 8 != 9"),
+            (13, 1, "\\[", "error: unclosed '\\['"),
+        ];
+        assert_eq!(res.len(), expected.len(), "{res:?}");
+        for (r, (line, col, form, value)) in res.iter().zip(expected) {
+            assert_eq!(r, &(line, col, form.to_string(), value.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_annotate() {
+        let doc = "Let $r = 2$. % tsc: =\r\nArea $\\pi r^{2}$ %tsc:= 3\r\n\
+                   half \\tsc{\\frac{r}{4}} or \\tsc{\\frac{1}{2} + 2.5}{7}.\r\n\
+                   \\[ 2^{10} \\] % tsc: = 1\r\n% tsc: =\r\nBad \\tsc{\\frac{1}{0}}{3}.";
+        let expected = "Let $r = 2$. % tsc: = 2\r\nArea $\\pi r^{2}$ %tsc:= 12.5663706144\r\n\
+                        half \\tsc{\\frac{r}{4}}{0.5} or \\tsc{\\frac{1}{2} + 2.5}{3.0}.\r\n\
+                        \\[ 2^{10} \\] % tsc: = 1024\r\n% tsc: =\r\nBad \\tsc{\\frac{1}{0}}{3}.";
+        let mut vars = HashMap::new();
+        let (new_doc, reports) = annotate_tex_doc(doc, &mut vars).unwrap();
+        assert_eq!(new_doc, expected);
+        let errs: Vec<_> = reports
+            .iter()
+            .filter(|r| r.result.is_err())
+            .map(|r| (r.line, r.col))
+            .collect();
+        assert_eq!(errs, [(5, 1), (6, 10)]);
+        assert_eq!(
+            diff_lines(doc, &new_doc)
+                .iter()
+                .map(|(line, ..)| *line)
+                .collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+        // 書き込んだ値は読み直しても変わらない
+        let mut vars = HashMap::new();
+        assert_eq!(annotate_tex_doc(&new_doc, &mut vars).unwrap().0, new_doc);
+    }
+}
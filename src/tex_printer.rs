@@ -0,0 +1,275 @@
+use bigdecimal::{BigDecimal, Signed};
+
+use crate::ast::{Ast, BinaryOp, NodeId, NodeKind, UnaryOp};
+use crate::error::*;
+use crate::registry::{
+    registry_reader, ArgStyle, Builtin, Command, Evaluator, Registry, PREC_ATOM, PREC_IMPLICIT_MUL,
+    PREC_POWER, PREC_PRODUCT, PREC_SUM,
+};
+
+/// ASTをTeXの数式に変換する
+/// 括弧は構文解析したときに同じ形のASTに戻るのに必要な所にだけ付ける
+///
+/// ex)
+/// ```text
+/// (+ 1 (* 2 (/ x 3)))  ->  1 + 2\frac{x}{3}
+/// ```
+pub fn to_tex(ast: &Ast, root: NodeId) -> Result<String, MyError> {
+    let registry = registry_reader()?;
+    let printer = TexPrinter {
+        ast,
+        registry: &registry,
+    };
+    Ok(printer.print(root).0)
+}
+
+/// 数値をTeXの数値リテラルにする
+pub fn num_to_tex(n: &BigDecimal) -> String {
+    n.normalized().to_plain_string()
+}
+
+struct TexPrinter<'a> {
+    ast: &'a Ast,
+    registry: &'a Registry,
+}
+
+impl TexPrinter<'_> {
+    /// idのnodeのTeXと, その結合力を返す
+    fn print(&self, id: NodeId) -> (String, u8) {
+        match &self.ast[id].kind {
+            // 負の数は単項マイナスと同じく扱う
+            NodeKind::Num(n) if n.is_negative() => (num_to_tex(n), PREC_POWER),
+            NodeKind::Num(n) => (num_to_tex(n), PREC_ATOM),
+            NodeKind::Var(v) => (v.clone(), PREC_ATOM),
+            NodeKind::Unary(UnaryOp::Neg, arg) => {
+                // -(ab)と(-a)bは等しいので積は括弧で囲まない
+                let min = if self.is_product(*arg) {
+                    PREC_IMPLICIT_MUL
+                } else {
+                    PREC_POWER
+                };
+                (format!("-{}", self.rhs_operand(*arg, min)), PREC_POWER)
+            }
+            NodeKind::Unary(op, arg) => self.builtin(Builtin::Unary(*op), &[*arg]),
+            NodeKind::Binary(op @ (BinaryOp::Add | BinaryOp::Sub), lhs, rhs) => {
+                let sign = if *op == BinaryOp::Add { "+" } else { "-" };
+                (
+                    format!(
+                        "{} {sign} {}",
+                        self.operand(*lhs, PREC_SUM),
+                        self.rhs_operand(*rhs, PREC_SUM + 1)
+                    ),
+                    PREC_SUM,
+                )
+            }
+            NodeKind::Binary(BinaryOp::Mul, lhs, rhs) => self.product(*lhs, *rhs),
+            NodeKind::Binary(BinaryOp::Div, lhs, rhs) => {
+                self.builtin(Builtin::Binary(BinaryOp::Div), &[*lhs, *rhs])
+            }
+            NodeKind::Binary(BinaryOp::Pow, base, exp) => (
+                format!(
+                    "{}^{{{}}}",
+                    self.operand(*base, PREC_ATOM),
+                    self.print(*exp).0
+                ),
+                PREC_POWER,
+            ),
+            NodeKind::Call(func, args) => match self.registry.get(*func) {
+                Some(Command {
+                    eval: Evaluator::List(f),
+                    ..
+                }) => self.command(&f.tex_name(), ArgStyle::Paren, self.ast.args(*args)),
+                Some(Command {
+                    eval: Evaluator::Special(f),
+                    ..
+                }) => match (f.subscripted(), self.ast.args(*args)) {
+                    (true, [n, rest @ ..]) => {
+                        let name = format!("{}_{{{}}}", f.tex_name(), self.print(*n).0);
+                        self.command(&name, ArgStyle::Paren, rest)
+                    }
+                    (_, args) => self.command(f.tex_name(), ArgStyle::Paren, args),
+                },
+                Some(cmd) => self.command(&cmd.name, cmd.arg_style, self.ast.args(*args)),
+                None => (format!("\\Func{func}"), PREC_ATOM),
+            },
+        }
+    }
+
+    /// 結合力がmin以上でなければ括弧で囲む
+    fn operand(&self, id: NodeId, min: u8) -> String {
+        let (tex, prec) = self.print(id);
+        if prec >= min {
+            tex
+        } else {
+            format!("({tex})")
+        }
+    }
+
+    /// 二項演算の右の被演算子. "a - -b"とならないよう-で始まるものも括弧で囲む
+    fn rhs_operand(&self, id: NodeId, min: u8) -> String {
+        let (tex, prec) = self.print(id);
+        if prec >= min && !tex.starts_with('-') {
+            tex
+        } else {
+            format!("({tex})")
+        }
+    }
+
+    /// 積は読める限り暗黙の乗算(2x)で表し, 読めなければ\cdotを使う
+    fn product(&self, lhs: NodeId, rhs: NodeId) -> (String, u8) {
+        let (ltex, lprec) = self.print(lhs);
+        let (rtex, rprec) = self.print(rhs);
+        // 数字と-で始まる式は暗黙の乗算の右の被演算子にならない. 2 10^{3}は2と10^{3}の積と読めない
+        let is_implicit = lprec != PREC_PRODUCT
+            && !matches!(self.ast[rhs].kind, NodeKind::Num(_))
+            && !rtex.starts_with(|c: char| c.is_ascii_digit() || c == '.' || c == '-');
+        if is_implicit {
+            let ltex = if lprec >= PREC_IMPLICIT_MUL {
+                ltex
+            } else {
+                format!("({ltex})")
+            };
+            // a(bc)と(ab)cは等しいので, 読み直せる暗黙の積は括弧で囲まない
+            let is_assoc = self.is_product(rhs) && !self.starts_with_num(rhs);
+            let rtex = if rprec > PREC_IMPLICIT_MUL || (is_assoc && rprec == PREC_IMPLICIT_MUL) {
+                rtex
+            } else {
+                format!("({rtex})")
+            };
+            // 2xのように数字の直後の文字は詰めて書く. 0xは16進数になるので離す
+            let is_joined = matches!(self.ast[lhs].kind, NodeKind::Num(_))
+                && ltex != "0"
+                && rtex.starts_with(|c: char| c.is_ascii_alphabetic() || c == '\\');
+            let sep = if is_joined { "" } else { " " };
+            (format!("{ltex}{sep}{rtex}"), PREC_IMPLICIT_MUL)
+        } else {
+            (
+                format!(
+                    "{} \\cdot {}",
+                    self.operand(lhs, PREC_PRODUCT),
+                    self.rhs_operand(rhs, PREC_PRODUCT + 1)
+                ),
+                PREC_PRODUCT,
+            )
+        }
+    }
+
+    fn is_product(&self, id: NodeId) -> bool {
+        matches!(self.ast[id].kind, NodeKind::Binary(BinaryOp::Mul, _, _))
+    }
+
+    /// 積の先頭の因子が数値か
+    fn starts_with_num(&self, id: NodeId) -> bool {
+        match self.ast[id].kind {
+            NodeKind::Num(_) => true,
+            NodeKind::Binary(BinaryOp::Mul, lhs, _) => self.starts_with_num(lhs),
+            _ => false,
+        }
+    }
+
+    fn builtin(&self, b: Builtin, args: &[NodeId]) -> (String, u8) {
+        match self.registry.builtin(b) {
+            Some(cmd) => self.command(&cmd.name, cmd.arg_style, args),
+            None => unreachable!("builtin command is not registered"),
+        }
+    }
+
+    fn command(&self, name: &str, style: ArgStyle, args: &[NodeId]) -> (String, u8) {
+        match style {
+            ArgStyle::Brace => {
+                let mut tex = name.to_string();
+                for arg in args {
+                    tex += &format!("{{{}}}", self.print(*arg).0);
+                }
+                (tex, PREC_ATOM)
+            }
+            ArgStyle::Paren => {
+                let args: Vec<String> = args.iter().map(|arg| self.print(*arg).0).collect();
+                (format!("{name}({})", args.join(", ")), PREC_ATOM)
+            }
+            // \sin xは結合力PREC_POWERで引数を読むので, 累乗の底には括弧が要る
+            ArgStyle::Signed => (
+                format!("{name} {}", self.operand(args[0], PREC_POWER)),
+                PREC_POWER,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::CONSTS;
+
+    fn parse(form: &str) -> (Ast, NodeId) {
+        Parser::new(form.to_string(), false)
+            .unwrap()
+            .build_expr()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_to_tex() {
+        let cases = [
+            ("1 + 2 * 3", "1 + 2 \\cdot 3"),
+            ("x^{2}", "x^{2}"),
+            ("\\sqrt 4 x", "\\sqrt{4} x"),
+            // TeXでは2^{3}^{2}は二重の上付き文字になるので括弧を付ける
+            ("2 ^{3}^{2}", "(2^{3})^{2}"),
+            ("2^{(3^{2})}", "2^{3^{2}}"),
+            ("(-2)^{2}", "(-2)^{2}"),
+            ("-2^{2}", "-2^{2}"),
+            ("1 - -3", "1 - (-3)"),
+            ("1 - (2 + 3)", "1 - (2 + 3)"),
+            ("(1 - 2) + 3", "1 - 2 + 3"),
+            ("a/2x", "\\frac{a}{2x}"),
+            ("2 \\times (3)", "2 \\cdot 3"),
+            ("2 \\cdot 10^{3}", "2 \\cdot 10^{3}"),
+            ("\\sin 2x", "\\sin 2 x"),
+            ("\\sin(2x)", "\\sin (2x)"),
+            ("\\sin(x)^{2}", "\\sin x^{2}"),
+            ("(\\sin x)^{2}", "(\\sin x)^{2}"),
+            ("\\abs(-x)", "\\abs(-x)"),
+        ];
+        for (form, expected) in cases {
+            let (ast, root) = parse(form);
+            assert_eq!(to_tex(&ast, root).unwrap(), expected, "{form}");
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut vars = CONSTS.read().unwrap().clone();
+        for (v, n) in [("x", "3"), ("y", "0.25"), ("a", "8")] {
+            vars.insert(v.to_string(), n.parse::<BigDecimal>().unwrap().into());
+        }
+        let forms = [
+            "-\\abs (-2)^{\\frac{1}{4/2}}^{6}",
+            "\\sin -x^{2}",
+            "\\frac{1}{2}\\frac{3}{4}",
+            "(1+2)(3+4)",
+            "3 \\times 4 \\div 2 \\cdot 5",
+            "\\sin \\frac{\\pi}{2} x^{2}",
+            "-x y - (x - y) / (x + y)",
+            "2 - (3 - 4) \\cdot -x",
+            "2 / (3 / 4) / y",
+            "(x y)^{2} + x^{-1} - -(-y)",
+            "\\log \\log 100 + \\ln e^{2}",
+            "\\csc 30 + \\sec 0 - \\cot 45",
+            "\\exp(1)\\abs(-2) \\sin(x + y) a",
+        ];
+        for form in forms {
+            let (ast, root) = parse(form);
+            let tex = to_tex(&ast, root).unwrap();
+            let (ast2, root2) = parse(&tex);
+            // 出力したTeXを読み直すと同じ値になり, もう一度出力すると同じTeXになる
+            assert_eq!(
+                crate::calc(&ast, root, &vars, form).unwrap(),
+                crate::calc(&ast2, root2, &vars, &tex).unwrap(),
+                "{form} -> {tex}"
+            );
+            assert_eq!(to_tex(&ast2, root2).unwrap(), tex, "{form}");
+        }
+    }
+}
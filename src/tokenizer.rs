@@ -1,9 +1,9 @@
-use regex::Regex;
 use std::fmt;
 
-use crate::debug;
-use crate::debugln;
 use crate::error::*;
+use crate::parser::{format_err_span, Span};
+use crate::registry::{infix_op, registry_reader, Evaluator, Registry, INFIX_OPS};
+use unicode_width::UnicodeWidthStr;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TokenKind {
@@ -75,208 +75,581 @@ tokenの種類
 - 変数: x, y
 */
 
-pub fn tokenize(formulas: &str) -> Result<(Vec<Token>, Vec<usize>), MyError> {
-    let mut tokens: Vec<Token> = Vec::new();
-    let mut token_loc: Vec<usize> = Vec::new();
-
-    let tex_command = Regex::new(r"^\\[A-Za-z]*").unwrap();
-    let tsc_command = Regex::new(r"^:[A-Za-z]*").unwrap();
-    let operator = Regex::new(r"^(\+|-|\*|=|/|!|_|\^|\|)").unwrap();
-    let var = Regex::new(r"^[A-Za-z][A-Za-z0-9]*").unwrap();
-    // scientific: 1.16E-6
-    let scientific_pat = r"[1-9]\.[0-9]+E(\+|-)[1-9]+";
-    // hex: 0x1234, 0x12_34
-    let hex_pat = r"0x([0-9a-fA-F]+_?)*[0-9a-fA-F]+";
-    // oct: 01234, 0_12_34
-    let oct_pat = r"0([0-7]+_?)*[0-7]+";
-    // bin: 0b1010, 0b10_10
-    let bin_pat = r"0b([0-1]+_?)*[0-1]+";
-    // dec(!int): '1.234', '1.2_34'
-    let dec_pat = r"([0-9]+(_|,)?)*[0-9]+\.([0-9]+(_|,)?)*[0-9]+";
-    // dec(int): '1234', '12_34', '1,234
-    let decint_pat = r"([0-9]+(_|,)?)*[0-9]+";
-    /*
-    let num =
-        Regex::new(r"[1-9]\.[0-9]+E(+|-)[1-9]+|(0x([0-9a-fA-F]+_?)*[0-9a-fA-F]+)|(0([0-7]+_?)*[0-7]+)|(0b([0-1]+_?)*[0-1]+)|(([0-9]+(_|,)?)*[0-9]+\.([0-9]+(_|,)?)*[0-9]+)|(([0-9]+(_|,)?)*[0-9]+)")
-            .unwrap();
-    */
-    let num_pat = format!(
-        r"^((?P<scientific>{})|(?P<hex>{})|(?P<oct>{})|(?P<bin>{})|(?P<dec>{})|(?P<decint>{}))",
-        scientific_pat, hex_pat, oct_pat, bin_pat, dec_pat, decint_pat
+pub fn tokenize(formulas: &str) -> Result<(Vec<Token>, Vec<Span>), MyError> {
+    let (tokens, token_loc, mut errors) = tokenize_recovering(formulas);
+    if errors.is_empty() {
+        Ok((tokens, token_loc))
+    } else {
+        Err(errors.remove(0).1)
+    }
+}
+
+/// エラーが起こったtokenを読み飛ばしながら字句解析する
+/// 発生したエラーは(数式中の位置, エラー)として全て返す
+pub fn tokenize_recovering(formulas: &str) -> (Vec<Token>, Vec<Span>, Vec<(usize, MyError)>) {
+    let mut lexer = Lexer {
+        form: formulas,
+        tokens: Vec::new(),
+        token_loc: Vec::new(),
+        errors: Vec::new(),
+    };
+    lexer.scan(formulas, &|start, end| Span { start, end });
+    lexer.ungroup_call_args();
+    lexer.push(
+        "EOT",
+        TokenKind::TkEOT,
+        Span {
+            start: formulas.len(),
+            end: formulas.len(),
+        },
     );
-    let num = Regex::new(&num_pat).unwrap();
-    let braces = Regex::new(r"^(\(|\)|\[|\]|\{|\})").unwrap();
-    let separator = Regex::new(r"^;").unwrap();
-    let mut processed_form_idx = 0;
-
-    let mut formulas = formulas;
-
-    'search: loop {
-        let mut c = match formulas.chars().next() {
-            Some(c) => c,
-            None => {
-                tokens.push(Token {
-                    token: "EOT".to_string(),
-                    token_kind: TokenKind::TkEOT,
-                });
-                token_loc.push(processed_form_idx);
-                print_token(&tokens);
-                break 'search;
-            }
+    print_token(&lexer.tokens);
+
+    assert_eq!(lexer.token_loc.len(), lexer.tokens.len());
+    (lexer.tokens, lexer.token_loc, lexer.errors)
+}
+
+struct Lexer<'a> {
+    form: &'a str,
+    tokens: Vec<Token>,
+    token_loc: Vec<Span>,
+    errors: Vec<(usize, MyError)>,
+}
+
+impl Lexer<'_> {
+    /// srcを字句解析してtokenを追加する
+    /// * `src` - 数式そのもの, もしくはUnicodeの文字を置き換えたTeXの記法
+    /// * `origin` - src中の範囲を数式中の位置に直す
+    fn scan(&mut self, src: &str, origin: &dyn Fn(usize, usize) -> Span) {
+        let bytes = src.as_bytes();
+        let mut idx = 0;
+        // tokenの区切りは全てASCII文字なので, idxは常にcharの境界にある
+        while let Some(c) = src[idx..].chars().next() {
+            let len = match c {
+                ' ' => 1,
+                '\\' => {
+                    // \operatorname{mean}は\meanと同じコマンドにする
+                    let (token, len) = match operator_name(&src[idx..]) {
+                        Some((name, len)) => (format!("\\{name}"), len),
+                        None => {
+                            let len =
+                                1 + count_while(&bytes[idx + 1..], |b| b.is_ascii_alphabetic());
+                            (src[idx..idx + len].to_string(), len)
+                        }
+                    };
+                    let token = token.as_str();
+                    let span = origin(idx, idx + len);
+                    match token {
+                        _ if infix_op(token).is_some() => {
+                            self.push(token, TokenKind::TkOperator, span)
+                        }
+                        _ if is_greek_letter(token) => {
+                            self.push(token, TokenKind::TkVariable, span)
+                        }
+                        _ if is_valid_texcommand(token) => {
+                            self.push(token, TokenKind::TkTexCommand, span)
+                        }
+                        _ => {
+                            // 未定義のコマンドは読み飛ばす
+                            // Unicodeの記号を置き換えたコマンドは入力されたものではないので候補を示さない
+                            let typed = self.form[span.start..].starts_with('\\');
+                            let hint = match suggest_texcommand(token) {
+                                Some(cand) if typed => format!(", did you mean '{cand}'?"),
+                                _ => String::new(),
+                            };
+                            self.errors.push((
+                                span.start,
+                                MyError::UDtexcommand(
+                                    token.to_string(),
+                                    hint,
+                                    format_err_span(self.form, span),
+                                ),
+                            ));
+                        }
+                    }
+                    len
+                }
+                ':' => {
+                    let len = 1 + count_while(&bytes[idx + 1..], |b| b.is_ascii_alphabetic());
+                    let span = origin(idx, idx + len);
+                    self.push(&src[idx..idx + len], TokenKind::TkTscCommand, span);
+                    len
+                }
+                '+' | '-' | '*' | '=' | '/' | '!' | '_' | '^' | '|' | ',' => {
+                    self.push(
+                        &src[idx..idx + 1],
+                        TokenKind::TkOperator,
+                        origin(idx, idx + 1),
+                    );
+                    1
+                }
+                '(' | ')' | '[' | ']' | '{' | '}' => {
+                    self.push(&src[idx..idx + 1], TokenKind::TkBrace, origin(idx, idx + 1));
+                    1
+                }
+                ';' => {
+                    self.push(
+                        &src[idx..idx + 1],
+                        TokenKind::TkSeparaotr,
+                        origin(idx, idx + 1),
+                    );
+                    1
+                }
+                '.' if bytes.get(idx + 1) == Some(&b'.') => {
+                    // 範囲の区切り: 0..10
+                    self.push("..", TokenKind::TkOperator, origin(idx, idx + 2));
+                    2
+                }
+                '0'..='9' => {
+                    let (len, format) = scan_num(&bytes[idx..]);
+                    let span = origin(idx, idx + len);
+                    self.push(&src[idx..idx + len], TokenKind::TkNum(format), span);
+                    len
+                }
+                'A'..='Z' | 'a'..='z' => {
+                    let len = 1 + count_while(&bytes[idx + 1..], |b| b.is_ascii_alphanumeric());
+                    let span = origin(idx, idx + len);
+                    self.push(&src[idx..idx + len], TokenKind::TkVariable, span);
+                    len
+                }
+                _ => self.scan_unicode(src, idx, origin),
+            };
+            idx += len;
+        }
+    }
+
+    /// ASCII以外の文字をTeXの記法に置き換えて字句解析し, 読んだbyte数を返す
+    /// 置き換えられない文字はエラーとして読み飛ばす
+    fn scan_unicode(
+        &mut self,
+        src: &str,
+        idx: usize,
+        origin: &dyn Fn(usize, usize) -> Span,
+    ) -> usize {
+        let c = src[idx..].chars().next().unwrap();
+        let len = c.len_utf8();
+        if is_unicode_space(c) {
+            return len;
+        }
+        if is_relation(c) {
+            let span = origin(idx, idx + len);
+            self.errors.push((
+                span.start,
+                MyError::RelationErr(c.to_string(), format_err_span(self.form, span)),
+            ));
+            return len;
+        }
+        if let Some(tex) = unicode_to_tex(c) {
+            let span = origin(idx, idx + len);
+            self.scan(tex, &|_, _| span);
+            return len;
+        }
+        if superscript_to_ascii(c).is_some() {
+            // 連続した上付き文字はまとめて1つの指数にする: x²³ -> x^{23}
+            let run: String = src[idx..].chars().map_while(superscript_to_ascii).collect();
+            let run_len: usize = src[idx..].chars().take(run.len()).map(char::len_utf8).sum();
+            let span = origin(idx, idx + run_len);
+            self.scan(&format!("^{{{run}}}"), &|_, _| span);
+            return run_len;
+        }
+        if fullwidth_to_ascii(c).is_some() {
+            // 全角英数字, 記号はASCIIに直す. どれもUTF-8で3byte
+            let run: String = src[idx..].chars().map_while(fullwidth_to_ascii).collect();
+            self.scan(&run, &|start, end| origin(idx + start * 3, idx + end * 3));
+            return run.len() * 3;
+        }
+        // 解釈できない文字は読み飛ばす
+        let span = origin(idx, idx + len);
+        let column = self.form[..span.start].width() + 1;
+        self.errors.push((
+            span.start,
+            MyError::InvalidInput(format!(
+                "'{c}' at column {column}\n{}",
+                format_err_span(self.form, span)
+            )),
+        ));
+        len
+    }
+
+    /// 関数呼び出しの括弧の中の "," は桁区切りにせず, 引数の区切りにする
+    /// \operatorname{mean}(1,2,3)の "1,2,3" は "1" "," "2" "," "3" に分ける
+    fn ungroup_call_args(&mut self) {
+        let Ok(registry) = registry_reader() else {
+            return;
         };
-        while c == ' ' {
-            formulas = &formulas[1..];
-            processed_form_idx += 1;
-            c = match formulas.chars().next() {
-                Some(c) => c,
-                None => {
-                    tokens.push(Token {
-                        token: "EOT".to_string(),
-                        token_kind: TokenKind::TkEOT,
-                    });
-                    token_loc.push(processed_form_idx);
-                    print_token(&tokens);
-                    break 'search;
+        let tokens = std::mem::take(&mut self.tokens);
+        let token_loc = std::mem::take(&mut self.token_loc);
+        // 開いている括弧ごとに, 関数呼び出しの括弧ならtrue
+        let mut parens = Vec::new();
+        for (token, span) in tokens.into_iter().zip(token_loc) {
+            match (&*token.token, token.token_kind) {
+                ("(", TokenKind::TkBrace) => parens.push(is_call_paren(&registry, &self.tokens)),
+                ("{" | "[", TokenKind::TkBrace) => parens.push(false),
+                (")" | "}" | "]", TokenKind::TkBrace) => {
+                    parens.pop();
+                }
+                (num, TokenKind::TkNum(NumFormat::Dec | NumFormat::DecInt))
+                    if num.contains(',') && parens.last() == Some(&true) =>
+                {
+                    self.split_num(num, span);
+                    continue;
                 }
+                _ => (),
             }
+            self.tokens.push(token);
+            self.token_loc.push(span);
         }
-        let mut ismatch = false;
-        macro_rules! push_token {
-            ($token: ident, $token_len: ident,$tk: expr) => {{
-                token_loc.push(processed_form_idx);
-                processed_form_idx += $token_len;
-                tokens.push(Token {
-                    $token,
-                    token_kind: $tk,
-                });
-            }};
-        }
-        if c == '\\' {
-            if let Some(caps) = tex_command.captures(formulas) {
-                let token = caps.get(0).unwrap().as_str().to_string();
-                let token_len = token.len();
-                match &*token {
-                    "\\times" => push_token!(token, token_len, TokenKind::TkOperator),
-                    "\\cdot" => push_token!(token, token_len, TokenKind::TkOperator),
-                    "\\div" => push_token!(token, token_len, TokenKind::TkOperator),
-                    "\\pi" => push_token!(token, token_len, TokenKind::TkVariable),
-                    _ => {
-                        if is_valid_texcommand(&token) {
-                            push_token!(token, token_len, TokenKind::TkTexCommand);
-                        } else {
-                            return Err(MyError::UDcommandErr(token));
-                        }
-                    }
+    }
+
+    /// 桁区切りの "," を含む数値を "," で区切った数値と "," のtokenにする
+    fn split_num(&mut self, num: &str, span: Span) {
+        // 全角数字から読んだ数値は数式中の長さが違うので, 全て数値全体の位置にする
+        let sub = |start: usize, end: usize| {
+            if span.end - span.start == num.len() {
+                Span {
+                    start: span.start + start,
+                    end: span.start + end,
                 }
-                formulas = &formulas[token_len..];
-                ismatch = true;
+            } else {
+                span
             }
-        } else if c == ':' {
-            if let Some(caps) = tsc_command.captures(formulas) {
-                let token = caps.get(0).unwrap().as_str().to_string();
-                let token_len = token.len();
-                push_token!(token, token_len, TokenKind::TkTscCommand);
-                formulas = &formulas[token_len..];
-                ismatch = true;
+        };
+        let bytes = num.as_bytes();
+        let mut idx = 0;
+        while idx < bytes.len() {
+            if bytes[idx] == b',' {
+                self.push(",", TokenKind::TkOperator, sub(idx, idx + 1));
+                idx += 1;
+                continue;
             }
-        } else if let Some(caps) = operator.captures(&c.to_string()) {
-            let token = caps.get(0).unwrap().as_str().to_string();
-            let token_len = token.len();
-            push_token!(token, token_len, TokenKind::TkOperator);
-            formulas = &formulas[token_len..];
-            ismatch = true;
-        } else if let Some(caps) = braces.captures(&c.to_string()) {
-            let token = caps.get(0).unwrap().as_str().to_string();
-            let token_len = token.len();
-            push_token!(token, token_len, TokenKind::TkBrace);
-            formulas = &formulas[token_len..];
-            ismatch = true;
-        } else if let Some(caps) = separator.captures(&c.to_string()) {
-            let token = caps.get(0).unwrap().as_str().to_string();
-            let token_len = token.len();
-            push_token!(token, token_len, TokenKind::TkSeparaotr);
-            formulas = &formulas[token_len..];
-            ismatch = true;
-        } else if let Some(caps) = num.captures(formulas) {
-            let token = caps.get(0).unwrap().as_str().to_string();
-            let token_len = token.len();
-            if caps.name("scientific").is_some() {
-                push_token!(token, token_len, TokenKind::TkNum(NumFormat::Scientific));
-            } else if caps.name("hex").is_some() {
-                push_token!(token, token_len, TokenKind::TkNum(NumFormat::Hex));
-            } else if caps.name("oct").is_some() {
-                push_token!(token, token_len, TokenKind::TkNum(NumFormat::Oct));
-            } else if caps.name("bin").is_some() {
-                push_token!(token, token_len, TokenKind::TkNum(NumFormat::Bin));
-            } else if caps.name("dec").is_some() {
-                push_token!(token, token_len, TokenKind::TkNum(NumFormat::Dec));
-            } else if caps.name("decint").is_some() {
-                push_token!(token, token_len, TokenKind::TkNum(NumFormat::DecInt));
+            let end = idx + count_while(&bytes[idx..], |b| b != b',');
+            let (len, format) = scan_num(&bytes[idx..end]);
+            let len = len.max(1);
+            self.push(
+                &num[idx..idx + len],
+                TokenKind::TkNum(format),
+                sub(idx, idx + len),
+            );
+            idx += len;
+        }
+    }
+
+    fn push(&mut self, token: &str, token_kind: TokenKind, span: Span) {
+        self.tokens.push(Token {
+            token: token.to_string(),
+            token_kind,
+        });
+        self.token_loc.push(span);
+    }
+}
+
+/// 先頭からpredを満たすbyteが続く長さ
+fn count_while(s: &[u8], pred: impl Fn(u8) -> bool) -> usize {
+    s.iter().take_while(|b| pred(**b)).count()
+}
+
+/// s[i..]から "d+(sep d+)*" の形の最も長い部分を読み, 読み終えた位置を返す
+/// 先頭がdigitでなければNone
+fn scan_digit_groups(s: &[u8], i: usize, is_digit: fn(u8) -> bool, seps: &[u8]) -> Option<usize> {
+    let mut j = i + count_while(&s[i.min(s.len())..], is_digit);
+    if j == i {
+        return None;
+    }
+    while j + 1 < s.len() && seps.contains(&s[j]) && is_digit(s[j + 1]) {
+        j += 1;
+        j += count_while(&s[j..], is_digit);
+    }
+    Some(j)
+}
+
+/// tokensの直後の "(" が関数呼び出しの括弧ならtrue
+/// - \operatorname{mean}(, \operatorname{normcdf}(: 引数を並べる関数
+/// - \Gamma(: ギリシャ文字で書く特殊関数
+/// - J_{0}(, J_0(: 添字で書く特殊関数
+fn is_call_paren(registry: &Registry, tokens: &[Token]) -> bool {
+    let special = |name: &str| {
+        registry.lookup(name).and_then(|(_, cmd)| match cmd.eval {
+            Evaluator::Special(f) => Some(f),
+            _ => None,
+        })
+    };
+    let Some(prev) = tokens.last() else {
+        return false;
+    };
+    match prev.token_kind {
+        TokenKind::TkTexCommand => {
+            return registry.lookup(&prev.token).is_some_and(|(_, cmd)| {
+                matches!(cmd.eval, Evaluator::List(_) | Evaluator::Special(_))
+            });
+        }
+        TokenKind::TkVariable if prev.token.starts_with('\\') => {
+            return special(&prev.token).is_some();
+        }
+        _ => (),
+    }
+    // 添字の前: J_{0}なら "{" の位置, J_0なら "0" の位置
+    let sub_start = if prev.token == "}" && prev.token_kind == TokenKind::TkBrace {
+        let mut depth = 0;
+        tokens.iter().rposition(|t| {
+            match (&*t.token, t.token_kind) {
+                ("}", TokenKind::TkBrace) => depth += 1,
+                ("{", TokenKind::TkBrace) => depth -= 1,
+                _ => (),
             }
-            formulas = &formulas[token_len..];
-            ismatch = true;
-        } else if let Some(caps) = var.captures(formulas) {
-            if c != caps.get(0).unwrap().as_str().chars().next().unwrap() {
-                return Err(MyError::InvalidInput(c.to_string()));
+            depth == 0
+        })
+    } else {
+        Some(tokens.len() - 1)
+    };
+    sub_start.and_then(|i| i.checked_sub(2)).is_some_and(|i| {
+        let (name, underscore) = (&tokens[i], &tokens[i + 1]);
+        underscore.token == "_"
+            && name.token_kind == TokenKind::TkVariable
+            && special(&format!("\\{}", name.token)).is_some_and(|f| f.subscripted())
+    })
+}
+
+/// 数値リテラルの長さと種類を返す
+/// sの先頭は[0-9]であること
+/// 前から順に試し, 最初に当てはまった形式を採る
+/// - scientific: 1.16E-6
+/// - hex: 0x1234, 0x12_34
+/// - oct: 01234, 012_34
+/// - bin: 0b1010, 0b10_10
+/// - dec(!int): '1.234', '1.2_34'
+/// - dec(int): '1234', '12_34', '1,234
+pub(crate) fn scan_num(s: &[u8]) -> (usize, NumFormat) {
+    fn is_dec(b: u8) -> bool {
+        b.is_ascii_digit()
+    }
+    fn is_nonzero(b: u8) -> bool {
+        matches!(b, b'1'..=b'9')
+    }
+    fn is_hex(b: u8) -> bool {
+        b.is_ascii_hexdigit()
+    }
+    fn is_oct(b: u8) -> bool {
+        matches!(b, b'0'..=b'7')
+    }
+    fn is_bin(b: u8) -> bool {
+        matches!(b, b'0' | b'1')
+    }
+    let at = |i: usize| s.get(i).copied().unwrap_or(0);
+
+    // [1-9]\.[0-9]+E(\+|-)[1-9]+
+    if is_nonzero(at(0)) && at(1) == b'.' {
+        let mant = 2 + count_while(&s[2..], is_dec);
+        if mant > 2 && at(mant) == b'E' && matches!(at(mant + 1), b'+' | b'-') {
+            let exp = count_while(&s[mant + 2..], is_nonzero);
+            if exp > 0 {
+                return (mant + 2 + exp, NumFormat::Scientific);
             }
-            let token = caps.get(0).unwrap().as_str().to_string();
-            let token_len = token.len();
-            push_token!(token, token_len, TokenKind::TkVariable);
-            formulas = &formulas[token_len..];
-            ismatch = true;
         }
-        if !ismatch {
-            return Err(MyError::InvalidInput(c.to_string()));
+    }
+    if at(0) == b'0' {
+        if at(1) == b'x'
+            && let Some(end) = scan_digit_groups(s, 2, is_hex, b"_")
+        {
+            return (end, NumFormat::Hex);
+        }
+        if let Some(end) = scan_digit_groups(s, 1, is_oct, b"_") {
+            return (end, NumFormat::Oct);
+        }
+        if at(1) == b'b'
+            && let Some(end) = scan_digit_groups(s, 2, is_bin, b"_")
+        {
+            return (end, NumFormat::Bin);
         }
+    }
+    let int_end = scan_digit_groups(s, 0, is_dec, b"_,").unwrap_or(0);
+    if at(int_end) == b'.'
+        && let Some(end) = scan_digit_groups(s, int_end + 1, is_dec, b"_,")
+    {
+        return (end, NumFormat::Dec);
+    }
+    (int_end, NumFormat::DecInt)
+}
 
-        if formulas.is_empty() {
-            token_loc.push(processed_form_idx);
-            tokens.push(Token {
-                token: "EOT".to_string(),
-                token_kind: TokenKind::TkEOT,
-            });
-            print_token(&tokens);
-            break;
+fn print_token(tokens: &[Token]) {
+    let conf = crate::CONFIG.read().expect("couldn't read CONFIG");
+    if cfg!(debug_assertions) || conf.debug {
+        for token in tokens.iter() {
+            eprint!("{}:'{}', ", token.token_kind, token.token);
         }
+        eprintln!();
     }
+}
 
-    assert_eq!(token_loc.len(), tokens.len());
-    Ok((tokens, token_loc))
+/// \operatorname{name}のnameと全体の長さ
+/// nameは英字で始まり, chi2cdfのように2文字目からは数字も使える
+fn operator_name(src: &str) -> Option<(&str, usize)> {
+    const PREFIX: &str = "\\operatorname{";
+    let rest = src.strip_prefix(PREFIX)?;
+    if !rest.as_bytes().first()?.is_ascii_alphabetic() {
+        return None;
+    }
+    let len = count_while(rest.as_bytes(), |b| b.is_ascii_alphanumeric());
+    rest[len..]
+        .starts_with('}')
+        .then(|| (&rest[..len], PREFIX.len() + len + 1))
 }
 
-fn print_token(tokens: &[Token]) {
-    for token in tokens.iter() {
-        debug!("{}:'{}', ", token.token_kind, token.token);
+/// registryに登録されたコマンドならtrue
+fn is_valid_texcommand(tc: &str) -> bool {
+    registry_reader().is_ok_and(|r| r.lookup(tc).is_some())
+}
+
+/// ギリシャ文字とそのTeXのコマンド. 変数として使える
+pub(crate) const GREEK_LETTERS: [(char, &str); 40] = [
+    ('α', "\\alpha"),
+    ('β', "\\beta"),
+    ('γ', "\\gamma"),
+    ('δ', "\\delta"),
+    ('ϵ', "\\epsilon"),
+    ('ε', "\\varepsilon"),
+    ('ζ', "\\zeta"),
+    ('η', "\\eta"),
+    ('θ', "\\theta"),
+    ('ϑ', "\\vartheta"),
+    ('ι', "\\iota"),
+    ('κ', "\\kappa"),
+    ('λ', "\\lambda"),
+    ('μ', "\\mu"),
+    ('ν', "\\nu"),
+    ('ξ', "\\xi"),
+    ('π', "\\pi"),
+    ('ϖ', "\\varpi"),
+    ('ρ', "\\rho"),
+    ('ϱ', "\\varrho"),
+    ('σ', "\\sigma"),
+    ('ς', "\\varsigma"),
+    ('τ', "\\tau"),
+    ('υ', "\\upsilon"),
+    ('ϕ', "\\phi"),
+    ('φ', "\\varphi"),
+    ('χ', "\\chi"),
+    ('ψ', "\\psi"),
+    ('ω', "\\omega"),
+    ('Γ', "\\Gamma"),
+    ('Δ', "\\Delta"),
+    ('Θ', "\\Theta"),
+    ('Λ', "\\Lambda"),
+    ('Ξ', "\\Xi"),
+    ('Π', "\\Pi"),
+    ('Σ', "\\Sigma"),
+    ('Υ', "\\Upsilon"),
+    ('Φ', "\\Phi"),
+    ('Ψ', "\\Psi"),
+    ('Ω', "\\Omega"),
+];
+
+pub(crate) fn is_greek_letter(tc: &str) -> bool {
+    GREEK_LETTERS.iter().any(|(_, name)| *name == tc)
+}
+
+/// PDFやWordからコピーした数式に含まれる文字をTeXの記法に置き換える
+fn unicode_to_tex(c: char) -> Option<&'static str> {
+    let tex = match c {
+        '×' => "\\times",
+        '÷' => "\\div",
+        '·' | '⋅' | '∙' => "\\cdot",
+        '−' | '–' => "-",
+        '∗' => "*",
+        '∕' => "/",
+        '√' => "\\sqrt",
+        '½' => "\\frac{1}{2}",
+        '⅓' => "\\frac{1}{3}",
+        '⅔' => "\\frac{2}{3}",
+        '¼' => "\\frac{1}{4}",
+        '¾' => "\\frac{3}{4}",
+        'µ' => "\\mu",
+        _ => {
+            return GREEK_LETTERS
+                .iter()
+                .find(|(g, _)| *g == c)
+                .map(|(_, name)| *name);
+        }
+    };
+    Some(tex)
+}
+
+/// 不等号等の関係を表す記号. 式ではないので評価できない
+fn is_relation(c: char) -> bool {
+    matches!(c, '≤' | '≥' | '≠' | '≦' | '≧' | '≈')
+}
+
+/// 上付きの数字, 符号をASCIIに直す
+fn superscript_to_ascii(c: char) -> Option<char> {
+    match c {
+        '⁰' => Some('0'),
+        '¹' => Some('1'),
+        '²' => Some('2'),
+        '³' => Some('3'),
+        '⁴' => Some('4'),
+        '⁵' => Some('5'),
+        '⁶' => Some('6'),
+        '⁷' => Some('7'),
+        '⁸' => Some('8'),
+        '⁹' => Some('9'),
+        '⁺' => Some('+'),
+        '⁻' => Some('-'),
+        _ => None,
+    }
+}
+
+/// 全角の英数字, 記号(U+FF01 - U+FF5E)を対応するASCIIに直す
+fn fullwidth_to_ascii(c: char) -> Option<char> {
+    match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0),
+        _ => None,
     }
-    debugln!("");
 }
 
-fn is_valid_texcommand(tc: &String) -> bool {
-    matches!(
-        &**tc,
-        "\\times"
-            | "\\cdot"
-            | "\\div"
-            | "\\frac"
-            | "\\sqrt"
-            | "\\log"
-            | "\\ln"
-            | "\\abs"
-            | "\\exp"
-            | "\\sin"
-            | "\\cos"
-            | "\\tan"
-            | "\\csc"
-            | "\\cot"
-            | "\\arcsin"
-            | "\\arccos"
-            | "\\arctan"
-    )
+/// 空白として読み飛ばす文字
+fn is_unicode_space(c: char) -> bool {
+    matches!(c, '\u{00A0}' | '\u{2009}' | '\u{202F}' | '\u{3000}')
+}
+
+/// 未定義のコマンドtcに最も近いコマンドを返す
+/// 編集距離が2より大きいもの, コマンド名より長いものは候補にしない
+fn suggest_texcommand(tc: &str) -> Option<String> {
+    let registry = registry_reader().ok()?;
+    registry
+        .commands()
+        .map(|cmd| cmd.name.as_str())
+        .chain(
+            INFIX_OPS
+                .iter()
+                .map(|op| op.token)
+                .filter(|op| op.starts_with('\\')),
+        )
+        .chain(GREEK_LETTERS.iter().map(|(_, name)| *name))
+        .map(|cand| (edit_distance(tc, cand), cand))
+        .filter(|(d, _)| *d <= 2 && *d < tc.len() - 1)
+        // 距離が同じなら先頭の文字が一致するものを優先する
+        .min_by_key(|(d, cand)| (*d, cand.chars().nth(1) != tc.chars().nth(1)))
+        .map(|(_, cand)| cand.to_string())
+}
+
+/// aとbのレーベンシュタイン距離
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
 }
 
 #[cfg(test)]
 mod test {
     use super::{NumFormat, Token, TokenKind};
+    use crate::error::MyError;
     #[test]
     fn test_tokenize() {
         let formulas = "1.16E-6 * 0x1 - \\frac{\\sin \\pi}{0b10} / 0x12 + 0.2; \\log a ;a=3";
@@ -311,14 +684,196 @@ mod test {
             62, 63,
         ];
         match super::tokenize(formulas) {
-            Ok((tokens, sizes)) => {
+            Ok((tokens, spans)) => {
                 assert_eq!(tokens, t);
-                assert_eq!(sizes, s);
+                let starts: Vec<usize> = spans.iter().map(|s| s.start).collect();
+                assert_eq!(starts, s);
             }
             Err(e) => panic!("{}", e),
         }
     }
 
+    #[test]
+    fn test_tokenize_num() {
+        // 形式は前から順に試し, 最初に当てはまったものを採る
+        let formulas = "0x 1,234.5_6 0b12 017 08 1.5E-3";
+        let t = [
+            new_token("0", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token("x", TokenKind::TkVariable),
+            new_token("1,234.5_6", TokenKind::TkNum(NumFormat::Dec)),
+            new_token("0b1", TokenKind::TkNum(NumFormat::Bin)),
+            new_token("2", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token("017", TokenKind::TkNum(NumFormat::Oct)),
+            new_token("08", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token("1.5E-3", TokenKind::TkNum(NumFormat::Scientific)),
+            new_token("EOT", TokenKind::TkEOT),
+        ];
+        match super::tokenize(formulas) {
+            Ok((tokens, _)) => assert_eq!(tokens, t),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn test_tokenize_unicode() {
+        // Unicodeの記号はTeXの記法に置き換え, 元の文字の位置を持つ
+        let formulas = "2×π−√α²\u{3000}÷½";
+        let t = [
+            new_token("2", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token("\\times", TokenKind::TkOperator),
+            new_token("\\pi", TokenKind::TkVariable),
+            new_token("-", TokenKind::TkOperator),
+            new_token("\\sqrt", TokenKind::TkTexCommand),
+            new_token("\\alpha", TokenKind::TkVariable),
+            new_token("^", TokenKind::TkOperator),
+            new_token("{", TokenKind::TkBrace),
+            new_token("2", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token("}", TokenKind::TkBrace),
+            new_token("\\div", TokenKind::TkOperator),
+            new_token("\\frac", TokenKind::TkTexCommand),
+            new_token("{", TokenKind::TkBrace),
+            new_token("1", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token("}", TokenKind::TkBrace),
+            new_token("{", TokenKind::TkBrace),
+            new_token("2", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token("}", TokenKind::TkBrace),
+            new_token("EOT", TokenKind::TkEOT),
+        ];
+        match super::tokenize(formulas) {
+            Ok((tokens, spans)) => {
+                assert_eq!(tokens, t);
+                // ²は"^{2}"の4つのtokenになる
+                let sup = formulas.find('²').unwrap();
+                for span in &spans[6..10] {
+                    assert_eq!((span.start, span.end), (sup, sup + '²'.len_utf8()));
+                }
+            }
+            Err(e) => panic!("{}", e),
+        }
+        // 大文字のギリシャ文字も変数になる
+        let (tokens, _) = super::tokenize("ΓΔΘΛΞΠΣΥΦΨΩ").unwrap();
+        let names: Vec<&str> = tokens[..tokens.len() - 1]
+            .iter()
+            .inspect(|t| assert_eq!(t.token_kind, TokenKind::TkVariable))
+            .map(|t| t.token.as_str())
+            .collect();
+        assert_eq!(
+            names.join(" "),
+            "\\Gamma \\Delta \\Theta \\Lambda \\Xi \\Pi \\Sigma \\Upsilon \\Phi \\Psi \\Omega"
+        );
+    }
+
+    #[test]
+    fn test_tokenize_fullwidth() {
+        let formulas = "１２＋ｘ";
+        let t = [
+            new_token("12", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token("+", TokenKind::TkOperator),
+            new_token("x", TokenKind::TkVariable),
+            new_token("EOT", TokenKind::TkEOT),
+        ];
+        match super::tokenize(formulas) {
+            Ok((tokens, spans)) => {
+                assert_eq!(tokens, t);
+                let s: Vec<(usize, usize)> = spans.iter().map(|s| (s.start, s.end)).collect();
+                assert_eq!(s, [(0, 6), (6, 9), (9, 12), (12, 12)]);
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn test_tokenize_cmd_args() {
+        // コマンドの引数の関数の区切りとオプション
+        let formulas = ":plot x, x^{2} --svg out";
+        let t = [
+            new_token(":plot", TokenKind::TkTscCommand),
+            new_token("x", TokenKind::TkVariable),
+            new_token(",", TokenKind::TkOperator),
+            new_token("x", TokenKind::TkVariable),
+            new_token("^", TokenKind::TkOperator),
+            new_token("{", TokenKind::TkBrace),
+            new_token("2", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token("}", TokenKind::TkBrace),
+            new_token("-", TokenKind::TkOperator),
+            new_token("-", TokenKind::TkOperator),
+            new_token("svg", TokenKind::TkVariable),
+            new_token("out", TokenKind::TkVariable),
+            new_token("EOT", TokenKind::TkEOT),
+        ];
+        match super::tokenize(formulas) {
+            Ok((tokens, _)) => assert_eq!(tokens, t),
+            Err(e) => panic!("{}", e),
+        }
+        let formulas = "x = 1..2";
+        let t = [
+            new_token("x", TokenKind::TkVariable),
+            new_token("=", TokenKind::TkOperator),
+            new_token("1", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token("..", TokenKind::TkOperator),
+            new_token("2", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token("EOT", TokenKind::TkEOT),
+        ];
+        match super::tokenize(formulas) {
+            Ok((tokens, _)) => assert_eq!(tokens, t),
+            Err(e) => panic!("{}", e),
+        }
+        // 関数呼び出しの括弧の中では "," で区切り, 外では桁区切りにする
+        let formulas = "\\operatorname{mean}(1,2.5,(3,000))";
+        let t = [
+            new_token("\\mean", TokenKind::TkTexCommand),
+            new_token("(", TokenKind::TkBrace),
+            new_token("1", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token(",", TokenKind::TkOperator),
+            new_token("2.5", TokenKind::TkNum(NumFormat::Dec)),
+            new_token(",", TokenKind::TkOperator),
+            new_token("(", TokenKind::TkBrace),
+            new_token("3,000", TokenKind::TkNum(NumFormat::DecInt)),
+            new_token(")", TokenKind::TkBrace),
+            new_token(")", TokenKind::TkBrace),
+            new_token("EOT", TokenKind::TkEOT),
+        ];
+        match super::tokenize(formulas) {
+            Ok((tokens, loc)) => {
+                assert_eq!(tokens, t);
+                assert_eq!((loc[4].start, loc[4].end), (22, 25));
+            }
+            Err(e) => panic!("{}", e),
+        }
+        // \operatorname{mean}は\meanになる
+        let (tokens, loc) = super::tokenize("\\operatorname{mean}(x)").unwrap();
+        assert_eq!(tokens[0], new_token("\\mean", TokenKind::TkTexCommand));
+        assert_eq!((loc[0].start, loc[0].end), (0, 19));
+        let (tokens, _) = super::tokenize("\\operatorname{chi2cdf}(x, 3)").unwrap();
+        assert_eq!(tokens[0], new_token("\\chi2cdf", TokenKind::TkTexCommand));
+    }
+
+    #[test]
+    fn test_tokenize_invalid_unicode() {
+        // 解釈できない文字はpanicせずにInvalidInputになり, 列は表示幅で数える
+        let formulas = "1 + 数 + 2";
+        let (tokens, _, errors) = super::tokenize_recovering(formulas);
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            (loc, MyError::InvalidInput(msg)) => {
+                assert_eq!(*loc, 4);
+                assert!(msg.starts_with("'数' at column 5"));
+            }
+            (_, e) => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(tokens.len(), 5);
+        // 不等号は評価できない関係としてエラーにする
+        let (_, _, errors) = super::tokenize_recovering("x ≤ 2 + y ≠ 3");
+        let relations: Vec<(usize, &str)> = errors
+            .iter()
+            .map(|(loc, e)| match e {
+                MyError::RelationErr(rel, _) => (*loc, rel.as_str()),
+                e => panic!("unexpected error: {}", e),
+            })
+            .collect();
+        assert_eq!(relations, [(2, "≤"), (12, "≠")]);
+    }
+
     fn new_token(t: &str, k: TokenKind) -> Token {
         Token {
             token: t.to_string(),
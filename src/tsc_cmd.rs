@@ -1,24 +1,94 @@
-use bigdecimal::{BigDecimal, ToPrimitive};
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
 use std::collections::HashMap;
 use text_colorizer::*;
 
+use crate::ast_json;
+use crate::codegen::{codegen, Lang};
+use crate::compile::Program;
 use crate::config::*;
 use crate::error::*;
+use crate::num_formatter::num_formatter;
+use crate::optimizer;
 use crate::parser::Parser;
+use crate::rational::Rational;
+use crate::registry::{registry_reader, Evaluator};
 use crate::str2num::*;
+use crate::tex_printer;
 use crate::tokenizer::TokenKind;
-use crate::CONSTS;
+use crate::{Value, CONSTS};
 
+mod data;
 mod gcd;
+mod integer;
+mod linsolve;
+mod plot;
+mod poly;
 mod prime_factorize;
+mod solve;
+mod table;
 
 pub fn process_tsccommand(
     parser: &Parser,
     cmd_idx: usize,
-    vars: &mut HashMap<String, BigDecimal>,
+    vars: &mut HashMap<String, Value>,
 ) -> Result<usize, MyError> {
     let t1 = parser.get_token(cmd_idx);
     let t2 = parser.get_token(cmd_idx + 1);
+    // 数式を引数にとるコマンドは, 構文解析でconfigを読むのでconfigのlockを取る前に処理する
+    if t1.token == ":simplify" {
+        let (form, consumed) = parser.cmd_arg_form(cmd_idx + 1)?;
+        println!("{}", simplify_form(&form, vars)?);
+        return Ok(consumed + 1);
+    }
+    // 設定ファイルで定義された関数の構文解析もconfigを読むので, lockを取る前に読み込む
+    if t1.token == ":reload" {
+        if t2.token != "conf" {
+            return Err(MyError::UnexpectedInput(
+                "conf".to_string(),
+                t2.token.clone(),
+            ));
+        }
+        println!("config reloaded from {:?}", load_config()?);
+        return Ok(2);
+    }
+    if t1.token == ":codegen" {
+        let lang: Lang = t2.token.parse()?;
+        let (form, consumed) = parser.cmd_arg_form(cmd_idx + 2)?;
+        println!("{}", codegen(&form, lang)?);
+        return Ok(consumed + 2);
+    }
+    if matches!(&*t1.token, ":roots" | ":expand" | ":factor" | ":polydiv") {
+        return poly::poly_cmd(parser, cmd_idx, vars);
+    }
+    if t1.token == ":linsolve" {
+        return linsolve::linsolve_cmd(parser, cmd_idx, vars);
+    }
+    if t1.token == ":solve" {
+        return solve::solve_cmd(parser, cmd_idx, vars);
+    }
+    if t1.token == ":plot" {
+        return plot::plot_cmd(parser, cmd_idx, vars);
+    }
+    if t1.token == ":table" {
+        return table::table_cmd(parser, cmd_idx, vars);
+    }
+    if integer::INTEGER_CMDS.contains(&&*t1.token) {
+        return integer::integer_cmd(parser, cmd_idx, vars);
+    }
+    if t1.token == ":loadast" {
+        let (_, consumed) = parser.cmd_arg_form(cmd_idx + 1)?;
+        load_ast(&parser.raw_form(cmd_idx, cmd_idx + 1 + consumed), vars)?;
+        return Ok(consumed + 1);
+    }
+    if t1.token == ":load" {
+        return data::load_cmd(parser, cmd_idx, vars);
+    }
+    if t1.token == ":hist" {
+        return data::hist_cmd(parser, cmd_idx, vars);
+    }
+    if t1.token == ":fit" {
+        return data::fit_cmd(parser, cmd_idx, vars);
+    }
     let mut conf = config_writer()?;
     let consumed_token;
     match &*t1.token {
@@ -48,9 +118,14 @@ pub fn process_tsccommand(
                     Ok(num) => conf.log_base = num,
                     Err(e) => return Err(e),
                 },
-                TokenKind::TkVariable => match vars.get(&t2.token) {
+                TokenKind::TkVariable => match vars.get(&t2.token).and_then(Value::num) {
                     Some(num) => conf.log_base = num.clone(),
-                    None => return Err(MyError::UDvariableErr(t2.token.to_string())),
+                    None => {
+                        return Err(MyError::UDvariableErr(
+                            t2.token.to_string(),
+                            parser.format_err_loc_idx(cmd_idx + 1),
+                        ));
+                    }
                 },
                 _ => {
                     return Err(MyError::NotTkNumber(
@@ -67,9 +142,18 @@ pub fn process_tsccommand(
                     Ok(num) => conf.num_of_digit = num as u32,
                     Err(e) => return Err(e),
                 },
-                TokenKind::TkVariable => match vars.get(&t2.token) {
-                    Some(num) => conf.num_of_digit = num.to_u32().unwrap(),
-                    None => return Err(MyError::UDvariableErr(t2.token.to_string())),
+                TokenKind::TkVariable => match vars.get(&t2.token).and_then(Value::num) {
+                    Some(num) => {
+                        conf.num_of_digit = num
+                            .to_u32()
+                            .ok_or(MyError::ConvertErr("u32".to_string(), num.clone()))?
+                    }
+                    None => {
+                        return Err(MyError::UDvariableErr(
+                            t2.token.to_string(),
+                            parser.format_err_loc_idx(cmd_idx + 1),
+                        ));
+                    }
                 },
                 _ => {
                     return Err(MyError::NotTkNumber(
@@ -94,38 +178,30 @@ pub fn process_tsccommand(
         }
         ":astform" => {
             consumed_token = 2;
-            match &*t2.token {
-                "tree" => conf.ast_format = AstFormat::Tree,
-                "sexpr" => conf.ast_format = AstFormat::Sexpr,
-                "both" => conf.ast_format = AstFormat::Both,
-                "none" => conf.ast_format = AstFormat::None,
-                _ => {
-                    return Err(MyError::UnexpectedInput(
-                        "tree|sexpr|both|none".to_string(),
-                        t2.token.clone(),
-                    ))
-                }
-            }
+            conf.ast_format = t2.token.parse()?;
         }
-        ":write" => {
+        ":syntax" => {
+            consumed_token = 2;
+            conf.syntax = t2.token.parse()?;
+        }
+        ":fast" => {
             consumed_token = 2;
             match &*t2.token {
-                "conf" => {
-                    println!("config write to {:?}", conf.write_to_file()?);
-                }
+                "on" => conf.fast_eval = true,
+                "off" => conf.fast_eval = false,
                 _ => {
                     return Err(MyError::UnexpectedInput(
-                        "conf".to_string(),
+                        "on/off".to_string(),
                         t2.token.clone(),
                     ))
                 }
             }
         }
-        ":reload" => {
+        ":write" => {
             consumed_token = 2;
             match &*t2.token {
                 "conf" => {
-                    println!("config reloaded from {:?}", conf.load_from_file()?);
+                    println!("config write to {:?}", conf.write_to_file()?);
                 }
                 _ => {
                     return Err(MyError::UnexpectedInput(
@@ -138,80 +214,6 @@ pub fn process_tsccommand(
         ":hex" | ":dec" | ":bin" | ":oct" => {
             consumed_token = 0;
         }
-        ":fact" => {
-            consumed_token = 2;
-            match t2.token_kind {
-                TokenKind::TkNum(f) => match u64_from_str(f, &t2.token) {
-                    Ok(num) => {
-                        let res = prime_factorize::factorize(num);
-                        println!("{res}");
-                    }
-                    Err(e) => return Err(e),
-                },
-                _ => {
-                    return Err(MyError::NotTkNumber(
-                        t2.token_kind.to_string(),
-                        parser.format_err_loc_idx(cmd_idx + 1),
-                    ));
-                }
-            }
-        }
-        ":gcd" | ":redu" => {
-            let mut counter = 0;
-            let mut nums = Vec::new();
-            loop {
-                let t = parser.get_token(cmd_idx + 1 + counter);
-                match t.token_kind {
-                    TokenKind::TkNum(f) => match u64_from_str(f, &t.token) {
-                        Ok(num) => {
-                            if num == 0 {
-                                return Err(MyError::InvalidInput(format!(
-                                    "{} don't allow 0",
-                                    t1.token
-                                )));
-                            } else {
-                                nums.push(num);
-                                counter += 1;
-                            }
-                        }
-                        Err(e) => return Err(e),
-                    },
-                    _ => break,
-                }
-            }
-            consumed_token = counter + 1;
-            if nums.len() < 2 {
-                return Err(MyError::InvalidInput(format!(
-                    "{} requires two or more numbers",
-                    t1.token
-                )));
-            }
-            if t1.token == ":gcd" {
-                let mut msg = String::from("gcd(");
-                for n in &nums {
-                    msg += &format!("{n}, ");
-                }
-                msg.pop();
-                msg.pop();
-                msg += ")";
-                println!("{} = {}", msg, gcd::gcd(nums));
-            } else if t1.token == ":redu" {
-                let mut msg = String::new();
-                for n in &nums {
-                    msg += &format!("{n} : ");
-                }
-                msg.pop();
-                msg.pop();
-                msg += "= ";
-                let gcd = gcd::gcd(nums.clone());
-                for n in &nums {
-                    msg += &format!("{} : ", n / gcd);
-                }
-                msg.pop();
-                msg.pop();
-                println!("{msg}");
-            }
-        }
         ":help" => {
             consumed_token = 1;
             cmd_help()
@@ -223,9 +225,10 @@ pub fn process_tsccommand(
                 "config" => println!("{conf}"),
                 "conf" => println!("{conf}"),
                 "const" => show_const()?,
+                "func" => show_functions()?,
                 _ => {
                     return Err(MyError::UnexpectedInput(
-                        "var/const/config".to_string(),
+                        "var/const/config/func".to_string(),
                         t2.token.clone(),
                     ))
                 }
@@ -236,14 +239,152 @@ pub fn process_tsccommand(
     Ok(consumed_token)
 }
 
-fn show_variables(vars: &HashMap<String, BigDecimal>) -> Result<(), MyError> {
+/// start番目から始まるTSCコマンドの引数の先頭が, 式の変数を指定するものならその変数名を返す
+/// 変数の後に演算子が続くなら, 変数は式の一部とみなす
+///
+/// * `end` - 引数の終わりのtoken index
+fn leading_var(parser: &Parser, start: usize, end: usize) -> Option<String> {
+    let t = parser.get_token(start);
+    if start + 1 < end
+        && matches!(t.token_kind, TokenKind::TkVariable)
+        && !matches!(
+            parser.get_token(start + 1).token_kind,
+            TokenKind::TkOperator
+        )
+    {
+        Some(t.token.clone())
+    } else {
+        None
+    }
+}
+
+/// 式の変数namesのうち, 変数にも定数にも定義されていないものが1つだけならその名前を返す
+fn only_unknown(names: &[String], vars: &HashMap<String, Value>) -> Result<String, MyError> {
+    let consts = match CONSTS.read() {
+        Ok(consts) => consts,
+        Err(e) => return Err(MyError::ConstsReadErr(e.to_string())),
+    };
+    let mut unknowns: Vec<&String> = names
+        .iter()
+        .filter(|v| !vars.contains_key(*v) && !consts.contains_key(*v))
+        .collect();
+    unknowns.sort();
+    unknowns.dedup();
+    match unknowns.as_slice() {
+        [var] => Ok(var.to_string()),
+        [] => Err(MyError::InvalidInput("no undefined variable".to_string())),
+        _ => Err(MyError::InvalidInput(format!(
+            "specify the variable from {{{}}}",
+            unknowns
+                .iter()
+                .map(|v| v.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// start..endから "--{name}" のオプションを探し, 先頭の位置と名前を返す
+fn find_option(
+    parser: &Parser,
+    start: usize,
+    end: usize,
+    names: &[&str],
+) -> Option<(usize, String)> {
+    let is_minus = |i: usize| {
+        let t = parser.get_token(i);
+        t.token_kind == TokenKind::TkOperator && t.token == "-"
+    };
+    (start..end.saturating_sub(2)).find_map(|i| {
+        let name = &parser.get_token(i + 2).token;
+        (is_minus(i) && is_minus(i + 1) && names.contains(&name.as_str()))
+            .then(|| (i, name.clone()))
+    })
+}
+
+/// idx番目の "--{name}" に続くendまでの引数を入力のまま返す. 無ければNone
+fn option_arg(parser: &Parser, idx: usize, end: usize) -> Option<String> {
+    let arg = parser.raw_form(idx + 2, end);
+    (!arg.is_empty()).then_some(arg)
+}
+
+/// start..endを括弧の外にある "," で区切った範囲. \operatorname{normcdf}(x, 0, 1)等の引数では区切らない
+fn split_commas(parser: &Parser, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut pieces = Vec::new();
+    let mut depth = 0;
+    let mut piece = start;
+    for i in start..end {
+        let t = parser.get_token(i);
+        match (&*t.token, t.token_kind) {
+            ("(" | "{", TokenKind::TkBrace) => depth += 1,
+            (")" | "}", TokenKind::TkBrace) => depth -= 1,
+            (",", TokenKind::TkOperator) if depth == 0 => {
+                pieces.push((piece, i));
+                piece = i + 1;
+            }
+            _ => (),
+        }
+    }
+    pieces.push((piece, end));
+    pieces
+}
+
+/// 引数の数式を簡約してTeXにする. 定義済みの変数の値で消せる項を消す
+pub(crate) fn simplify_form(form: &str, vars: &HashMap<String, Value>) -> Result<String, MyError> {
+    let (ast, root) = Parser::new(form.to_string(), false)?.build_expr()?;
+    let (ast, root) = optimizer::simplify_to_show(&ast, root, vars)?;
+    tex_printer::to_tex(&ast, root)
+}
+
+/// :astform jsonで出力したASTのファイルを読んで評価し, 結果を表示する
+pub(crate) fn load_ast(
+    path: &str,
+    vars: &mut HashMap<String, Value>,
+) -> Result<Vec<BigDecimal>, MyError> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| MyError::FileReadErr(path.to_string(), e.to_string()))?;
+    let (ast, root) = ast_json::from_json(&json)?;
+    // JSONのspanは元の数式の位置なので, エラーの個所を示せるようTeXに直してから評価する
+    crate::process_form_as(tex_printer::to_tex(&ast, root)?, Syntax::Tex, vars)
+}
+
+/// 引数の数式を評価する
+fn eval_form(
+    form: &str,
+    vars: &HashMap<String, Value>,
+    conf: &Config,
+) -> Result<BigDecimal, MyError> {
+    let (ast, root) = Parser::new(form.to_string(), false)?.build_expr()?;
+    let prog = Program::compile(&ast, root, form);
+    prog.eval(&prog.bind(vars)?, conf)
+}
+
+/// 有理数の値を表示する文字列にする. 有限小数で表せないときは分数も付ける
+/// ex) 1.5, \frac{1}{3} = 0.333333333333
+fn format_rational(val: &Rational, num_of_digit: u32) -> String {
+    let dec = num_formatter(&val.to_bigdecimal(), num_of_digit);
+    if val.is_terminating() {
+        dec
+    } else {
+        format!("{} = {dec}", val.to_tex())
+    }
+}
+
+/// f64で求めた値を, 丸め誤差を除くため15桁に丸める
+fn round_f64(x: f64) -> Option<BigDecimal> {
+    BigDecimal::from_f64(x).map(|x| x.with_prec(15).normalized())
+}
+
+fn show_variables(vars: &HashMap<String, Value>) -> Result<(), MyError> {
     let consts = match CONSTS.read() {
         Ok(consts) => consts,
         Err(e) => return Err(MyError::ConstsReadErr(e.to_string())),
     };
     for (name, value) in vars {
-        if consts.get(name).is_none() {
-            println!("{name:<6}: {value}");
+        match value {
+            _ if consts.contains_key(name) => (),
+            Value::Num(n) => println!("{name:<6}: {n}"),
+            Value::List(values) => println!("{name:<6}: {}", data::format_list(values)),
         }
     }
     Ok(())
@@ -253,7 +394,9 @@ fn show_const() -> Result<(), MyError> {
     match CONSTS.read() {
         Ok(consts) => {
             for (name, value) in consts.iter() {
-                println!("{name:<6}: {value}");
+                if let Value::Num(n) = value {
+                    println!("{name:<6}: {n}");
+                }
             }
             Ok(())
         }
@@ -261,6 +404,21 @@ fn show_const() -> Result<(), MyError> {
     }
 }
 
+fn show_functions() -> Result<(), MyError> {
+    for cmd in registry_reader()?.commands() {
+        let arity = match &cmd.eval {
+            Evaluator::List(_) => "list of".to_string(),
+            Evaluator::Special(f) => f.arity_text(),
+            _ => cmd.arity.to_string(),
+        };
+        println!(
+            "{:<10}: {arity} argument(s), {} style",
+            cmd.name, cmd.arg_style
+        );
+    }
+    Ok(())
+}
+
 fn cmd_help() {
     println!(
         "{: <14}
@@ -279,6 +437,10 @@ fn cmd_help() {
         set format of trigonometric function argument
     {: <12}
         set ast format
+    {: <12}
+        set input syntax of formulas
+    {: <12}
+        evaluate :table with f64 (faster, less precise). :plot and :solve always use f64
     {: <12}
         write current config to config.toml
     {: <12}
@@ -292,13 +454,59 @@ fn cmd_help() {
     {: <12}
         after this command show value of expression in octal formats
     {: <12}
-        prime factorize number
+        prime factorize integer
+    {: <12}
+        set seed of random numbers
     {: <12}
         calcuate greatest common divisor
     {: <12}
-        divide numbers by greatest common divisor
+        calcuate least common multiple
+    {: <12}
+        divide integers by greatest common divisor
+    {: <12}
+        test whether integer is prime
+    {: <12}
+        find smallest prime greater than integer
+    {: <12}
+        calcuate Euler's totient function
+    {: <12}
+        list positive divisors of integer
+    {: <12}
+        find modular multiplicative inverse
+    {: <12}
+        calcuate modular exponentiation
+    {: <12}
+        solve system of congruences by Chinese remainder theorem
+    {: <12}
+        simplify expression and show it as TeX
+    {: <12}
+        generate a function computing expression in python, numpy, c, rust or js
+    {: <12}
+        find real roots of equation in [a, b] (default [-100, 100]) and bind the first one with bind
+    {: <12}
+        solve system of linear equations separated by ; exactly and bind the solution with bind
+    {: <12}
+        find all real and complex roots of polynomial
+    {: <12}
+        expand polynomial
+    {: <12}
+        factor polynomial over rational numbers
+    {: <12}
+        divide polynomials and show quotient and remainder
+    {: <12}
+        plot functions in [a, b] with braille characters or write them to SVG file with --svg
+    {: <12}
+        show table of values over all combinations of variables as text, CSV, Markdown or LaTeX tabular
+    {: <12}
+        bind each column of CSV file to list variable
+    {: <12}
+        evaluate AST written by :astform json
+    {: <12}
+        show summary statistics and histogram of list
+    {: <12}
+        fit least-squares model to lists and show coefficients and R^2
     {: <12}
-        show variable or config or embedded const number",
+        show variable or config or embedded const number or TeX commands",
         ":TSC_COMMAND {option}".yellow(),
         "description".yellow(),
         ":q".green(),
@@ -307,16 +515,41 @@ fn cmd_help() {
         ":logbase {num(f64)}".green(),
         ":rlen {num(u32)}".green(),
         ":trarg {rad|deg}".green(),
-        ":astform {tree|sexpr|both|none}".green(),
+        ":astform {tree|sexpr|both|tex|dot|json|none}".green(),
+        ":syntax {tex|sexpr|plain|mathml|asciimath}".green(),
+        ":fast {on|off}".green(),
         ":write conf".green(),
         ":reload conf".green(),
         ":hex {tex formulas} ...".green(),
         ":dec {tex formulas} ...".green(),
         ":bin {tex formulas} ...".green(),
         ":oct {tex formulas} ...".green(),
-        ":fact {num(u64)}".green(),
-        ":gcd {num(u64)} {num(u64)} ...".green(),
-        ":redu {num(u64)} {num(u64)} ...".green(),
-        ":show {var|const|config|conf}".green()
+        ":fact {integer}".green(),
+        ":seed {integer}".green(),
+        ":gcd {integer}, {integer}, ...".green(),
+        ":lcm {integer}, {integer}, ...".green(),
+        ":redu {integer}, {integer}, ...".green(),
+        ":isprime {integer}".green(),
+        ":nextprime {integer}".green(),
+        ":totient {integer}".green(),
+        ":divisors {integer}".green(),
+        ":modinv {a}, {m}".green(),
+        ":modpow {a}, {e}, {m}".green(),
+        ":crt {r1}, {m1}, {r2}, {m2}, ...".green(),
+        ":simplify {tex formula}".green(),
+        ":codegen {python|numpy|c|rust|js} {tex formula}".green(),
+        ":solve {var} {lhs} = {rhs} from {a} to {b} bind".green(),
+        ":linsolve {lhs} = {rhs}; {lhs} = {rhs} ... bind".green(),
+        ":roots {var} {polynomial}".green(),
+        ":expand {var} {polynomial}".green(),
+        ":factor {var} {polynomial}".green(),
+        ":polydiv {var} \\frac{p}{q}".green(),
+        ":plot {f}, {g} ... {var} from {a} to {b} --svg {file}".green(),
+        ":table {f}, {g} ..., {x} = {a}..{b} step {h}, ... --{csv|md|tex} {file}".green(),
+        ":load {file} as {name}".green(),
+        ":loadast {file}".green(),
+        ":hist {list} {bins}".green(),
+        ":fit {linear|poly n|exp} {xs} {ys}".green(),
+        ":show {var|const|config|conf|func}".green()
     );
 }
@@ -0,0 +1,386 @@
+use bigdecimal::{BigDecimal, ToPrimitive};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::config::*;
+use crate::error::*;
+use crate::num_formatter::num_formatter;
+use crate::parser::Parser;
+use crate::stats::{fit, histogram, sturges, ListFn, Model};
+use crate::str2num::u64_from_str;
+use crate::tokenizer::TokenKind;
+use crate::Value;
+
+use super::{eval_form, round_f64};
+
+/// ヒストグラムの区間の数の上限
+const MAX_BINS: usize = 100;
+/// ヒストグラムの棒の最大の長さ
+const BAR_WIDTH: usize = 40;
+/// 多項式であてはめる次数の上限
+const MAX_DEGREE: u64 = 20;
+
+/// :load {file} as {name}
+/// CSVファイルの各列をリスト変数にする. 数値でない欄がある先頭行は列名とみなす
+/// 列名が無いか変数名にできないときは, ファイル名に列の番号を付けた名前にする
+/// as {name}を付けると列が1つならname, 複数ならname1, name2, ...にする
+/// 消費したtoken数を返す
+pub fn load_cmd(
+    parser: &Parser,
+    cmd_idx: usize,
+    vars: &mut HashMap<String, Value>,
+) -> Result<usize, MyError> {
+    let (_, len) = parser.cmd_arg_form(cmd_idx + 1)?;
+    let start = cmd_idx + 1;
+    let end = start + len;
+    let is_as = |i: usize| {
+        let t = parser.get_token(i);
+        t.token_kind == TokenKind::TkVariable && t.token == "as"
+    };
+    let (path_end, name) = if len >= 3 && is_as(end - 2) {
+        let t = parser.get_token(end - 1);
+        if t.token_kind != TokenKind::TkVariable {
+            return Err(MyError::NotTkVariable(
+                t.token_kind.to_string(),
+                parser.format_err_loc_idx(end - 1),
+            ));
+        }
+        (end - 2, Some(t.token.as_str()))
+    } else {
+        (end, None)
+    };
+    if path_end == start {
+        return Err(MyError::NoToken);
+    }
+    let path = parser.raw_form(cmd_idx, path_end);
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| MyError::FileReadErr(path.clone(), e.to_string()))?;
+    let stem = Path::new(&path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let columns =
+        parse_csv(&text, &stem, name).map_err(|msg| MyError::FileReadErr(path.clone(), msg))?;
+
+    let rows = columns[0].1.len();
+    println!("{} column(s) of {rows} value(s) from {path}", columns.len());
+    for (name, values) in &columns {
+        println!("{name:<6}: {}", format_list(values));
+    }
+    vars.extend(
+        columns
+            .into_iter()
+            .map(|(name, values)| (name, Value::List(values))),
+    );
+    Ok(len + 1)
+}
+
+/// CSVを列ごとの値にする. エラーは行番号を付けたメッセージ
+fn parse_csv(
+    text: &str,
+    stem: &str,
+    name: Option<&str>,
+) -> Result<Vec<(String, Vec<BigDecimal>)>, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| (i + 1, split_csv(line)));
+    let Some((first_no, first)) = lines.next() else {
+        return Err("no data".to_string());
+    };
+    let width = first.len();
+    let header = first.iter().any(|f| BigDecimal::from_str(f).is_err());
+    let mut values = vec![Vec::new(); width];
+    let rows = if header {
+        None
+    } else {
+        Some((first_no, first.clone()))
+    };
+    for (no, fields) in rows.into_iter().chain(lines) {
+        if fields.len() != width {
+            return Err(format!(
+                "line {no}: expected {width} field(s) but {}",
+                fields.len()
+            ));
+        }
+        for (column, field) in values.iter_mut().zip(&fields) {
+            match BigDecimal::from_str(field) {
+                Ok(v) => column.push(v),
+                Err(_) => return Err(format!("line {no}: '{field}' is not a number")),
+            }
+        }
+    }
+    if values[0].is_empty() {
+        return Err("no data rows".to_string());
+    }
+
+    let stem = sanitize(stem).unwrap_or_else(|| "data".to_string());
+    let names: Vec<String> = match name {
+        Some(name) if width == 1 => vec![name.to_string()],
+        Some(name) => (1..=width).map(|i| format!("{name}{i}")).collect(),
+        None => (0..width)
+            .map(|i| {
+                header
+                    .then(|| sanitize(&first[i]))
+                    .flatten()
+                    .unwrap_or_else(|| format!("{stem}{}", i + 1))
+            })
+            .collect(),
+    };
+    for (i, name) in names.iter().enumerate() {
+        if names[..i].contains(name) {
+            return Err(format!("line {first_no}: duplicate column '{name}'"));
+        }
+    }
+    Ok(names.into_iter().zip(values).collect())
+}
+
+/// CSVの1行をフィールドに分ける. "で囲んだフィールドは,を含められる
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().unwrap();
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => field.push(c),
+        }
+    }
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// 英数字だけを残して変数名にする. 英字で始まらなければNone
+fn sanitize(s: &str) -> Option<String> {
+    let name: String = s.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        .then_some(name)
+}
+
+/// リストを表示する文字列にする. 長いものは途中を省く
+/// ex) [1, 2, 3], [1, 2, 3, ..., 9, 10] (10 values)
+pub(super) fn format_list(values: &[BigDecimal]) -> String {
+    let join = |v: &[BigDecimal]| {
+        v.iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    if values.len() <= 6 {
+        format!("[{}]", join(values))
+    } else {
+        format!(
+            "[{}, ..., {}] ({} values)",
+            join(&values[..3]),
+            join(&values[values.len() - 2..]),
+            values.len()
+        )
+    }
+}
+
+/// idx番目のtokenが指すリスト変数の名前と値
+fn list_arg(
+    parser: &Parser,
+    idx: usize,
+    vars: &HashMap<String, Value>,
+) -> Result<(String, Vec<BigDecimal>), MyError> {
+    let t = parser.get_token(idx);
+    if t.token_kind != TokenKind::TkVariable {
+        return Err(MyError::NotTkVariable(
+            t.token_kind.to_string(),
+            parser.format_err_loc_idx(idx),
+        ));
+    }
+    match vars.get(&t.token) {
+        Some(Value::List(values)) => Ok((t.token.clone(), values.clone())),
+        _ => Err(MyError::UDlistErr(
+            t.token.clone(),
+            parser.format_err_loc_idx(idx),
+        )),
+    }
+}
+
+/// :hist {list} {bins}
+/// 要約統計量と度数分布を表示する. binsを省略するとSturgesの公式で決める
+/// 消費したtoken数を返す
+pub fn hist_cmd(
+    parser: &Parser,
+    cmd_idx: usize,
+    vars: &HashMap<String, Value>,
+) -> Result<usize, MyError> {
+    let (_, len) = parser.cmd_arg_form(cmd_idx + 1)?;
+    let end = cmd_idx + 1 + len;
+    let (name, data) = list_arg(parser, cmd_idx + 1, vars)?;
+    let conf = config_reader()?.clone();
+    let bins = if cmd_idx + 2 < end {
+        let form = parser.tokens_form(cmd_idx + 2, end - cmd_idx - 2)?;
+        let n = eval_form(&form, vars, &conf)?;
+        n.to_usize()
+            .filter(|n| (1..=MAX_BINS).contains(n))
+            .ok_or_else(|| {
+                MyError::InvalidInput(format!("number of bins must be 1 to {MAX_BINS} but {n}"))
+            })?
+    } else {
+        sturges(data.len())
+    };
+    let fmt = |v: &BigDecimal| num_formatter(v, conf.num_of_digit);
+    let fmt_f64 = |x: f64| round_f64(x).map_or(x.to_string(), |v| fmt(&v));
+
+    let mut summary = vec![format!("n = {}", data.len())];
+    for (label, f) in [
+        ("min", ListFn::Min),
+        ("median", ListFn::Median),
+        ("max", ListFn::Max),
+        ("mean", ListFn::Mean),
+        ("sd", ListFn::Sd),
+    ] {
+        // 値が1つのときのsdは省く
+        if let Ok(v) = f.eval(&data) {
+            summary.push(format!("{label} = {}", fmt(&v)));
+        }
+    }
+    println!("{name}: {}", summary.join(", "));
+
+    let xs: Vec<f64> = data
+        .iter()
+        .map(|v| v.to_f64().unwrap_or(f64::NAN))
+        .collect();
+    let hist = histogram(&xs, bins);
+    let labels: Vec<String> = hist
+        .iter()
+        .enumerate()
+        .map(|(i, (lo, hi, _))| {
+            let close = if i + 1 == hist.len() { ']' } else { ')' };
+            format!("[{}, {}{close}", fmt_f64(*lo), fmt_f64(*hi))
+        })
+        .collect();
+    let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+    let max = hist.iter().map(|(_, _, c)| *c).max().unwrap_or(0).max(1);
+    let count_width = max.to_string().len();
+    for (label, (_, _, count)) in labels.iter().zip(&hist) {
+        let bar = "█".repeat((count * BAR_WIDTH).div_ceil(max));
+        println!("{label:<label_width$} {count:>count_width$} {bar}");
+    }
+    Ok(len + 1)
+}
+
+/// :fit {linear|poly n|exp} {xs} {ys}
+/// 最小二乗法でysをxsのモデルにあてはめ, 係数と決定係数を表示する
+/// 消費したtoken数を返す
+pub fn fit_cmd(
+    parser: &Parser,
+    cmd_idx: usize,
+    vars: &HashMap<String, Value>,
+) -> Result<usize, MyError> {
+    let (_, len) = parser.cmd_arg_form(cmd_idx + 1)?;
+    let end = cmd_idx + 1 + len;
+    let mut idx = cmd_idx + 1;
+    let t = parser.get_token(idx);
+    let model = match &*t.token {
+        "linear" => Model::Poly(1),
+        "exp" => Model::Exp,
+        "poly" => {
+            idx += 1;
+            let d = parser.get_token(idx);
+            let deg = match d.token_kind {
+                TokenKind::TkNum(f) => u64_from_str(f, &d.token)?,
+                _ => {
+                    return Err(MyError::NotTkNumber(
+                        d.token_kind.to_string(),
+                        parser.format_err_loc_idx(idx),
+                    ));
+                }
+            };
+            if deg > MAX_DEGREE {
+                return Err(MyError::InvalidInput(format!(
+                    "degree must be at most {MAX_DEGREE} but {deg}"
+                )));
+            }
+            Model::Poly(deg as usize)
+        }
+        _ => {
+            return Err(MyError::UnexpectedInput(
+                "linear/poly/exp".to_string(),
+                t.token.clone(),
+            ));
+        }
+    };
+    let (x, xs) = list_arg(parser, idx + 1, vars)?;
+    let (y, ys) = list_arg(parser, idx + 2, vars)?;
+    if idx + 3 < end {
+        return Err(MyError::UnprocessedToekn(
+            parser.get_token(idx + 3).token.clone(),
+            parser.format_err_loc_idx(idx + 3),
+        ));
+    }
+    let res = fit(model, &xs, &ys)?;
+
+    // 直線と指数関数は係数をa, bとする
+    let (names, rhs) = match model {
+        Model::Poly(1) => (vec!["a".to_string(), "b".to_string()], format!("a + b {x}")),
+        Model::Exp => (
+            vec!["a".to_string(), "b".to_string()],
+            format!("a e^{{b {x}}}"),
+        ),
+        Model::Poly(n) => (
+            (0..=n).map(|k| format!("a_{{{k}}}")).collect(),
+            (0..=n)
+                .map(|k| match k {
+                    0 => "a_{0}".to_string(),
+                    1 => format!("a_{{1}} {x}"),
+                    _ => format!("a_{{{k}}} {x}^{{{k}}}"),
+                })
+                .collect::<Vec<String>>()
+                .join(" + "),
+        ),
+    };
+    let conf = config_reader()?;
+    println!("{y} = {rhs}");
+    for (name, c) in names.iter().zip(&res.coefs) {
+        println!("{name} = {}", num_formatter(c, conf.num_of_digit));
+    }
+    println!("R^{{2}} = {}", num_formatter(&res.r2, conf.num_of_digit));
+    Ok(len + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let dec = |s: &str| BigDecimal::from_str(s).unwrap();
+        let cols = parse_csv("time (s),\"x, m\"\n0,1.5\n\n1,-2e1\n", "run", None).unwrap();
+        assert_eq!(cols[0], ("times".to_string(), vec![dec("0"), dec("1")]));
+        assert_eq!(cols[1], ("xm".to_string(), vec![dec("1.5"), dec("-20")]));
+        let cols = parse_csv("1,2\n3,4\n", "my-data", None).unwrap();
+        assert_eq!(cols[0].0, "mydata1");
+        assert_eq!(cols[1], ("mydata2".to_string(), vec![dec("2"), dec("4")]));
+        let cols = parse_csv("v\n1\n", "d", Some("w")).unwrap();
+        assert_eq!(cols[0].0, "w");
+        assert_eq!(
+            parse_csv("a,b\n1,2\n3\n", "d", None).unwrap_err(),
+            "line 3: expected 2 field(s) but 1"
+        );
+        assert_eq!(
+            parse_csv("1,2\n3,x\n", "d", None).unwrap_err(),
+            "line 2: 'x' is not a number"
+        );
+        assert!(parse_csv("a,a\n1,2\n", "d", None).is_err());
+        assert!(parse_csv("a\n", "d", None).is_err());
+    }
+
+    #[test]
+    fn test_format_list() {
+        let list: Vec<BigDecimal> = (1..=10).map(BigDecimal::from).collect();
+        assert_eq!(format_list(&list[..3]), "[1, 2, 3]");
+        assert_eq!(format_list(&list), "[1, 2, 3, ..., 9, 10] (10 values)");
+    }
+}
@@ -1,18 +1,105 @@
-pub fn gcd(mut nums: Vec<u64>) -> u64 {
-    assert!(nums.len() > 1);
-    nums.sort_by(|a, b| b.cmp(a));
-    let mut a = nums.pop().unwrap();
-    for n in nums.iter().rev() {
-        a = calc_gcd(*n, a);
-    }
-    a
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Signed, Zero};
+
+pub fn gcd(nums: &[BigInt]) -> BigInt {
+    nums.iter().fold(BigInt::zero(), |a, n| a.gcd(n))
+}
+
+pub fn lcm(nums: &[BigInt]) -> BigInt {
+    nums.iter().fold(BigInt::one(), |a, n| a.lcm(n))
 }
 
-fn calc_gcd(a: u64, b: u64) -> u64 {
-    assert!(a >= b);
-    if b == 0 {
-        a
+/// mを法とするaの逆元. m > 0. 無ければNone
+pub fn mod_inv(a: &BigInt, m: &BigInt) -> Option<BigInt> {
+    let e = a.mod_floor(m).extended_gcd(m);
+    e.gcd.is_one().then(|| e.x.mod_floor(m))
+}
+
+/// a^e mod m. m > 0. 指数が負なら逆元の累乗で, 逆元が無ければNone
+pub fn mod_pow(a: &BigInt, e: &BigInt, m: &BigInt) -> Option<BigInt> {
+    let base = if e.is_negative() {
+        mod_inv(a, m)?
     } else {
-        calc_gcd(b, a % b)
+        a.mod_floor(m)
+    };
+    Some(base.modpow(&e.abs(), m))
+}
+
+/// 連立合同式 x ≡ r_i (mod m_i) の解 (r, lcm(m_i)). m_i > 0
+/// 法は互いに素でなくてもよい. 解が無ければ, 矛盾する式の番号をErrで返す
+pub fn crt(congruences: &[(BigInt, BigInt)]) -> Result<(BigInt, BigInt), usize> {
+    let mut r = BigInt::zero();
+    let mut m = BigInt::one();
+    for (i, (ri, mi)) in congruences.iter().enumerate() {
+        // r + m t ≡ ri (mod mi) をtについて解く
+        let g = m.gcd(mi);
+        let diff = ri - &r;
+        if !(&diff % &g).is_zero() {
+            return Err(i);
+        }
+        let mi_g = mi / &g;
+        // m / g と mi / g は互いに素なので逆元がある
+        let Some(inv) = mod_inv(&(&m / &g), &mi_g) else {
+            return Err(i);
+        };
+        let t = (diff / &g * inv).mod_floor(&mi_g);
+        r += &m * t;
+        m *= mi_g;
+        r = r.mod_floor(&m);
+    }
+    Ok((r, m))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ints(v: &[i64]) -> Vec<BigInt> {
+        v.iter().map(|n| BigInt::from(*n)).collect()
+    }
+
+    #[test]
+    fn test_gcd() {
+        assert_eq!(gcd(&ints(&[12, 42, 66])), BigInt::from(6));
+        assert_eq!(gcd(&ints(&[-4, 6])), BigInt::from(2));
+        assert_eq!(lcm(&ints(&[4, 6, 10])), BigInt::from(60));
+        assert_eq!(
+            mod_inv(&BigInt::from(3), &BigInt::from(7)),
+            Some(BigInt::from(5))
+        );
+        assert_eq!(
+            mod_inv(&BigInt::from(-3), &BigInt::from(7)),
+            Some(BigInt::from(2))
+        );
+        assert_eq!(mod_inv(&BigInt::from(4), &BigInt::from(10)), None);
+        let m = BigInt::from(1_000_000_007);
+        assert_eq!(
+            mod_pow(&BigInt::from(2), &BigInt::from(1_000_000_006), &m),
+            Some(BigInt::one())
+        );
+        assert_eq!(
+            mod_pow(&BigInt::from(3), &BigInt::from(-1), &BigInt::from(7)),
+            Some(BigInt::from(5))
+        );
+    }
+
+    #[test]
+    fn test_crt() {
+        let pairs = |v: &[(i64, i64)]| -> Vec<(BigInt, BigInt)> {
+            v.iter()
+                .map(|(r, m)| (BigInt::from(*r), BigInt::from(*m)))
+                .collect()
+        };
+        assert_eq!(
+            crt(&pairs(&[(2, 3), (3, 5), (2, 7)])),
+            Ok((BigInt::from(23), BigInt::from(105)))
+        );
+        // 互いに素でない法
+        assert_eq!(
+            crt(&pairs(&[(3, 4), (5, 6)])),
+            Ok((BigInt::from(11), BigInt::from(12)))
+        );
+        assert_eq!(crt(&pairs(&[(1, 4), (2, 6)])), Err(1));
     }
 }
@@ -0,0 +1,208 @@
+use num_bigint::BigInt;
+use num_traits::{Signed, Zero};
+use std::collections::HashMap;
+
+use crate::config::*;
+use crate::error::*;
+use crate::parser::Parser;
+use crate::rational::ExactEval;
+use crate::tokenizer::TokenKind;
+use crate::Value;
+
+use super::gcd;
+use super::prime_factorize::{self, Primality, MAX_DIVISORS};
+use super::split_commas;
+
+/// 整数を引数にとるコマンド
+pub const INTEGER_CMDS: [&str; 12] = [
+    ":fact",
+    ":gcd",
+    ":lcm",
+    ":redu",
+    ":isprime",
+    ":nextprime",
+    ":totient",
+    ":divisors",
+    ":modinv",
+    ":modpow",
+    ":crt",
+    ":seed",
+];
+
+/// :fact, :gcd等 {integer}, {integer}, ...
+/// 引数は , で区切った式で, 値は整数であること. 空白で区切った数の並びもそれぞれを引数とする
+/// 消費したtoken数を返す
+pub fn integer_cmd(
+    parser: &Parser,
+    cmd_idx: usize,
+    vars: &mut HashMap<String, Value>,
+) -> Result<usize, MyError> {
+    let cmd = parser.get_token(cmd_idx).token.clone();
+    let (_, len) = parser.cmd_arg_form(cmd_idx + 1)?;
+    let nums = int_args(parser, cmd_idx + 1, cmd_idx + 1 + len, vars)?;
+    let arg_count = |expected: &str, ok: bool| {
+        if ok {
+            Ok(())
+        } else {
+            Err(MyError::ArgCountErr(
+                cmd.clone(),
+                expected.to_string(),
+                nums.len(),
+                parser.format_err_loc_idx(cmd_idx),
+            ))
+        }
+    };
+    let join = |sep: &str, nums: &[BigInt]| {
+        nums.iter()
+            .map(BigInt::to_string)
+            .collect::<Vec<_>>()
+            .join(sep)
+    };
+    match &*cmd {
+        ":gcd" | ":lcm" | ":redu" => {
+            arg_count("2 or more", nums.len() >= 2)?;
+            if cmd == ":redu" && nums.iter().any(Zero::is_zero) {
+                return Err(MyError::InvalidInput(format!("{cmd} don't allow 0")));
+            }
+            match &*cmd {
+                ":gcd" => println!("gcd({}) = {}", join(", ", &nums), gcd::gcd(&nums)),
+                ":lcm" => println!("lcm({}) = {}", join(", ", &nums), gcd::lcm(&nums)),
+                _ => {
+                    let g = gcd::gcd(&nums);
+                    let reduced: Vec<BigInt> = nums.iter().map(|n| n / &g).collect();
+                    println!("{} = {}", join(" : ", &nums), join(" : ", &reduced));
+                }
+            }
+        }
+        ":modinv" => {
+            arg_count("2", nums.len() == 2)?;
+            let (a, m) = (&nums[0], positive_modulus(&nums[1])?);
+            let inv = gcd::mod_inv(a, m)
+                .ok_or_else(|| MyError::InvalidInput(format!("{a} has no inverse modulo {m}")))?;
+            println!("{}^{{-1}} \\equiv {inv} \\pmod{{{m}}}", paren_neg(a));
+        }
+        ":modpow" => {
+            arg_count("3", nums.len() == 3)?;
+            let (a, e, m) = (&nums[0], &nums[1], positive_modulus(&nums[2])?);
+            let res = gcd::mod_pow(a, e, m)
+                .ok_or_else(|| MyError::InvalidInput(format!("{a} has no inverse modulo {m}")))?;
+            println!("{}^{{{e}}} \\equiv {res} \\pmod{{{m}}}", paren_neg(a));
+        }
+        ":crt" => {
+            arg_count("2, 4, 6, ...", !nums.is_empty() && nums.len() % 2 == 0)?;
+            let mut congruences = Vec::new();
+            for pair in nums.chunks(2) {
+                congruences.push((pair[0].clone(), positive_modulus(&pair[1])?.clone()));
+            }
+            let (r, m) = gcd::crt(&congruences).map_err(|i| MyError::InconsistentSystem(i + 1))?;
+            println!("x \\equiv {r} \\pmod{{{m}}}");
+        }
+        ":seed" => {
+            arg_count("1", nums.len() == 1)?;
+            let seed = u64::try_from(&nums[0]).map_err(|_| {
+                MyError::InvalidInput(format!("seed must be in 0..2^{{64}} but {}", nums[0]))
+            })?;
+            crate::distributions::seed(seed);
+        }
+        _ => {
+            arg_count("1", nums.len() == 1)?;
+            let n = &nums[0];
+            match &*cmd {
+                ":fact" => println!("{}", prime_factorize::factorize(n)?),
+                ":isprime" => match prime_factorize::is_prime(n) {
+                    Primality::Prime => println!("{n} is prime"),
+                    Primality::ProbablePrime => println!("{n} is probably prime"),
+                    Primality::Composite => println!("{n} is not prime"),
+                },
+                ":nextprime" => {
+                    let (p, primality) = prime_factorize::next_prime(n);
+                    let note = if primality == Primality::ProbablePrime {
+                        " (probably prime)"
+                    } else {
+                        ""
+                    };
+                    println!("nextprime({n}) = {p}{note}");
+                }
+                ":totient" => {
+                    if !n.is_positive() {
+                        return Err(MyError::InvalidInput(format!(
+                            "{cmd} requires a positive integer but {n}"
+                        )));
+                    }
+                    println!(
+                        "\\varphi({n}) = {}",
+                        prime_factorize::factorize(n)?.totient()
+                    );
+                }
+                _ => {
+                    // :divisors
+                    let divisors = prime_factorize::factorize(n)?.divisors().ok_or_else(|| {
+                        MyError::InvalidInput(format!("{n} has more than {MAX_DIVISORS} divisors"))
+                    })?;
+                    println!("divisors({n}) = {}", join(", ", &divisors));
+                }
+            }
+        }
+    }
+    Ok(len + 1)
+}
+
+/// start..endの引数を整数として評価する
+fn int_args(
+    parser: &Parser,
+    start: usize,
+    end: usize,
+    vars: &HashMap<String, Value>,
+) -> Result<Vec<BigInt>, MyError> {
+    let conf = config_reader()?.clone();
+    let mut pieces = Vec::new();
+    for (s, e) in split_commas(parser, start, end) {
+        // :gcd 12 42 66 のような数の並び
+        let is_literal = |i: usize| matches!(parser.get_token(i).token_kind, TokenKind::TkNum(_));
+        if e - s > 1 && (s..e).all(is_literal) {
+            pieces.extend((s..e).map(|i| (i, i + 1)));
+        } else {
+            pieces.push((s, e));
+        }
+    }
+    let mut nums = Vec::new();
+    for (s, e) in pieces {
+        let form = parser.tokens_form(s, e - s)?;
+        let (ast, root) = Parser::new(form.clone(), false)?.build_expr()?;
+        let exact = ExactEval {
+            ast: &ast,
+            form: &form,
+            vars,
+            conf: &conf,
+        };
+        let value = exact.value(root)?;
+        if !value.is_integer() {
+            return Err(MyError::NotIntegerErr(
+                value.to_tex(),
+                parser.format_err_loc_idx(s),
+            ));
+        }
+        nums.push(value.numer().clone());
+    }
+    Ok(nums)
+}
+
+/// 法は正であること
+fn positive_modulus(m: &BigInt) -> Result<&BigInt, MyError> {
+    if m.is_positive() {
+        Ok(m)
+    } else {
+        Err(MyError::InvalidInput(format!(
+            "modulus must be positive but {m}"
+        )))
+    }
+}
+
+/// 負の数を累乗の底にするときは括弧で囲む
+fn paren_neg(n: &BigInt) -> String {
+    if n.is_negative() {
+        format!("({n})")
+    } else {
+        n.to_string()
+    }
+}
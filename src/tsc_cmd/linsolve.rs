@@ -0,0 +1,390 @@
+use num_traits::{One, Zero};
+use std::collections::HashMap;
+
+use crate::ast::{BinaryOp, NodeId, NodeKind, UnaryOp};
+use crate::compile::Program;
+use crate::config::*;
+use crate::domain::domain_err;
+use crate::error::*;
+use crate::parser::{format_err_span, Parser};
+use crate::rational::{sum_to_tex, ExactEval, Rational};
+use crate::tokenizer::TokenKind;
+use crate::{Value, CONSTS};
+
+use super::format_rational;
+
+/// :linsolve {lhs} = {rhs}; {lhs} = {rhs}; ... bind
+/// 定義されていない変数を未知数として連立1次方程式を有理数で厳密に解く
+/// 方程式は;で区切り, 行末か次のTSCコマンドまでを読む. bindを付けると解を変数に代入する
+/// 消費したtoken数を返す
+pub fn linsolve_cmd(
+    parser: &Parser,
+    cmd_idx: usize,
+    vars: &mut HashMap<String, Value>,
+) -> Result<usize, MyError> {
+    let start = cmd_idx + 1;
+    let mut end = start;
+    while !matches!(
+        parser.get_token(end).token_kind,
+        TokenKind::TkTscCommand | TokenKind::TkEOT
+    ) {
+        end += 1;
+    }
+    let last = parser.get_token(end.saturating_sub(1));
+    let bind =
+        end - 1 > start && matches!(last.token_kind, TokenKind::TkVariable) && last.token == "bind";
+    let eqs_end = if bind { end - 1 } else { end };
+
+    let mut equations = Vec::new();
+    let mut from = start;
+    for i in start..=eqs_end {
+        if i == eqs_end || matches!(parser.get_token(i).token_kind, TokenKind::TkSeparaotr) {
+            if i > from {
+                equations.push(split_equation(parser, from, i)?);
+            }
+            from = i + 1;
+        }
+    }
+    if equations.is_empty() {
+        return Err(MyError::NoToken);
+    }
+
+    let conf = config_reader()?.clone();
+    let system = System::new(&equations, vars, &conf)?;
+    let solution = system.solve()?;
+    for (name, val) in system.unknowns.iter().zip(&solution) {
+        println!("{name} = {}", format_rational(val, conf.num_of_digit));
+    }
+    if bind {
+        for (name, val) in system.unknowns.iter().zip(&solution) {
+            vars.insert(name.clone(), val.to_bigdecimal().into());
+        }
+    }
+    Ok(end - cmd_idx)
+}
+
+/// from..toのtokenの方程式を左辺と右辺に分ける
+fn split_equation(parser: &Parser, from: usize, to: usize) -> Result<(String, String), MyError> {
+    let eqs: Vec<usize> = (from..to)
+        .filter(|&i| {
+            let t = parser.get_token(i);
+            matches!(t.token_kind, TokenKind::TkOperator) && t.token == "="
+        })
+        .collect();
+    match eqs.as_slice() {
+        [eq] => Ok((
+            parser.tokens_form(from, eq - from)?,
+            parser.tokens_form(eq + 1, to - eq - 1)?,
+        )),
+        [] => Err(MyError::UnexpectedToken(
+            "'='".to_string(),
+            "end of equation".to_string(),
+            parser.format_err_loc_idx(to - 1),
+        )),
+        [_, eq, ..] => Err(MyError::UnexpectedToken(
+            "end of equation".to_string(),
+            "'='".to_string(),
+            parser.format_err_loc_idx(*eq),
+        )),
+    }
+}
+
+/// 未知数の1次式 Σ coefs[i] x_i + constant
+#[derive(Clone, Debug)]
+struct Linear {
+    coefs: Vec<Rational>,
+    constant: Rational,
+}
+
+impl Linear {
+    fn constant(n: usize, constant: Rational) -> Linear {
+        Linear {
+            coefs: vec![Rational::zero(); n],
+            constant,
+        }
+    }
+
+    fn combine(self, rhs: Linear, op: BinaryOp) -> Linear {
+        let f = |a: &Rational, b: &Rational| {
+            if op == BinaryOp::Add {
+                a + b
+            } else {
+                a - b
+            }
+        };
+        Linear {
+            coefs: self
+                .coefs
+                .iter()
+                .zip(&rhs.coefs)
+                .map(|(a, b)| f(a, b))
+                .collect(),
+            constant: f(&self.constant, &rhs.constant),
+        }
+    }
+
+    fn scale(self, k: &Rational) -> Linear {
+        Linear {
+            coefs: self.coefs.iter().map(|c| c * k).collect(),
+            constant: &self.constant * k,
+        }
+    }
+}
+
+/// 1つの辺の式から係数を取り出す
+struct Linearizer<'a> {
+    unknowns: &'a [String],
+    exact: ExactEval<'a>,
+}
+
+impl Linearizer<'_> {
+    fn has_unknown(&self, id: NodeId) -> bool {
+        match &self.exact.ast[id].kind {
+            NodeKind::Var(v) => self.unknowns.contains(v),
+            _ => self.exact.ast.children(id).any(|c| self.has_unknown(c)),
+        }
+    }
+
+    fn non_linear(&self, id: NodeId) -> MyError {
+        MyError::NonLinearErr(
+            self.unknowns.join(", "),
+            format_err_span(self.exact.form, self.exact.ast[id].span),
+        )
+    }
+
+    /// idの式を未知数の1次式にする
+    fn linear(&self, id: NodeId) -> Result<Linear, MyError> {
+        let n = self.unknowns.len();
+        if !self.has_unknown(id) {
+            return Ok(Linear::constant(n, self.exact.value(id)?));
+        }
+        match &self.exact.ast[id].kind {
+            NodeKind::Var(v) => {
+                let mut l = Linear::constant(n, Rational::zero());
+                if let Some(i) = self.unknowns.iter().position(|u| u == v) {
+                    l.coefs[i] = Rational::one();
+                }
+                Ok(l)
+            }
+            NodeKind::Unary(UnaryOp::Neg, arg) => {
+                Ok(self.linear(*arg)?.scale(&Rational::from_int(-1)))
+            }
+            NodeKind::Binary(op @ (BinaryOp::Add | BinaryOp::Sub), lhs, rhs) => {
+                Ok(self.linear(*lhs)?.combine(self.linear(*rhs)?, *op))
+            }
+            NodeKind::Binary(BinaryOp::Mul, lhs, rhs) if !self.has_unknown(*lhs) => {
+                Ok(self.linear(*rhs)?.scale(&self.exact.value(*lhs)?))
+            }
+            NodeKind::Binary(BinaryOp::Mul, lhs, rhs) if !self.has_unknown(*rhs) => {
+                Ok(self.linear(*lhs)?.scale(&self.exact.value(*rhs)?))
+            }
+            NodeKind::Binary(BinaryOp::Div, lhs, rhs) if !self.has_unknown(*rhs) => {
+                match self.exact.value(*rhs)?.recip() {
+                    Some(k) => Ok(self.linear(*lhs)?.scale(&k)),
+                    None => Err(domain_err(
+                        &self.exact.ast[id],
+                        self.exact.form,
+                        "divisor 0".to_string(),
+                    )),
+                }
+            }
+            NodeKind::Binary(BinaryOp::Pow, lhs, rhs)
+                if !self.has_unknown(*rhs) && self.exact.value(*rhs)?.is_one() =>
+            {
+                self.linear(*lhs)
+            }
+            _ => Err(self.non_linear(id)),
+        }
+    }
+}
+
+/// 連立1次方程式 a x = b
+struct System {
+    unknowns: Vec<String>,
+    a: Vec<Vec<Rational>>,
+    b: Vec<Rational>,
+}
+
+impl System {
+    fn new(
+        equations: &[(String, String)],
+        vars: &HashMap<String, Value>,
+        conf: &Config,
+    ) -> Result<System, MyError> {
+        let mut sides = Vec::new();
+        for (lhs, rhs) in equations {
+            for form in [lhs, rhs] {
+                let (ast, root) = Parser::new(form.to_string(), false)?.build_expr()?;
+                sides.push((form, ast, root));
+            }
+        }
+        // 定義されていない変数を出現順に未知数にする
+        let mut unknowns: Vec<String> = Vec::new();
+        {
+            let consts = match CONSTS.read() {
+                Ok(consts) => consts,
+                Err(e) => return Err(MyError::ConstsReadErr(e.to_string())),
+            };
+            for (form, ast, root) in &sides {
+                for v in Program::compile(ast, *root, form).slots() {
+                    if !vars.contains_key(v) && !consts.contains_key(v) && !unknowns.contains(v) {
+                        unknowns.push(v.clone());
+                    }
+                }
+            }
+        }
+        if unknowns.is_empty() {
+            return Err(MyError::InvalidInput(
+                "no undefined variable to solve for".to_string(),
+            ));
+        }
+        let mut a = Vec::new();
+        let mut b = Vec::new();
+        for pair in sides.chunks(2) {
+            let mut linear = pair.iter().map(|(form, ast, root)| {
+                Linearizer {
+                    unknowns: &unknowns,
+                    exact: ExactEval {
+                        ast,
+                        form,
+                        vars,
+                        conf,
+                    },
+                }
+                .linear(*root)
+            });
+            let (lhs, rhs) = (linear.next().unwrap()?, linear.next().unwrap()?);
+            // lhs - rhs = 0 を a x = b の形にする
+            let row = lhs.combine(rhs, BinaryOp::Sub);
+            a.push(row.coefs);
+            b.push(-row.constant);
+        }
+        Ok(System { unknowns, a, b })
+    }
+
+    /// Gauss-Jordanの消去法で解く
+    /// 解が1つに決まらないときは, 矛盾する方程式か自由変数をエラーで返す
+    fn solve(&self) -> Result<Vec<Rational>, MyError> {
+        let n = self.unknowns.len();
+        let mut a = self.a.clone();
+        let mut b = self.b.clone();
+        // 各行が元の何番目の方程式か
+        let mut origin: Vec<usize> = (0..a.len()).collect();
+        let mut pivots = Vec::new();
+        for col in 0..n {
+            let row = pivots.len();
+            let Some(p) = (row..a.len()).find(|&r| !a[r][col].is_zero()) else {
+                continue;
+            };
+            a.swap(row, p);
+            b.swap(row, p);
+            origin.swap(row, p);
+            let k = a[row][col].recip().unwrap();
+            a[row] = a[row].iter().map(|v| v * &k).collect();
+            b[row] = &b[row] * &k;
+            for r in 0..a.len() {
+                if r == row || a[r][col].is_zero() {
+                    continue;
+                }
+                let f = a[r][col].clone();
+                a[r] = a[r]
+                    .iter()
+                    .zip(&a[row])
+                    .map(|(v, p)| v - &(p * &f))
+                    .collect();
+                b[r] = &b[r] - &(&b[row] * &f);
+            }
+            pivots.push(col);
+        }
+        // 係数が全て0になった行は右辺も0でなければならない
+        if let Some(r) = (pivots.len()..a.len()).find(|&r| !b[r].is_zero()) {
+            return Err(MyError::InconsistentSystem(origin[r] + 1));
+        }
+        let free: Vec<usize> = (0..n).filter(|c| !pivots.contains(c)).collect();
+        if !free.is_empty() {
+            // 自由変数で表した一般解
+            let general = pivots
+                .iter()
+                .enumerate()
+                .map(|(row, &col)| {
+                    let mut terms: Vec<(Rational, String)> = free
+                        .iter()
+                        .map(|&f| (-&a[row][f], self.unknowns[f].clone()))
+                        .collect();
+                    terms.push((b[row].clone(), String::new()));
+                    format!("{} = {}", self.unknowns[col], sum_to_tex(&terms))
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+            let free = free
+                .iter()
+                .map(|&f| self.unknowns[f].as_str())
+                .collect::<Vec<&str>>()
+                .join(", ");
+            return Err(MyError::UnderdeterminedSystem(free, general));
+        }
+        Ok(b[..n].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use num_bigint::BigInt;
+
+    fn solve(equations: &[(&str, &str)]) -> Result<Vec<Rational>, MyError> {
+        let equations: Vec<(String, String)> = equations
+            .iter()
+            .map(|(l, r)| (l.to_string(), r.to_string()))
+            .collect();
+        let mut vars = HashMap::new();
+        vars.insert("R".to_string(), BigDecimal::from(4).into());
+        System::new(&equations, &vars, &Config::default())?.solve()
+    }
+
+    fn r(num: i64, den: i64) -> Rational {
+        Rational::new(BigInt::from(num), BigInt::from(den))
+    }
+
+    #[test]
+    fn test_linsolve() {
+        assert_eq!(
+            solve(&[("2x + 3y", "5"), ("x - y", "1")]).unwrap(),
+            [r(8, 5), r(3, 5)]
+        );
+        // 定義済みの変数は係数になる
+        assert_eq!(
+            solve(&[("\\frac{x}{R} + 0.5y", "1"), ("y", "-(x - 3)")]).unwrap(),
+            [r(2, 1), r(1, 1)]
+        );
+        assert_eq!(
+            solve(&[("2^{-1} a", "b - 1"), ("3(a - b)", "0")]).unwrap(),
+            [r(2, 1), r(2, 1)]
+        );
+    }
+
+    #[test]
+    fn test_linsolve_err() {
+        assert!(matches!(
+            solve(&[("x + y", "1"), ("2x + 2y", "3")]),
+            Err(MyError::InconsistentSystem(2))
+        ));
+        match solve(&[("x + 2z", "3"), ("y - z", "1")]) {
+            Err(MyError::UnderdeterminedSystem(free, general)) => {
+                // 後に出てくる未知数が自由変数になる
+                assert_eq!(free, "y");
+                assert_eq!(general, "x = -2y + 5\nz = y - 1");
+            }
+            r => panic!("expected UnderdeterminedSystem but {r:?}"),
+        }
+        assert!(matches!(
+            solve(&[("x y", "1"), ("x", "2")]),
+            Err(MyError::NonLinearErr(..))
+        ));
+        assert!(matches!(
+            solve(&[("\\frac{x}{R - 4}", "1")]),
+            Err(MyError::DomainErr(..))
+        ));
+    }
+}
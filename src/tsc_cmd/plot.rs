@@ -0,0 +1,611 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use text_colorizer::*;
+
+use crate::compile::Program;
+use crate::config::*;
+use crate::error::*;
+use crate::parser::Parser;
+use crate::tokenizer::TokenKind;
+use crate::Value;
+
+use super::solve::{eval_bound, Func};
+use super::{find_option, only_unknown, option_arg, split_commas};
+
+/// 区間を指定しないときに描く区間
+const DEFAULT_RANGE: (f64, f64) = (-10.0, 10.0);
+/// 度数法で区間を指定しないときは三角関数の1周期を描く
+const DEFAULT_DEGREE_RANGE: (f64, f64) = (0.0, 360.0);
+/// 区間を分ける数
+const SAMPLES: usize = 1000;
+/// 端末に描くときの文字数
+const WIDTH: usize = 64;
+const HEIGHT: usize = 16;
+/// SVGの大きさと余白
+const SVG_WIDTH: f64 = 640.0;
+const SVG_HEIGHT: f64 = 400.0;
+const SVG_MARGIN: f64 = 50.0;
+/// 関数ごとの色. 端末とSVGで順番を揃える
+const COLORS: [(Color, &str); 6] = [
+    (Color::Green, "#2ca02c"),
+    (Color::Cyan, "#17becf"),
+    (Color::Magenta, "#e377c2"),
+    (Color::Yellow, "#bcbd22"),
+    (Color::Blue, "#1f77b4"),
+    (Color::Red, "#d62728"),
+];
+
+/// :plot {f}, {g}, ... {var} from {a} to {b} --svg {file}
+/// 変数は関数に未定義の変数が1つだけなら, 区間は省略できる
+/// --svgを付けると端末に描く代わりにSVGのファイルに書き出す
+/// 消費したtoken数を返す
+pub fn plot_cmd(
+    parser: &Parser,
+    cmd_idx: usize,
+    vars: &mut HashMap<String, Value>,
+) -> Result<usize, MyError> {
+    let (_, len) = parser.cmd_arg_form(cmd_idx + 1)?;
+    let end = cmd_idx + 1 + len;
+    let start = cmd_idx + 1;
+    let is_kind = |i: usize, kind: TokenKind, word: &str| {
+        let t = parser.get_token(i);
+        t.token_kind == kind && t.token == word
+    };
+    let is_word = |i: usize, word: &str| is_kind(i, TokenKind::TkVariable, word);
+    let svg = find_option(parser, start, end, &["svg"]).map(|(i, _)| i);
+    let args_end = svg.unwrap_or(end);
+    let from = (start..args_end).find(|&i| is_word(i, "from"));
+    let mut funcs_end = from.unwrap_or(args_end);
+    // from の直前の変数は, 関数に含まれていれば描く変数とみなす
+    let mut var = None;
+    if from.is_some() && funcs_end - 1 > start {
+        let t = parser.get_token(funcs_end - 1);
+        if t.token_kind == TokenKind::TkVariable
+            && (start..funcs_end - 1).any(|i| is_word(i, &t.token))
+        {
+            var = Some(t.token.clone());
+            funcs_end -= 1;
+        }
+    }
+    let mut forms = Vec::new();
+    for (piece, i) in split_commas(parser, start, funcs_end) {
+        forms.push(parser.tokens_form(piece, i - piece)?);
+    }
+    let path = match svg {
+        Some(i) => Some(option_arg(parser, i, end).ok_or_else(|| {
+            MyError::UnexpectedToken(
+                "file name".to_string(),
+                "end of command".to_string(),
+                parser.format_err_loc_idx(i + 2),
+            )
+        })?),
+        None => None,
+    };
+
+    let mut progs = Vec::new();
+    for form in &forms {
+        let (ast, root) = Parser::new(form.clone(), false)?.build_expr()?;
+        progs.push(Program::compile(&ast, root, form));
+    }
+    let var = match var {
+        Some(var) => var,
+        None => {
+            let mut names: Vec<String> = Vec::new();
+            for name in progs.iter().flat_map(|p| p.slots()) {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+            only_unknown(&names, vars)?
+        }
+    };
+    let conf = config_reader()?.clone();
+    let range = match from {
+        Some(from) => {
+            let to = (from + 1..args_end)
+                .find(|&i| is_word(i, "to"))
+                .ok_or_else(|| {
+                    MyError::UnexpectedToken(
+                        "'to'".to_string(),
+                        "end of command".to_string(),
+                        parser.format_err_loc_idx(from),
+                    )
+                })?;
+            let lo = parser.tokens_form(from + 1, to - from - 1)?;
+            let hi = parser.tokens_form(to + 1, args_end - to - 1)?;
+            (eval_bound(&lo, vars, &conf)?, eval_bound(&hi, vars, &conf)?)
+        }
+        None => match conf.trig_func_arg {
+            TrigFuncArg::Degree => DEFAULT_DEGREE_RANGE,
+            TrigFuncArg::Radian => DEFAULT_RANGE,
+        },
+    };
+    if range.0.is_nan() || range.1.is_nan() || range.0 >= range.1 {
+        return Err(MyError::InvalidInput(format!(
+            "empty range [{}, {}]",
+            range.0, range.1
+        )));
+    }
+
+    let mut funcs = Vec::new();
+    for (form, prog) in forms.into_iter().zip(progs) {
+        funcs.push((form, Func::new(prog, &var, vars)?));
+    }
+    let plot = Plot::sample(var, range, funcs, &conf)?;
+    match path {
+        Some(path) => std::fs::write(&path, plot.to_svg())
+            .map_err(|e| MyError::FileWriteErr(path, e.to_string()))?,
+        None => plot.print(),
+    }
+    Ok(len + 1)
+}
+
+/// 1つの関数の標本
+struct Series {
+    label: String,
+    ys: Vec<f64>,
+    /// i番目とi+1番目の間が連続ならtrue
+    cont: Vec<bool>,
+}
+
+/// 点の列. 1点だけのこともある
+type Polyline = Vec<(f64, f64)>;
+
+struct Plot {
+    var: String,
+    xs: Vec<f64>,
+    series: Vec<Series>,
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+}
+
+impl Plot {
+    /// 関数を区間で標本化し, y軸の範囲を決める
+    fn sample(
+        var: String,
+        (lo, hi): (f64, f64),
+        funcs: Vec<(String, Func)>,
+        conf: &Config,
+    ) -> Result<Plot, MyError> {
+        let at = |i: f64| lo + (hi - lo) * i / SAMPLES as f64;
+        let xs: Vec<f64> = (0..=SAMPLES).map(|i| at(i as f64)).collect();
+        let mut sampled = Vec::new();
+        for (label, mut f) in funcs {
+            let ys: Vec<f64> = xs.iter().map(|&x| f.eval(x, conf)).collect();
+            let mids: Vec<f64> = (0..SAMPLES)
+                .map(|i| f.eval(at(i as f64 + 0.5), conf))
+                .collect();
+            sampled.push((label, ys, mids));
+        }
+        let y_range = auto_scale(sampled.iter().flat_map(|(_, ys, _)| ys)).ok_or_else(|| {
+            let labels: Vec<&str> = sampled.iter().map(|(l, _, _)| l.as_str()).collect();
+            MyError::InvalidInput(format!(
+                "'{}' is undefined in [{lo}, {hi}]",
+                labels.join(", ")
+            ))
+        })?;
+        let span = y_range.1 - y_range.0;
+        let series = sampled
+            .into_iter()
+            .map(|(label, ys, mids)| {
+                let cont = (0..SAMPLES)
+                    .map(|i| is_continuous(ys[i], mids[i], ys[i + 1], span))
+                    .collect();
+                Series { label, ys, cont }
+            })
+            .collect();
+        Ok(Plot {
+            var,
+            xs,
+            series,
+            x_range: (lo, hi),
+            y_range,
+        })
+    }
+
+    /// 描画範囲に切り取った, 途切れずに描く点の列
+    fn polylines(&self, s: &Series) -> Vec<Polyline> {
+        let (lo, hi) = self.y_range;
+        let mut lines = Vec::new();
+        let mut line: Polyline = Vec::new();
+        for i in 0..SAMPLES {
+            let p = (self.xs[i], s.ys[i]);
+            let q = (self.xs[i + 1], s.ys[i + 1]);
+            let clipped = if s.cont[i] {
+                clip_y(p, q, lo, hi)
+            } else {
+                None
+            };
+            let Some((a, b)) = clipped else {
+                // 前後とつながらない点は1点だけ描く
+                let isolated = (i == 0 || !s.cont[i - 1]) && !s.cont[i];
+                if isolated && (lo..=hi).contains(&p.1) {
+                    lines.push(vec![p]);
+                }
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                continue;
+            };
+            if line.last() != Some(&a) {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push(a);
+            }
+            line.push(b);
+            if b != q {
+                lines.push(std::mem::take(&mut line));
+            }
+        }
+        let last = (self.xs[SAMPLES], s.ys[SAMPLES]);
+        if !s.cont[SAMPLES - 1] && (lo..=hi).contains(&last.1) {
+            lines.push(vec![last]);
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// 点字で描いた各行の, (文字, 描いた関数の番号) の列
+    fn braille(&self) -> Vec<Vec<(char, Option<usize>)>> {
+        let mut canvas = Canvas::new(WIDTH, HEIGHT);
+        let (x0, x1) = self.x_range;
+        let (y0, y1) = self.y_range;
+        let (w, h) = ((canvas.width - 1) as f64, (canvas.height - 1) as f64);
+        let to_px = |(x, y): (f64, f64)| ((x - x0) / (x1 - x0) * w, (y1 - y) / (y1 - y0) * h);
+        for (n, s) in self.series.iter().enumerate() {
+            for line in self.polylines(s) {
+                let px: Vec<(f64, f64)> = line.into_iter().map(to_px).collect();
+                canvas.dot(px[0], n);
+                for w in px.windows(2) {
+                    canvas.line(w[0], w[1], n);
+                }
+            }
+        }
+        canvas.rows()
+    }
+
+    /// 目盛りと凡例を付けて端末に描く
+    fn print(&self) {
+        let top = format_tick(self.y_range.1);
+        let bottom = format_tick(self.y_range.0);
+        let margin = top.len().max(bottom.len());
+        let rows = self.braille();
+        for (i, row) in rows.iter().enumerate() {
+            let (label, axis) = match i {
+                0 => (top.as_str(), '┤'),
+                _ if i == rows.len() - 1 => (bottom.as_str(), '┤'),
+                _ => ("", '│'),
+            };
+            let mut line = format!("{label:>margin$} {axis}");
+            for &(c, n) in row {
+                match n {
+                    Some(n) => write!(
+                        line,
+                        "{}",
+                        c.to_string().as_str().color(COLORS[n % COLORS.len()].0)
+                    ),
+                    None => write!(line, "{c}"),
+                }
+                .unwrap();
+            }
+            println!("{line}");
+        }
+        println!("{:margin$} └{}", "", "─".repeat(WIDTH));
+        let left = format_tick(self.x_range.0);
+        let right = format_tick(self.x_range.1);
+        let gap = (WIDTH + 1).saturating_sub(left.len() + right.len()).max(1);
+        println!("{:margin$}  {left}{:gap$}{right}", "", "");
+        if self.series.len() > 1 {
+            for (n, s) in self.series.iter().enumerate() {
+                println!(
+                    "{:margin$}  {} {}",
+                    "",
+                    "⣀⣀".color(COLORS[n % COLORS.len()].0),
+                    s.label
+                );
+            }
+        }
+    }
+
+    /// 目盛りと凡例を付けたSVG
+    fn to_svg(&self) -> String {
+        let (w, h, m) = (SVG_WIDTH, SVG_HEIGHT, SVG_MARGIN);
+        let (x0, x1) = self.x_range;
+        let (y0, y1) = self.y_range;
+        let sx = |x: f64| m + (x - x0) / (x1 - x0) * (w - 2.0 * m);
+        let sy = |y: f64| m + (y1 - y) / (y1 - y0) * (h - 2.0 * m);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n"
+        );
+        svg += "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n";
+        // 範囲に入っていれば座標軸を描く
+        let axis = |svg: &mut String, (ax, ay): (f64, f64), (bx, by): (f64, f64)| {
+            writeln!(
+                svg,
+                "<line x1=\"{ax:.2}\" y1=\"{ay:.2}\" x2=\"{bx:.2}\" y2=\"{by:.2}\" stroke=\"#999\"/>"
+            )
+            .unwrap()
+        };
+        if x0 < 0.0 && 0.0 < x1 {
+            axis(&mut svg, (sx(0.0), sy(y0)), (sx(0.0), sy(y1)));
+        }
+        if y0 < 0.0 && 0.0 < y1 {
+            axis(&mut svg, (sx(x0), sy(0.0)), (sx(x1), sy(0.0)));
+        }
+        writeln!(
+            svg,
+            "<rect x=\"{m}\" y=\"{m}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"black\"/>",
+            w - 2.0 * m,
+            h - 2.0 * m
+        )
+        .unwrap();
+        let text = |svg: &mut String, x: f64, y: f64, anchor: &str, s: &str| {
+            writeln!(
+                svg,
+                "<text x=\"{x:.2}\" y=\"{y:.2}\" text-anchor=\"{anchor}\" font-family=\"sans-serif\" font-size=\"12\">{}</text>",
+                escape_xml(s)
+            )
+            .unwrap()
+        };
+        text(&mut svg, m - 5.0, m + 4.0, "end", &format_tick(y1));
+        text(&mut svg, m - 5.0, h - m + 4.0, "end", &format_tick(y0));
+        text(&mut svg, m, h - m + 16.0, "middle", &format_tick(x0));
+        text(&mut svg, w - m, h - m + 16.0, "middle", &format_tick(x1));
+        text(&mut svg, w / 2.0, h - m + 16.0, "middle", &self.var);
+        for (n, s) in self.series.iter().enumerate() {
+            let color = COLORS[n % COLORS.len()].1;
+            for line in self.polylines(s) {
+                if let [(x, y)] = line[..] {
+                    writeln!(
+                        svg,
+                        "<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"1.5\" fill=\"{color}\"/>",
+                        sx(x),
+                        sy(y)
+                    )
+                    .unwrap();
+                    continue;
+                }
+                let points: Vec<String> = line
+                    .iter()
+                    .map(|&(x, y)| format!("{:.2},{:.2}", sx(x), sy(y)))
+                    .collect();
+                writeln!(
+                    svg,
+                    "<polyline points=\"{}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"1.5\"/>",
+                    points.join(" ")
+                )
+                .unwrap();
+            }
+            let ly = m - 10.0 - 14.0 * (self.series.len() - 1 - n) as f64;
+            writeln!(
+                svg,
+                "<line x1=\"{m}\" y1=\"{:.2}\" x2=\"{}\" y2=\"{:.2}\" stroke=\"{color}\" stroke-width=\"1.5\"/>",
+                ly - 4.0,
+                m + 20.0,
+                ly - 4.0
+            )
+            .unwrap();
+            text(&mut svg, m + 25.0, ly, "start", &s.label);
+        }
+        svg += "</svg>\n";
+        svg
+    }
+}
+
+/// 点字の点を打つ画面. 1文字が横2点, 縦4点
+struct Canvas {
+    width: usize,
+    height: usize,
+    cols: usize,
+    cells: Vec<(u8, Option<usize>)>,
+}
+
+impl Canvas {
+    fn new(cols: usize, rows: usize) -> Canvas {
+        Canvas {
+            width: cols * 2,
+            height: rows * 4,
+            cols,
+            cells: vec![(0, None); cols * rows],
+        }
+    }
+
+    /// 点の位置を丸めて打つ. 関数nの点として色を付ける
+    fn dot(&mut self, (x, y): (f64, f64), n: usize) {
+        let (x, y) = (x.round(), y.round());
+        if !(0.0..self.width as f64).contains(&x) || !(0.0..self.height as f64).contains(&y) {
+            return;
+        }
+        let (x, y) = (x as usize, y as usize);
+        // 点字の点の番号: 左の列が上から0, 1, 2, 6, 右の列が3, 4, 5, 7
+        const BITS: [[u8; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+        let cell = &mut self.cells[y / 4 * self.cols + x / 2];
+        cell.0 |= BITS[x % 2][y % 4];
+        cell.1 = Some(n);
+    }
+
+    fn line(&mut self, a: (f64, f64), b: (f64, f64), n: usize) {
+        let steps = (b.0 - a.0).abs().max((b.1 - a.1).abs()).ceil().max(1.0) as usize;
+        for i in 1..=steps {
+            let t = i as f64 / steps as f64;
+            self.dot((a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t), n);
+        }
+    }
+
+    fn rows(&self) -> Vec<Vec<(char, Option<usize>)>> {
+        self.cells
+            .chunks(self.cols)
+            .map(|row| {
+                row.iter()
+                    .map(|&(bits, n)| (char::from_u32(0x2800 + bits as u32).unwrap(), n))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// 有限な値の範囲. 特異点の近くの大きな値は外れ値として除く
+fn auto_scale<'a>(ys: impl Iterator<Item = &'a f64>) -> Option<(f64, f64)> {
+    let mut ys: Vec<f64> = ys.copied().filter(|y| y.is_finite()).collect();
+    if ys.is_empty() {
+        return None;
+    }
+    ys.sort_by(f64::total_cmp);
+    let q = |p: f64| ys[((ys.len() - 1) as f64 * p).round() as usize];
+    let spread = q(0.95) - q(0.05);
+    let lo = ys[0].max(q(0.05) - spread);
+    let hi = ys[ys.len() - 1].min(q(0.95) + spread);
+    if hi - lo <= f64::EPSILON * hi.abs().max(lo.abs()) {
+        // 定数関数は値を中心に描く
+        let pad = if lo == 0.0 { 1.0 } else { lo.abs() * 0.1 };
+        return Some((lo - pad, hi + pad));
+    }
+    Some((lo, hi))
+}
+
+/// 隣り合う標本点の間で関数が連続とみなせるか
+/// 大きく跳ぶ間で中点の値が両端の間に無ければ, 特異点か不連続点として線を切る
+fn is_continuous(y0: f64, mid: f64, y1: f64, span: f64) -> bool {
+    if !(y0.is_finite() && mid.is_finite() && y1.is_finite()) {
+        return false;
+    }
+    (y1 - y0).abs() <= span / 2.0 || (y0.min(y1)..=y0.max(y1)).contains(&mid)
+}
+
+/// 線分のy座標が[lo, hi]に入る部分
+fn clip_y(
+    (x0, y0): (f64, f64),
+    (x1, y1): (f64, f64),
+    lo: f64,
+    hi: f64,
+) -> Option<((f64, f64), (f64, f64))> {
+    let dy = y1 - y0;
+    let (mut t0, mut t1) = (0.0, 1.0);
+    for (p, q) in [(-dy, y0 - lo), (dy, hi - y0)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let r = q / p;
+        if p < 0.0 {
+            t0 = r.max(t0);
+        } else {
+            t1 = r.min(t1);
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    // 切り取らない端は元の点のまま返す
+    let at = |t: f64| match t {
+        0.0 => (x0, y0),
+        1.0 => (x1, y1),
+        _ => (x0 + (x1 - x0) * t, y0 + dy * t),
+    };
+    Some((at(t0), at(t1)))
+}
+
+/// 目盛りの数値. 桁の多い値は指数表記にする
+fn format_tick(x: f64) -> String {
+    if x == 0.0 || (1e-3..1e6).contains(&x.abs()) {
+        let s = format!("{x:.4}");
+        let s = s.trim_end_matches('0').trim_end_matches('.');
+        if s == "-0" {
+            "0".to_string()
+        } else {
+            s.to_string()
+        }
+    } else {
+        format!("{x:.3e}")
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn plot(forms: &[&str], range: (f64, f64), conf: &Config) -> Plot {
+        let vars = HashMap::new();
+        let funcs = forms
+            .iter()
+            .map(|form| {
+                let (ast, root) = Parser::new(form.to_string(), false)
+                    .unwrap()
+                    .build_expr()
+                    .unwrap();
+                let prog = Program::compile(&ast, root, form);
+                (form.to_string(), Func::new(prog, "x", &vars).unwrap())
+            })
+            .collect();
+        Plot::sample("x".to_string(), range, funcs, conf).unwrap()
+    }
+
+    fn text(plot: &Plot) -> Vec<String> {
+        plot.braille()
+            .iter()
+            .map(|row| row.iter().map(|&(c, _)| c).collect())
+            .collect()
+    }
+
+    fn rows_bits(row: &str, col: usize) -> u32 {
+        row.chars().nth(col).unwrap() as u32 - 0x2800
+    }
+
+    #[test]
+    fn test_braille() {
+        // 直線は左下から右上へ, 両端の点が打たれる
+        let rows = text(&plot(&["x"], (0.0, 1.0), &Config::default()));
+        assert_eq!(rows.len(), HEIGHT);
+        assert_ne!(rows_bits(&rows[0], WIDTH - 1) & 0x08, 0);
+        assert_ne!(rows_bits(&rows[HEIGHT - 1], 0) & 0x40, 0);
+        assert!(rows.iter().all(|r| r.chars().any(|c| c != '⠀')));
+    }
+
+    #[test]
+    fn test_singularity() {
+        // 1/xは0の前後で線を切る
+        let p = plot(&["\\frac{1}{x}"], (-1.0, 1.0), &Config::default());
+        let lines = p.polylines(&p.series[0]);
+        assert!(lines
+            .iter()
+            .all(|l| l.iter().all(|&(x, _)| x < 0.0) || l.iter().all(|&(x, _)| x > 0.0)));
+        assert!(lines.iter().any(|l| l[0].0 < 0.0));
+        assert!(lines.iter().any(|l| l[0].0 > 0.0));
+        // 外れ値で範囲が広がりすぎない
+        assert!(p.y_range.1 < 100.0);
+        // 連続な関数は1本につながる
+        let p = plot(&["x^{2}", "\\sin x"], (-3.0, 3.0), &Config::default());
+        assert_eq!(p.polylines(&p.series[0]).len(), 1);
+        assert_eq!(p.polylines(&p.series[1]).len(), 1);
+        assert_eq!(p.y_range.1, 9.0);
+        assert!((p.y_range.0 + 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_svg() {
+        let conf = Config {
+            trig_func_arg: TrigFuncArg::Degree,
+            ..Default::default()
+        };
+        let p = plot(&["\\sin x", "\\cos x"], DEFAULT_DEGREE_RANGE, &conf);
+        // 度数法で1周期なので[-1, 1]に収まる
+        assert!((p.y_range.0 + 1.0).abs() < 1e-9 && (p.y_range.1 - 1.0).abs() < 1e-9);
+        let svg = p.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert!(svg.contains(">360</text>"));
+        assert_eq!(format_tick(-0.00001), "-1.000e-5");
+        assert_eq!(format_tick(2.5), "2.5");
+    }
+}
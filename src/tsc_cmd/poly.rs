@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use crate::ast::{BinaryOp, NodeKind};
+use crate::compile::Program;
+use crate::config::*;
+use crate::domain::domain_err;
+use crate::error::*;
+use crate::num_formatter::num_formatter;
+use crate::parser::Parser;
+use crate::poly::{self, Root};
+use crate::Value;
+
+use super::{format_rational, leading_var, only_unknown, round_f64};
+
+/// :roots, :expand, :factor {var} {polynomial}
+/// :polydiv {var} \frac{p}{q}
+/// 変数は式に未定義の変数が1つだけなら省略できる
+/// 消費したtoken数を返す
+pub fn poly_cmd(
+    parser: &Parser,
+    cmd_idx: usize,
+    vars: &mut HashMap<String, Value>,
+) -> Result<usize, MyError> {
+    let cmd = parser.get_token(cmd_idx).token.clone();
+    let (_, len) = parser.cmd_arg_form(cmd_idx + 1)?;
+    let end = cmd_idx + 1 + len;
+    let var = leading_var(parser, cmd_idx + 1, end);
+    let start = cmd_idx + 1 + var.is_some() as usize;
+    let form = parser.tokens_form(start, end - start)?;
+    let (ast, root) = Parser::new(form.clone(), false)?.build_expr()?;
+    let var = match var {
+        Some(var) => var,
+        None => only_unknown(Program::compile(&ast, root, &form).slots(), vars)?,
+    };
+    let conf = config_reader()?.clone();
+    let read = |id| poly::from_ast(&ast, id, &var, &form, vars, &conf);
+    match &*cmd {
+        ":expand" => println!("{}", read(root)?.to_tex(&var)),
+        ":factor" => println!("{}", read(root)?.factor().to_tex(&var)),
+        ":roots" => {
+            let p = read(root)?;
+            if p.degree() == 0 {
+                return Err(MyError::InvalidInput(format!(
+                    "'{form}' is constant in {var}"
+                )));
+            }
+            for (r, m) in p.roots() {
+                let multiplicity = if m > 1 {
+                    format!(" (multiplicity {m})")
+                } else {
+                    String::new()
+                };
+                println!(
+                    "{var} = {}{multiplicity}",
+                    format_root(&r, conf.num_of_digit)
+                );
+            }
+        }
+        _ => {
+            // :polydiv
+            let NodeKind::Binary(BinaryOp::Div, num, den) = ast[root].kind else {
+                return Err(MyError::InvalidInput(format!(
+                    "{cmd} expects \\frac{{p}}{{q}} but '{form}'"
+                )));
+            };
+            let (p, d) = (read(num)?, read(den)?);
+            if d.is_zero() {
+                return Err(domain_err(&ast[root], &form, "divisor 0".to_string()));
+            }
+            let (q, r) = p.div_rem(&d);
+            println!("q = {}", q.to_tex(&var));
+            println!("r = {}", r.to_tex(&var));
+        }
+    }
+    Ok(len + 1)
+}
+
+fn format_root(root: &Root, num_of_digit: u32) -> String {
+    let fmt = |x: f64| match round_f64(x) {
+        Some(x) => num_formatter(&x, num_of_digit),
+        None => x.to_string(),
+    };
+    match root {
+        Root::Exact(r) => format_rational(r, num_of_digit),
+        Root::Real(x) => fmt(*x),
+        Root::Complex(z) => {
+            let im = match fmt(z.im.abs()) {
+                one if one == "1" => "i".to_string(),
+                abs => format!("{abs}i"),
+            };
+            // 実部が丸め誤差なら純虚数にする
+            if z.re.abs() <= 1e-14 * z.im.abs() {
+                if z.im < 0.0 {
+                    format!("-{im}")
+                } else {
+                    im
+                }
+            } else {
+                let sign = if z.im < 0.0 { "-" } else { "+" };
+                format!("{} {sign} {im}", fmt(z.re))
+            }
+        }
+    }
+}
@@ -1,56 +1,331 @@
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Signed, Zero};
 use std::collections::BTreeMap;
 use std::fmt;
 
+use crate::error::MyError;
+
+/// 試し割りに使う素数の上限
+const TRIAL_LIMIT: u32 = 1000;
+/// Miller–Rabin法の底. 最初の13個で判定が確定するのはDETERMINISTIC_LIMIT未満
+const MR_BASES: [u32; 23] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83,
+];
+const DETERMINISTIC_BASES: usize = 13;
+const DETERMINISTIC_LIMIT: &str = "3317044064679887385961981";
+/// Pollard–Brentのρ法で1つの因数を探す反復の上限
+const MAX_RHO_STEPS: u64 = 1 << 22;
+/// :divisorsで列挙する約数の個数の上限
+pub const MAX_DIVISORS: u64 = 100_000;
+
+/// 素数判定の結果
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Primality {
+    Composite,
+    Prime,
+    /// Miller–Rabin法の全ての底で素数と判定したが, 証明はしていない
+    ProbablePrime,
+}
+
 pub struct Factorized {
-    num: u64,
-    facters: BTreeMap<u64, u64>,
+    num: BigInt,
+    facters: BTreeMap<BigInt, u32>,
 }
 
 impl fmt::Display for Factorized {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} = ", self.num)?;
-        for (base, exp, is_last) in self
-            .facters
-            .iter()
-            .enumerate()
-            .map(|(i, (b, e))| (b, e, i == self.facters.len() - 1))
-        {
+        let mut terms = Vec::new();
+        if self.num.is_negative() {
+            terms.push("-1".to_string());
+        }
+        for (base, exp) in &self.facters {
             if *exp == 1 {
-                if is_last {
-                    write!(f, "{base}")?;
-                } else {
-                    write!(f, "{base} * ")?;
-                }
-            } else if is_last {
-                write!(f, "{base}^{{{exp}}}")?;
+                terms.push(base.to_string());
             } else {
-                write!(f, "{base}^{{{exp}}} * ")?;
+                terms.push(format!("{base}^{{{exp}}}"));
             }
         }
-        write!(f, "")
+        if self.facters.is_empty() && !self.num.is_negative() {
+            terms.push("1".to_string());
+        }
+        write!(f, "{}", terms.join(" * "))
+    }
+}
+
+impl Factorized {
+    /// 正の約数を小さい順に並べる. 個数がMAX_DIVISORSを超えるならNone
+    pub fn divisors(&self) -> Option<Vec<BigInt>> {
+        let count = self
+            .facters
+            .values()
+            .try_fold(1u64, |n, e| n.checked_mul(u64::from(*e) + 1))
+            .filter(|n| *n <= MAX_DIVISORS)?;
+        let mut divisors = Vec::with_capacity(count as usize);
+        divisors.push(BigInt::one());
+        for (p, e) in &self.facters {
+            let len = divisors.len();
+            let mut pk = BigInt::one();
+            for _ in 0..*e {
+                pk *= p;
+                for i in 0..len {
+                    let d = &divisors[i] * &pk;
+                    divisors.push(d);
+                }
+            }
+        }
+        divisors.sort();
+        Some(divisors)
+    }
+
+    /// Eulerのφ関数 φ(|num|)
+    pub fn totient(&self) -> BigInt {
+        self.facters.iter().fold(BigInt::one(), |acc, (p, e)| {
+            acc * p.pow(e - 1) * (p - BigInt::one())
+        })
     }
 }
 
-pub fn factorize(num: u64) -> Factorized {
+/// numを素因数分解する. 小さい素数で試し割りした残りをPollard–Brentのρ法で分解する
+pub fn factorize(num: &BigInt) -> Result<Factorized, MyError> {
+    if num.is_zero() {
+        return Err(MyError::InvalidInput("0 can't be factorized".to_string()));
+    }
     let mut facters = BTreeMap::new();
-    let mut n = num;
-    if num == 1 {
-        facters.insert(1, 1);
+    let mut n = num.abs();
+    for p in small_primes() {
+        let p = BigInt::from(p);
+        while (&n % &p).is_zero() {
+            n /= &p;
+            *facters.entry(p.clone()).or_insert(0) += 1;
+        }
+    }
+    let mut rest = vec![n];
+    while let Some(n) = rest.pop() {
+        if n.is_one() {
+            continue;
+        }
+        if is_prime(&n) != Primality::Composite {
+            *facters.entry(n).or_insert(0) += 1;
+            continue;
+        }
+        // ρ法は素数の累乗に時間がかかるので, 先に累乗根を探す
+        if let Some((root, k)) = perfect_power(&n) {
+            rest.extend(std::iter::repeat_n(root, k as usize));
+            continue;
+        }
+        let d = brent(&n).ok_or_else(|| {
+            MyError::CalcErr(format!("couldn't find a factor of {n} by Pollard's rho"))
+        })?;
+        rest.push(&n / &d);
+        rest.push(d);
+    }
+    Ok(Factorized {
+        num: num.clone(),
+        facters,
+    })
+}
+
+/// Miller–Rabin法で素数判定する
+pub fn is_prime(n: &BigInt) -> Primality {
+    if n < &BigInt::from(2) {
+        return Primality::Composite;
     }
-    for i in 2..num {
-        if i * i > num {
-            break;
-        } else if n.is_multiple_of(i) {
-            let mut exp = 0;
-            while n.is_multiple_of(i) {
-                n /= i;
-                exp += 1;
+    for p in small_primes() {
+        if n == &BigInt::from(p) {
+            return Primality::Prime;
+        }
+        if (n % p).is_zero() {
+            return Primality::Composite;
+        }
+    }
+    if n < &BigInt::from(TRIAL_LIMIT * TRIAL_LIMIT) {
+        return Primality::Prime;
+    }
+    let one = BigInt::one();
+    let n1 = n - &one;
+    let s = n1.trailing_zeros().unwrap_or(0);
+    let d = &n1 >> s;
+    let deterministic = n < &DETERMINISTIC_LIMIT.parse::<BigInt>().unwrap();
+    let bases = if deterministic {
+        &MR_BASES[..DETERMINISTIC_BASES]
+    } else {
+        &MR_BASES[..]
+    };
+    for a in bases {
+        let mut x = BigInt::from(*a).modpow(&d, n);
+        if x.is_one() || x == n1 {
+            continue;
+        }
+        let mut witness = true;
+        for _ in 1..s {
+            x = &x * &x % n;
+            if x == n1 {
+                witness = false;
+                break;
             }
-            facters.insert(i, exp);
+        }
+        if witness {
+            return Primality::Composite;
         }
     }
-    if n != 1 {
-        facters.insert(n, 1);
+    if deterministic {
+        Primality::Prime
+    } else {
+        Primality::ProbablePrime
+    }
+}
+
+/// nより大きい最小の素数
+pub fn next_prime(n: &BigInt) -> (BigInt, Primality) {
+    let two = BigInt::from(2);
+    if n < &two {
+        return (two, Primality::Prime);
+    }
+    let mut p: BigInt = n + 1;
+    if p.is_even() && p != two {
+        p += 1;
+    }
+    loop {
+        match is_prime(&p) {
+            Primality::Composite => p += &two,
+            primality => return (p, primality),
+        }
+    }
+}
+
+/// TRIAL_LIMIT未満の素数
+fn small_primes() -> Vec<u32> {
+    let mut sieve = vec![true; TRIAL_LIMIT as usize];
+    let mut primes = Vec::new();
+    for i in 2..TRIAL_LIMIT as usize {
+        if sieve[i] {
+            primes.push(i as u32);
+            for j in (i * i..TRIAL_LIMIT as usize).step_by(i) {
+                sieve[j] = false;
+            }
+        }
+    }
+    primes
+}
+
+/// n = r^k (k >= 2) となる最小のrとk. 無ければNone
+fn perfect_power(n: &BigInt) -> Option<(BigInt, u32)> {
+    // 試し割りの後なので, 底はTRIAL_LIMIT以上
+    let max_k = (n.bits() as f64 / f64::from(TRIAL_LIMIT).log2()) as u32;
+    (2..=max_k).rev().find_map(|k| {
+        let root = n.nth_root(k);
+        (root.pow(k) == *n).then_some((root, k))
+    })
+}
+
+/// 合成数nの自明でない約数をPollard–Brentのρ法で探す
+fn brent(n: &BigInt) -> Option<BigInt> {
+    const BATCH: u64 = 128;
+    let mut steps = 0;
+    // 見つからなければ多項式 y^2 + c のcを変えてやり直す
+    let mut c = BigInt::zero();
+    loop {
+        c += 1;
+        let f = |y: &BigInt| (y * y + &c) % n;
+        let mut y = BigInt::from(2);
+        let (mut x, mut ys) = (y.clone(), y.clone());
+        let (mut g, mut q, mut r) = (BigInt::one(), BigInt::one(), 1u64);
+        while g.is_one() {
+            x = y.clone();
+            for _ in 0..r {
+                y = f(&y);
+            }
+            let mut k = 0;
+            while k < r && g.is_one() {
+                ys = y.clone();
+                for _ in 0..BATCH.min(r - k) {
+                    y = f(&y);
+                    q = q * (&x - &y).abs() % n;
+                }
+                g = q.gcd(n);
+                k += BATCH;
+            }
+            r *= 2;
+            steps += r;
+            if steps > MAX_RHO_STEPS {
+                return None;
+            }
+        }
+        // まとめて掛けた積がnの倍数になったら1つずつ戻って探す
+        if &g == n {
+            loop {
+                ys = f(&ys);
+                g = (&x - &ys).abs().gcd(n);
+                if !g.is_one() {
+                    break;
+                }
+            }
+        }
+        if &g != n {
+            return Some(g);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_is_prime() {
+        let primes: Vec<u32> = (0..100)
+            .filter(|n| is_prime(&BigInt::from(*n)) == Primality::Prime)
+            .collect();
+        assert_eq!(primes, small_primes()[..25]);
+        // Carmichael数と強擬素数
+        for n in ["561", "3215031751", "3825123056546413051"] {
+            assert_eq!(is_prime(&big(n)), Primality::Composite, "{n}");
+        }
+        assert_eq!(is_prime(&big("2305843009213693951")), Primality::Prime);
+        assert_eq!(
+            is_prime(&big("618970019642690137449562111")),
+            Primality::ProbablePrime
+        );
+        assert_eq!(
+            next_prime(&big("1000000000000")),
+            (big("1000000000039"), Primality::Prime)
+        );
+        assert_eq!(next_prime(&BigInt::from(2)).0, BigInt::from(3));
+    }
+
+    #[test]
+    fn test_factorize() {
+        let cases = [
+            ("42", "42 = 2 * 3 * 7"),
+            ("1", "1 = 1"),
+            ("-12", "-12 = -1 * 2^{2} * 3"),
+            (
+                "18446744073709551617",
+                "18446744073709551617 = 274177 * 67280421310721",
+            ),
+            (
+                "1000000016000000063",
+                "1000000016000000063 = 1000000007 * 1000000009",
+            ),
+            (
+                "5316911983139663487003542222693990401",
+                "5316911983139663487003542222693990401 = 2305843009213693951^{2}",
+            ),
+        ];
+        for (n, expected) in cases {
+            assert_eq!(factorize(&big(n)).unwrap().to_string(), expected);
+        }
+        assert!(factorize(&BigInt::zero()).is_err());
+        let f = factorize(&BigInt::from(360)).unwrap();
+        assert_eq!(f.totient(), BigInt::from(96));
+        let divisors = f.divisors().unwrap();
+        assert_eq!(divisors.len(), 24);
+        assert_eq!(divisors[..5], [1, 2, 3, 4, 5].map(BigInt::from));
     }
-    Factorized { num, facters }
 }
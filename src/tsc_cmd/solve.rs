@@ -0,0 +1,417 @@
+use bigdecimal::{BigDecimal, ToPrimitive};
+use std::collections::HashMap;
+
+use crate::compile::Program;
+use crate::config::*;
+use crate::derivative::derivative;
+use crate::error::*;
+use crate::num_formatter::num_formatter;
+use crate::parser::Parser;
+use crate::tokenizer::TokenKind;
+use crate::Value;
+
+use super::{eval_form, leading_var, only_unknown, round_f64};
+
+/// 区間を指定しないときに根を探す区間
+const DEFAULT_RANGE: (f64, f64) = (-100.0, 100.0);
+/// 符号の変化を探すために区間を分ける数
+const SAMPLES: usize = 2000;
+/// 1つの根を絞り込む反復の上限
+const MAX_ITER: usize = 200;
+/// 接する根とみなす |f| の上限
+const TOUCH_TOL: f64 = 1e-12;
+
+/// :solve {var} {lhs} = {rhs} from {a} to {b} bind
+/// 変数は方程式に未定義の変数が1つだけなら, = {rhs} は右辺が0なら省略できる
+/// bindを付けると最初の根を変数に代入する
+/// 消費したtoken数を返す
+pub fn solve_cmd(
+    parser: &Parser,
+    cmd_idx: usize,
+    vars: &mut HashMap<String, Value>,
+) -> Result<usize, MyError> {
+    let (_, len) = parser.cmd_arg_form(cmd_idx + 1)?;
+    let end = cmd_idx + 1 + len;
+    let is_word = |i: usize, word: &str| {
+        let t = parser.get_token(i);
+        matches!(t.token_kind, TokenKind::TkVariable) && t.token == word
+    };
+    let var = leading_var(parser, cmd_idx + 1, end);
+    let start = cmd_idx + 1 + var.is_some() as usize;
+    let bind = end - 1 > start && is_word(end - 1, "bind");
+    let range_end = if bind { end - 1 } else { end };
+    let from = (start..range_end).find(|&i| is_word(i, "from"));
+    let eq_end = from.unwrap_or(range_end);
+    let eq = (start..eq_end).find(|&i| {
+        let t = parser.get_token(i);
+        matches!(t.token_kind, TokenKind::TkOperator) && t.token == "="
+    });
+    let lhs = parser.tokens_form(start, eq.unwrap_or(eq_end) - start)?;
+    let rhs = match eq {
+        Some(eq) => Some(parser.tokens_form(eq + 1, eq_end - eq - 1)?),
+        None => None,
+    };
+
+    let conf = config_reader()?.clone();
+    let range = match from {
+        Some(from) => {
+            let to = (from + 1..range_end)
+                .find(|&i| is_word(i, "to"))
+                .ok_or_else(|| {
+                    MyError::UnexpectedToken(
+                        "'to'".to_string(),
+                        "end of command".to_string(),
+                        parser.format_err_loc_idx(from),
+                    )
+                })?;
+            let lo = parser.tokens_form(from + 1, to - from - 1)?;
+            let hi = parser.tokens_form(to + 1, range_end - to - 1)?;
+            (eval_bound(&lo, vars, &conf)?, eval_bound(&hi, vars, &conf)?)
+        }
+        None => DEFAULT_RANGE,
+    };
+
+    let mut eq = Equation::new(&lhs, rhs.as_deref(), var.as_deref(), vars, &conf)?;
+    let roots = eq.roots(range.0, range.1);
+    if roots.is_empty() {
+        let form = match &rhs {
+            Some(rhs) => format!("{lhs} = {rhs}"),
+            None => lhs,
+        };
+        return Err(MyError::NoRoot(
+            form,
+            range.0.to_string(),
+            range.1.to_string(),
+        ));
+    }
+    for r in &roots {
+        println!("{} = {}", eq.var, num_formatter(r, conf.num_of_digit));
+    }
+    if bind {
+        vars.insert(eq.var.clone(), roots[0].clone().into());
+    }
+    Ok(len + 1)
+}
+
+/// 区間の端の式を評価する
+pub(super) fn eval_bound(
+    form: &str,
+    vars: &HashMap<String, Value>,
+    conf: &Config,
+) -> Result<f64, MyError> {
+    let val = eval_form(form, vars, conf)?;
+    val.to_f64()
+        .ok_or(MyError::ConvertErr("f64".to_string(), val))
+}
+
+/// varに値を入れてf64で評価する式
+pub(super) struct Func {
+    prog: Program,
+    slots: Vec<f64>,
+    var_slot: Option<usize>,
+}
+
+impl Func {
+    pub(super) fn new(
+        prog: Program,
+        var: &str,
+        vars: &HashMap<String, Value>,
+    ) -> Result<Func, MyError> {
+        let mut vars = vars.clone();
+        vars.insert(var.to_string(), BigDecimal::from(0).into());
+        let slots = prog.bind_f64(&vars)?;
+        let var_slot = prog.slot(var);
+        Ok(Func {
+            prog,
+            slots,
+            var_slot,
+        })
+    }
+
+    /// 評価できない点ではNaNを返す
+    pub(super) fn eval(&mut self, x: f64, conf: &Config) -> f64 {
+        if let Some(i) = self.var_slot {
+            self.slots[i] = x;
+        }
+        self.prog.eval_f64(&self.slots, conf).unwrap_or(f64::NAN)
+    }
+}
+
+/// 絞り込む関数
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    F,  // lhs - rhs
+    Df, // その導関数
+}
+
+/// 方程式 lhs = rhs を f(var) = lhs - rhs = 0 として解く
+struct Equation {
+    var: String,
+    conf: Config,
+    lhs: Func,
+    rhs: Option<Func>,
+    // ASTから求めた導関数. 微分できなければNone
+    deriv: Option<(Func, Option<Func>)>,
+}
+
+impl Equation {
+    fn new(
+        lhs: &str,
+        rhs: Option<&str>,
+        var: Option<&str>,
+        vars: &HashMap<String, Value>,
+        conf: &Config,
+    ) -> Result<Equation, MyError> {
+        let parse = |form: &str| Parser::new(form.to_string(), false)?.build_expr();
+        let lhs_ast = parse(lhs)?;
+        let rhs_ast = rhs.map(parse).transpose()?;
+        let lhs_prog = Program::compile(&lhs_ast.0, lhs_ast.1, lhs);
+        let rhs_prog = rhs
+            .zip(rhs_ast.as_ref())
+            .map(|(form, (ast, root))| Program::compile(ast, *root, form));
+
+        let mut names = lhs_prog.slots().to_vec();
+        if let Some(rhs) = &rhs_prog {
+            names.extend(rhs.slots().iter().cloned());
+        }
+        let var = match var {
+            Some(var) => {
+                if !names.iter().any(|v| v == var) {
+                    return Err(MyError::InvalidInput(format!(
+                        "'{var}' doesn't appear in the equation"
+                    )));
+                }
+                var.to_string()
+            }
+            None => only_unknown(&names, vars)?,
+        };
+
+        let diff = |(ast, root): &(_, _), form: &str| -> Result<Option<Func>, MyError> {
+            match derivative(ast, *root, &var, conf)? {
+                Some((d, droot)) => Ok(Some(Func::new(
+                    Program::compile(&d, droot, form),
+                    &var,
+                    vars,
+                )?)),
+                None => Ok(None),
+            }
+        };
+        let dlhs = diff(&lhs_ast, lhs)?;
+        let drhs = match (rhs, &rhs_ast) {
+            (Some(form), Some(ast)) => Some(diff(ast, form)?),
+            _ => None,
+        };
+        let deriv = match (dlhs, drhs) {
+            (Some(dl), None) => Some((dl, None)),
+            (Some(dl), Some(Some(dr))) => Some((dl, Some(dr))),
+            _ => None,
+        };
+        Ok(Equation {
+            lhs: Func::new(lhs_prog, &var, vars)?,
+            rhs: rhs_prog.map(|p| Func::new(p, &var, vars)).transpose()?,
+            deriv,
+            var,
+            conf: conf.clone(),
+        })
+    }
+
+    fn f(&mut self, x: f64) -> f64 {
+        let l = self.lhs.eval(x, &self.conf);
+        match &mut self.rhs {
+            Some(rhs) => l - rhs.eval(x, &self.conf),
+            None => l,
+        }
+    }
+
+    /// fの導関数. 微分できなければ中心差分で近似する
+    fn df(&mut self, x: f64) -> f64 {
+        match &mut self.deriv {
+            Some((dl, dr)) => {
+                let l = dl.eval(x, &self.conf);
+                match dr {
+                    Some(dr) => l - dr.eval(x, &self.conf),
+                    None => l,
+                }
+            }
+            None => {
+                let h = 1e-6 * (1.0 + x.abs());
+                (self.f(x + h) - self.f(x - h)) / (2.0 * h)
+            }
+        }
+    }
+
+    fn value(&mut self, target: Target, x: f64) -> f64 {
+        match target {
+            Target::F => self.f(x),
+            Target::Df => self.df(x),
+        }
+    }
+
+    /// [lo, hi]の根を昇順に返す
+    fn roots(&mut self, lo: f64, hi: f64) -> Vec<BigDecimal> {
+        let (lo, hi) = (lo.min(hi), lo.max(hi));
+        let xs: Vec<f64> = (0..=SAMPLES)
+            .map(|i| lo + (hi - lo) * i as f64 / SAMPLES as f64)
+            .collect();
+        let fs: Vec<f64> = xs.iter().map(|x| self.f(*x)).collect();
+        let finite = |i: usize| fs[i].is_finite();
+        let mut roots = Vec::new();
+        for i in 0..=SAMPLES {
+            if fs[i] == 0.0 {
+                roots.push(xs[i]);
+            } else if i < SAMPLES && finite(i) && finite(i + 1) && fs[i] * fs[i + 1] < 0.0 {
+                // 符号が変わる区間. 極で符号が変わるときは |f| が小さくならない
+                if let Some(r) = self.bracket(Target::F, xs[i], xs[i + 1], fs[i], fs[i + 1])
+                    && self.f(r).abs() <= fs[i].abs().min(fs[i + 1].abs())
+                {
+                    roots.push(r);
+                }
+            } else if 0 < i
+                && i < SAMPLES
+                && finite(i - 1)
+                && finite(i + 1)
+                && fs[i - 1] * fs[i] > 0.0
+                && fs[i] * fs[i + 1] > 0.0
+                && fs[i].abs() < fs[i - 1].abs()
+                && fs[i].abs() <= fs[i + 1].abs()
+            {
+                // |f| が極小になる区間. 接する根は導関数の根として求める
+                let (a, b) = (xs[i - 1], xs[i + 1]);
+                let (da, db) = (self.df(a), self.df(b));
+                if da * db < 0.0
+                    && let Some(r) = self.bracket(Target::Df, a, b, da, db)
+                    && self.f(r).abs() <= TOUCH_TOL * (1.0 + fs[i - 1].abs() + fs[i + 1].abs())
+                {
+                    roots.push(r);
+                }
+            }
+        }
+        roots.sort_by(f64::total_cmp);
+        roots.dedup_by(|a, b| (*a - *b).abs() <= 1e-9 * (1.0 + b.abs()));
+        roots
+            .into_iter()
+            .filter_map(|r| {
+                // 0の近くの丸め誤差は0にする
+                let r = if r.abs() <= 1e-14 * (hi - lo) { 0.0 } else { r };
+                round_f64(r)
+            })
+            .collect()
+    }
+
+    /// 端の値fa, fbの符号が異なる区間[a, b]でtargetの根を絞り込む
+    /// 次の点は, Fの導関数があればNewton法で, なければ割線法で求め, 区間を外れるか区間が半分以下に
+    /// 縮まなかったときは二分法で求める
+    fn bracket(&mut self, target: Target, a: f64, b: f64, fa: f64, fb: f64) -> Option<f64> {
+        let newton = target == Target::F && self.deriv.is_some();
+        let (mut a, mut b, mut fa, mut fb) = (a, b, fa, fb);
+        let (mut x, mut fx) = if fa.abs() < fb.abs() {
+            (a, fa)
+        } else {
+            (b, fb)
+        };
+        let mut bisect = false;
+        for _ in 0..MAX_ITER {
+            let width = b - a;
+            if width <= 4.0 * f64::EPSILON * a.abs().max(b.abs()) || width == 0.0 {
+                break;
+            }
+            let step = if newton {
+                x - fx / self.df(x)
+            } else {
+                a - fa * width / (fb - fa)
+            };
+            let next = if !bisect && a < step && step < b {
+                step
+            } else {
+                0.5 * (a + b)
+            };
+            let fnext = self.value(target, next);
+            if fnext == 0.0 {
+                return Some(next);
+            }
+            if fnext.is_nan() {
+                return None;
+            }
+            if fnext.signum() == fa.signum() {
+                (a, fa) = (next, fnext);
+            } else {
+                (b, fb) = (next, fnext);
+            }
+            (x, fx) = (next, fnext);
+            bisect = b - a > 0.5 * width;
+        }
+        Some(if fa.abs() < fb.abs() { a } else { b })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn roots(lhs: &str, rhs: Option<&str>, range: (f64, f64), conf: &Config) -> Vec<f64> {
+        let vars = HashMap::new();
+        Equation::new(lhs, rhs, Some("x"), &vars, conf)
+            .unwrap()
+            .roots(range.0, range.1)
+            .iter()
+            .map(|r| r.to_f64().unwrap())
+            .collect()
+    }
+
+    fn assert_roots(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len(), "{actual:?}");
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-10, "{actual:?} {expected:?}");
+        }
+    }
+
+    #[test]
+    fn test_roots() {
+        let conf = Config::default();
+        let sqrt2 = 2.0_f64.sqrt();
+        assert_roots(
+            &roots("x^{2} - 2", None, DEFAULT_RANGE, &conf),
+            &[-sqrt2, sqrt2],
+        );
+        assert_roots(&roots("x^{2}", Some("2"), (0.0, 10.0), &conf), &[sqrt2]);
+        // 接する根
+        assert_roots(&roots("(x - 1)^{2}", None, DEFAULT_RANGE, &conf), &[1.0]);
+        assert_roots(
+            &roots("x^{3} - 3x + 2", None, DEFAULT_RANGE, &conf),
+            &[-2.0, 1.0],
+        );
+        // 極は根ではない
+        assert_roots(
+            &roots("\\tan x", None, (-4.0, 4.0), &conf),
+            &[-std::f64::consts::PI, 0.0, std::f64::consts::PI],
+        );
+        assert_roots(&roots("\\frac{1}{x}", None, DEFAULT_RANGE, &conf), &[]);
+        assert_roots(
+            &roots("\\exp(x)", Some("x + 2"), DEFAULT_RANGE, &conf),
+            &[-1.84140566043696, 1.14619322062058],
+        );
+    }
+
+    #[test]
+    fn test_roots_degree() {
+        let conf = Config {
+            trig_func_arg: TrigFuncArg::Degree,
+            ..Default::default()
+        };
+        assert_roots(
+            &roots("\\sin x", Some("0.5"), (0.0, 360.0), &conf),
+            &[30.0, 150.0],
+        );
+    }
+
+    #[test]
+    fn test_unknown() {
+        let conf = Config::default();
+        let mut vars = HashMap::new();
+        vars.insert("a".to_string(), BigDecimal::from(3).into());
+        let eq = Equation::new("a y - \\pi", None, None, &vars, &conf).unwrap();
+        assert_eq!(eq.var, "y");
+        assert!(Equation::new("a y - z", None, None, &vars, &conf).is_err());
+        assert!(Equation::new("a y", None, Some("x"), &vars, &conf).is_err());
+    }
+}
@@ -0,0 +1,395 @@
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use num_traits::{Signed, Zero};
+use std::collections::HashMap;
+use unicode_width::UnicodeWidthStr;
+
+use crate::compile::Program;
+use crate::config::*;
+use crate::error::*;
+use crate::parser::{Parser, TscCmd};
+use crate::tokenizer::TokenKind;
+use crate::{OutpuFormat, Value};
+
+use super::{eval_form, find_option, option_arg, split_commas};
+
+/// 表の行数の上限
+const MAX_ROWS: usize = 10000;
+
+/// 表の書式
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TableFormat {
+    Text,
+    Csv,
+    Markdown,
+    Latex,
+}
+
+/// :table {f}, {g} ..., {x} = {a}..{b} step {h}, {y} = ... --{csv|md|tex} {file}
+/// 変数が複数なら全ての組み合わせを, 前の変数ほど外側にして並べる
+/// stepを省略すると1刻み. 書式のオプションの後にファイル名を書くとファイルに書き出す
+/// 消費したtoken数を返す
+pub fn table_cmd(
+    parser: &Parser,
+    cmd_idx: usize,
+    vars: &mut HashMap<String, Value>,
+) -> Result<usize, MyError> {
+    let (_, len) = parser.cmd_arg_form(cmd_idx + 1)?;
+    let end = cmd_idx + 1 + len;
+    let start = cmd_idx + 1;
+    let is_kind = |i: usize, kind: TokenKind, word: &str| {
+        let t = parser.get_token(i);
+        t.token_kind == kind && t.token == word
+    };
+    let option = find_option(parser, start, end, &["csv", "md", "tex"]);
+    let args_end = option.as_ref().map_or(end, |(i, _)| *i);
+    let (format, path) = match option {
+        Some((i, name)) => {
+            let format = match &*name {
+                "csv" => TableFormat::Csv,
+                "md" => TableFormat::Markdown,
+                _ => TableFormat::Latex,
+            };
+            (format, option_arg(parser, i, end))
+        }
+        None => (TableFormat::Text, None),
+    };
+    // 結果と同じく, 行の前にある:hex等の表示形式に従う
+    let radix = (0..cmd_idx)
+        .rev()
+        .find_map(|i| TscCmd::from_name(&parser.get_token(i).token))
+        .map_or(OutpuFormat::Default, OutpuFormat::from);
+
+    // , で区切った引数のうち = を含むものが変数の範囲, それ以外が列の式
+    let mut forms = Vec::new();
+    let mut ranges = Vec::new();
+    for (piece, i) in split_commas(parser, start, args_end) {
+        if (piece..i).any(|j| is_kind(j, TokenKind::TkOperator, "=")) {
+            ranges.push((piece, i));
+        } else {
+            forms.push(parser.tokens_form(piece, i - piece)?);
+        }
+    }
+    if ranges.is_empty() {
+        return Err(MyError::UnexpectedToken(
+            "'{var} = {a}..{b}'".to_string(),
+            "end of command".to_string(),
+            parser.format_err_loc_idx(cmd_idx),
+        ));
+    }
+
+    let conf = config_reader()?.clone();
+    let mut columns = Vec::new();
+    for (s, e) in ranges {
+        let var = parser.get_token(s);
+        if var.token_kind != TokenKind::TkVariable || !is_kind(s + 1, TokenKind::TkOperator, "=") {
+            return Err(MyError::UnexpectedToken(
+                "'{var} = '".to_string(),
+                format!("'{}'", var.token),
+                parser.format_err_loc_idx(s),
+            ));
+        }
+        let dots = (s + 2..e)
+            .find(|&i| is_kind(i, TokenKind::TkOperator, ".."))
+            .ok_or_else(|| {
+                MyError::UnexpectedToken(
+                    "'..'".to_string(),
+                    "end of range".to_string(),
+                    parser.format_err_loc_idx(s + 1),
+                )
+            })?;
+        let step = (dots + 1..e).find(|&i| is_kind(i, TokenKind::TkVariable, "step"));
+        let eval =
+            |from: usize, to: usize| eval_form(&parser.tokens_form(from, to - from)?, vars, &conf);
+        let lo = eval(s + 2, dots)?;
+        let hi = eval(dots + 1, step.unwrap_or(e))?;
+        let h = match step {
+            Some(step) => eval(step + 1, e)?,
+            None => BigDecimal::from(1),
+        };
+        columns.push((var.token.clone(), steps(&lo, &hi, &h)?));
+    }
+    let rows = columns
+        .iter()
+        .try_fold(1usize, |n, (_, v)| n.checked_mul(v.len()))
+        .filter(|&n| n <= MAX_ROWS)
+        .ok_or_else(|| MyError::InvalidInput(format!("table has more than {MAX_ROWS} rows")))?;
+
+    let mut bound = vars.clone();
+    for (var, values) in &columns {
+        bound.insert(var.clone(), values[0].clone().into());
+    }
+    let mut funcs = Vec::new();
+    for form in &forms {
+        let (ast, root) = Parser::new(form.clone(), false)?.build_expr()?;
+        let prog = Program::compile(&ast, root, form);
+        let slots = if conf.fast_eval {
+            Slots::Fast(prog.bind_f64(&bound)?)
+        } else {
+            Slots::Exact(prog.bind(&bound)?)
+        };
+        let var_slots: Vec<Option<usize>> = columns.iter().map(|(var, _)| prog.slot(var)).collect();
+        funcs.push((prog, slots, var_slots));
+    }
+
+    let mut table = Table {
+        header: columns
+            .iter()
+            .map(|(var, _)| var.clone())
+            .chain(forms)
+            .collect(),
+        rows: Vec::with_capacity(rows),
+    };
+    for r in 0..rows {
+        // 後の変数ほど速く変わる
+        let mut rest = r;
+        let mut idx = vec![0; columns.len()];
+        for (k, (_, values)) in columns.iter().enumerate().rev() {
+            idx[k] = rest % values.len();
+            rest /= values.len();
+        }
+        let mut row: Vec<Option<BigDecimal>> = columns
+            .iter()
+            .zip(&idx)
+            .map(|((_, values), &i)| Some(values[i].clone()))
+            .collect();
+        for (prog, slots, var_slots) in &mut funcs {
+            for (k, slot) in var_slots.iter().enumerate() {
+                if let Some(slot) = slot {
+                    slots.set(*slot, &columns[k].1[idx[k]]);
+                }
+            }
+            // 定義されない点は表の中で示し, 表は最後まで作る
+            row.push(slots.eval(prog, &conf));
+        }
+        table.rows.push(row);
+    }
+    let text = table.render(format, |v| radix.format(v, conf.num_of_digit));
+    match path {
+        Some(path) => {
+            std::fs::write(&path, text).map_err(|e| MyError::FileWriteErr(path, e.to_string()))?
+        }
+        None => print!("{text}"),
+    }
+    Ok(len + 1)
+}
+
+/// 式の変数の値. 設定のfast_evalならf64で評価する
+enum Slots {
+    Exact(Vec<BigDecimal>),
+    Fast(Vec<f64>),
+}
+
+impl Slots {
+    fn set(&mut self, slot: usize, value: &BigDecimal) {
+        match self {
+            Slots::Exact(slots) => slots[slot] = value.clone(),
+            Slots::Fast(slots) => slots[slot] = value.to_f64().unwrap_or(f64::NAN),
+        }
+    }
+
+    /// 定義されない点ならNone. f64で表せない値も定義されない点として扱う
+    fn eval(&self, prog: &Program, conf: &Config) -> Option<BigDecimal> {
+        match self {
+            Slots::Exact(slots) => prog.eval(slots, conf).ok(),
+            Slots::Fast(slots) => prog
+                .eval_f64(slots, conf)
+                .ok()
+                .and_then(BigDecimal::from_f64),
+        }
+    }
+}
+
+/// lo から hi まで h 刻みの値. 刻みの丸め誤差で hi を落とさないよう少し余裕をみる
+fn steps(lo: &BigDecimal, hi: &BigDecimal, h: &BigDecimal) -> Result<Vec<BigDecimal>, MyError> {
+    if h.is_zero() || (hi != lo && (hi - lo).is_negative() != h.is_negative()) {
+        return Err(MyError::InvalidInput(format!(
+            "{hi} is unreachable from {lo} by step {h}"
+        )));
+    }
+    let tol = -(h.abs() * BigDecimal::new(1.into(), 12));
+    let mut values = Vec::new();
+    let mut v = lo.clone();
+    while (hi - &v) * h.signum() >= tol {
+        if values.len() == MAX_ROWS {
+            return Err(MyError::InvalidInput(format!(
+                "table has more than {MAX_ROWS} rows"
+            )));
+        }
+        values.push(v.normalized());
+        v += h;
+    }
+    Ok(values)
+}
+
+/// 見出しと値の表. 値の無いセルは定義されない点
+struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<Option<BigDecimal>>>,
+}
+
+impl Table {
+    fn render(&self, format: TableFormat, fmt: impl Fn(&BigDecimal) -> String) -> String {
+        let cells: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|v| match (v, format) {
+                        (Some(v), TableFormat::Latex) => {
+                            format!("${}$", fmt(v).replace('*', "\\times"))
+                        }
+                        (Some(v), _) => fmt(v),
+                        (None, TableFormat::Csv) => String::new(),
+                        (None, _) => "undefined".to_string(),
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut out = String::new();
+        match format {
+            TableFormat::Text => {
+                let widths: Vec<usize> = (0..self.header.len())
+                    .map(|c| {
+                        cells
+                            .iter()
+                            .map(|row| row[c].width())
+                            .chain([self.header[c].width()])
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .collect();
+                let line = |row: &[String]| {
+                    let cols: Vec<String> = row
+                        .iter()
+                        .zip(&widths)
+                        .map(|(s, &w)| format!("{}{s}", " ".repeat(w - s.width())))
+                        .collect();
+                    cols.join("  ") + "\n"
+                };
+                out += &line(&self.header);
+                let rule: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+                out += &line(&rule);
+                for row in &cells {
+                    out += &line(row);
+                }
+            }
+            TableFormat::Csv => {
+                let header: Vec<String> = self.header.iter().map(|h| csv_field(h)).collect();
+                out += &(header.join(",") + "\n");
+                for row in &cells {
+                    let row: Vec<String> = row.iter().map(|c| csv_field(c)).collect();
+                    out += &(row.join(",") + "\n");
+                }
+            }
+            TableFormat::Markdown => {
+                let header: Vec<String> = self
+                    .header
+                    .iter()
+                    .map(|h| format!("${}$", h.replace('|', "\\|")))
+                    .collect();
+                out += &format!("| {} |\n", header.join(" | "));
+                out += &format!("|{}\n", " ---: |".repeat(header.len()));
+                for row in &cells {
+                    out += &format!("| {} |\n", row.join(" | "));
+                }
+            }
+            TableFormat::Latex => {
+                out += &format!(
+                    "\\begin{{tabular}}{{{}}}\n\\hline\n",
+                    "r".repeat(self.header.len())
+                );
+                let header: Vec<String> = self.header.iter().map(|h| format!("${h}$")).collect();
+                out += &format!("{} \\\\\n\\hline\n", header.join(" & "));
+                for row in &cells {
+                    out += &format!("{} \\\\\n", row.join(" & "));
+                }
+                out += "\\hline\n\\end{tabular}\n";
+            }
+        }
+        out
+    }
+}
+
+/// , や " を含むCSVのフィールドは " で囲む
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn dec(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_steps() {
+        let v = steps(&dec("0"), &dec("1"), &dec("0.25")).unwrap();
+        assert_eq!(v.len(), 5);
+        assert_eq!(v[4], dec("1"));
+        // 下る刻みと, 刻みの丸め誤差
+        let v = steps(&dec("1"), &dec("-1"), &dec("-1")).unwrap();
+        assert_eq!(v, [dec("1"), dec("0"), dec("-1")]);
+        let third = dec("0.3333333333333333");
+        assert_eq!(steps(&dec("0"), &dec("1"), &third).unwrap().len(), 4);
+        assert!(steps(&dec("0"), &dec("1"), &dec("-1")).is_err());
+        assert!(steps(&dec("0"), &dec("1"), &dec("0")).is_err());
+        assert_eq!(steps(&dec("2"), &dec("2"), &dec("1")).unwrap(), [dec("2")]);
+    }
+
+    #[test]
+    fn test_slots() {
+        let form = "\\frac{1}{x} + x^{2}";
+        let (ast, root) = Parser::new(form.to_string(), false)
+            .unwrap()
+            .build_expr()
+            .unwrap();
+        let prog = Program::compile(&ast, root, form);
+        let conf = Config::default();
+        let x = prog.slot("x").unwrap();
+        let mut exact = Slots::Exact(vec![BigDecimal::zero(); prog.slots().len()]);
+        let mut fast = Slots::Fast(vec![0.0; prog.slots().len()]);
+        for slots in [&mut exact, &mut fast] {
+            assert_eq!(slots.eval(&prog, &conf), None);
+            slots.set(x, &dec("4"));
+        }
+        assert_eq!(exact.eval(&prog, &conf), Some(dec("16.25")));
+        assert_eq!(fast.eval(&prog, &conf), Some(dec("16.25")));
+    }
+
+    #[test]
+    fn test_render() {
+        let table = Table {
+            header: vec!["x".to_string(), "\\frac{1}{x}".to_string()],
+            rows: vec![
+                vec![Some(dec("0")), None],
+                vec![Some(dec("2")), Some(dec("0.5"))],
+            ],
+        };
+        let fmt = |v: &BigDecimal| v.to_string();
+        assert_eq!(
+            table.render(TableFormat::Text, fmt),
+            "x  \\frac{1}{x}\n-  -----------\n0    undefined\n2          0.5\n"
+        );
+        assert_eq!(
+            table.render(TableFormat::Csv, fmt),
+            "x,\\frac{1}{x}\n0,\n2,0.5\n"
+        );
+        assert_eq!(
+            table.render(TableFormat::Markdown, fmt),
+            "| $x$ | $\\frac{1}{x}$ |\n| ---: | ---: |\n| 0 | undefined |\n| 2 | 0.5 |\n"
+        );
+        assert_eq!(
+            table.render(TableFormat::Latex, fmt),
+            "\\begin{tabular}{rr}\n\\hline\n$x$ & $\\frac{1}{x}$ \\\\\n\\hline\n\
+             $0$ & undefined \\\\\n$2$ & $0.5$ \\\\\n\\hline\n\\end{tabular}\n"
+        );
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+}